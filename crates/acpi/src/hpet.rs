@@ -0,0 +1,26 @@
+use x86::addr::PhysAddr;
+
+use crate::sdt::{GenericAddress, SdtHeader};
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct HpetTable {
+    pub header: SdtHeader,
+    pub event_timer_block_id: u32,
+    pub base_address: GenericAddress,
+    pub hpet_number: u8,
+    pub minimum_tick: u16,
+    pub page_protection: u8,
+}
+
+impl HpetTable {
+    pub const SIGNATURE: &'static [u8; 4] = b"HPET";
+
+    pub fn base_address(&self) -> PhysAddr {
+        PhysAddr::new(self.base_address.address)
+    }
+
+    pub fn comparator_count(&self) -> u8 {
+        ((self.event_timer_block_id >> 8) & 0x1F) as u8 + 1
+    }
+}
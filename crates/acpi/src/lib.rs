@@ -0,0 +1,162 @@
+#![no_std]
+
+pub mod hpet;
+pub mod madt;
+pub mod sdt;
+
+use core::mem::size_of;
+
+use snafu::Snafu;
+use x86::addr::{PhysAddr, VirtAddr};
+
+use hpet::HpetTable;
+use madt::Madt;
+use sdt::{Rsdp, SdtHeader};
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const EBDA_POINTER: u64 = 0x40E;
+const BIOS_AREA_START: u64 = 0xE0000;
+const BIOS_AREA_END: u64 = 0x100000;
+
+#[derive(Debug, Snafu)]
+pub enum AcpiError {
+    #[snafu(display("RSDP not found"))]
+    RsdpNotFound,
+    #[snafu(display("Invalid checksum for table {}", core::str::from_utf8(signature).unwrap_or("????")))]
+    InvalidChecksum { signature: [u8; 4] },
+}
+
+pub struct Acpi {
+    phys_offset: VirtAddr,
+    root: PhysAddr,
+    // The XSDT stores 64 bits pointers while the RSDT stores 32 bits ones
+    entry_size: usize,
+}
+
+impl Acpi {
+    /// Locates the RSDP in the BIOS memory areas and validates the root table.
+    ///
+    /// The whole physical memory must be mapped at `phys_offset`.
+    pub unsafe fn new(phys_offset: VirtAddr) -> Result<Self, AcpiError> {
+        let rsdp_addr = unsafe { find_rsdp(phys_offset) }.ok_or(AcpiError::RsdpNotFound)?;
+        let rsdp: Rsdp = unsafe { read_phys(phys_offset, rsdp_addr) };
+
+        let (root, entry_size) = match rsdp.revision {
+            0 => (PhysAddr::new(rsdp.rsdt_address as u64), size_of::<u32>()),
+            _ => (PhysAddr::new(rsdp.xsdt_address), size_of::<u64>()),
+        };
+
+        let acpi = Self {
+            phys_offset,
+            root,
+            entry_size,
+        };
+        acpi.validate(root)?;
+
+        Ok(acpi)
+    }
+
+    pub fn header(&self, addr: PhysAddr) -> SdtHeader {
+        unsafe { read_phys(self.phys_offset, addr) }
+    }
+
+    pub fn tables(&self) -> impl Iterator<Item = PhysAddr> + '_ {
+        let header = self.header(self.root);
+        let count = (header.length as usize - size_of::<SdtHeader>()) / self.entry_size;
+        let entries = self.root + size_of::<SdtHeader>() as u64;
+
+        (0..count).map(move |i| {
+            let entry = entries + (i * self.entry_size) as u64;
+            let addr = match self.entry_size {
+                4 => unsafe { read_phys::<u32>(self.phys_offset, entry) as u64 },
+                _ => unsafe { read_phys::<u64>(self.phys_offset, entry) },
+            };
+            PhysAddr::new(addr)
+        })
+    }
+
+    pub fn find_table(&self, signature: &[u8; 4]) -> Option<PhysAddr> {
+        self.tables()
+            .find(|&addr| &self.header(addr).signature == signature)
+    }
+
+    pub fn hpet(&self) -> Result<Option<HpetTable>, AcpiError> {
+        self.read_table(HpetTable::SIGNATURE)
+    }
+
+    pub fn madt(&self) -> Result<Option<Madt>, AcpiError> {
+        match self.find_table(Madt::SIGNATURE) {
+            Some(addr) => {
+                self.validate(addr)?;
+                Ok(Some(Madt::new(self.phys_offset, addr)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Reads a whole fixed size table after validating its checksum.
+    pub fn read_table<T>(&self, signature: &[u8; 4]) -> Result<Option<T>, AcpiError> {
+        match self.find_table(signature) {
+            Some(addr) => {
+                self.validate(addr)?;
+                Ok(Some(unsafe { read_phys(self.phys_offset, addr) }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn validate(&self, addr: PhysAddr) -> Result<(), AcpiError> {
+        let header = self.header(addr);
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                (self.phys_offset + addr.as_u64()).as_ptr::<u8>(),
+                header.length as usize,
+            )
+        };
+
+        match checksum(bytes) {
+            0 => Ok(()),
+            _ => Err(AcpiError::InvalidChecksum {
+                signature: header.signature,
+            }),
+        }
+    }
+
+    pub fn phys_offset(&self) -> VirtAddr {
+        self.phys_offset
+    }
+}
+
+unsafe fn find_rsdp(phys_offset: VirtAddr) -> Option<PhysAddr> {
+    // The first KiB of the Extended BIOS Data Area, the segment is stored at 0x40E
+    let ebda_segment: u16 = unsafe { read_phys(phys_offset, PhysAddr::new(EBDA_POINTER)) };
+    let ebda_start = (ebda_segment as u64) << 4;
+
+    let ebda = ebda_start..ebda_start + 1024;
+    let bios = BIOS_AREA_START..BIOS_AREA_END;
+
+    ebda.step_by(16)
+        .chain(bios.step_by(16))
+        .map(PhysAddr::new)
+        .find(|&addr| {
+            let signature: [u8; 8] = unsafe { read_phys(phys_offset, addr) };
+            if &signature != RSDP_SIGNATURE {
+                return false;
+            }
+
+            // Only the ACPI 1.0 part of the structure is covered by the checksum
+            let bytes = unsafe {
+                core::slice::from_raw_parts((phys_offset + addr.as_u64()).as_ptr::<u8>(), 20)
+            };
+            checksum(bytes) == 0
+        })
+}
+
+pub(crate) unsafe fn read_phys<T>(phys_offset: VirtAddr, addr: PhysAddr) -> T {
+    let ptr = (phys_offset + addr.as_u64()).as_ptr::<T>();
+    unsafe { core::ptr::read_unaligned(ptr) }
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
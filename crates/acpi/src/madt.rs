@@ -0,0 +1,158 @@
+use core::mem::size_of;
+
+use x86::addr::{PhysAddr, VirtAddr};
+
+use crate::{read_phys, sdt::SdtHeader};
+
+const ENTRY_LOCAL_APIC: u8 = 0;
+const ENTRY_IO_APIC: u8 = 1;
+const ENTRY_INTERRUPT_SOURCE_OVERRIDE: u8 = 2;
+const ENTRY_LOCAL_APIC_NMI: u8 = 4;
+const ENTRY_LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+
+const PROCESSOR_ENABLED: u32 = 1 << 0;
+const PROCESSOR_ONLINE_CAPABLE: u32 = 1 << 1;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct MadtHeader {
+    pub header: SdtHeader,
+    pub local_apic_address: u32,
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MadtEntry {
+    LocalApic {
+        processor_id: u8,
+        apic_id: u8,
+        flags: u32,
+    },
+    IoApic {
+        id: u8,
+        address: u32,
+        global_system_interrupt_base: u32,
+    },
+    InterruptSourceOverride {
+        bus: u8,
+        source: u8,
+        global_system_interrupt: u32,
+        flags: u16,
+    },
+    LocalApicNmi {
+        processor_id: u8,
+        flags: u16,
+        lint: u8,
+    },
+    LocalApicAddressOverride {
+        address: u64,
+    },
+    Unknown {
+        entry_type: u8,
+    },
+}
+
+/// A processor listed in the MADT, identified by the id of its local APIC.
+#[derive(Debug, Clone, Copy)]
+pub struct Processor {
+    pub processor_id: u8,
+    pub apic_id: u8,
+}
+
+/// The Multiple APIC Description Table, its entries follow the fixed header.
+pub struct Madt {
+    phys_offset: VirtAddr,
+    addr: PhysAddr,
+    header: MadtHeader,
+}
+
+impl Madt {
+    pub const SIGNATURE: &'static [u8; 4] = b"APIC";
+
+    pub(crate) fn new(phys_offset: VirtAddr, addr: PhysAddr) -> Self {
+        let header = unsafe { read_phys(phys_offset, addr) };
+        Self {
+            phys_offset,
+            addr,
+            header,
+        }
+    }
+
+    pub fn header(&self) -> MadtHeader {
+        self.header
+    }
+
+    pub fn local_apic_address(&self) -> PhysAddr {
+        let address = self.entries().find_map(|entry| match entry {
+            MadtEntry::LocalApicAddressOverride { address } => Some(address),
+            _ => None,
+        });
+        PhysAddr::new(address.unwrap_or(self.header.local_apic_address as u64))
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = MadtEntry> + '_ {
+        let end = self.addr.as_u64() + self.header.header.length as u64;
+        let mut next = self.addr.as_u64() + size_of::<MadtHeader>() as u64;
+
+        core::iter::from_fn(move || {
+            if next + 2 > end {
+                return None;
+            }
+
+            let [entry_type, length]: [u8; 2] = self.read(next, 0);
+            if length < 2 {
+                return None;
+            }
+
+            let entry = match entry_type {
+                ENTRY_LOCAL_APIC => MadtEntry::LocalApic {
+                    processor_id: self.read(next, 2),
+                    apic_id: self.read(next, 3),
+                    flags: self.read(next, 4),
+                },
+                ENTRY_IO_APIC => MadtEntry::IoApic {
+                    id: self.read(next, 2),
+                    address: self.read(next, 4),
+                    global_system_interrupt_base: self.read(next, 8),
+                },
+                ENTRY_INTERRUPT_SOURCE_OVERRIDE => MadtEntry::InterruptSourceOverride {
+                    bus: self.read(next, 2),
+                    source: self.read(next, 3),
+                    global_system_interrupt: self.read(next, 4),
+                    flags: self.read(next, 8),
+                },
+                ENTRY_LOCAL_APIC_NMI => MadtEntry::LocalApicNmi {
+                    processor_id: self.read(next, 2),
+                    flags: self.read(next, 3),
+                    lint: self.read(next, 5),
+                },
+                ENTRY_LOCAL_APIC_ADDRESS_OVERRIDE => MadtEntry::LocalApicAddressOverride {
+                    address: self.read(next, 4),
+                },
+                entry_type => MadtEntry::Unknown { entry_type },
+            };
+
+            next += length as u64;
+            Some(entry)
+        })
+    }
+
+    /// Processors that are enabled or can be brought online.
+    pub fn processors(&self) -> impl Iterator<Item = Processor> + '_ {
+        self.entries().filter_map(|entry| match entry {
+            MadtEntry::LocalApic {
+                processor_id,
+                apic_id,
+                flags,
+            } if flags & (PROCESSOR_ENABLED | PROCESSOR_ONLINE_CAPABLE) != 0 => Some(Processor {
+                processor_id,
+                apic_id,
+            }),
+            _ => None,
+        })
+    }
+
+    fn read<T>(&self, entry: u64, offset: u64) -> T {
+        unsafe { read_phys(self.phys_offset, PhysAddr::new(entry + offset)) }
+    }
+}
@@ -0,0 +1,157 @@
+use core::ptr;
+
+use bit_field::BitField;
+use x86::{
+    addr::{PhysAddr, VirtAddr},
+    registers::model_specific::{ApicBase, ApicBaseFlags},
+};
+
+pub const MMIO_SIZE: u64 = 0x1000;
+
+const ID: u64 = 0x020;
+const VERSION: u64 = 0x030;
+const TASK_PRIORITY: u64 = 0x080;
+const END_OF_INTERRUPT: u64 = 0x0B0;
+const SPURIOUS_INTERRUPT_VECTOR: u64 = 0x0F0;
+const ERROR_STATUS: u64 = 0x280;
+const INTERRUPT_COMMAND_LOW: u64 = 0x300;
+const INTERRUPT_COMMAND_HIGH: u64 = 0x310;
+const LVT_TIMER: u64 = 0x320;
+const TIMER_INITIAL_COUNT: u64 = 0x380;
+const TIMER_CURRENT_COUNT: u64 = 0x390;
+const TIMER_DIVIDE_CONFIGURATION: u64 = 0x3E0;
+
+const APIC_SOFTWARE_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+
+const ICR_DELIVERY_FIXED: u32 = 0b000 << 8;
+const ICR_DELIVERY_INIT: u32 = 0b101 << 8;
+const ICR_DELIVERY_STARTUP: u32 = 0b110 << 8;
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+const ICR_LEVEL_ASSERT: u32 = 1 << 14;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy)]
+pub enum TimerMode {
+    OneShot = 0b00 << 17,
+    Periodic = 0b01 << 17,
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy)]
+pub enum TimerDivide {
+    By1 = 0b1011,
+    By2 = 0b0000,
+    By4 = 0b0001,
+    By8 = 0b0010,
+    By16 = 0b0011,
+    By32 = 0b1000,
+    By64 = 0b1001,
+    By128 = 0b1010,
+}
+
+/// Physical address of the local APIC registers of the current CPU.
+pub fn base_address() -> PhysAddr {
+    let (frame, _) = ApicBase::read();
+    frame.start_address()
+}
+
+pub struct LocalApic {
+    base: VirtAddr,
+}
+
+impl LocalApic {
+    /// The local APIC registers must be mapped as uncacheable memory at `base`.
+    pub unsafe fn new(base: VirtAddr) -> Self {
+        Self { base }
+    }
+
+    /// Enables the local APIC of the current CPU.
+    ///
+    /// Spurious interrupts are delivered to `spurious_vector`.
+    pub fn enable(&self, spurious_vector: u8) {
+        let (frame, flags) = ApicBase::read();
+        unsafe { ApicBase::write(frame, flags | ApicBaseFlags::LAPIC_ENABLE) };
+
+        self.write(TASK_PRIORITY, 0);
+        self.write(
+            SPURIOUS_INTERRUPT_VECTOR,
+            APIC_SOFTWARE_ENABLE | spurious_vector as u32,
+        );
+    }
+
+    pub fn id(&self) -> u8 {
+        self.read(ID).get_bits(24..32) as u8
+    }
+
+    pub fn version(&self) -> u8 {
+        self.read(VERSION).get_bits(0..8) as u8
+    }
+
+    pub fn end_of_interrupt(&self) {
+        self.write(END_OF_INTERRUPT, 0);
+    }
+
+    pub fn error_status(&self) -> u32 {
+        // The register must be written before being read to latch the errors
+        self.write(ERROR_STATUS, 0);
+        self.read(ERROR_STATUS)
+    }
+
+    pub fn set_timer_divide(&self, divide: TimerDivide) {
+        self.write(TIMER_DIVIDE_CONFIGURATION, divide as u32);
+    }
+
+    /// Starts the timer, `vector` is raised each time the count reaches zero.
+    pub fn start_timer(&self, vector: u8, mode: TimerMode, initial_count: u32) {
+        self.write(LVT_TIMER, mode as u32 | vector as u32);
+        self.write(TIMER_INITIAL_COUNT, initial_count);
+    }
+
+    pub fn stop_timer(&self) {
+        self.write(LVT_TIMER, LVT_MASKED);
+        self.write(TIMER_INITIAL_COUNT, 0);
+    }
+
+    pub fn timer_current_count(&self) -> u32 {
+        self.read(TIMER_CURRENT_COUNT)
+    }
+
+    /// Sends the INIT IPI that resets the processor with the local APIC `apic_id`.
+    pub fn send_init(&self, apic_id: u8) {
+        self.send_command(apic_id, ICR_DELIVERY_INIT | ICR_LEVEL_ASSERT);
+    }
+
+    /// Sends a startup IPI, the processor starts in real mode at `vector * 0x1000`.
+    pub fn send_startup(&self, apic_id: u8, vector: u8) {
+        self.send_command(
+            apic_id,
+            ICR_DELIVERY_STARTUP | ICR_LEVEL_ASSERT | vector as u32,
+        );
+    }
+
+    pub fn send_ipi(&self, apic_id: u8, vector: u8) {
+        self.send_command(
+            apic_id,
+            ICR_DELIVERY_FIXED | ICR_LEVEL_ASSERT | vector as u32,
+        );
+    }
+
+    /// Writing the low half of the command register sends the interrupt.
+    fn send_command(&self, apic_id: u8, command: u32) {
+        self.write(INTERRUPT_COMMAND_HIGH, (apic_id as u32) << 24);
+        self.write(INTERRUPT_COMMAND_LOW, command);
+
+        while self.read(INTERRUPT_COMMAND_LOW) & ICR_DELIVERY_PENDING != 0 {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn read(&self, offset: u64) -> u32 {
+        unsafe { ptr::read_volatile((self.base + offset).as_ptr::<u32>()) }
+    }
+
+    fn write(&self, offset: u64, value: u32) {
+        unsafe { ptr::write_volatile((self.base + offset).as_mut_ptr::<u32>(), value) }
+    }
+}
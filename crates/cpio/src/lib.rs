@@ -0,0 +1,175 @@
+//! Archives of the cpio "newc" format, read by the kernel and written by its build script.
+//!
+//! An entry is a header of ASCII hexadecimal fields, its name with a NUL, then its data, both
+//! padded to a multiple of 4 bytes. The archive ends with an entry named `TRAILER!!!`.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{format, vec::Vec};
+use core::str;
+
+use snafu::Snafu;
+
+pub const MAGIC: &[u8] = b"070701";
+/// The magic followed by 13 fields of 8 digits: inode, mode, uid, gid, links, mtime, size, device
+/// major and minor, rdev major and minor, name size with its NUL, checksum.
+pub const HEADER_SIZE: usize = MAGIC.len() + FIELDS * 8;
+pub const TRAILER: &str = "TRAILER!!!";
+
+const FIELDS: usize = 13;
+const FIELD_MODE: usize = 1;
+const FIELD_SIZE: usize = 6;
+const FIELD_NAME_SIZE: usize = 11;
+
+/// File type bits of the mode.
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Snafu)]
+pub enum CpioError {
+    #[snafu(display("Archive ends inside an entry or before its trailer"))]
+    Truncated,
+    #[snafu(display("Not a newc cpio header"))]
+    BadMagic,
+    #[snafu(display("Header field is not 8 hexadecimal digits"))]
+    BadHeader,
+    #[snafu(display("Name is not UTF-8"))]
+    BadName,
+}
+
+/// An entry of an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry<'a> {
+    pub name: &'a str,
+    pub mode: u32,
+    pub data: &'a [u8],
+}
+
+/// Walks the entries of an archive up to its trailer, stopping at the first error.
+pub struct Entries<'a> {
+    archive: &'a [u8],
+    offset: usize,
+    done: bool,
+}
+
+impl<'a> Entries<'a> {
+    pub fn new(archive: &'a [u8]) -> Self {
+        Self {
+            archive,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CpioError> {
+        let bytes = self
+            .archive
+            .get(self.offset..)
+            .and_then(|rest| rest.get(..len))
+            .ok_or(CpioError::Truncated)?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    /// Skips the padding after the name and after the data, up to a multiple of 4 bytes.
+    fn align(&mut self) {
+        self.offset = self.offset.next_multiple_of(4);
+    }
+
+    fn next_entry(&mut self) -> Result<Option<Entry<'a>>, CpioError> {
+        let header = self.take(HEADER_SIZE)?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(CpioError::BadMagic);
+        }
+        let field = |index: usize| {
+            let start = MAGIC.len() + index * 8;
+            str::from_utf8(&header[start..start + 8])
+                .ok()
+                .filter(|digits| digits.bytes().all(|digit| digit.is_ascii_hexdigit()))
+                .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                .ok_or(CpioError::BadHeader)
+        };
+        let mode = field(FIELD_MODE)?;
+        let size = field(FIELD_SIZE)? as usize;
+        let name_size = field(FIELD_NAME_SIZE)? as usize;
+
+        let name = self.take(name_size)?;
+        self.align();
+        let name = name.strip_suffix(&[0]).unwrap_or(name);
+        let name = str::from_utf8(name).map_err(|_| CpioError::BadName)?;
+        if name == TRAILER {
+            return Ok(None);
+        }
+
+        let data = self.take(size)?;
+        self.align();
+        Ok(Some(Entry { name, mode, data }))
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Result<Entry<'a>, CpioError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let entry = self.next_entry().transpose();
+        if !matches!(entry, Some(Ok(_))) {
+            self.done = true;
+        }
+        entry
+    }
+}
+
+/// Writes an archive, entry by entry.
+///
+/// The times, owners and devices stay zero and the inodes are numbered in order, so the archive
+/// only changes with the names, modes and data.
+#[derive(Default)]
+pub struct Writer {
+    bytes: Vec<u8>,
+    next_inode: u32,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: &str, mode: u32, data: &[u8]) {
+        self.next_inode += 1;
+        let links = if mode & S_IFMT == S_IFDIR { 2 } else { 1 };
+        let mut fields = [0; FIELDS];
+        fields[0] = self.next_inode;
+        fields[FIELD_MODE] = mode;
+        fields[4] = links;
+        fields[FIELD_SIZE] = data.len() as u32;
+        fields[FIELD_NAME_SIZE] = name.len() as u32 + 1;
+
+        self.bytes.extend_from_slice(MAGIC);
+        for field in fields {
+            self.bytes
+                .extend_from_slice(format!("{:08x}", field).as_bytes());
+        }
+        self.bytes.extend_from_slice(name.as_bytes());
+        self.bytes.push(0);
+        self.pad();
+        self.bytes.extend_from_slice(data);
+        self.pad();
+    }
+
+    /// Adds the trailer and returns the archive.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.add(TRAILER, 0, &[]);
+        self.bytes
+    }
+
+    fn pad(&mut self) {
+        self.bytes.resize(self.bytes.len().next_multiple_of(4), 0);
+    }
+}
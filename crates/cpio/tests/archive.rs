@@ -0,0 +1,134 @@
+//! Tests of the newc reader on archives made by the writer of the kernel build script. They run on
+//! the host: `cargo test -p cpio --target x86_64-unknown-linux-gnu -Z build-std=std,panic_unwind`.
+
+use cpio::{CpioError, Entries, Entry, Writer, HEADER_SIZE, MAGIC, S_IFDIR, S_IFLNK, S_IFREG};
+
+fn archive() -> Vec<u8> {
+    let mut writer = Writer::new();
+    writer.add("etc", S_IFDIR | 0o755, &[]);
+    writer.add("etc/motd", S_IFREG | 0o644, b"Welcome!\n");
+    writer.add("etc/empty", S_IFREG | 0o600, &[]);
+    writer.add("motd", S_IFLNK | 0o777, b"etc/motd");
+    writer.finish()
+}
+
+fn entries(archive: &[u8]) -> Result<Vec<Entry<'_>>, CpioError> {
+    Entries::new(archive).collect()
+}
+
+/// Replaces the 8 digits of field `index` of the first header.
+fn set_field(archive: &mut [u8], index: usize, digits: &[u8; 8]) {
+    let start = MAGIC.len() + index * 8;
+    archive[start..start + 8].copy_from_slice(digits);
+}
+
+#[test]
+fn reads_what_the_writer_wrote() {
+    let archive = archive();
+    assert_eq!(archive.len() % 4, 0);
+    assert_eq!(
+        entries(&archive).unwrap(),
+        [
+            Entry {
+                name: "etc",
+                mode: S_IFDIR | 0o755,
+                data: b"",
+            },
+            Entry {
+                name: "etc/motd",
+                mode: S_IFREG | 0o644,
+                data: b"Welcome!\n",
+            },
+            Entry {
+                name: "etc/empty",
+                mode: S_IFREG | 0o600,
+                data: b"",
+            },
+            Entry {
+                name: "motd",
+                mode: S_IFLNK | 0o777,
+                data: b"etc/motd",
+            },
+        ]
+    );
+    assert_eq!(entries(&Writer::new().finish()).unwrap(), []);
+}
+
+#[test]
+fn writes_reproducible_headers() {
+    let archive = archive();
+    let header = std::str::from_utf8(&archive[..HEADER_SIZE]).unwrap();
+    assert_eq!(
+        header,
+        "070701\
+         00000001\
+         000041ed\
+         00000000\
+         00000000\
+         00000002\
+         00000000\
+         00000000\
+         00000000\
+         00000000\
+         00000000\
+         00000000\
+         00000004\
+         00000000"
+    );
+    assert_eq!(archive, self::archive());
+}
+
+#[test]
+fn stops_at_the_trailer() {
+    let mut archive = archive();
+    archive.extend(b"garbage after the trailer");
+    assert_eq!(entries(&archive).unwrap().len(), 4);
+}
+
+#[test]
+fn rejects_truncated_archives() {
+    let archive = archive();
+    // Inside the first header, its name, and the data of the second entry
+    for len in [0, HEADER_SIZE - 1, HEADER_SIZE + 2, 2 * HEADER_SIZE + 16] {
+        let result = entries(&archive[..len]);
+        assert_eq!(result.err(), Some(CpioError::Truncated), "{} bytes", len);
+    }
+}
+
+#[test]
+fn rejects_archives_without_a_trailer() {
+    let mut writer = Writer::new();
+    writer.add("file", S_IFREG | 0o644, b"data");
+    let mut archive = writer.finish();
+    let trailer = archive.len() - (HEADER_SIZE + "TRAILER!!!\0".len()).next_multiple_of(4);
+    archive.truncate(trailer);
+
+    let mut entries = Entries::new(&archive);
+    assert_eq!(entries.next().unwrap().unwrap().name, "file");
+    assert_eq!(entries.next(), Some(Err(CpioError::Truncated)));
+    assert_eq!(entries.next(), None);
+}
+
+#[test]
+fn rejects_bad_magic() {
+    let mut archive = archive();
+    // The old binary and "odc" formats
+    archive[..6].copy_from_slice(b"070707");
+    assert_eq!(entries(&archive).err(), Some(CpioError::BadMagic));
+}
+
+#[test]
+fn rejects_fields_that_are_not_hexadecimal() {
+    for digits in [b"0000004g", b"+00041ed", b"        "] {
+        let mut archive = archive();
+        set_field(&mut archive, 1, digits);
+        assert_eq!(entries(&archive).err(), Some(CpioError::BadHeader));
+    }
+}
+
+#[test]
+fn rejects_names_that_are_not_utf8() {
+    let mut archive = archive();
+    archive[HEADER_SIZE] = 0xFF;
+    assert_eq!(entries(&archive).err(), Some(CpioError::BadName));
+}
@@ -0,0 +1,47 @@
+pub const ELF_MAGIC: [u8; 4] = *b"\x7fELF";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfType {
+    Relocatable,
+    Executable,
+    Shared,
+    Core,
+    Other(u16),
+}
+
+impl From<u16> for ElfType {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => ElfType::Relocatable,
+            2 => ElfType::Executable,
+            3 => ElfType::Shared,
+            4 => ElfType::Core,
+            other => ElfType::Other(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ElfHeader {
+    pub ident: [u8; 16],
+    pub kind: u16,
+    pub machine: u16,
+    pub version: u32,
+    pub entry: u64,
+    pub phoff: u64,
+    pub shoff: u64,
+    pub flags: u32,
+    pub ehsize: u16,
+    pub phentsize: u16,
+    pub phnum: u16,
+    pub shentsize: u16,
+    pub shnum: u16,
+    pub shstrndx: u16,
+}
+
+impl ElfHeader {
+    pub fn elf_type(&self) -> ElfType {
+        ElfType::from(self.kind)
+    }
+}
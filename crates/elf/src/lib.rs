@@ -0,0 +1,133 @@
+#![no_std]
+
+pub mod header;
+pub mod program;
+
+use core::mem::size_of;
+
+use snafu::Snafu;
+
+use header::{ElfHeader, ELF_MAGIC};
+use program::{ProgramHeader, SegmentType};
+
+const CLASS_64: u8 = 2;
+const DATA_LITTLE_ENDIAN: u8 = 1;
+const MACHINE_X86_64: u16 = 0x3E;
+
+#[derive(Debug, Snafu)]
+pub enum ElfError {
+    #[snafu(display("File too small for its headers"))]
+    Truncated,
+    #[snafu(display("Not an ELF file"))]
+    InvalidMagic,
+    #[snafu(display("Not a 64 bits little endian ELF file"))]
+    UnsupportedClass,
+    #[snafu(display("Not an x86_64 ELF file"))]
+    UnsupportedMachine,
+    #[snafu(display("Unexpected program header size {}", size))]
+    InvalidProgramHeaderSize { size: u16 },
+    #[snafu(display("Segment {} is out of the file", index))]
+    SegmentOutOfBounds { index: usize },
+    #[snafu(display("Segment {} has invalid sizes", index))]
+    InvalidSegmentSize { index: usize },
+    #[snafu(display("Segment {} is not aligned like its offset in the file", index))]
+    MisalignedSegment { index: usize },
+    #[snafu(display("Segment {} overlaps or comes before the previous one", index))]
+    OverlappingSegment { index: usize },
+}
+
+/// An ELF64 file validated for x86_64.
+pub struct ElfFile<'a> {
+    data: &'a [u8],
+    header: ElfHeader,
+}
+
+impl<'a> ElfFile<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, ElfError> {
+        let header: ElfHeader = read(data, 0).ok_or(ElfError::Truncated)?;
+        if header.ident[..4] != ELF_MAGIC {
+            return Err(ElfError::InvalidMagic);
+        }
+        if header.ident[4] != CLASS_64 || header.ident[5] != DATA_LITTLE_ENDIAN {
+            return Err(ElfError::UnsupportedClass);
+        }
+        if header.machine != MACHINE_X86_64 {
+            return Err(ElfError::UnsupportedMachine);
+        }
+        if header.phnum > 0 && header.phentsize as usize != size_of::<ProgramHeader>() {
+            return Err(ElfError::InvalidProgramHeaderSize {
+                size: header.phentsize,
+            });
+        }
+
+        let elf = Self { data, header };
+        // The loadable segments are sorted by address
+        let mut loaded_end = 0;
+        for (index, program) in elf.program_headers().enumerate() {
+            let program = program.ok_or(ElfError::Truncated)?;
+            if elf.segment_data(&program).is_none() {
+                return Err(ElfError::SegmentOutOfBounds { index });
+            }
+            if program.segment_type() != SegmentType::Load || program.memsz == 0 {
+                continue;
+            }
+
+            let end = program
+                .vaddr
+                .checked_add(program.memsz)
+                .filter(|_| program.filesz <= program.memsz)
+                .ok_or(ElfError::InvalidSegmentSize { index })?;
+            if !is_aligned(&program) {
+                return Err(ElfError::MisalignedSegment { index });
+            }
+            if program.vaddr < loaded_end {
+                return Err(ElfError::OverlappingSegment { index });
+            }
+            loaded_end = end;
+        }
+
+        Ok(elf)
+    }
+
+    pub fn header(&self) -> &ElfHeader {
+        &self.header
+    }
+
+    pub fn entry(&self) -> u64 {
+        self.header.entry
+    }
+
+    /// The program headers, `None` for the ones past the end of the file.
+    pub fn program_headers(&self) -> impl Iterator<Item = Option<ProgramHeader>> + 'a {
+        let data = self.data;
+        let offset = self.header.phoff as usize;
+        (0..self.header.phnum as usize).map(move |i| {
+            let start = offset.checked_add(i * size_of::<ProgramHeader>())?;
+            read(data, start)
+        })
+    }
+
+    /// Bytes of the segment present in the file.
+    pub fn segment_data(&self, program: &ProgramHeader) -> Option<&'a [u8]> {
+        let start = usize::try_from(program.offset).ok()?;
+        let end = start.checked_add(usize::try_from(program.filesz).ok()?)?;
+        self.data.get(start..end)
+    }
+}
+
+/// Whether the address of a segment is congruent to its offset modulo its alignment, 0 and 1
+/// meaning none.
+fn is_aligned(program: &ProgramHeader) -> bool {
+    match program.align {
+        0 | 1 => true,
+        align if align.is_power_of_two() => program.vaddr % align == program.offset % align,
+        _ => false,
+    }
+}
+
+/// Reads a plain structure at `offset`, `None` if it doesn't fit.
+fn read<T: Copy>(data: &[u8], offset: usize) -> Option<T> {
+    let end = offset.checked_add(size_of::<T>())?;
+    let bytes = data.get(offset..end)?;
+    Some(unsafe { bytes.as_ptr().cast::<T>().read_unaligned() })
+}
@@ -0,0 +1,60 @@
+use bitflags::bitflags;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentType {
+    Null,
+    Load,
+    Dynamic,
+    Interp,
+    Note,
+    ProgramHeader,
+    Tls,
+    Other(u32),
+}
+
+impl From<u32> for SegmentType {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => SegmentType::Null,
+            1 => SegmentType::Load,
+            2 => SegmentType::Dynamic,
+            3 => SegmentType::Interp,
+            4 => SegmentType::Note,
+            6 => SegmentType::ProgramHeader,
+            7 => SegmentType::Tls,
+            other => SegmentType::Other(other),
+        }
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SegmentFlags: u32 {
+        const EXECUTE = 1;
+        const WRITE = 1 << 1;
+        const READ = 1 << 2;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ProgramHeader {
+    pub kind: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub paddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+    pub align: u64,
+}
+
+impl ProgramHeader {
+    pub fn segment_type(&self) -> SegmentType {
+        SegmentType::from(self.kind)
+    }
+
+    pub fn segment_flags(&self) -> SegmentFlags {
+        SegmentFlags::from_bits_truncate(self.flags)
+    }
+}
@@ -0,0 +1,222 @@
+//! Tests on hand made ELF files and on the programs the kernel embeds. They run on the host:
+//! `cargo test -p elf --target x86_64-unknown-linux-gnu -Z build-std=std,panic_unwind`.
+
+use elf::{
+    header::ElfType,
+    program::{ProgramHeader, SegmentFlags, SegmentType},
+    ElfError, ElfFile,
+};
+
+const HELLO: &[u8] = include_bytes!("../../../programs/hello");
+const LINUX_HELLO: &[u8] = include_bytes!("../../../programs/linux-hello");
+
+const HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+/// Size of the files made by `elf`, its segments point in there.
+const FILE_SIZE: usize = 0x3000;
+const PT_LOAD: u32 = 1;
+
+fn segment(offset: u64, vaddr: u64, filesz: u64, memsz: u64) -> ProgramHeader {
+    ProgramHeader {
+        kind: PT_LOAD,
+        flags: (SegmentFlags::READ | SegmentFlags::EXECUTE).bits(),
+        offset,
+        vaddr,
+        paddr: vaddr,
+        filesz,
+        memsz,
+        align: 0x1000,
+    }
+}
+
+/// An x86_64 executable with `segments`, its program headers right after the ELF header.
+fn elf(segments: &[ProgramHeader]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend(b"\x7fELF");
+    data.extend([2, 1, 1]);
+    data.resize(16, 0);
+    data.extend(2u16.to_le_bytes());
+    data.extend(0x3Eu16.to_le_bytes());
+    data.extend(1u32.to_le_bytes());
+    data.extend(0x40_1000u64.to_le_bytes());
+    data.extend((HEADER_SIZE as u64).to_le_bytes());
+    data.extend(0u64.to_le_bytes());
+    data.extend(0u32.to_le_bytes());
+    data.extend((HEADER_SIZE as u16).to_le_bytes());
+    data.extend((PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+    data.extend((segments.len() as u16).to_le_bytes());
+    data.extend([0; 6]);
+    assert_eq!(data.len(), HEADER_SIZE);
+
+    for segment in segments {
+        data.extend(segment.kind.to_le_bytes());
+        data.extend(segment.flags.to_le_bytes());
+        for field in [
+            segment.offset,
+            segment.vaddr,
+            segment.paddr,
+            segment.filesz,
+            segment.memsz,
+            segment.align,
+        ] {
+            data.extend(field.to_le_bytes());
+        }
+    }
+    data.resize(FILE_SIZE, 0xCC);
+    data
+}
+
+fn parse_error(data: &[u8]) -> ElfError {
+    match ElfFile::parse(data) {
+        Ok(_) => panic!("parsed"),
+        Err(err) => err,
+    }
+}
+
+#[test]
+fn parses_the_embedded_programs() {
+    let hello = ElfFile::parse(HELLO).unwrap();
+    assert_eq!(hello.header().elf_type(), ElfType::Executable);
+    assert_eq!(hello.entry(), 0x4000_0000_1000);
+    let segments: Vec<ProgramHeader> = hello.program_headers().map(Option::unwrap).collect();
+    assert_eq!(segments.len(), 3);
+    assert!(segments
+        .iter()
+        .all(|segment| segment.segment_type() == SegmentType::Load));
+    assert_eq!(
+        segments[1].segment_flags(),
+        SegmentFlags::READ | SegmentFlags::EXECUTE
+    );
+    let text = hello.segment_data(&segments[1]).unwrap();
+    assert_eq!(text.len() as u64, segments[1].filesz);
+
+    let linux_hello = ElfFile::parse(LINUX_HELLO).unwrap();
+    assert_eq!(linux_hello.header().elf_type(), ElfType::Executable);
+    // Its data segment ends with zeroed memory past the file data
+    assert!(linux_hello
+        .program_headers()
+        .flatten()
+        .any(|segment| segment.memsz > segment.filesz));
+}
+
+#[test]
+fn parses_segments() {
+    let data = elf(&[
+        segment(0x1000, 0x40_1000, 0x800, 0x800),
+        // In the same page as the one before, and larger in memory
+        segment(0x1800, 0x40_1800, 0x100, 0x3000),
+    ]);
+    let elf = ElfFile::parse(&data).unwrap();
+    let segments: Vec<ProgramHeader> = elf.program_headers().map(Option::unwrap).collect();
+    assert_eq!(segments.len(), 2);
+    assert_eq!(elf.segment_data(&segments[1]).unwrap(), [0xCC; 0x100]);
+}
+
+#[test]
+fn rejects_bad_headers() {
+    let data = elf(&[]);
+    assert!(matches!(parse_error(&data[..40]), ElfError::Truncated));
+
+    let mut bad_magic = data.clone();
+    bad_magic[1] = b'X';
+    assert!(matches!(parse_error(&bad_magic), ElfError::InvalidMagic));
+
+    let mut class_32 = data.clone();
+    class_32[4] = 1;
+    assert!(matches!(parse_error(&class_32), ElfError::UnsupportedClass));
+
+    let mut big_endian = data.clone();
+    big_endian[5] = 2;
+    assert!(matches!(
+        parse_error(&big_endian),
+        ElfError::UnsupportedClass
+    ));
+
+    let mut arm = data.clone();
+    arm[18] = 0xB7;
+    assert!(matches!(parse_error(&arm), ElfError::UnsupportedMachine));
+
+    let mut header_size = elf(&[segment(0x1000, 0x40_1000, 0x10, 0x10)]);
+    header_size[54] = 32;
+    assert!(matches!(
+        parse_error(&header_size),
+        ElfError::InvalidProgramHeaderSize { size: 32 }
+    ));
+}
+
+#[test]
+fn rejects_program_headers_past_the_end() {
+    let data = elf(&[segment(0x1000, 0x40_1000, 0x10, 0x10)]);
+    assert!(matches!(
+        parse_error(&data[..HEADER_SIZE + 20]),
+        ElfError::Truncated
+    ));
+    let outside = elf(&[segment(0x2800, 0x40_2800, 0x1000, 0x1000)]);
+    assert!(matches!(
+        parse_error(&outside),
+        ElfError::SegmentOutOfBounds { index: 0 }
+    ));
+}
+
+#[test]
+fn rejects_segments_larger_in_the_file() {
+    let data = elf(&[segment(0x1000, 0x40_1000, 0x200, 0x100)]);
+    assert!(matches!(
+        parse_error(&data),
+        ElfError::InvalidSegmentSize { index: 0 }
+    ));
+
+    let wrapping = elf(&[segment(0x1000, u64::MAX - 0xFFF, 0x10, 0x2000)]);
+    assert!(matches!(
+        parse_error(&wrapping),
+        ElfError::InvalidSegmentSize { index: 0 }
+    ));
+}
+
+#[test]
+fn rejects_misaligned_segments() {
+    let data = elf(&[segment(0x1000, 0x40_1800, 0x10, 0x10)]);
+    assert!(matches!(
+        parse_error(&data),
+        ElfError::MisalignedSegment { index: 0 }
+    ));
+
+    let mut odd = segment(0x1000, 0x40_1000, 0x10, 0x10);
+    odd.align = 0x1800;
+    assert!(matches!(
+        parse_error(&elf(&[odd])),
+        ElfError::MisalignedSegment { index: 0 }
+    ));
+
+    // Without alignment, any address goes
+    let mut unaligned = segment(0x1000, 0x40_1234, 0x10, 0x10);
+    unaligned.align = 1;
+    assert!(ElfFile::parse(&elf(&[unaligned])).is_ok());
+}
+
+#[test]
+fn rejects_overlapping_segments() {
+    let data = elf(&[
+        segment(0x1000, 0x40_1000, 0x800, 0x1000),
+        segment(0x1800, 0x40_1800, 0x100, 0x100),
+    ]);
+    assert!(matches!(
+        parse_error(&data),
+        ElfError::OverlappingSegment { index: 1 }
+    ));
+
+    let unsorted = elf(&[
+        segment(0x2000, 0x40_2000, 0x100, 0x100),
+        segment(0x1000, 0x40_1000, 0x100, 0x100),
+    ]);
+    assert!(matches!(
+        parse_error(&unsorted),
+        ElfError::OverlappingSegment { index: 1 }
+    ));
+
+    // Only the loaded segments take room
+    let mut note = segment(0x1000, 0x40_1000, 0x10, 0x10);
+    note.kind = 4;
+    let with_note = elf(&[segment(0x1000, 0x40_1000, 0x100, 0x100), note]);
+    assert!(ElfFile::parse(&with_note).is_ok());
+}
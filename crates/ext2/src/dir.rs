@@ -0,0 +1,72 @@
+//! Directory entries, linked by their lengths in each block of a directory.
+
+use alloc::{string::String, vec::Vec};
+
+use vfs::{FileType, Result, VfsError};
+
+/// Inode number, record length, name length and file type.
+const HEADER_SIZE: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub inode: u32,
+    pub name: Vec<u8>,
+    /// Type of the file if the directory stores it.
+    pub file_type: Option<FileType>,
+}
+
+impl Entry {
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.name).into_owned()
+    }
+}
+
+/// The entries of the blocks of a directory. With `file_types`, the name length is a single
+/// byte followed by the file type, else it takes both bytes.
+pub fn parse(data: &[u8], block_size: usize, file_types: bool) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for block in data.chunks(block_size) {
+        let mut offset = 0;
+        while offset + HEADER_SIZE <= block.len() {
+            let header = &block[offset..offset + HEADER_SIZE];
+            let inode = u32::from_le_bytes(header[..4].try_into().unwrap());
+            let record_len = u16::from_le_bytes([header[4], header[5]]) as usize;
+            let (name_len, file_type) = match file_types {
+                true => (header[6] as usize, file_type(header[7])),
+                false => (u16::from_le_bytes([header[6], header[7]]) as usize, None),
+            };
+            // Records are aligned on 4 bytes and never cross a block
+            if record_len < HEADER_SIZE
+                || !record_len.is_multiple_of(4)
+                || offset + record_len > block.len()
+                || HEADER_SIZE + name_len > record_len
+            {
+                return Err(VfsError::Io);
+            }
+            // A record without inode is free space
+            if inode != 0 {
+                let name = block[offset + HEADER_SIZE..offset + HEADER_SIZE + name_len].to_vec();
+                entries.push(Entry {
+                    inode,
+                    name,
+                    file_type,
+                });
+            }
+            offset += record_len;
+        }
+    }
+    Ok(entries)
+}
+
+fn file_type(value: u8) -> Option<FileType> {
+    match value {
+        1 => Some(FileType::Regular),
+        2 => Some(FileType::Directory),
+        3 => Some(FileType::CharDevice),
+        4 => Some(FileType::BlockDevice),
+        5 => Some(FileType::Fifo),
+        6 => Some(FileType::Socket),
+        7 => Some(FileType::Symlink),
+        _ => None,
+    }
+}
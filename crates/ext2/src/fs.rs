@@ -0,0 +1,354 @@
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+
+use spin::Mutex;
+use vfs::{BlockDevice, DirEntry, FileSystem, FileType, Inode, Metadata, Result, VfsError};
+
+use crate::{
+    dir::{self, Entry},
+    inode::{self, RawInode, RAW_INODE_SIZE},
+    superblock::{self, Superblock, SUPERBLOCK_OFFSET, SUPERBLOCK_SIZE},
+    Ext2Error,
+};
+
+const ROOT_INODE: u32 = 2;
+/// Indirect blocks kept in memory.
+const CACHE_BLOCKS: usize = 16;
+/// Largest directory read, all of its entries are read at once.
+const MAX_DIR_SIZE: u64 = 64 * 1024;
+
+/// An indirect block, the block numbers it holds.
+struct Indirect {
+    blocks: Arc<[u32]>,
+    /// Time of the last access, to evict the least recently used block.
+    used: u64,
+}
+
+struct Cache {
+    blocks: BTreeMap<u32, Indirect>,
+    clock: u64,
+}
+
+/// An ext2 file system on a block device, read only.
+///
+/// The inodes are read when they are looked up, and the indirect blocks last used are cached.
+pub struct Ext2Fs {
+    device: Arc<dyn BlockDevice>,
+    superblock: Superblock,
+    /// Block of the inode table of each group.
+    inode_tables: Vec<u32>,
+    cache: Mutex<Cache>,
+    this: Weak<Ext2Fs>,
+}
+
+impl Ext2Fs {
+    pub fn new(device: Arc<dyn BlockDevice>) -> Result<Arc<Self>, Ext2Error> {
+        let mut bytes = vec![0; SUPERBLOCK_SIZE];
+        device.read_at(SUPERBLOCK_OFFSET, &mut bytes)?;
+        let superblock = Superblock::parse(&bytes)?;
+
+        let mut table = vec![0; superblock.group_table_size()];
+        device.read_at(superblock.group_table_offset(), &mut table)?;
+        let inode_tables = superblock::parse_groups(&table);
+
+        Ok(Arc::new_cyclic(|this| Self {
+            device,
+            superblock,
+            inode_tables,
+            cache: Mutex::new(Cache {
+                blocks: BTreeMap::new(),
+                clock: 0,
+            }),
+            this: this.clone(),
+        }))
+    }
+
+    /// The volume name.
+    pub fn label(&self) -> String {
+        String::from_utf8_lossy(self.superblock.label()).into_owned()
+    }
+
+    /// Bytes the volume holds, and the bytes in use.
+    pub fn usage(&self) -> (u64, u64) {
+        let block_size = self.block_size() as u64;
+        let total = self.superblock.block_count as u64 * block_size;
+        // The free count of a corrupt superblock can be past the total
+        let free = self.superblock.free_blocks as u64 * block_size;
+        (total, total.saturating_sub(free))
+    }
+
+    fn block_size(&self) -> usize {
+        self.superblock.block_size as usize
+    }
+
+    fn inode(&self, number: u32) -> Result<Arc<Ext2Inode>> {
+        if number == 0 || number > self.superblock.inode_count {
+            return Err(VfsError::Io);
+        }
+        let group = (number - 1) / self.superblock.inodes_per_group;
+        let index = (number - 1) % self.superblock.inodes_per_group;
+        let table = *self.inode_tables.get(group as usize).ok_or(VfsError::Io)?;
+        let offset = table as u64 * self.block_size() as u64
+            + index as u64 * self.superblock.inode_size as u64;
+
+        let mut bytes = [0; RAW_INODE_SIZE];
+        self.device.read_at(offset, &mut bytes)?;
+        let raw = RawInode::parse(&bytes);
+        if raw.file_type().is_none() {
+            return Err(VfsError::Io);
+        }
+        Ok(Arc::new(Ext2Inode {
+            fs: self.this.upgrade().unwrap(),
+            number,
+            raw,
+            entries: Mutex::new(Vec::new()),
+        }))
+    }
+
+    /// The block numbers of the indirect block `block`.
+    fn indirect(&self, block: u32) -> Result<Arc<[u32]>> {
+        let mut cache = self.cache.lock();
+        cache.clock += 1;
+        let clock = cache.clock;
+        if let Some(indirect) = cache.blocks.get_mut(&block) {
+            indirect.used = clock;
+            return Ok(indirect.blocks.clone());
+        }
+
+        let mut bytes = vec![0; self.block_size()];
+        self.read_block(block, 0, &mut bytes)?;
+        let blocks: Arc<[u32]> = bytes
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        if cache.blocks.len() >= CACHE_BLOCKS {
+            let oldest = cache
+                .blocks
+                .iter()
+                .min_by_key(|(_, indirect)| indirect.used);
+            let oldest = *oldest.map(|(block, _)| block).unwrap();
+            cache.blocks.remove(&oldest);
+        }
+        let indirect = Indirect {
+            blocks: blocks.clone(),
+            used: clock,
+        };
+        cache.blocks.insert(block, indirect);
+        Ok(blocks)
+    }
+
+    /// The block holding block `index` of a file, `None` in a hole.
+    fn map(&self, raw: &RawInode, index: u64) -> Result<Option<u32>> {
+        let per_block = (self.block_size() / 4) as u64;
+        if index < inode::DIRECT_BLOCKS as u64 {
+            return Ok(Some(raw.blocks[index as usize]).filter(|&block| block != 0));
+        }
+
+        // The single, double and triple indirect blocks each map `per_block` times the blocks
+        // of the level before
+        let mut index = index - inode::DIRECT_BLOCKS as u64;
+        let mut span = 1;
+        for (level, &top) in raw.blocks[inode::SINGLE_INDIRECT..].iter().enumerate() {
+            span *= per_block;
+            if index >= span {
+                index -= span;
+                continue;
+            }
+            let mut block = top;
+            for _ in 0..=level {
+                if block == 0 {
+                    return Ok(None);
+                }
+                span /= per_block;
+                block = self.indirect(block)?[(index / span) as usize];
+                index %= span;
+            }
+            return Ok(Some(block).filter(|&block| block != 0));
+        }
+        Err(VfsError::FileTooLarge)
+    }
+
+    fn read_block(&self, block: u32, offset: usize, buffer: &mut [u8]) -> Result<()> {
+        if block >= self.superblock.block_count {
+            return Err(VfsError::Io);
+        }
+        let position = block as u64 * self.block_size() as u64 + offset as u64;
+        self.device.read_at(position, buffer)
+    }
+
+    /// Reads the data of a file from `offset`, the holes read as zeroes.
+    fn read(&self, raw: &RawInode, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        if offset >= raw.size {
+            return Ok(0);
+        }
+        let len = buffer.len().min((raw.size - offset) as usize);
+        let block_size = self.block_size();
+        let mut done = 0;
+        while done < len {
+            let position = offset + done as u64;
+            let start = (position % block_size as u64) as usize;
+            let count = (len - done).min(block_size - start);
+            let chunk = &mut buffer[done..done + count];
+            match self.map(raw, position / block_size as u64)? {
+                Some(block) => self.read_block(block, start, chunk)?,
+                None => chunk.fill(0),
+            }
+            done += count;
+        }
+        Ok(len)
+    }
+
+    /// The entries of the directory `raw`.
+    fn entries(&self, raw: &RawInode) -> Result<Vec<Entry>> {
+        // A corrupt size could exhaust the heap
+        let volume_size = self.superblock.block_count as u64 * self.block_size() as u64;
+        if raw.size > MAX_DIR_SIZE.min(volume_size) {
+            return Err(VfsError::Io);
+        }
+        let mut data = vec![0; raw.size as usize];
+        self.read(raw, 0, &mut data)?;
+        let file_types = self.superblock.has_file_types();
+        dir::parse(&data, self.block_size(), file_types)
+    }
+}
+
+impl FileSystem for Ext2Fs {
+    fn name(&self) -> &'static str {
+        "ext2"
+    }
+
+    fn root(&self) -> Result<Arc<dyn Inode>> {
+        Ok(self.inode(ROOT_INODE)?)
+    }
+}
+
+/// An inode of an ext2 file system, read when it was looked up.
+pub struct Ext2Inode {
+    fs: Arc<Ext2Fs>,
+    number: u32,
+    raw: RawInode,
+    /// Entries of a directory being listed, read again from its first one.
+    entries: Mutex<Vec<DirEntry>>,
+}
+
+impl Ext2Inode {
+    fn file_type(&self) -> FileType {
+        self.raw.file_type().unwrap()
+    }
+
+    fn check_dir(&self) -> Result<()> {
+        match self.file_type() {
+            FileType::Directory => Ok(()),
+            _ => Err(VfsError::NotDirectory),
+        }
+    }
+}
+
+impl Inode for Ext2Inode {
+    fn metadata(&self) -> Result<Metadata> {
+        let raw = &self.raw;
+        let file_type = self.file_type();
+        let mut metadata =
+            Metadata::new(self.number as u64, file_type, raw.permissions(), raw.size);
+        metadata.links = raw.links as u32;
+        metadata.uid = raw.uid;
+        metadata.gid = raw.gid;
+        if let FileType::CharDevice | FileType::BlockDevice = file_type {
+            metadata.device = raw.device();
+        }
+        metadata.accessed = raw.accessed();
+        metadata.modified = raw.modified();
+        metadata.changed = raw.changed();
+        Ok(metadata)
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        match self.file_type() {
+            FileType::Regular => self.fs.read(&self.raw, offset, buffer),
+            FileType::Directory => Err(VfsError::IsDirectory),
+            _ => Err(VfsError::InvalidArgument),
+        }
+    }
+
+    fn write_at(&self, _offset: u64, _buffer: &[u8]) -> Result<usize> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn truncate(&self, _size: u64) -> Result<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>> {
+        self.check_dir()?;
+        let entry = self
+            .fs
+            .entries(&self.raw)?
+            .into_iter()
+            .find(|entry| entry.name == name.as_bytes())
+            .ok_or(VfsError::NotFound)?;
+        Ok(self.fs.inode(entry.inode)?)
+    }
+
+    fn read_dir(&self, index: usize) -> Result<Option<DirEntry>> {
+        self.check_dir()?;
+        let mut entries = self.entries.lock();
+        if index == 0 {
+            let mut listing = Vec::new();
+            for entry in self.fs.entries(&self.raw)? {
+                // Without the types in the directory, they are in the inodes
+                let file_type = match entry.file_type {
+                    Some(file_type) => file_type,
+                    None => self.fs.inode(entry.inode)?.file_type(),
+                };
+                listing.push(DirEntry {
+                    name: entry.name(),
+                    inode: entry.inode as u64,
+                    file_type,
+                });
+            }
+            *entries = listing;
+        }
+        Ok(entries.get(index).cloned())
+    }
+
+    fn create(&self, _name: &str, _file_type: FileType, _mode: u16) -> Result<Arc<dyn Inode>> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn symlink(&self, _name: &str, _target: &str) -> Result<Arc<dyn Inode>> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn unlink(&self, _name: &str) -> Result<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn rmdir(&self, _name: &str) -> Result<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn rename(&self, _name: &str, _new_dir: &Arc<dyn Inode>, _new_name: &str) -> Result<()> {
+        Err(VfsError::ReadOnly)
+    }
+
+    fn read_link(&self) -> Result<String> {
+        if self.file_type() != FileType::Symlink {
+            return Err(VfsError::InvalidArgument);
+        }
+        let size = self.raw.size as usize;
+        let target = match self.raw.is_fast_symlink(self.fs.superblock.block_size) {
+            true => self.raw.inline_data()[..size].to_vec(),
+            false => {
+                let mut target = vec![0; size.min(self.fs.block_size())];
+                self.fs.read(&self.raw, 0, &mut target)?;
+                target
+            }
+        };
+        String::from_utf8(target).map_err(|_| VfsError::Io)
+    }
+}
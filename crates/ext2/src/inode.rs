@@ -0,0 +1,131 @@
+//! The inodes, in the inode table of their block group.
+
+use core::time::Duration;
+
+use vfs::FileType;
+
+/// Direct blocks, followed by the single, double and triple indirect blocks.
+pub const DIRECT_BLOCKS: usize = 12;
+pub const SINGLE_INDIRECT: usize = 12;
+pub const BLOCK_POINTERS: usize = 15;
+
+/// The symlinks shorter than this are stored in the block pointers.
+pub const FAST_SYMLINK_MAX: u64 = (BLOCK_POINTERS * 4) as u64;
+
+/// Smallest inode, the inode of revision 0.
+pub const RAW_INODE_SIZE: usize = 128;
+
+const TYPE_MASK: u16 = 0xF000;
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+#[derive(Debug, Clone)]
+pub struct RawInode {
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub accessed: u32,
+    pub changed: u32,
+    pub modified: u32,
+    pub links: u16,
+    /// Space used, in 512 bytes sectors.
+    pub sectors: u32,
+    /// Block of the extended attributes, 0 without.
+    pub attributes_block: u32,
+    pub blocks: [u32; BLOCK_POINTERS],
+}
+
+impl RawInode {
+    pub fn parse(bytes: &[u8]) -> Self {
+        let mut blocks = [0; BLOCK_POINTERS];
+        for (i, block) in blocks.iter_mut().enumerate() {
+            *block = u32_at(bytes, 40 + i * 4);
+        }
+        let mode = u16_at(bytes, 0);
+        // The high half of the size is the ACL block of the directories in revision 0
+        let size_high = match mode & TYPE_MASK == 0x4000 {
+            true => 0,
+            false => u32_at(bytes, 108),
+        };
+        Self {
+            mode,
+            uid: (u16_at(bytes, 120) as u32) << 16 | u16_at(bytes, 2) as u32,
+            gid: (u16_at(bytes, 122) as u32) << 16 | u16_at(bytes, 24) as u32,
+            size: (size_high as u64) << 32 | u32_at(bytes, 4) as u64,
+            accessed: u32_at(bytes, 8),
+            changed: u32_at(bytes, 12),
+            modified: u32_at(bytes, 16),
+            links: u16_at(bytes, 26),
+            sectors: u32_at(bytes, 28),
+            attributes_block: u32_at(bytes, 104),
+            blocks,
+        }
+    }
+
+    pub fn file_type(&self) -> Option<FileType> {
+        match self.mode & TYPE_MASK {
+            0x1000 => Some(FileType::Fifo),
+            0x2000 => Some(FileType::CharDevice),
+            0x4000 => Some(FileType::Directory),
+            0x6000 => Some(FileType::BlockDevice),
+            0x8000 => Some(FileType::Regular),
+            0xA000 => Some(FileType::Symlink),
+            0xC000 => Some(FileType::Socket),
+            _ => None,
+        }
+    }
+
+    pub fn permissions(&self) -> u16 {
+        self.mode & !TYPE_MASK
+    }
+
+    /// Whether the inode is a symlink with its target in place of the block pointers, when it
+    /// has no block but the one of its extended attributes.
+    pub fn is_fast_symlink(&self, block_size: u32) -> bool {
+        let attribute_sectors = match self.attributes_block {
+            0 => 0,
+            _ => block_size / 512,
+        };
+        self.file_type() == Some(FileType::Symlink)
+            && self.size < FAST_SYMLINK_MAX
+            && self.sectors == attribute_sectors
+    }
+
+    /// The bytes of the block pointers, where a fast symlink is.
+    pub fn inline_data(&self) -> [u8; FAST_SYMLINK_MAX as usize] {
+        let mut data = [0; FAST_SYMLINK_MAX as usize];
+        for (chunk, block) in data.chunks_exact_mut(4).zip(self.blocks) {
+            chunk.copy_from_slice(&block.to_le_bytes());
+        }
+        data
+    }
+
+    /// Device number of the device nodes, in the first block pointer in the old format and in
+    /// the second in the new one, packed like the old 16 bits `dev_t`.
+    pub fn device(&self) -> u64 {
+        let (major, minor) = match (self.blocks[0], self.blocks[1]) {
+            (0, new) => ((new >> 8) & 0xFFF, (new & 0xFF) | ((new >> 12) & 0xF_FF00)),
+            (old, _) => ((old >> 8) & 0xFF, old & 0xFF),
+        };
+        ((major as u64) << 8) | minor as u64
+    }
+
+    pub fn accessed(&self) -> Duration {
+        Duration::from_secs(self.accessed as u64)
+    }
+
+    pub fn modified(&self) -> Duration {
+        Duration::from_secs(self.modified as u64)
+    }
+
+    pub fn changed(&self) -> Duration {
+        Duration::from_secs(self.changed as u64)
+    }
+}
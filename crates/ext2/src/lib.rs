@@ -0,0 +1,33 @@
+//! Read-only ext2 file systems on a block device, mounted through the VFS.
+
+#![no_std]
+
+extern crate alloc;
+
+mod dir;
+mod fs;
+mod inode;
+mod superblock;
+
+use snafu::Snafu;
+use vfs::VfsError;
+
+pub use fs::{Ext2Fs, Ext2Inode};
+
+#[derive(Debug, Snafu)]
+pub enum Ext2Error {
+    #[snafu(display("Not an ext2 file system"))]
+    NotExt2,
+    #[snafu(display("Unsupported block size {}", size))]
+    BlockSize { size: u64 },
+    #[snafu(display("Unsupported features {:#x}", features))]
+    Features { features: u32 },
+    #[snafu(display("Device error: {}", error))]
+    Device { error: VfsError },
+}
+
+impl From<VfsError> for Ext2Error {
+    fn from(error: VfsError) -> Self {
+        Ext2Error::Device { error }
+    }
+}
@@ -0,0 +1,129 @@
+//! The superblock, 1024 bytes into the volume, and the block group descriptors after it.
+
+use alloc::vec::Vec;
+
+use crate::Ext2Error;
+
+/// Offset of the superblock on the volume, and its size.
+pub const SUPERBLOCK_OFFSET: u64 = 1024;
+pub const SUPERBLOCK_SIZE: usize = 1024;
+
+const MAGIC: u16 = 0xEF53;
+/// Inode size of the revision 0 file systems, which don't store it.
+const GOOD_OLD_INODE_SIZE: u32 = 128;
+/// Largest block size, 64 KiB.
+const MAX_LOG_BLOCK_SIZE: u32 = 6;
+
+/// The incompatible features, which change how the file system is read.
+pub mod features {
+    /// The directory entries have the type of their file.
+    pub const FILETYPE: u32 = 0x0002;
+    /// The bitmaps and inode tables of the groups are packed together, wherever the
+    /// descriptors say.
+    pub const FLEX_BG: u32 = 0x0200;
+    pub const SUPPORTED: u32 = FILETYPE | FLEX_BG;
+}
+
+const GROUP_DESCRIPTOR_SIZE: usize = 32;
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+#[derive(Debug, Clone)]
+pub struct Superblock {
+    pub inode_count: u32,
+    pub block_count: u32,
+    pub free_blocks: u32,
+    /// Block of the superblock, 1 with 1 KiB blocks and 0 otherwise.
+    pub first_data_block: u32,
+    pub block_size: u32,
+    pub blocks_per_group: u32,
+    pub inodes_per_group: u32,
+    pub inode_size: u32,
+    pub incompatible: u32,
+    pub label: [u8; 16],
+}
+
+impl Superblock {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Ext2Error> {
+        if bytes.len() < SUPERBLOCK_SIZE || u16_at(bytes, 56) != MAGIC {
+            return Err(Ext2Error::NotExt2);
+        }
+
+        let log_block_size = u32_at(bytes, 24);
+        if log_block_size > MAX_LOG_BLOCK_SIZE {
+            return Err(Ext2Error::BlockSize {
+                size: 1024u64 << log_block_size.min(32),
+            });
+        }
+        let (inode_size, incompatible) = match u32_at(bytes, 76) {
+            0 => (GOOD_OLD_INODE_SIZE, 0),
+            _ => (u16_at(bytes, 88) as u32, u32_at(bytes, 96)),
+        };
+        let unsupported = incompatible & !features::SUPPORTED;
+        if unsupported != 0 {
+            return Err(Ext2Error::Features {
+                features: unsupported,
+            });
+        }
+
+        let superblock = Self {
+            inode_count: u32_at(bytes, 0),
+            block_count: u32_at(bytes, 4),
+            free_blocks: u32_at(bytes, 12),
+            first_data_block: u32_at(bytes, 20),
+            block_size: 1024 << log_block_size,
+            blocks_per_group: u32_at(bytes, 32),
+            inodes_per_group: u32_at(bytes, 40),
+            inode_size,
+            incompatible,
+            label: bytes[120..136].try_into().unwrap(),
+        };
+        if superblock.blocks_per_group == 0
+            || superblock.inodes_per_group == 0
+            || superblock.block_count <= superblock.first_data_block
+            || !superblock.inode_size.is_power_of_two()
+            || !(GOOD_OLD_INODE_SIZE..=superblock.block_size).contains(&superblock.inode_size)
+        {
+            return Err(Ext2Error::NotExt2);
+        }
+        Ok(superblock)
+    }
+
+    pub fn group_count(&self) -> u32 {
+        (self.block_count - self.first_data_block).div_ceil(self.blocks_per_group)
+    }
+
+    /// Offset of the block group descriptor table, in the block after the superblock.
+    pub fn group_table_offset(&self) -> u64 {
+        (self.first_data_block as u64 + 1) * self.block_size as u64
+    }
+
+    pub fn group_table_size(&self) -> usize {
+        self.group_count() as usize * GROUP_DESCRIPTOR_SIZE
+    }
+
+    /// Whether the directory entries have the type of their file.
+    pub fn has_file_types(&self) -> bool {
+        self.incompatible & features::FILETYPE != 0
+    }
+
+    /// The volume name, without its padding.
+    pub fn label(&self) -> &[u8] {
+        let end = self.label.iter().position(|&b| b == 0).unwrap_or(16);
+        &self.label[..end]
+    }
+}
+
+/// The block of the inode table of each group, from the group descriptor table.
+pub fn parse_groups(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks_exact(GROUP_DESCRIPTOR_SIZE)
+        .map(|descriptor| u32_at(descriptor, 8))
+        .collect()
+}
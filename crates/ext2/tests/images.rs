@@ -0,0 +1,296 @@
+//! Tests on the images of `images/`, made by `images/generate.sh`. They run on the host:
+//! `cargo test -p ext2 --target x86_64-unknown-linux-gnu -Z build-std=std,panic_unwind`.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use ext2::Ext2Fs;
+use vfs::{BlockDevice, FileSystem, FileType, Inode, Result, VfsError};
+
+const BLOCK_SIZE: usize = 512;
+const IMAGES: [&str; 2] = ["ext2-1k.img", "ext2-4k.img"];
+/// Time of every file of the images, 2024-01-01 12:00 UTC.
+const TIME: Duration = Duration::from_secs(1_704_110_400);
+
+/// An image loaded in memory.
+struct Image(Mutex<Vec<u8>>);
+
+impl BlockDevice for Image {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        (self.0.lock().unwrap().len() / BLOCK_SIZE) as u64
+    }
+
+    fn read_blocks(&self, block: u64, buffer: &mut [u8]) -> Result<()> {
+        let start = block as usize * BLOCK_SIZE;
+        let data = self.0.lock().unwrap();
+        let blocks = data.get(start..start + buffer.len()).ok_or(VfsError::Io)?;
+        buffer.copy_from_slice(blocks);
+        Ok(())
+    }
+
+    fn write_blocks(&self, _block: u64, _buffer: &[u8]) -> Result<()> {
+        panic!("written to a read-only file system");
+    }
+}
+
+fn load(name: &str) -> Arc<Image> {
+    let path = format!("{}/tests/images/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let data = std::fs::read(&path).unwrap_or_else(|err| panic!("{}: {}", path, err));
+    Arc::new(Image(Mutex::new(data)))
+}
+
+/// Every image, mounted.
+fn images() -> impl Iterator<Item = Arc<Ext2Fs>> {
+    IMAGES
+        .iter()
+        .map(|name| Ext2Fs::new(load(name)).expect("not mounted"))
+}
+
+fn resolve(fs: &Ext2Fs, path: &str) -> Result<Arc<dyn Inode>> {
+    let mut inode = fs.root()?;
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        inode = inode.lookup(name)?;
+    }
+    Ok(inode)
+}
+
+fn read_all(inode: &dyn Inode) -> Vec<u8> {
+    let mut data = vec![0; inode.metadata().unwrap().size as usize];
+    assert_eq!(inode.read_at(0, &mut data).unwrap(), data.len());
+    data
+}
+
+fn names(dir: &dyn Inode) -> Vec<String> {
+    let mut names = Vec::new();
+    while let Some(entry) = dir.read_dir(names.len()).unwrap() {
+        names.push(entry.name);
+    }
+    names.sort();
+    names
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn set_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Offset of the root inode in the image, through the first group descriptor.
+fn root_inode_offset(data: &[u8]) -> usize {
+    const SUPERBLOCK: usize = 1024;
+    let block_size = 1024 << u32_at(data, SUPERBLOCK + 24);
+    let inode_size = u16::from_le_bytes([data[SUPERBLOCK + 88], data[SUPERBLOCK + 89]]) as usize;
+    let group_table = (u32_at(data, SUPERBLOCK + 20) as usize + 1) * block_size;
+    let inode_table = u32_at(data, group_table + 8) as usize * block_size;
+    inode_table + inode_size
+}
+
+#[test]
+fn reads_the_superblock() {
+    for (fs, name) in images().zip(IMAGES) {
+        assert_eq!(fs.label(), name);
+        let (total, used) = fs.usage();
+        assert_eq!(total, load(name).0.lock().unwrap().len() as u64);
+        assert!(used > 400_000 && used < total);
+    }
+}
+
+#[test]
+fn rejects_other_volumes() {
+    let image = Arc::new(Image(Mutex::new(vec![0; 64 * BLOCK_SIZE])));
+    assert!(Ext2Fs::new(image).is_err());
+}
+
+#[test]
+fn lists_directories() {
+    for fs in images() {
+        let root = fs.root().unwrap();
+        assert_eq!(
+            names(&*root),
+            [
+                ".",
+                "..",
+                "big.bin",
+                "dir",
+                "fifo",
+                "hello.txt",
+                "link",
+                "long-link",
+                "lost+found",
+                "many",
+                "sparse.bin"
+            ]
+        );
+        let mut types = Vec::new();
+        while let Some(entry) = root.read_dir(types.len()).unwrap() {
+            types.push((entry.name, entry.file_type));
+        }
+        assert!(types.contains(&("dir".to_string(), FileType::Directory)));
+        assert!(types.contains(&("link".to_string(), FileType::Symlink)));
+        assert!(types.contains(&("fifo".to_string(), FileType::Fifo)));
+    }
+}
+
+#[test]
+fn reads_the_metadata() {
+    for fs in images() {
+        let root = fs.root().unwrap().metadata().unwrap();
+        assert_eq!(root.inode, 2);
+        assert_eq!(root.file_type, FileType::Directory);
+
+        let hello = resolve(&fs, "hello.txt").unwrap().metadata().unwrap();
+        assert_eq!(hello.file_type, FileType::Regular);
+        assert_eq!(hello.mode, 0o600);
+        assert_eq!(hello.size, 17);
+        assert_eq!(hello.links, 1);
+        assert_eq!(hello.modified, TIME);
+
+        let fifo = resolve(&fs, "fifo").unwrap().metadata().unwrap();
+        assert_eq!(fifo.file_type, FileType::Fifo);
+    }
+}
+
+#[test]
+fn reads_small_files() {
+    for fs in images() {
+        let hello = resolve(&fs, "hello.txt").unwrap();
+        assert_eq!(read_all(&*hello), b"Hello from ext2!\n");
+        let mut tail = [0; 10];
+        assert_eq!(hello.read_at(6, &mut tail).unwrap(), 10);
+        assert_eq!(&tail, b"from ext2!");
+        assert_eq!(hello.read_at(17, &mut tail).unwrap(), 0);
+        assert_eq!(resolve(&fs, "missing").err(), Some(VfsError::NotFound));
+        // Names are compared byte for byte
+        assert_eq!(resolve(&fs, "HELLO.TXT").err(), Some(VfsError::NotFound));
+    }
+}
+
+#[test]
+fn reads_files_through_indirect_blocks() {
+    for fs in images() {
+        let big = resolve(&fs, "big.bin").unwrap();
+        assert_eq!(big.metadata().unwrap().size, 400_000);
+        assert_eq!(read_all(&*big), pattern(400_000));
+
+        // Across the direct, single and double indirect blocks of 1 KiB blocks
+        let mut data = vec![0; 300_000];
+        assert_eq!(big.read_at(10_000, &mut data).unwrap(), 300_000);
+        assert_eq!(data, pattern(310_000)[10_000..]);
+        assert_eq!(big.read_at(399_990, &mut data).unwrap(), 10);
+    }
+}
+
+#[test]
+fn reads_holes_as_zeroes() {
+    for fs in images() {
+        let sparse = resolve(&fs, "sparse.bin").unwrap();
+        let data = read_all(&*sparse);
+        assert_eq!(data.len(), 300 * 1024 + 4);
+        assert!(data[..300 * 1024].iter().all(|&byte| byte == 0));
+        assert_eq!(&data[300 * 1024..], b"end\n");
+    }
+}
+
+#[test]
+fn walks_nested_directories() {
+    for fs in images() {
+        let dir = resolve(&fs, "dir").unwrap();
+        assert!(dir.metadata().unwrap().is_dir());
+        assert_eq!(names(&*dir), [".", "..", "nested"]);
+        let deep = resolve(&fs, "dir/nested/deep.txt").unwrap();
+        assert_eq!(read_all(&*deep), b"Deep inside.\n");
+        assert_eq!(
+            resolve(&fs, "hello.txt/x").err(),
+            Some(VfsError::NotDirectory)
+        );
+        assert_eq!(
+            dir.read_at(0, &mut [0; 4]).err(),
+            Some(VfsError::IsDirectory)
+        );
+    }
+}
+
+#[test]
+fn lists_directories_of_several_blocks() {
+    for fs in images() {
+        let many = resolve(&fs, "many").unwrap();
+        let names = names(&*many);
+        assert_eq!(names.len(), 202);
+        for i in 1..=200 {
+            let file = many.lookup(&format!("file-{}", i)).unwrap();
+            assert_eq!(read_all(&*file), format!("{}\n", i).as_bytes());
+        }
+    }
+}
+
+#[test]
+fn reads_symlinks() {
+    for fs in images() {
+        let link = resolve(&fs, "link").unwrap();
+        assert_eq!(link.metadata().unwrap().file_type, FileType::Symlink);
+        assert_eq!(link.read_link().unwrap(), "hello.txt");
+        // Too long to be in the inode
+        let long = resolve(&fs, "long-link").unwrap();
+        assert_eq!(
+            long.read_link().unwrap(),
+            "dir/nested/../nested/../nested/../nested/../nested/../nested/../nested/deep.txt"
+        );
+        assert_eq!(
+            resolve(&fs, "hello.txt").unwrap().read_link().err(),
+            Some(VfsError::InvalidArgument)
+        );
+    }
+}
+
+#[test]
+fn refuses_changes() {
+    for fs in images() {
+        let root = fs.root().unwrap();
+        let hello = root.lookup("hello.txt").unwrap();
+        assert_eq!(hello.write_at(0, b"x").err(), Some(VfsError::ReadOnly));
+        assert_eq!(hello.truncate(0).err(), Some(VfsError::ReadOnly));
+        assert_eq!(
+            root.create("new", FileType::Regular, 0o644).err(),
+            Some(VfsError::ReadOnly)
+        );
+        assert_eq!(root.unlink("hello.txt").err(), Some(VfsError::ReadOnly));
+        assert_eq!(root.rmdir("dir").err(), Some(VfsError::ReadOnly));
+        assert_eq!(
+            root.rename("hello.txt", &root, "moved").err(),
+            Some(VfsError::ReadOnly)
+        );
+    }
+}
+
+#[test]
+fn rejects_corrupt_sizes() {
+    for name in IMAGES {
+        let image = load(name);
+        {
+            let mut data = image.0.lock().unwrap();
+            // Far past the volume, and more free blocks than blocks
+            let root = root_inode_offset(&data);
+            set_u32(&mut data, root + 4, u32::MAX);
+            set_u32(&mut data, 1024 + 12, u32::MAX);
+        }
+        let fs = Ext2Fs::new(image).expect("not mounted");
+        let root = fs.root().unwrap();
+        assert_eq!(root.lookup("hello.txt").err(), Some(VfsError::Io));
+        assert_eq!(root.read_dir(0).err(), Some(VfsError::Io));
+        let (total, used) = fs.usage();
+        assert!(total > 0);
+        assert_eq!(used, 0);
+    }
+}
@@ -0,0 +1,39 @@
+#!/bin/sh
+# Regenerates the ext2 images of the tests with mke2fs from e2fsprogs.
+set -e
+cd "$(dirname "$0")"
+
+tree=$(mktemp -d)
+trap 'rm -rf "$tree"' EXIT
+printf 'Hello from ext2!\n' > "$tree/hello.txt"
+mkdir -p "$tree/dir/nested"
+printf 'Deep inside.\n' > "$tree/dir/nested/deep.txt"
+# 400000 bytes counting modulo 251, past the double indirect blocks with 1 KiB blocks
+python3 -c 'import sys; sys.stdout.buffer.write(bytes(i % 251 for i in range(400000)))' > "$tree/big.bin"
+# A hole of 300 KiB before its data
+truncate -s 300K "$tree/sparse.bin"
+printf 'end\n' >> "$tree/sparse.bin"
+# Enough entries for the directory to take several blocks
+mkdir "$tree/many"
+for i in $(seq 1 200); do
+    printf '%d\n' "$i" > "$tree/many/file-$i"
+done
+ln -s hello.txt "$tree/link"
+# Too long for the inode, stored in a block
+ln -s "dir/nested/../nested/../nested/../nested/../nested/../nested/../nested/deep.txt" \
+    "$tree/long-link"
+mkfifo "$tree/fifo"
+chmod 600 "$tree/hello.txt"
+find "$tree" -exec touch -h -d @1704110400 {} +
+
+# image <file> <block size> <blocks per group> <inodes> <size>
+image() {
+    rm -f "$1"
+    E2FSPROGS_FAKE_TIME=1704110400 mke2fs -q -t ext2 -b "$2" -g "$3" -N "$4" -L "$1" \
+        -U 4b45524e-0000-4000-8000-000000000000 -E hash_seed=4b45524e-0000-4000-8000-000000000000,root_owner=0:0 \
+        -d "$tree" "$1" "$5"
+}
+
+# Few inodes in each group, so that the files spread over both groups
+image ext2-1k.img 1024 2048 256 4M
+image ext2-4k.img 4096 32768 512 2M
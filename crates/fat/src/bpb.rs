@@ -0,0 +1,159 @@
+//! The BIOS parameter block in the boot sector, giving the layout of the volume.
+
+use crate::FatError;
+
+const SIGNATURE: [u8; 2] = [0x55, 0xAA];
+/// Fewer clusters make a FAT12, and a FAT16 holds up to 65524.
+const FAT12_MAX_CLUSTERS: u32 = 4084;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    /// Values from which an entry marks the end of a chain.
+    pub fn end_of_chain(self) -> u32 {
+        match self {
+            FatType::Fat12 => 0xFF8,
+            FatType::Fat16 => 0xFFF8,
+            FatType::Fat32 => 0x0FFF_FFF8,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Bpb {
+    pub fat_type: FatType,
+    pub bytes_per_sector: u32,
+    pub sectors_per_cluster: u32,
+    pub reserved_sectors: u32,
+    pub fat_count: u32,
+    /// Entries of the fixed root directory of FAT12 and FAT16.
+    pub root_entries: u32,
+    /// Sectors of each FAT.
+    pub fat_size: u32,
+    /// First cluster of the root directory of FAT32.
+    pub root_cluster: u32,
+    /// Sector of the FSInfo structure of FAT32, 0 without one.
+    pub fs_info: u32,
+    pub cluster_count: u32,
+}
+
+fn u16_at(sector: &[u8], offset: usize) -> u32 {
+    u16::from_le_bytes([sector[offset], sector[offset + 1]]) as u32
+}
+
+fn u32_at(sector: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(sector[offset..offset + 4].try_into().unwrap())
+}
+
+impl Bpb {
+    /// Parses the first 512 bytes of the volume.
+    pub fn parse(sector: &[u8]) -> Result<Self, FatError> {
+        if sector.len() < 512 || sector[510..512] != SIGNATURE {
+            return Err(FatError::NotFat);
+        }
+
+        let bytes_per_sector = u16_at(sector, 11);
+        if !bytes_per_sector.is_power_of_two() || !(512..=4096).contains(&bytes_per_sector) {
+            return Err(FatError::SectorSize {
+                size: bytes_per_sector,
+            });
+        }
+        let sectors_per_cluster = sector[13] as u32;
+        let reserved_sectors = u16_at(sector, 14);
+        let fat_count = sector[16] as u32;
+        let root_entries = u16_at(sector, 17);
+        let total_sectors = match u16_at(sector, 19) {
+            0 => u32_at(sector, 32),
+            total => total,
+        };
+        // Like Linux, a FAT32 is told by its FAT size being out of the FAT16 field
+        let (fat_size, fat32) = match u16_at(sector, 22) {
+            0 => (u32_at(sector, 36), true),
+            size => (size, false),
+        };
+        if !sectors_per_cluster.is_power_of_two()
+            || reserved_sectors == 0
+            || fat_count == 0
+            || fat_size == 0
+            || (fat32 && root_entries != 0)
+        {
+            return Err(FatError::NotFat);
+        }
+
+        let root_sectors = (root_entries * 32).div_ceil(bytes_per_sector);
+        let data_start =
+            reserved_sectors as u64 + fat_count as u64 * fat_size as u64 + root_sectors as u64;
+        let data_sectors = (total_sectors as u64)
+            .checked_sub(data_start)
+            .ok_or(FatError::NotFat)?;
+        let mut cluster_count = (data_sectors / sectors_per_cluster as u64) as u32;
+        let fat_type = match (fat32, cluster_count) {
+            (true, _) => FatType::Fat32,
+            (false, count) if count <= FAT12_MAX_CLUSTERS => FatType::Fat12,
+            (false, _) => FatType::Fat16,
+        };
+        // Clusters past what the FAT holds can't be used
+        let fat_bytes = fat_size as u64 * bytes_per_sector as u64;
+        let fat_entries = match fat_type {
+            FatType::Fat12 => fat_bytes * 2 / 3,
+            FatType::Fat16 => fat_bytes / 2,
+            FatType::Fat32 => (fat_bytes / 4).min(0x0FFF_FFF7),
+        };
+        cluster_count = cluster_count.min(fat_entries.saturating_sub(2) as u32);
+        if cluster_count == 0 {
+            return Err(FatError::NotFat);
+        }
+
+        let (root_cluster, fs_info) = match fat_type {
+            FatType::Fat32 => (u32_at(sector, 44), u16_at(sector, 48)),
+            _ => (0, 0),
+        };
+        if fat_type == FatType::Fat32 && !(2..cluster_count + 2).contains(&root_cluster) {
+            return Err(FatError::NotFat);
+        }
+
+        Ok(Self {
+            fat_type,
+            bytes_per_sector,
+            sectors_per_cluster,
+            reserved_sectors,
+            fat_count,
+            root_entries,
+            fat_size,
+            root_cluster,
+            fs_info,
+            cluster_count,
+        })
+    }
+
+    pub fn cluster_size(&self) -> u32 {
+        self.bytes_per_sector * self.sectors_per_cluster
+    }
+
+    /// Byte offset of the FAT `index`.
+    pub fn fat_offset(&self, index: u32) -> u64 {
+        let sector = self.reserved_sectors as u64 + index as u64 * self.fat_size as u64;
+        sector * self.bytes_per_sector as u64
+    }
+
+    /// Byte offset of the fixed root directory of FAT12 and FAT16.
+    pub fn root_offset(&self) -> u64 {
+        self.fat_offset(self.fat_count)
+    }
+
+    /// Byte offset of `cluster`, numbered from 2.
+    pub fn cluster_offset(&self, cluster: u32) -> u64 {
+        let root_size = (self.root_entries * 32).next_multiple_of(self.bytes_per_sector);
+        self.root_offset() + root_size as u64 + (cluster - 2) as u64 * self.cluster_size() as u64
+    }
+
+    /// Whether `cluster` is in the data area.
+    pub fn is_valid_cluster(&self, cluster: u32) -> bool {
+        (2..self.cluster_count + 2).contains(&cluster)
+    }
+}
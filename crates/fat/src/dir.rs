@@ -0,0 +1,336 @@
+//! Directory entries: a 32 bytes short entry with an 8.3 name for every file, preceded by the
+//! entries of its long name in UTF-16 when the name doesn't fit in 8.3.
+
+use alloc::{format, string::String, vec::Vec};
+use core::char;
+
+use vfs::{Result, VfsError};
+
+pub const ENTRY_SIZE: usize = 32;
+
+pub type RawEntry = [u8; ENTRY_SIZE];
+
+pub mod attributes {
+    pub const READ_ONLY: u8 = 0x01;
+    pub const VOLUME_ID: u8 = 0x08;
+    pub const DIRECTORY: u8 = 0x10;
+    pub const ARCHIVE: u8 = 0x20;
+    /// Read-only, hidden, system and volume ID together mark a long name entry.
+    pub const LONG_NAME: u8 = 0x0F;
+}
+
+/// First byte of a free entry, and of the end of the directory.
+const DELETED: u8 = 0xE5;
+const END: u8 = 0x00;
+/// Stands for a first byte of 0xE5 in a short name.
+const KANJI_E5: u8 = 0x05;
+
+/// Flags of the short names in lowercase, set by Windows NT instead of a long name.
+const LOWERCASE_BASE: u8 = 0x08;
+const LOWERCASE_EXTENSION: u8 = 0x10;
+
+/// The last long name entry, stored first, has this bit in its order.
+const LAST_LONG_ENTRY: u8 = 0x40;
+const LONG_ENTRY_CHARS: usize = 13;
+/// Offsets of the characters in a long name entry.
+const LONG_ENTRY_OFFSETS: [usize; LONG_ENTRY_CHARS] =
+    [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+const MAX_NAME_LEN: usize = 255;
+
+/// Date of the entries we create, 1980-01-01: there is no clock to date them.
+pub const DOS_EPOCH_DATE: u16 = (1 << 5) | 1;
+
+#[derive(Debug, Clone)]
+pub struct ShortEntry {
+    pub name: [u8; 11],
+    pub attributes: u8,
+    pub case: u8,
+    pub created_time: u16,
+    pub created_date: u16,
+    pub accessed_date: u16,
+    pub modified_time: u16,
+    pub modified_date: u16,
+    pub cluster: u32,
+    pub size: u32,
+}
+
+fn u16_at(raw: &RawEntry, offset: usize) -> u16 {
+    u16::from_le_bytes([raw[offset], raw[offset + 1]])
+}
+
+impl ShortEntry {
+    pub fn new(name: [u8; 11], attributes: u8) -> Self {
+        Self {
+            name,
+            attributes,
+            case: 0,
+            created_time: 0,
+            created_date: DOS_EPOCH_DATE,
+            accessed_date: DOS_EPOCH_DATE,
+            modified_time: 0,
+            modified_date: DOS_EPOCH_DATE,
+            cluster: 0,
+            size: 0,
+        }
+    }
+
+    pub fn parse(raw: &RawEntry) -> Self {
+        Self {
+            name: raw[..11].try_into().unwrap(),
+            attributes: raw[11],
+            case: raw[12],
+            created_time: u16_at(raw, 14),
+            created_date: u16_at(raw, 16),
+            accessed_date: u16_at(raw, 18),
+            modified_time: u16_at(raw, 22),
+            modified_date: u16_at(raw, 24),
+            cluster: ((u16_at(raw, 20) as u32) << 16) | u16_at(raw, 26) as u32,
+            size: u32::from_le_bytes(raw[28..32].try_into().unwrap()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> RawEntry {
+        let mut raw = [0; ENTRY_SIZE];
+        raw[..11].copy_from_slice(&self.name);
+        raw[11] = self.attributes;
+        raw[12] = self.case;
+        raw[14..16].copy_from_slice(&self.created_time.to_le_bytes());
+        raw[16..18].copy_from_slice(&self.created_date.to_le_bytes());
+        raw[18..20].copy_from_slice(&self.accessed_date.to_le_bytes());
+        raw[20..22].copy_from_slice(&((self.cluster >> 16) as u16).to_le_bytes());
+        raw[22..24].copy_from_slice(&self.modified_time.to_le_bytes());
+        raw[24..26].copy_from_slice(&self.modified_date.to_le_bytes());
+        raw[26..28].copy_from_slice(&(self.cluster as u16).to_le_bytes());
+        raw[28..32].copy_from_slice(&self.size.to_le_bytes());
+        raw
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.attributes & attributes::DIRECTORY != 0
+    }
+
+    /// The 8.3 name with its dot, in lowercase where the case flags say so.
+    pub fn display_name(&self) -> String {
+        let part = |bytes: &[u8], lowercase: bool| -> String {
+            let mut part: String = bytes.iter().map(|&byte| char::from(byte)).collect();
+            part.truncate(part.trim_end_matches(' ').len());
+            match lowercase {
+                true => part.to_lowercase(),
+                false => part,
+            }
+        };
+        let mut name = self.name;
+        if name[0] == KANJI_E5 {
+            name[0] = DELETED;
+        }
+        let base = part(&name[..8], self.case & LOWERCASE_BASE != 0);
+        let extension = part(&name[8..], self.case & LOWERCASE_EXTENSION != 0);
+        match extension.is_empty() {
+            true => base,
+            false => format!("{}.{}", base, extension),
+        }
+    }
+}
+
+/// Checksum of a short name, stored in its long name entries.
+pub fn checksum(name: &[u8; 11]) -> u8 {
+    name.iter()
+        .fold(0u8, |sum, &byte| sum.rotate_right(1).wrapping_add(byte))
+}
+
+/// A file of a directory.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub short: ShortEntry,
+    /// Index of the first slot of the file, its first long name entry or its short entry.
+    pub first_slot: usize,
+    /// Index of the short entry.
+    pub slot: usize,
+}
+
+impl Entry {
+    /// Whether the entry is named `name`, by its long or its short name. Names are compared
+    /// ignoring the case like other systems do.
+    pub fn is_named(&self, name: &str) -> bool {
+        same_name(&self.name, name) || same_name(&self.short.display_name(), name)
+    }
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    a.chars()
+        .flat_map(char::to_lowercase)
+        .eq(b.chars().flat_map(char::to_lowercase))
+}
+
+/// The files of a directory from its slots, without the volume label, `.` and `..`.
+pub fn parse(slots: &[RawEntry]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    // Characters of the long name being read, its checksum, first slot and next order expected
+    let mut long: Option<(Vec<u16>, u8, usize, u8)> = None;
+
+    for (index, raw) in slots.iter().enumerate() {
+        match raw[0] {
+            END => break,
+            DELETED => {
+                long = None;
+                continue;
+            }
+            _ => {}
+        }
+
+        if raw[11] & 0x3F == attributes::LONG_NAME {
+            let order = raw[0] & !LAST_LONG_ENTRY;
+            let (mut chars, sum, first, expected) =
+                match (raw[0] & LAST_LONG_ENTRY != 0, long.take()) {
+                    (true, _) => (Vec::new(), raw[13], index, order),
+                    (false, Some(long)) => long,
+                    (false, None) => continue,
+                };
+            if order == 0 || order != expected || raw[13] != sum {
+                continue;
+            }
+            let start = (order as usize - 1) * LONG_ENTRY_CHARS;
+            chars.resize(chars.len().max(start + LONG_ENTRY_CHARS), 0);
+            for (i, offset) in LONG_ENTRY_OFFSETS.into_iter().enumerate() {
+                chars[start + i] = u16_at(raw, offset);
+            }
+            long = Some((chars, sum, first, order - 1));
+            continue;
+        }
+
+        let short = ShortEntry::parse(raw);
+        let long = long.take();
+        if short.attributes & attributes::VOLUME_ID != 0 || short.name[0] == b'.' {
+            continue;
+        }
+        let (name, first_slot) = match long {
+            Some((chars, sum, first, 0)) if sum == checksum(&short.name) => {
+                let end = chars.iter().position(|&c| c == 0).unwrap_or(chars.len());
+                let name = char::decode_utf16(chars[..end].iter().copied())
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect();
+                (name, first)
+            }
+            _ => (short.display_name(), index),
+        };
+        entries.push(Entry {
+            name,
+            short,
+            first_slot,
+            slot: index,
+        });
+    }
+    entries
+}
+
+/// Index of the first of `count` free slots in a row, `None` if the directory has to grow.
+pub fn free_slots(slots: &[RawEntry], count: usize) -> Option<usize> {
+    let mut start = 0;
+    for (index, raw) in slots.iter().enumerate() {
+        match raw[0] {
+            // Everything after the end is free
+            END if slots.len() - start >= count => return Some(start),
+            END => return None,
+            DELETED if index + 1 - start >= count => return Some(start),
+            DELETED => {}
+            _ => start = index + 1,
+        }
+    }
+    None
+}
+
+/// Marks a slot free.
+pub fn delete(raw: &mut RawEntry) {
+    raw[0] = DELETED;
+}
+
+/// Fails for names FAT can't store.
+pub fn check_name(name: &str) -> Result<()> {
+    if name.encode_utf16().count() > MAX_NAME_LEN {
+        return Err(VfsError::NameTooLong);
+    }
+    let invalid = |c: char| c < ' ' || "\"*/:<>?\\|\x7F".contains(c);
+    if name.is_empty() || name.contains(invalid) || name.ends_with(['.', ' ']) {
+        return Err(VfsError::InvalidArgument);
+    }
+    Ok(())
+}
+
+fn is_short_char(c: char) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || "!#$%&'()-@^_`{}~".contains(c)
+}
+
+/// The short name spelling `name` exactly, if it is an 8.3 name in uppercase.
+pub fn exact_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, extension) = name.split_once('.').unwrap_or((name, ""));
+    let valid = |part: &str, max: usize| part.len() <= max && part.chars().all(is_short_char);
+    if base.is_empty() || !valid(base, 8) || !valid(extension, 3) {
+        return None;
+    }
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..8 + extension.len()].copy_from_slice(extension.as_bytes());
+    Some(short)
+}
+
+/// The short name of the long name `name` with the numeric tail `~number`, like Windows
+/// derives them.
+pub fn numbered_short_name(name: &str, number: u32) -> [u8; 11] {
+    let name = name.trim_start_matches('.');
+    let (base, extension) = match name.rsplit_once('.') {
+        Some((base, extension)) => (base, extension),
+        None => (name, ""),
+    };
+    let convert = |part: &str, max: usize| -> Vec<u8> {
+        part.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| c.to_ascii_uppercase())
+            .map(|c| if is_short_char(c) { c as u8 } else { b'_' })
+            .take(max)
+            .collect()
+    };
+
+    let tail = format!("~{}", number);
+    let mut base = convert(base, 8 - tail.len());
+    if base.is_empty() {
+        base.push(b'_');
+    }
+    base.extend_from_slice(tail.as_bytes());
+    let extension = convert(extension, 3);
+
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(&base);
+    short[8..8 + extension.len()].copy_from_slice(&extension);
+    short
+}
+
+/// The long name entries of `name` for the short name with `checksum`, in the order they are
+/// stored.
+pub fn long_entries(name: &str, checksum: u8) -> Vec<RawEntry> {
+    let mut chars: Vec<u16> = name.encode_utf16().collect();
+    let count = chars.len().div_ceil(LONG_ENTRY_CHARS);
+    // The name ends with a null if it has room, then the padding
+    if chars.len() < count * LONG_ENTRY_CHARS {
+        chars.push(0);
+    }
+    chars.resize(count * LONG_ENTRY_CHARS, 0xFFFF);
+
+    (1..=count)
+        .rev()
+        .map(|order| {
+            let mut raw = [0; ENTRY_SIZE];
+            raw[0] = order as u8;
+            if order == count {
+                raw[0] |= LAST_LONG_ENTRY;
+            }
+            raw[11] = attributes::LONG_NAME;
+            raw[13] = checksum;
+            let start = (order - 1) * LONG_ENTRY_CHARS;
+            for (i, offset) in LONG_ENTRY_OFFSETS.into_iter().enumerate() {
+                raw[offset..offset + 2].copy_from_slice(&chars[start + i].to_le_bytes());
+            }
+            raw
+        })
+        .collect()
+}
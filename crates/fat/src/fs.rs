@@ -0,0 +1,795 @@
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use core::{any::Any, time::Duration};
+
+use spin::{Mutex, MutexGuard};
+use vfs::{BlockDevice, DirEntry, FileSystem, FileType, Inode, Metadata, Result, VfsError};
+
+use crate::{
+    bpb::{Bpb, FatType},
+    dir::{self, attributes, Entry, RawEntry, ShortEntry, ENTRY_SIZE},
+    table::{Fat, Next},
+    FatError,
+};
+
+/// Node of the root directory, which has no entry.
+const ROOT: u64 = 0;
+/// Inode number of the root. The others are the offsets of their short entry on the volume.
+const ROOT_INODE: u64 = 1;
+/// Largest directory, in entries.
+const MAX_DIR_ENTRIES: usize = 65536;
+
+const FS_INFO_LEAD: u32 = 0x4161_5252;
+const FS_INFO_STRUCT: u32 = 0x6141_7272;
+
+/// A file or directory some inode refers to. Its entry is cached here so that every inode of
+/// the file sees the same size and clusters.
+struct Node {
+    /// Offset of the short entry on the volume, `None` for the root and the removed files.
+    offset: Option<u64>,
+    entry: ShortEntry,
+    refs: usize,
+}
+
+struct State {
+    fat: Fat,
+    nodes: BTreeMap<u64, Node>,
+    /// Node of each short entry offset.
+    offsets: BTreeMap<u64, u64>,
+    next_node: u64,
+}
+
+/// The slots of a directory and where they are.
+struct Listing {
+    slots: Vec<RawEntry>,
+    /// Clusters of the directory, `None` for the fixed root directory of FAT12 and FAT16.
+    clusters: Option<Vec<u32>>,
+}
+
+/// A FAT12, FAT16 or FAT32 volume on a block device.
+///
+/// A single lock covers the volume. There is no clock, so the files created are dated
+/// 1980-01-01 and the times of the others are kept.
+pub struct FatFs {
+    device: Arc<dyn BlockDevice>,
+    bpb: Bpb,
+    state: Mutex<State>,
+    this: Weak<FatFs>,
+}
+
+impl FatFs {
+    pub fn new(device: Arc<dyn BlockDevice>) -> Result<Arc<Self>, FatError> {
+        let mut boot = [0; 512];
+        device.read_at(0, &mut boot)?;
+        let bpb = Bpb::parse(&boot)?;
+
+        let mut fat = Fat::new(device.clone(), bpb.clone());
+        if bpb.fs_info != 0 {
+            let mut info = [0; 512];
+            device.read_at(bpb.fs_info as u64 * bpb.bytes_per_sector as u64, &mut info)?;
+            if u32_at(&info, 0) == FS_INFO_LEAD && u32_at(&info, 484) == FS_INFO_STRUCT {
+                fat.set_next_free(u32_at(&info, 492));
+            }
+        }
+
+        let mut root = ShortEntry::new([b' '; 11], attributes::DIRECTORY);
+        root.cluster = bpb.root_cluster;
+        let mut nodes = BTreeMap::new();
+        let node = Node {
+            offset: None,
+            entry: root,
+            refs: 0,
+        };
+        nodes.insert(ROOT, node);
+
+        Ok(Arc::new_cyclic(|this| Self {
+            device,
+            bpb,
+            state: Mutex::new(State {
+                fat,
+                nodes,
+                offsets: BTreeMap::new(),
+                next_node: ROOT + 1,
+            }),
+            this: this.clone(),
+        }))
+    }
+
+    pub fn fat_type(&self) -> FatType {
+        self.bpb.fat_type
+    }
+
+    /// Bytes the volume holds, and the bytes in use.
+    pub fn usage(&self) -> Result<(u64, u64)> {
+        let free = self.state.lock().fat.free_count()?;
+        let cluster_size = self.bpb.cluster_size() as u64;
+        let total = self.bpb.cluster_count as u64 * cluster_size;
+        Ok((total, total - free as u64 * cluster_size))
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.bpb.cluster_size() as usize
+    }
+
+    /// An inode for `node`, counted in its references.
+    fn inode(&self, state: &mut State, node: u64) -> Arc<FatInode> {
+        state.nodes.get_mut(&node).unwrap().refs += 1;
+        Arc::new(FatInode {
+            fs: self.this.upgrade().unwrap(),
+            node,
+            entries: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// The node of the file with its short entry at `offset`, added if no inode has it.
+    fn node_at(&self, state: &mut State, offset: u64, entry: &ShortEntry) -> u64 {
+        if let Some(&node) = state.offsets.get(&offset) {
+            return node;
+        }
+        let node = state.next_node;
+        state.next_node += 1;
+        let new = Node {
+            offset: Some(offset),
+            entry: entry.clone(),
+            refs: 0,
+        };
+        state.nodes.insert(node, new);
+        state.offsets.insert(offset, node);
+        node
+    }
+
+    /// Writes the entry of `node` back to its directory.
+    fn save(&self, state: &State, node: u64) -> Result<()> {
+        let node = &state.nodes[&node];
+        match node.offset {
+            Some(offset) => self.device.write_at(offset, &node.entry.to_bytes()),
+            None => Ok(()),
+        }
+    }
+
+    fn listing(&self, state: &mut State, dir: &ShortEntry) -> Result<Listing> {
+        if !dir.is_dir() {
+            return Err(VfsError::NotDirectory);
+        }
+        if dir.cluster == 0 {
+            let mut slots = vec![[0; ENTRY_SIZE]; self.bpb.root_entries as usize];
+            self.device
+                .read_at(self.bpb.root_offset(), slots.as_flattened_mut())?;
+            return Ok(Listing {
+                slots,
+                clusters: None,
+            });
+        }
+
+        let clusters = state.fat.chain(dir.cluster)?;
+        let per_cluster = self.cluster_size() / ENTRY_SIZE;
+        let mut slots = vec![[0; ENTRY_SIZE]; clusters.len() * per_cluster];
+        for (&cluster, chunk) in clusters.iter().zip(slots.chunks_mut(per_cluster)) {
+            self.device
+                .read_at(self.bpb.cluster_offset(cluster), chunk.as_flattened_mut())?;
+        }
+        Ok(Listing {
+            slots,
+            clusters: Some(clusters),
+        })
+    }
+
+    /// Offset of the slot `index` on the volume.
+    fn slot_offset(&self, listing: &Listing, index: usize) -> u64 {
+        let position = (index * ENTRY_SIZE) as u64;
+        match &listing.clusters {
+            None => self.bpb.root_offset() + position,
+            Some(clusters) => {
+                let cluster_size = self.cluster_size() as u64;
+                let cluster = clusters[(position / cluster_size) as usize];
+                self.bpb.cluster_offset(cluster) + position % cluster_size
+            }
+        }
+    }
+
+    fn write_slot(&self, listing: &mut Listing, index: usize, raw: RawEntry) -> Result<()> {
+        listing.slots[index] = raw;
+        self.device.write_at(self.slot_offset(listing, index), &raw)
+    }
+
+    /// Adds a zeroed cluster to a directory.
+    fn grow(&self, state: &mut State, listing: &mut Listing) -> Result<()> {
+        let per_cluster = self.cluster_size() / ENTRY_SIZE;
+        let clusters = match &mut listing.clusters {
+            Some(clusters) if listing.slots.len() + per_cluster <= MAX_DIR_ENTRIES => clusters,
+            _ => return Err(VfsError::NoSpace),
+        };
+        let cluster = state.fat.allocate(clusters.last().copied())?;
+        self.zero_cluster(cluster)?;
+        clusters.push(cluster);
+        listing
+            .slots
+            .resize(listing.slots.len() + per_cluster, [0; ENTRY_SIZE]);
+        Ok(())
+    }
+
+    fn zero_cluster(&self, cluster: u32) -> Result<()> {
+        let zeroes = vec![0; self.cluster_size()];
+        self.device
+            .write_at(self.bpb.cluster_offset(cluster), &zeroes)
+    }
+
+    /// The entry of `dir` named `name`, with the listing of `dir`.
+    fn find(&self, state: &mut State, dir: u64, name: &str) -> Result<(Listing, Entry)> {
+        let entry = state.nodes[&dir].entry.clone();
+        let listing = self.listing(state, &entry)?;
+        let entry = dir::parse(&listing.slots)
+            .into_iter()
+            .find(|entry| entry.is_named(name))
+            .ok_or(VfsError::NotFound)?;
+        Ok((listing, entry))
+    }
+
+    /// Adds `entry` to `dir` named `name`, giving it its short name, returns the offset of its
+    /// short entry. The entry at `replacing` may have the name already, it is being renamed.
+    fn insert(
+        &self,
+        state: &mut State,
+        dir: u64,
+        name: &str,
+        entry: &mut ShortEntry,
+        replacing: Option<u64>,
+    ) -> Result<u64> {
+        dir::check_name(name)?;
+        let dir_entry = state.nodes[&dir].entry.clone();
+        let mut listing = self.listing(state, &dir_entry)?;
+        let entries = dir::parse(&listing.slots);
+        let exists = entries.iter().any(|entry| {
+            entry.is_named(name) && Some(self.slot_offset(&listing, entry.slot)) != replacing
+        });
+        if exists {
+            return Err(VfsError::AlreadyExists);
+        }
+
+        let taken = |short: &[u8; 11]| entries.iter().any(|entry| &entry.short.name == short);
+        let (short, long) = match dir::exact_short_name(name) {
+            Some(short) if !taken(&short) => (short, Vec::new()),
+            _ => {
+                let short = (1..1_000_000)
+                    .map(|number| dir::numbered_short_name(name, number))
+                    .find(|short| !taken(short))
+                    .ok_or(VfsError::NoSpace)?;
+                (short, dir::long_entries(name, dir::checksum(&short)))
+            }
+        };
+
+        let count = long.len() + 1;
+        let start = loop {
+            match dir::free_slots(&listing.slots, count) {
+                Some(start) => break start,
+                None => self.grow(state, &mut listing)?,
+            }
+        };
+        entry.name = short;
+        entry.case = 0;
+        for (i, raw) in long.into_iter().chain([entry.to_bytes()]).enumerate() {
+            self.write_slot(&mut listing, start + i, raw)?;
+        }
+        Ok(self.slot_offset(&listing, start + count - 1))
+    }
+
+    /// Removes `entry` from its directory. Its clusters are freed once no inode has it.
+    fn remove(&self, state: &mut State, listing: &mut Listing, entry: &Entry) -> Result<()> {
+        for index in entry.first_slot..=entry.slot {
+            let mut raw = listing.slots[index];
+            dir::delete(&mut raw);
+            self.write_slot(listing, index, raw)?;
+        }
+
+        let offset = self.slot_offset(listing, entry.slot);
+        match state.offsets.remove(&offset) {
+            Some(node) => state.nodes.get_mut(&node).unwrap().offset = None,
+            None if entry.short.cluster != 0 => state.fat.free(entry.short.cluster)?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Whether the directory `entry` only has `.` and `..`.
+    fn is_empty_dir(&self, state: &mut State, entry: &ShortEntry) -> Result<bool> {
+        let listing = self.listing(state, entry)?;
+        Ok(dir::parse(&listing.slots).is_empty())
+    }
+
+    /// Cluster to put in the `..` entries of the children of `dir`, 0 for the root.
+    fn parent_cluster(&self, state: &State, dir: u64) -> u32 {
+        match dir {
+            ROOT => 0,
+            dir => state.nodes[&dir].entry.cluster,
+        }
+    }
+
+    fn read(&self, state: &mut State, node: u64, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let entry = &state.nodes[&node].entry;
+        if entry.is_dir() {
+            return Err(VfsError::IsDirectory);
+        }
+        let (first, size) = (entry.cluster, entry.size as u64);
+        if offset >= size || buffer.is_empty() {
+            return Ok(0);
+        }
+        let len = buffer.len().min((size - offset) as usize);
+
+        let cluster_size = self.cluster_size() as u64;
+        let mut cluster = match state.fat.nth(first, (offset / cluster_size) as u32)? {
+            Some(cluster) => cluster,
+            None => return Err(VfsError::Io),
+        };
+        let mut done = 0;
+        while done < len {
+            let position = offset + done as u64;
+            let start = position % cluster_size;
+            let chunk = (len - done).min((cluster_size - start) as usize);
+            self.device.read_at(
+                self.bpb.cluster_offset(cluster) + start,
+                &mut buffer[done..done + chunk],
+            )?;
+            done += chunk;
+            if done < len {
+                cluster = match state.fat.next(cluster)? {
+                    Next::Cluster(next) => next,
+                    _ => return Err(VfsError::Io),
+                };
+            }
+        }
+        Ok(len)
+    }
+
+    /// Writes `data`, or zeroes if `None`, at `offset` of the file, allocating its clusters.
+    fn write(
+        &self,
+        state: &mut State,
+        node: u64,
+        offset: u64,
+        len: usize,
+        data: Option<&[u8]>,
+    ) -> Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        let cluster_size = self.cluster_size() as u64;
+        let mut cluster = match state.nodes[&node].entry.cluster {
+            0 => {
+                let cluster = state.fat.allocate(None)?;
+                state.nodes.get_mut(&node).unwrap().entry.cluster = cluster;
+                self.save(state, node)?;
+                cluster
+            }
+            cluster => cluster,
+        };
+        for _ in 0..offset / cluster_size {
+            cluster = self.next_or_allocate(state, cluster)?;
+        }
+
+        let zeroes = vec![0; cluster_size as usize];
+        let mut done = 0;
+        while done < len {
+            let position = offset + done as u64;
+            let start = position % cluster_size;
+            let chunk = (len - done).min((cluster_size - start) as usize);
+            let bytes = match data {
+                Some(data) => &data[done..done + chunk],
+                None => &zeroes[..chunk],
+            };
+            self.device
+                .write_at(self.bpb.cluster_offset(cluster) + start, bytes)?;
+            done += chunk;
+            if done < len {
+                cluster = self.next_or_allocate(state, cluster)?;
+            }
+        }
+
+        let entry = &mut state.nodes.get_mut(&node).unwrap().entry;
+        entry.size = entry.size.max((offset + len as u64) as u32);
+        entry.attributes |= attributes::ARCHIVE;
+        self.save(state, node)
+    }
+
+    fn next_or_allocate(&self, state: &mut State, cluster: u32) -> Result<u32> {
+        match state.fat.next(cluster)? {
+            Next::Cluster(next) => Ok(next),
+            Next::End => state.fat.allocate(Some(cluster)),
+            Next::Free => Err(VfsError::Io),
+        }
+    }
+
+    /// Writes the free count and the next free cluster of a FAT32 to its FSInfo.
+    fn write_fs_info(&self, state: &mut State) -> Result<()> {
+        if self.bpb.fs_info == 0 {
+            return Ok(());
+        }
+        let offset = self.bpb.fs_info as u64 * self.bpb.bytes_per_sector as u64;
+        let mut info = [0; 512];
+        self.device.read_at(offset, &mut info)?;
+        if u32_at(&info, 0) != FS_INFO_LEAD || u32_at(&info, 484) != FS_INFO_STRUCT {
+            return Ok(());
+        }
+        info[488..492].copy_from_slice(&state.fat.free_count()?.to_le_bytes());
+        info[492..496].copy_from_slice(&state.fat.next_free().to_le_bytes());
+        self.device.write_at(offset, &info)
+    }
+}
+
+impl FileSystem for FatFs {
+    fn name(&self) -> &'static str {
+        "vfat"
+    }
+
+    fn root(&self) -> Result<Arc<dyn Inode>> {
+        let mut state = self.state.lock();
+        Ok(self.inode(&mut state, ROOT))
+    }
+
+    fn sync(&self) -> Result<()> {
+        let mut state = self.state.lock();
+        state.fat.flush()?;
+        self.write_fs_info(&mut state)?;
+        self.device.flush()
+    }
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Time since the Unix epoch of a DOS date and time, in local time taken as UTC.
+fn dos_time(date: u16, time: u16) -> Duration {
+    let year = 1980 + (date >> 9) as i64;
+    let month = ((date >> 5) & 0xF).max(1) as i64;
+    let day = (date & 0x1F).max(1) as i64;
+    // Days from the civil date, counting years from March to put leap days last
+    let (year, month) = match month > 2 {
+        true => (year, month - 3),
+        false => (year - 1, month + 9),
+    };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let seconds = (time >> 11) as i64 * 3600 + ((time >> 5) & 0x3F) as i64 * 60;
+    Duration::from_secs((days * 86400 + seconds + (time & 0x1F) as i64 * 2) as u64)
+}
+
+pub struct FatInode {
+    fs: Arc<FatFs>,
+    node: u64,
+    /// Listing of a directory, read again when it is listed from the start.
+    entries: Mutex<Vec<DirEntry>>,
+}
+
+impl FatInode {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.fs.state.lock()
+    }
+
+    /// Flushes the FAT after an operation that may have changed it, keeping its result.
+    fn finish<T>(&self, state: &mut State, result: Result<T>) -> Result<T> {
+        let flushed = state.fat.flush();
+        let value = result?;
+        flushed?;
+        Ok(value)
+    }
+}
+
+impl Drop for FatInode {
+    fn drop(&mut self) {
+        let mut state = self.fs.state.lock();
+        let node = state.nodes.get_mut(&self.node).unwrap();
+        node.refs -= 1;
+        if node.refs > 0 || self.node == ROOT {
+            return;
+        }
+
+        let node = state.nodes.remove(&self.node).unwrap();
+        match node.offset {
+            Some(offset) => {
+                state.offsets.remove(&offset);
+            }
+            // The file was removed while open
+            None if node.entry.cluster != 0 => {
+                let _ = state.fat.free(node.entry.cluster);
+                let _ = state.fat.flush();
+            }
+            None => {}
+        }
+    }
+}
+
+impl Inode for FatInode {
+    fn metadata(&self) -> Result<Metadata> {
+        let state = self.lock();
+        let node = &state.nodes[&self.node];
+        let entry = &node.entry;
+        let (file_type, mut mode, size) = match entry.is_dir() {
+            true => (FileType::Directory, 0o755, 0),
+            false => (FileType::Regular, 0o644, entry.size as u64),
+        };
+        if entry.attributes & attributes::READ_ONLY != 0 {
+            mode &= !0o222;
+        }
+        let inode = match (self.node, node.offset) {
+            (ROOT, _) => ROOT_INODE,
+            (_, Some(offset)) => offset,
+            // A removed file gets a number no entry has
+            (id, None) => u64::MAX - id,
+        };
+
+        let mut metadata = Metadata::new(inode, file_type, mode, size);
+        metadata.modified = dos_time(entry.modified_date, entry.modified_time);
+        metadata.accessed = dos_time(entry.accessed_date, 0);
+        metadata.changed = metadata.modified;
+        Ok(metadata)
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let mut state = self.lock();
+        self.fs.read(&mut state, self.node, offset, buffer)
+    }
+
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> Result<usize> {
+        let mut state = self.lock();
+        let entry = &state.nodes[&self.node].entry;
+        if entry.is_dir() {
+            return Err(VfsError::IsDirectory);
+        }
+        let size = entry.size as u64;
+        let end = offset
+            .checked_add(buffer.len() as u64)
+            .ok_or(VfsError::FileTooLarge)?;
+        if end > u32::MAX as u64 {
+            return Err(VfsError::FileTooLarge);
+        }
+
+        let result = match offset > size {
+            // The gap reads as zeroes
+            true => self
+                .fs
+                .write(&mut state, self.node, size, (offset - size) as usize, None),
+            false => Ok(()),
+        };
+        let result = result.and_then(|()| {
+            let len = buffer.len();
+            self.fs
+                .write(&mut state, self.node, offset, len, Some(buffer))
+        });
+        self.finish(&mut state, result.map(|()| buffer.len()))
+    }
+
+    fn truncate(&self, size: u64) -> Result<()> {
+        let mut state = self.lock();
+        let entry = state.nodes[&self.node].entry.clone();
+        if entry.is_dir() {
+            return Err(VfsError::IsDirectory);
+        }
+        if size > u32::MAX as u64 {
+            return Err(VfsError::FileTooLarge);
+        }
+        let old = entry.size as u64;
+        if size > old {
+            let result = self
+                .fs
+                .write(&mut state, self.node, old, (size - old) as usize, None);
+            return self.finish(&mut state, result);
+        }
+
+        let cluster_size = self.fs.cluster_size() as u64;
+        let result = match (entry.cluster, size.div_ceil(cluster_size)) {
+            (0, _) => Ok(()),
+            (first, 0) => state.fat.free(first),
+            (first, count) => match state.fat.nth(first, count as u32 - 1) {
+                Ok(Some(last)) => state.fat.truncate(last),
+                Ok(None) => Ok(()),
+                Err(err) => Err(err),
+            },
+        };
+        let result = result.and_then(|()| {
+            let entry = &mut state.nodes.get_mut(&self.node).unwrap().entry;
+            entry.size = size as u32;
+            if size == 0 {
+                entry.cluster = 0;
+            }
+            self.fs.save(&state, self.node)
+        });
+        self.finish(&mut state, result)
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>> {
+        let mut state = self.lock();
+        let (listing, entry) = self.fs.find(&mut state, self.node, name)?;
+        let offset = self.fs.slot_offset(&listing, entry.slot);
+        let node = self.fs.node_at(&mut state, offset, &entry.short);
+        Ok(self.fs.inode(&mut state, node))
+    }
+
+    fn read_dir(&self, index: usize) -> Result<Option<DirEntry>> {
+        let mut entries = self.entries.lock();
+        if index == 0 {
+            let mut state = self.lock();
+            let dir = state.nodes[&self.node].entry.clone();
+            let listing = self.fs.listing(&mut state, &dir)?;
+            *entries = dir::parse(&listing.slots)
+                .into_iter()
+                .map(|entry| DirEntry {
+                    inode: self.fs.slot_offset(&listing, entry.slot),
+                    file_type: match entry.short.is_dir() {
+                        true => FileType::Directory,
+                        false => FileType::Regular,
+                    },
+                    name: entry.name,
+                })
+                .collect();
+        }
+        Ok(entries.get(index).cloned())
+    }
+
+    fn create(&self, name: &str, file_type: FileType, mode: u16) -> Result<Arc<dyn Inode>> {
+        let mut state = self.lock();
+        let mut entry = match file_type {
+            FileType::Regular => ShortEntry::new([b' '; 11], attributes::ARCHIVE),
+            FileType::Directory => ShortEntry::new([b' '; 11], attributes::DIRECTORY),
+            _ => return Err(VfsError::NotSupported),
+        };
+        if mode & 0o222 == 0 {
+            entry.attributes |= attributes::READ_ONLY;
+        }
+
+        let result = (|| {
+            if file_type == FileType::Directory {
+                // The directory starts with its `.` and `..` entries
+                let cluster = state.fat.allocate(None)?;
+                entry.cluster = cluster;
+                let parent = self.fs.parent_cluster(&state, self.node);
+                let mut dot = entry.clone();
+                dot.name = *b".          ";
+                let mut dot_dot = entry.clone();
+                dot_dot.name = *b"..         ";
+                dot_dot.cluster = parent;
+                let write = self.fs.zero_cluster(cluster).and_then(|()| {
+                    let mut slots = [0; 2 * ENTRY_SIZE];
+                    slots[..ENTRY_SIZE].copy_from_slice(&dot.to_bytes());
+                    slots[ENTRY_SIZE..].copy_from_slice(&dot_dot.to_bytes());
+                    self.fs
+                        .device
+                        .write_at(self.fs.bpb.cluster_offset(cluster), &slots)
+                });
+                if let Err(err) = write {
+                    state.fat.free(cluster)?;
+                    return Err(err);
+                }
+            }
+            match self
+                .fs
+                .insert(&mut state, self.node, name, &mut entry, None)
+            {
+                Ok(offset) => Ok(offset),
+                Err(err) => {
+                    if entry.cluster != 0 {
+                        state.fat.free(entry.cluster)?;
+                    }
+                    Err(err)
+                }
+            }
+        })();
+        let offset = self.finish(&mut state, result)?;
+        let node = self.fs.node_at(&mut state, offset, &entry);
+        Ok(self.fs.inode(&mut state, node))
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        let mut state = self.lock();
+        let (mut listing, entry) = self.fs.find(&mut state, self.node, name)?;
+        if entry.short.is_dir() {
+            return Err(VfsError::IsDirectory);
+        }
+        let result = self.fs.remove(&mut state, &mut listing, &entry);
+        self.finish(&mut state, result)
+    }
+
+    fn rmdir(&self, name: &str) -> Result<()> {
+        let mut state = self.lock();
+        let (mut listing, entry) = self.fs.find(&mut state, self.node, name)?;
+        if !entry.short.is_dir() {
+            return Err(VfsError::NotDirectory);
+        }
+        if !self.fs.is_empty_dir(&mut state, &entry.short)? {
+            return Err(VfsError::NotEmpty);
+        }
+        let result = self.fs.remove(&mut state, &mut listing, &entry);
+        self.finish(&mut state, result)
+    }
+
+    fn rename(&self, name: &str, new_dir: &Arc<dyn Inode>, new_name: &str) -> Result<()> {
+        let new_dir: Arc<dyn Any + Send + Sync> = new_dir.clone();
+        let new_dir = new_dir
+            .downcast::<FatInode>()
+            .map_err(|_| VfsError::CrossDevice)?;
+        if !Arc::ptr_eq(&self.fs, &new_dir.fs) {
+            return Err(VfsError::CrossDevice);
+        }
+        dir::check_name(new_name)?;
+
+        let mut state = self.lock();
+        let fs = &self.fs;
+        let (listing, entry) = fs.find(&mut state, self.node, name)?;
+        let old_offset = fs.slot_offset(&listing, entry.slot);
+        // The entry of an open file is the one of its node
+        let mut short = match state.offsets.get(&old_offset) {
+            Some(node) => state.nodes[node].entry.clone(),
+            None => entry.short.clone(),
+        };
+
+        let result = (|| {
+            match fs.find(&mut state, new_dir.node, new_name) {
+                Ok((mut listing, target)) => {
+                    let same = fs.slot_offset(&listing, target.slot) == old_offset;
+                    if same && target.name == new_name {
+                        return Ok(());
+                    }
+                    if !same {
+                        match (short.is_dir(), target.short.is_dir()) {
+                            (true, false) => return Err(VfsError::NotDirectory),
+                            (false, true) => return Err(VfsError::IsDirectory),
+                            (true, true) if !fs.is_empty_dir(&mut state, &target.short)? => {
+                                return Err(VfsError::NotEmpty)
+                            }
+                            _ => fs.remove(&mut state, &mut listing, &target)?,
+                        }
+                    }
+                }
+                Err(VfsError::NotFound) => {}
+                Err(err) => return Err(err),
+            }
+
+            // The new entry is added before the old one goes, so a full directory loses nothing
+            let new_offset = fs.insert(
+                &mut state,
+                new_dir.node,
+                new_name,
+                &mut short,
+                Some(old_offset),
+            )?;
+            let dir = state.nodes[&self.node].entry.clone();
+            let mut listing = fs.listing(&mut state, &dir)?;
+            let old = dir::parse(&listing.slots)
+                .into_iter()
+                .find(|entry| fs.slot_offset(&listing, entry.slot) == old_offset)
+                .ok_or(VfsError::Io)?;
+            for index in old.first_slot..=old.slot {
+                let mut raw = listing.slots[index];
+                dir::delete(&mut raw);
+                fs.write_slot(&mut listing, index, raw)?;
+            }
+
+            if let Some(node) = state.offsets.remove(&old_offset) {
+                let moved = state.nodes.get_mut(&node).unwrap();
+                moved.offset = Some(new_offset);
+                moved.entry.name = short.name;
+                moved.entry.case = short.case;
+                state.offsets.insert(new_offset, node);
+            }
+            if short.is_dir() && self.node != new_dir.node {
+                // Point `..` at the new parent
+                let parent = fs.parent_cluster(&state, new_dir.node);
+                let offset = fs.bpb.cluster_offset(short.cluster) + ENTRY_SIZE as u64;
+                let mut raw = [0; ENTRY_SIZE];
+                fs.device.read_at(offset, &mut raw)?;
+                let mut dot_dot = ShortEntry::parse(&raw);
+                dot_dot.cluster = parent;
+                fs.device.write_at(offset, &dot_dot.to_bytes())?;
+            }
+            Ok(())
+        })();
+        self.finish(&mut state, result)
+    }
+}
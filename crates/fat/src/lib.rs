@@ -0,0 +1,33 @@
+//! FAT12, FAT16 and FAT32 file systems with long file names, read and written on a block
+//! device and mounted through the VFS.
+
+#![no_std]
+
+extern crate alloc;
+
+mod bpb;
+mod dir;
+mod fs;
+mod table;
+
+use snafu::Snafu;
+use vfs::VfsError;
+
+pub use bpb::FatType;
+pub use fs::{FatFs, FatInode};
+
+#[derive(Debug, Snafu)]
+pub enum FatError {
+    #[snafu(display("Not a FAT file system"))]
+    NotFat,
+    #[snafu(display("Unsupported sector size {}", size))]
+    SectorSize { size: u32 },
+    #[snafu(display("Device error: {}", error))]
+    Device { error: VfsError },
+}
+
+impl From<VfsError> for FatError {
+    fn from(error: VfsError) -> Self {
+        FatError::Device { error }
+    }
+}
@@ -0,0 +1,272 @@
+//! The file allocation table, cached a few sectors at a time. A changed sector is written to
+//! every copy of the FAT.
+
+use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
+
+use vfs::{BlockDevice, Result, VfsError};
+
+use crate::bpb::{Bpb, FatType};
+
+/// Sectors of the FAT kept in memory.
+const CACHE_SECTORS: usize = 16;
+
+/// What an entry of the FAT says about the cluster after its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Next {
+    Free,
+    Cluster(u32),
+    End,
+}
+
+struct Sector {
+    data: Vec<u8>,
+    dirty: bool,
+    /// Time of the last access, to evict the least recently used sector.
+    used: u64,
+}
+
+pub struct Fat {
+    device: Arc<dyn BlockDevice>,
+    bpb: Bpb,
+    sectors: BTreeMap<u64, Sector>,
+    clock: u64,
+    /// Cluster where the search for a free one starts.
+    next_free: u32,
+}
+
+impl Fat {
+    pub fn new(device: Arc<dyn BlockDevice>, bpb: Bpb) -> Self {
+        Self {
+            device,
+            bpb,
+            sectors: BTreeMap::new(),
+            clock: 0,
+            next_free: 2,
+        }
+    }
+
+    pub fn set_next_free(&mut self, cluster: u32) {
+        if self.bpb.is_valid_cluster(cluster) {
+            self.next_free = cluster;
+        }
+    }
+
+    pub fn next_free(&self) -> u32 {
+        self.next_free
+    }
+
+    fn sector(&mut self, index: u64) -> Result<&mut Sector> {
+        self.clock += 1;
+        if !self.sectors.contains_key(&index) {
+            if self.sectors.len() >= CACHE_SECTORS {
+                let oldest = self.sectors.iter().min_by_key(|(_, sector)| sector.used);
+                let oldest = *oldest.map(|(index, _)| index).unwrap();
+                self.write_back(oldest)?;
+                self.sectors.remove(&oldest);
+            }
+            let size = self.bpb.bytes_per_sector as usize;
+            let mut data = vec![0; size];
+            let offset = self.bpb.fat_offset(0) + index * size as u64;
+            self.device.read_at(offset, &mut data)?;
+            let sector = Sector {
+                data,
+                dirty: false,
+                used: 0,
+            };
+            self.sectors.insert(index, sector);
+        }
+
+        let sector = self.sectors.get_mut(&index).unwrap();
+        sector.used = self.clock;
+        Ok(sector)
+    }
+
+    fn write_back(&mut self, index: u64) -> Result<()> {
+        let sector = match self.sectors.get_mut(&index) {
+            Some(sector) if sector.dirty => sector,
+            _ => return Ok(()),
+        };
+        let offset = index * self.bpb.bytes_per_sector as u64;
+        for copy in 0..self.bpb.fat_count {
+            self.device
+                .write_at(self.bpb.fat_offset(copy) + offset, &sector.data)?;
+        }
+        sector.dirty = false;
+        Ok(())
+    }
+
+    /// Writes the changed sectors to the device.
+    pub fn flush(&mut self) -> Result<()> {
+        let dirty: Vec<u64> = self
+            .sectors
+            .iter()
+            .filter(|(_, sector)| sector.dirty)
+            .map(|(&index, _)| index)
+            .collect();
+        for index in dirty {
+            self.write_back(index)?;
+        }
+        Ok(())
+    }
+
+    fn byte(&mut self, offset: u64) -> Result<u8> {
+        let size = self.bpb.bytes_per_sector as u64;
+        let sector = self.sector(offset / size)?;
+        Ok(sector.data[(offset % size) as usize])
+    }
+
+    fn set_byte(&mut self, offset: u64, value: u8) -> Result<()> {
+        let size = self.bpb.bytes_per_sector as u64;
+        let sector = self.sector(offset / size)?;
+        sector.data[(offset % size) as usize] = value;
+        sector.dirty = true;
+        Ok(())
+    }
+
+    /// Raw entry of `cluster`.
+    fn get(&mut self, cluster: u32) -> Result<u32> {
+        let cluster = cluster as u64;
+        match self.bpb.fat_type {
+            FatType::Fat12 => {
+                let offset = cluster + cluster / 2;
+                let value = u16::from_le_bytes([self.byte(offset)?, self.byte(offset + 1)?]);
+                Ok(match cluster % 2 {
+                    0 => value & 0xFFF,
+                    _ => value >> 4,
+                } as u32)
+            }
+            FatType::Fat16 => {
+                let offset = cluster * 2;
+                Ok(u16::from_le_bytes([self.byte(offset)?, self.byte(offset + 1)?]) as u32)
+            }
+            FatType::Fat32 => {
+                let mut bytes = [0; 4];
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    *byte = self.byte(cluster * 4 + i as u64)?;
+                }
+                Ok(u32::from_le_bytes(bytes) & 0x0FFF_FFFF)
+            }
+        }
+    }
+
+    fn set(&mut self, cluster: u32, value: u32) -> Result<()> {
+        let cluster = cluster as u64;
+        match self.bpb.fat_type {
+            FatType::Fat12 => {
+                let offset = cluster + cluster / 2;
+                let old = u16::from_le_bytes([self.byte(offset)?, self.byte(offset + 1)?]);
+                let value = value as u16 & 0xFFF;
+                let new = match cluster % 2 {
+                    0 => (old & 0xF000) | value,
+                    _ => (old & 0x000F) | (value << 4),
+                };
+                let [low, high] = new.to_le_bytes();
+                self.set_byte(offset, low)?;
+                self.set_byte(offset + 1, high)
+            }
+            FatType::Fat16 => {
+                let [low, high] = (value as u16).to_le_bytes();
+                self.set_byte(cluster * 2, low)?;
+                self.set_byte(cluster * 2 + 1, high)
+            }
+            FatType::Fat32 => {
+                // The top 4 bits are reserved and kept
+                let old = self.byte(cluster * 4 + 3)? & 0xF0;
+                let mut bytes = (value & 0x0FFF_FFFF).to_le_bytes();
+                bytes[3] |= old;
+                for (i, byte) in bytes.into_iter().enumerate() {
+                    self.set_byte(cluster * 4 + i as u64, byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// What follows `cluster`. A bad cluster or one out of the volume in a chain is an error.
+    pub fn next(&mut self, cluster: u32) -> Result<Next> {
+        match self.get(cluster)? {
+            0 => Ok(Next::Free),
+            next if next >= self.bpb.fat_type.end_of_chain() => Ok(Next::End),
+            next if self.bpb.is_valid_cluster(next) => Ok(Next::Cluster(next)),
+            _ => Err(VfsError::Io),
+        }
+    }
+
+    /// The cluster `index` of the chain starting at `first`, `None` past its end.
+    pub fn nth(&mut self, first: u32, index: u32) -> Result<Option<u32>> {
+        let mut cluster = first;
+        for _ in 0..index {
+            cluster = match self.next(cluster)? {
+                Next::Cluster(next) => next,
+                Next::End => return Ok(None),
+                Next::Free => return Err(VfsError::Io),
+            };
+        }
+        Ok(Some(cluster))
+    }
+
+    /// Clusters of the chain starting at `first`.
+    pub fn chain(&mut self, first: u32) -> Result<Vec<u32>> {
+        let mut chain = vec![first];
+        let mut cluster = first;
+        loop {
+            cluster = match self.next(cluster)? {
+                Next::Cluster(next) => next,
+                Next::End => return Ok(chain),
+                Next::Free => return Err(VfsError::Io),
+            };
+            // A chain longer than the volume loops
+            if chain.len() > self.bpb.cluster_count as usize {
+                return Err(VfsError::Io);
+            }
+            chain.push(cluster);
+        }
+    }
+
+    /// Takes a free cluster, ends a chain with it and links it after `previous`.
+    pub fn allocate(&mut self, previous: Option<u32>) -> Result<u32> {
+        let count = self.bpb.cluster_count;
+        for i in 0..count {
+            let cluster = 2 + (self.next_free - 2 + i) % count;
+            if self.get(cluster)? != 0 {
+                continue;
+            }
+            self.set(cluster, 0x0FFF_FFFF)?;
+            if let Some(previous) = previous {
+                self.set(previous, cluster)?;
+            }
+            self.next_free = 2 + (cluster - 2 + 1) % count;
+            return Ok(cluster);
+        }
+        Err(VfsError::NoSpace)
+    }
+
+    /// Frees the clusters after `cluster`, which ends its chain.
+    pub fn truncate(&mut self, cluster: u32) -> Result<()> {
+        let next = self.next(cluster)?;
+        self.set(cluster, 0x0FFF_FFFF)?;
+        match next {
+            Next::Cluster(next) => self.free(next),
+            _ => Ok(()),
+        }
+    }
+
+    /// Frees the chain starting at `first`.
+    pub fn free(&mut self, first: u32) -> Result<()> {
+        for cluster in self.chain(first)? {
+            self.set(cluster, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Number of free clusters, counted through the whole FAT.
+    pub fn free_count(&mut self) -> Result<u32> {
+        let mut count = 0;
+        for cluster in 2..self.bpb.cluster_count + 2 {
+            if self.get(cluster)? == 0 {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
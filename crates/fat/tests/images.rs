@@ -0,0 +1,356 @@
+//! Tests on the images of `images/`, made by `images/generate.sh`. They run on the host:
+//! `cargo test -p fat --target x86_64-unknown-linux-gnu -Z build-std=std,panic_unwind`.
+
+use std::sync::{Arc, Mutex};
+
+use fat::{FatFs, FatType};
+use vfs::{BlockDevice, FileSystem, FileType, Inode, Result, VfsError};
+
+const BLOCK_SIZE: usize = 512;
+const IMAGES: [(&str, FatType); 3] = [
+    ("fat12.img", FatType::Fat12),
+    ("fat16.img", FatType::Fat16),
+    ("fat32.img", FatType::Fat32),
+];
+
+/// An image loaded in memory, so that the tests don't change the files.
+struct Image(Mutex<Vec<u8>>);
+
+impl BlockDevice for Image {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        (self.0.lock().unwrap().len() / BLOCK_SIZE) as u64
+    }
+
+    fn read_blocks(&self, block: u64, buffer: &mut [u8]) -> Result<()> {
+        let start = block as usize * BLOCK_SIZE;
+        let data = self.0.lock().unwrap();
+        let blocks = data.get(start..start + buffer.len()).ok_or(VfsError::Io)?;
+        buffer.copy_from_slice(blocks);
+        Ok(())
+    }
+
+    fn write_blocks(&self, block: u64, buffer: &[u8]) -> Result<()> {
+        let start = block as usize * BLOCK_SIZE;
+        let mut data = self.0.lock().unwrap();
+        let blocks = data
+            .get_mut(start..start + buffer.len())
+            .ok_or(VfsError::Io)?;
+        blocks.copy_from_slice(buffer);
+        Ok(())
+    }
+}
+
+fn load(name: &str) -> Arc<Image> {
+    let path = format!("{}/tests/images/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let data = std::fs::read(&path).unwrap_or_else(|err| panic!("{}: {}", path, err));
+    Arc::new(Image(Mutex::new(data)))
+}
+
+fn mount(image: &Arc<Image>) -> Arc<FatFs> {
+    FatFs::new(image.clone()).expect("not mounted")
+}
+
+/// Every image, mounted.
+fn images() -> impl Iterator<Item = (Arc<Image>, Arc<FatFs>)> {
+    IMAGES.iter().map(|(name, _)| {
+        let image = load(name);
+        let fs = mount(&image);
+        (image, fs)
+    })
+}
+
+fn resolve(fs: &FatFs, path: &str) -> Result<Arc<dyn Inode>> {
+    let mut inode = fs.root()?;
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        inode = inode.lookup(name)?;
+    }
+    Ok(inode)
+}
+
+fn read_all(inode: &dyn Inode) -> Vec<u8> {
+    let mut data = vec![0; inode.metadata().unwrap().size as usize];
+    assert_eq!(inode.read_at(0, &mut data).unwrap(), data.len());
+    data
+}
+
+fn names(dir: &dyn Inode) -> Vec<String> {
+    let mut names = Vec::new();
+    while let Some(entry) = dir.read_dir(names.len()).unwrap() {
+        names.push(entry.name);
+    }
+    names.sort();
+    names
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn detects_the_fat_type() {
+    for (name, fat_type) in IMAGES {
+        assert_eq!(mount(&load(name)).fat_type(), fat_type, "{}", name);
+    }
+}
+
+#[test]
+fn rejects_other_volumes() {
+    let image = Arc::new(Image(Mutex::new(vec![0; 64 * BLOCK_SIZE])));
+    assert!(FatFs::new(image).is_err());
+}
+
+#[test]
+fn lists_short_and_long_names() {
+    for (_, fs) in images() {
+        let root = fs.root().unwrap();
+        assert_eq!(
+            names(&*root),
+            ["HELLO.TXT", "Long File Name.txt", "big.bin", "dir"]
+        );
+        let entry = root.read_dir(0).unwrap().unwrap();
+        assert_eq!(entry.file_type, FileType::Regular);
+    }
+}
+
+#[test]
+fn looks_up_names_ignoring_the_case() {
+    for (_, fs) in images() {
+        let hello = resolve(&fs, "hello.txt").unwrap();
+        assert_eq!(read_all(&*hello), b"Hello from FAT!\n");
+        let long = resolve(&fs, "LONG FILE NAME.TXT").unwrap();
+        assert_eq!(read_all(&*long), b"A file with a long name.\n");
+        // The short name of a long name works too
+        let short = resolve(&fs, "LONGFI~1.TXT").unwrap();
+        assert_eq!(read_all(&*short), b"A file with a long name.\n");
+        assert_eq!(resolve(&fs, "missing").err(), Some(VfsError::NotFound));
+    }
+}
+
+#[test]
+fn reads_files_across_clusters() {
+    for (_, fs) in images() {
+        let big = resolve(&fs, "big.bin").unwrap();
+        assert_eq!(big.metadata().unwrap().size, 100_000);
+        assert_eq!(read_all(&*big), pattern(100_000));
+
+        let mut middle = [0; 1000];
+        assert_eq!(big.read_at(50_000, &mut middle).unwrap(), 1000);
+        assert_eq!(&middle[..], &pattern(51_000)[50_000..]);
+        assert_eq!(big.read_at(99_900, &mut middle).unwrap(), 100);
+        assert_eq!(big.read_at(100_000, &mut middle).unwrap(), 0);
+    }
+}
+
+#[test]
+fn walks_nested_directories() {
+    for (_, fs) in images() {
+        let dir = resolve(&fs, "dir").unwrap();
+        assert!(dir.metadata().unwrap().is_dir());
+        assert_eq!(names(&*dir), ["nested"]);
+        let deep = resolve(&fs, "dir/nested/deep.txt").unwrap();
+        assert_eq!(read_all(&*deep), b"Deep inside.\n");
+        assert_eq!(
+            resolve(&fs, "HELLO.TXT/x").err(),
+            Some(VfsError::NotDirectory)
+        );
+    }
+}
+
+#[test]
+fn writes_files_kept_after_a_remount() {
+    for (image, fs) in images() {
+        let root = fs.root().unwrap();
+        let file = root
+            .create("A new file with a long name.txt", FileType::Regular, 0o644)
+            .unwrap();
+        assert_eq!(file.write_at(0, &pattern(3000)).unwrap(), 3000);
+        // The gap up to the second write reads as zeroes
+        assert_eq!(file.write_at(10_000, b"end").unwrap(), 3);
+        fs.sync().unwrap();
+        drop((file, root, fs));
+
+        let fs = mount(&image);
+        let file = resolve(&fs, "a new file with a long name.txt").unwrap();
+        let data = read_all(&*file);
+        assert_eq!(data.len(), 10_003);
+        assert_eq!(&data[..3000], &pattern(3000)[..]);
+        assert!(data[3000..10_000].iter().all(|&byte| byte == 0));
+        assert_eq!(&data[10_000..], b"end");
+        assert_eq!(
+            fs.root()
+                .unwrap()
+                .create("A NEW FILE WITH A LONG NAME.TXT", FileType::Regular, 0o644)
+                .err(),
+            Some(VfsError::AlreadyExists)
+        );
+    }
+}
+
+#[test]
+fn creates_and_removes_directories() {
+    for (image, fs) in images() {
+        let (_, used) = fs.usage().unwrap();
+        let root = fs.root().unwrap();
+        let dir = root
+            .create("New Directory", FileType::Directory, 0o755)
+            .unwrap();
+        let file = dir.create("inside.txt", FileType::Regular, 0o644).unwrap();
+        file.write_at(0, b"inside").unwrap();
+        drop((file, dir));
+
+        assert_eq!(root.rmdir("New Directory").err(), Some(VfsError::NotEmpty));
+        assert_eq!(
+            root.unlink("New Directory").err(),
+            Some(VfsError::IsDirectory)
+        );
+        let fs2 = mount(&image);
+        assert_eq!(
+            read_all(&*resolve(&fs2, "New Directory/inside.txt").unwrap()),
+            b"inside"
+        );
+
+        let dir = root.lookup("new directory").unwrap();
+        dir.unlink("INSIDE.TXT").unwrap();
+        drop(dir);
+        root.rmdir("New Directory").unwrap();
+        assert_eq!(root.lookup("New Directory").err(), Some(VfsError::NotFound));
+        assert_eq!(fs.usage().unwrap().1, used);
+    }
+}
+
+#[test]
+fn grows_directories() {
+    for (image, fs) in images() {
+        let dir = resolve(&fs, "dir").unwrap();
+        for i in 0..100 {
+            let name = format!("file number {}", i);
+            dir.create(&name, FileType::Regular, 0o644).unwrap();
+        }
+        let fs = mount(&image);
+        let dir = resolve(&fs, "dir").unwrap();
+        assert_eq!(names(&*dir).len(), 101);
+        assert!(dir.lookup("FILE NUMBER 99").is_ok());
+    }
+}
+
+#[test]
+fn fills_the_fixed_root_directory() {
+    let image = load("fat12.img");
+    let fs = mount(&image);
+    let root = fs.root().unwrap();
+    let mut created = 0;
+    let err = loop {
+        match root.create(&format!("F{}", created), FileType::Regular, 0o644) {
+            Ok(_) => created += 1,
+            Err(err) => break err,
+        }
+    };
+    // The label and the files of the image take a few of the 512 entries
+    assert_eq!(err, VfsError::NoSpace);
+    assert!((500..512).contains(&created));
+    // Subdirectories grow instead
+    let dir = root.lookup("dir").unwrap();
+    assert!(dir.create("F0", FileType::Regular, 0o644).is_ok());
+}
+
+#[test]
+fn frees_clusters_once_files_are_closed() {
+    for (_, fs) in images() {
+        let (_, used) = fs.usage().unwrap();
+        let root = fs.root().unwrap();
+        let file = root.create("temporary", FileType::Regular, 0o644).unwrap();
+        file.write_at(0, &pattern(20_000)).unwrap();
+        assert!(fs.usage().unwrap().1 > used);
+
+        root.unlink("temporary").unwrap();
+        assert_eq!(root.lookup("temporary").err(), Some(VfsError::NotFound));
+        // The open file still has its data
+        assert_eq!(read_all(&*file), pattern(20_000));
+        assert!(fs.usage().unwrap().1 > used);
+        drop(file);
+        assert_eq!(fs.usage().unwrap().1, used);
+    }
+}
+
+#[test]
+fn truncates_files() {
+    for (image, fs) in images() {
+        let (_, used) = fs.usage().unwrap();
+        let big = resolve(&fs, "big.bin").unwrap();
+        big.truncate(1000).unwrap();
+        assert!(fs.usage().unwrap().1 < used);
+        big.truncate(2000).unwrap();
+        drop(big);
+
+        let fs = mount(&image);
+        let data = read_all(&*resolve(&fs, "big.bin").unwrap());
+        assert_eq!(&data[..1000], &pattern(1000)[..]);
+        assert_eq!(&data[1000..], &[0; 1000][..]);
+
+        let hello = resolve(&fs, "HELLO.TXT").unwrap();
+        hello.truncate(0).unwrap();
+        assert_eq!(read_all(&*hello), b"");
+        hello.write_at(0, b"again").unwrap();
+        assert_eq!(read_all(&*hello), b"again");
+    }
+}
+
+#[test]
+fn renames_across_directories() {
+    for (image, fs) in images() {
+        let root = fs.root().unwrap();
+        let nested = resolve(&fs, "dir/nested").unwrap();
+        let hello = root.lookup("HELLO.TXT").unwrap();
+        root.rename("HELLO.TXT", &nested, "Hello again.txt")
+            .unwrap();
+        assert_eq!(root.lookup("HELLO.TXT").err(), Some(VfsError::NotFound));
+        // The open file follows its entry
+        hello.write_at(16, b"Moved.\n").unwrap();
+
+        // Over an existing file, then a case change
+        nested
+            .rename("deep.txt", &nested, "Hello again.txt")
+            .unwrap();
+        nested
+            .rename("Hello again.txt", &nested, "HELLO AGAIN.TXT")
+            .unwrap();
+        root.rename("dir", &root, "Directory").unwrap();
+        drop((hello, nested, root));
+
+        let fs = mount(&image);
+        let nested = resolve(&fs, "Directory/nested").unwrap();
+        assert_eq!(names(&*nested), ["HELLO AGAIN.TXT"]);
+        assert_eq!(
+            read_all(&*nested.lookup("hello again.txt").unwrap()),
+            b"Deep inside.\n"
+        );
+    }
+}
+
+#[test]
+fn refuses_invalid_names() {
+    for (_, fs) in images() {
+        let root = fs.root().unwrap();
+        for name in ["a:b", "what?", "trailing.", "tab\t"] {
+            assert_eq!(
+                root.create(name, FileType::Regular, 0o644).err(),
+                Some(VfsError::InvalidArgument),
+                "{}",
+                name
+            );
+        }
+        let long = "x".repeat(256);
+        assert_eq!(
+            root.create(&long, FileType::Regular, 0o644).err(),
+            Some(VfsError::NameTooLong)
+        );
+        assert_eq!(
+            root.create("link", FileType::Symlink, 0o777).err(),
+            Some(VfsError::NotSupported)
+        );
+    }
+}
@@ -0,0 +1,24 @@
+#!/bin/sh
+# Regenerates the FAT images of the tests with mkfs.fat from dosfstools and mcopy from mtools.
+set -e
+cd "$(dirname "$0")"
+
+tree=$(mktemp -d)
+trap 'rm -rf "$tree"' EXIT
+printf 'Hello from FAT!\n' > "$tree/HELLO.TXT"
+printf 'A file with a long name.\n' > "$tree/Long File Name.txt"
+mkdir -p "$tree/dir/nested"
+printf 'Deep inside.\n' > "$tree/dir/nested/deep.txt"
+# 100000 bytes counting modulo 251, over many clusters
+python3 -c 'import sys; sys.stdout.buffer.write(bytes(i % 251 for i in range(100000)))' > "$tree/big.bin"
+
+# image <file> <FAT bits> <label> <KiB>
+image() {
+    rm -f "$1"
+    mkfs.fat -C -F "$2" -s 1 -n "$3" -i 4B45524E "$1" "$4"
+    mcopy -s -i "$1" "$tree"/* ::/
+}
+
+image fat12.img 12 FAT12 1024
+image fat16.img 16 FAT16 4096
+image fat32.img 32 FAT32 33792
@@ -0,0 +1,250 @@
+#![no_std]
+
+use core::ptr;
+
+use bit_field::BitField;
+use bitflags::bitflags;
+use x86::addr::VirtAddr;
+
+pub const MMIO_SIZE: u64 = 0x400;
+/// Longest period of the main counter the specification allows, 100 ns in femtoseconds.
+pub const MAX_PERIOD: u64 = 0x05F5_E100;
+
+const CAPABILITIES: u64 = 0x000;
+const CONFIGURATION: u64 = 0x010;
+const INTERRUPT_STATUS: u64 = 0x020;
+const MAIN_COUNTER: u64 = 0x0F0;
+const TIMER_BASE: u64 = 0x100;
+const TIMER_STRIDE: u64 = 0x20;
+
+const FEMTOSECONDS_PER_SECOND: u64 = 1_000_000_000_000_000;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct ConfigurationFlags: u64 {
+        const ENABLE = 1;
+        const LEGACY_REPLACEMENT = 1 << 1;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct TimerFlags: u64 {
+        const LEVEL_TRIGGERED = 1 << 1;
+        const INTERRUPT_ENABLE = 1 << 2;
+        const PERIODIC = 1 << 3;
+        const PERIODIC_CAPABLE = 1 << 4;
+        const SIZE_64 = 1 << 5;
+        const VALUE_SET = 1 << 6;
+        const MODE_32 = 1 << 8;
+        const FSB_ENABLE = 1 << 14;
+        const FSB_CAPABLE = 1 << 15;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities(u64);
+
+impl Capabilities {
+    pub fn revision(&self) -> u8 {
+        self.0.get_bits(0..8) as u8
+    }
+
+    pub fn timer_count(&self) -> u8 {
+        self.0.get_bits(8..13) as u8 + 1
+    }
+
+    pub fn counter_64(&self) -> bool {
+        self.0.get_bit(13)
+    }
+
+    pub fn legacy_replacement(&self) -> bool {
+        self.0.get_bit(15)
+    }
+
+    pub fn vendor_id(&self) -> u16 {
+        self.0.get_bits(16..32) as u16
+    }
+
+    /// Period of the main counter in femtoseconds.
+    pub fn period(&self) -> u64 {
+        self.0.get_bits(32..64)
+    }
+}
+
+pub struct Hpet {
+    base: VirtAddr,
+}
+
+impl Hpet {
+    /// The HPET registers must be mapped as uncacheable memory at `base`.
+    pub unsafe fn new(base: VirtAddr) -> Self {
+        Self { base }
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities(self.read(CAPABILITIES))
+    }
+
+    pub fn period(&self) -> u64 {
+        self.capabilities().period()
+    }
+
+    pub fn frequency(&self) -> u64 {
+        FEMTOSECONDS_PER_SECOND / self.period()
+    }
+
+    pub fn configuration(&self) -> ConfigurationFlags {
+        ConfigurationFlags::from_bits_truncate(self.read(CONFIGURATION))
+    }
+
+    pub fn set_configuration(&mut self, flags: ConfigurationFlags) {
+        let reserved = self.read(CONFIGURATION) & !ConfigurationFlags::all().bits();
+        self.write(CONFIGURATION, reserved | flags.bits());
+    }
+
+    pub fn enable(&mut self) {
+        self.set_configuration(self.configuration() | ConfigurationFlags::ENABLE);
+    }
+
+    pub fn disable(&mut self) {
+        self.set_configuration(self.configuration() - ConfigurationFlags::ENABLE);
+    }
+
+    /// Routes timer 0 to IRQ0 and timer 1 to IRQ8, replacing the PIT and the RTC.
+    pub fn enable_legacy_replacement(&mut self) {
+        self.set_configuration(self.configuration() | ConfigurationFlags::LEGACY_REPLACEMENT);
+    }
+
+    pub fn main_counter(&self) -> u64 {
+        self.read(MAIN_COUNTER)
+    }
+
+    /// The main counter can only be written while the HPET is disabled.
+    pub fn set_main_counter(&mut self, value: u64) {
+        self.write(MAIN_COUNTER, value);
+    }
+
+    /// Converts a main counter value into nanoseconds.
+    pub fn ticks_to_nanos(&self, ticks: u64) -> u64 {
+        ((ticks as u128 * self.period() as u128) / 1_000_000) as u64
+    }
+
+    pub fn nanos_to_ticks(&self, nanos: u64) -> u64 {
+        ((nanos as u128 * 1_000_000) / self.period() as u128) as u64
+    }
+
+    pub fn interrupt_status(&self) -> u64 {
+        self.read(INTERRUPT_STATUS)
+    }
+
+    /// Clears the interrupt status of a level triggered timer.
+    pub fn acknowledge(&mut self, timer: u8) {
+        self.write(INTERRUPT_STATUS, 1 << timer);
+    }
+
+    pub fn timer(&mut self, index: u8) -> Timer<'_> {
+        assert!(
+            index < self.capabilities().timer_count(),
+            "HPET timer {} does not exist",
+            index
+        );
+        Timer { hpet: self, index }
+    }
+
+    fn read(&self, offset: u64) -> u64 {
+        unsafe { ptr::read_volatile((self.base + offset).as_ptr::<u64>()) }
+    }
+
+    fn write(&mut self, offset: u64, value: u64) {
+        unsafe { ptr::write_volatile((self.base + offset).as_mut_ptr::<u64>(), value) }
+    }
+}
+
+pub struct Timer<'a> {
+    hpet: &'a mut Hpet,
+    index: u8,
+}
+
+impl<'a> Timer<'a> {
+    fn configuration_offset(&self) -> u64 {
+        TIMER_BASE + self.index as u64 * TIMER_STRIDE
+    }
+
+    fn comparator_offset(&self) -> u64 {
+        self.configuration_offset() + 0x8
+    }
+
+    pub fn flags(&self) -> TimerFlags {
+        TimerFlags::from_bits_truncate(self.hpet.read(self.configuration_offset()))
+    }
+
+    fn set_flags(&mut self, flags: TimerFlags) {
+        let offset = self.configuration_offset();
+        let value = self.hpet.read(offset);
+        // The capability bits are read-only, writing them back is harmless
+        let value = (value & !TimerFlags::all().bits()) | flags.bits();
+        self.hpet.write(offset, value);
+    }
+
+    /// Bitmap of the I/O APIC inputs this timer can be routed to.
+    pub fn route_capabilities(&self) -> u32 {
+        self.hpet.read(self.configuration_offset()).get_bits(32..64) as u32
+    }
+
+    pub fn route(&self) -> u8 {
+        self.hpet.read(self.configuration_offset()).get_bits(9..14) as u8
+    }
+
+    pub fn set_route(&mut self, irq: u8) {
+        let offset = self.configuration_offset();
+        let mut value = self.hpet.read(offset);
+        value.set_bits(9..14, irq as u64);
+        self.hpet.write(offset, value);
+    }
+
+    pub fn supports_periodic(&self) -> bool {
+        self.flags().contains(TimerFlags::PERIODIC_CAPABLE)
+    }
+
+    pub fn comparator(&self) -> u64 {
+        self.hpet.read(self.comparator_offset())
+    }
+
+    /// Fires a single interrupt once the main counter reaches `now + ticks`.
+    pub fn set_one_shot(&mut self, ticks: u64) {
+        let flags = (self.flags() - TimerFlags::PERIODIC - TimerFlags::MODE_32)
+            | TimerFlags::INTERRUPT_ENABLE;
+        self.set_flags(flags);
+
+        let deadline = self.hpet.main_counter().wrapping_add(ticks);
+        let offset = self.comparator_offset();
+        self.hpet.write(offset, deadline);
+    }
+
+    /// Fires an interrupt every `ticks` main counter increments.
+    pub fn set_periodic(&mut self, ticks: u64) {
+        assert!(
+            self.supports_periodic(),
+            "HPET timer {} does not support periodic mode",
+            self.index
+        );
+
+        let flags = (self.flags() - TimerFlags::MODE_32)
+            | TimerFlags::INTERRUPT_ENABLE
+            | TimerFlags::PERIODIC
+            | TimerFlags::VALUE_SET;
+        self.set_flags(flags);
+
+        // With VALUE_SET, the first write sets the comparator and the second one the accumulator
+        let offset = self.comparator_offset();
+        let first = self.hpet.main_counter().wrapping_add(ticks);
+        self.hpet.write(offset, first);
+        self.hpet.write(offset, ticks);
+    }
+
+    pub fn stop(&mut self) {
+        let flags = self.flags() - TimerFlags::INTERRUPT_ENABLE - TimerFlags::PERIODIC;
+        self.set_flags(flags);
+    }
+}
@@ -0,0 +1,76 @@
+//! Packs the `initramfs/` directory at the root of the repository into a cpio archive of the
+//! "newc" format, embedded in the kernel and unpacked into the root file system at boot.
+//!
+//! Also copies the programs of `programs/` that need more than the GNU tools, empty when
+//! `programs/build.sh` didn't build them.
+
+use std::{
+    env, fs,
+    io::{self, Write},
+    os::unix::fs::PermissionsExt,
+    path::Path,
+};
+
+use cpio::{Writer, S_IFDIR, S_IFLNK, S_IFREG};
+
+/// Built with musl and with the userland runtime.
+const OPTIONAL_PROGRAMS: &[&str] = &["musl-hello", "userland-hello"];
+
+fn main() -> io::Result<()> {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = env::var("OUT_DIR").unwrap();
+
+    for name in OPTIONAL_PROGRAMS {
+        let path = Path::new(&manifest_dir).join("../../programs").join(name);
+        println!("cargo:rerun-if-changed={}", path.display());
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        fs::write(Path::new(&out_dir).join(name), bytes)?;
+    }
+
+    let root = Path::new(&manifest_dir).join("../../initramfs");
+    println!("cargo:rerun-if-changed={}", root.display());
+
+    let mut archive = Writer::new();
+    if root.is_dir() {
+        add_dir(&mut archive, &root, "")?;
+    }
+
+    let out = Path::new(&out_dir).join("initramfs.cpio");
+    fs::File::create(out)?.write_all(&archive.finish())
+}
+
+/// Adds the entries of the directory `dir`, named `prefix` in the archive, sorted by name so that
+/// the archive only changes with the files.
+fn add_dir(archive: &mut Writer, dir: &Path, prefix: &str) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = entry
+            .file_name()
+            .into_string()
+            .expect("non UTF-8 initramfs path");
+        let name = match prefix {
+            "" => name,
+            prefix => format!("{}/{}", prefix, name),
+        };
+        let metadata = fs::symlink_metadata(entry.path())?;
+        let permissions = metadata.permissions().mode() & 0o7777;
+
+        if metadata.is_dir() {
+            archive.add(&name, S_IFDIR | permissions, &[]);
+            add_dir(archive, &entry.path(), &name)?;
+        } else if metadata.file_type().is_symlink() {
+            let target = fs::read_link(entry.path())?;
+            let target = target.to_str().expect("non UTF-8 initramfs symlink");
+            archive.add(&name, S_IFLNK | 0o777, target.as_bytes());
+        } else if metadata.is_file() {
+            archive.add(&name, S_IFREG | permissions, &fs::read(entry.path())?);
+        }
+    }
+    Ok(())
+}
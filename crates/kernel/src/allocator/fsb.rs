@@ -3,6 +3,8 @@ use core::{
     ptr::{self, NonNull},
 };
 
+use x86::instructions::interrupts;
+
 use super::Locked;
 
 const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
@@ -37,8 +39,19 @@ impl FixedSizeBlockAllocator {
     }
 }
 
+// Interrupts are disabled while the allocator is locked so that interrupt handlers can allocate
 unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        interrupts::without_interrupts(|| self.alloc_block(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        interrupts::without_interrupts(|| self.dealloc_block(ptr, layout))
+    }
+}
+
+impl Locked<FixedSizeBlockAllocator> {
+    fn alloc_block(&self, layout: Layout) -> *mut u8 {
         let mut allocator = self.lock();
         match list_index(&layout) {
             Some(index) => match allocator.list_heads[index].take() {
@@ -57,7 +70,7 @@ unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
         }
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    unsafe fn dealloc_block(&self, ptr: *mut u8, layout: Layout) {
         let mut allocator = self.lock();
         match list_index(&layout) {
             Some(index) => {
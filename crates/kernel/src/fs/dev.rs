@@ -0,0 +1,129 @@
+//! Device file system, the nodes of the devices of the kernel.
+
+use core::fmt::Write;
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+};
+use vfs::{DirEntry, FileSystem, FileType, Inode, Metadata, Result, VfsError};
+
+use crate::tty::TTY;
+
+const ROOT_INODE: u64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Device {
+    /// Reads nothing, swallows what is written.
+    Null,
+    /// Reads zeros, swallows what is written.
+    Zero,
+    /// The terminal, under its two names.
+    Tty,
+    Console,
+}
+
+impl Device {
+    const ALL: [Device; 4] = [Device::Null, Device::Zero, Device::Tty, Device::Console];
+
+    fn name(self) -> &'static str {
+        match self {
+            Device::Null => "null",
+            Device::Zero => "zero",
+            Device::Tty => "tty",
+            Device::Console => "console",
+        }
+    }
+
+    fn inode(self) -> u64 {
+        ROOT_INODE + 1 + self as u64
+    }
+
+    /// Major and minor numbers of Linux, packed like its old 16 bits `dev_t`.
+    fn number(self) -> u64 {
+        let (major, minor) = match self {
+            Device::Null => (1, 3),
+            Device::Zero => (1, 5),
+            Device::Tty => (5, 0),
+            Device::Console => (5, 1),
+        };
+        (major << 8) | minor
+    }
+}
+
+impl Inode for Device {
+    fn metadata(&self) -> Result<Metadata> {
+        let mut metadata = Metadata::new(self.inode(), FileType::CharDevice, 0o666, 0);
+        metadata.device = self.number();
+        Ok(metadata)
+    }
+
+    fn read_at(&self, _offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        match self {
+            Device::Zero => {
+                buffer.fill(0);
+                Ok(buffer.len())
+            }
+            // There is no console input for user programs yet
+            Device::Null | Device::Tty | Device::Console => Ok(0),
+        }
+    }
+
+    fn write_at(&self, _offset: u64, buffer: &[u8]) -> Result<usize> {
+        if let Device::Tty | Device::Console = self {
+            let _ = TTY.lock().write_str(&String::from_utf8_lossy(buffer));
+        }
+        Ok(buffer.len())
+    }
+
+    fn truncate(&self, _size: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn is_terminal(&self) -> bool {
+        matches!(self, Device::Tty | Device::Console)
+    }
+}
+
+struct DevDir;
+
+impl Inode for DevDir {
+    fn metadata(&self) -> Result<Metadata> {
+        let mut metadata = Metadata::new(ROOT_INODE, FileType::Directory, 0o755, 0);
+        metadata.links = 2;
+        Ok(metadata)
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>> {
+        Device::ALL
+            .into_iter()
+            .find(|device| device.name() == name)
+            .map(|device| Arc::new(device) as Arc<dyn Inode>)
+            .ok_or(VfsError::NotFound)
+    }
+
+    fn read_dir(&self, index: usize) -> Result<Option<DirEntry>> {
+        Ok(Device::ALL.get(index).map(|device| DirEntry {
+            name: device.name().to_string(),
+            inode: device.inode(),
+            file_type: FileType::CharDevice,
+        }))
+    }
+
+    fn create(&self, _name: &str, _file_type: FileType, _mode: u16) -> Result<Arc<dyn Inode>> {
+        Err(VfsError::PermissionDenied)
+    }
+}
+
+/// The device nodes, usually mounted at `/dev`.
+pub struct DevFs;
+
+impl FileSystem for DevFs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn root(&self) -> Result<Arc<dyn Inode>> {
+        Ok(Arc::new(DevDir))
+    }
+}
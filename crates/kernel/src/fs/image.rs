@@ -0,0 +1,67 @@
+//! Disk images stored in files, used as block devices to mount the file systems in them.
+
+use alloc::sync::Arc;
+use vfs::{BlockDevice, Inode, Result, VfsError};
+
+use super::vfs;
+
+/// Block size of the images, the sector size of most disks.
+const BLOCK_SIZE: usize = 512;
+
+/// A file seen as a disk, its tail past the last whole block is ignored.
+pub struct ImageDevice {
+    inode: Arc<dyn Inode>,
+    blocks: u64,
+}
+
+impl ImageDevice {
+    /// Opens the image at `path`, relative to `cwd`.
+    pub fn open(cwd: &str, path: &str) -> Result<Self> {
+        let inode = vfs().resolve(cwd, path, true)?.inode;
+        let metadata = inode.metadata()?;
+        if metadata.is_dir() {
+            return Err(VfsError::IsDirectory);
+        }
+        Ok(Self {
+            inode,
+            blocks: metadata.size / BLOCK_SIZE as u64,
+        })
+    }
+}
+
+impl BlockDevice for ImageDevice {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.blocks
+    }
+
+    fn read_blocks(&self, block: u64, buffer: &mut [u8]) -> Result<()> {
+        let offset = block * BLOCK_SIZE as u64;
+        let mut done = 0;
+        while done < buffer.len() {
+            match self
+                .inode
+                .read_at(offset + done as u64, &mut buffer[done..])?
+            {
+                0 => return Err(VfsError::Io),
+                count => done += count,
+            }
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&self, block: u64, buffer: &[u8]) -> Result<()> {
+        let offset = block * BLOCK_SIZE as u64;
+        let mut done = 0;
+        while done < buffer.len() {
+            match self.inode.write_at(offset + done as u64, &buffer[done..])? {
+                0 => return Err(VfsError::Io),
+                count => done += count,
+            }
+        }
+        Ok(())
+    }
+}
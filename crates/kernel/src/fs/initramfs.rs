@@ -0,0 +1,76 @@
+//! Initial files of the root file system, a cpio archive of the "newc" format packed from the
+//! `initramfs/` directory of the repository by the build script of the kernel.
+
+use alloc::{format, string::String};
+use core::str;
+
+use cpio::{CpioError, Entries, S_IFDIR, S_IFLNK, S_IFMT, S_IFREG};
+use vfs::{File, OpenFlags, VfsError};
+
+use super::vfs;
+
+/// The archive embedded in the kernel image.
+pub static ARCHIVE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/initramfs.cpio"));
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InitramfsError {
+    Archive(CpioError),
+    /// An entry couldn't be created in the VFS.
+    File {
+        path: String,
+        error: VfsError,
+    },
+}
+
+impl From<CpioError> for InitramfsError {
+    fn from(value: CpioError) -> Self {
+        InitramfsError::Archive(value)
+    }
+}
+
+/// Creates the directories, files and symlinks of `archive` under `/` and returns how many it
+/// created. Other entries, like device nodes, are skipped, and existing directories are kept.
+pub fn unpack(archive: &[u8]) -> Result<usize, InitramfsError> {
+    let mut count = 0;
+    for entry in Entries::new(archive) {
+        let entry = entry?;
+        let name = entry.name.trim_start_matches("./").trim_matches('/');
+        if name.is_empty() || name == "." {
+            continue;
+        }
+
+        let path = format!("/{}", name);
+        let permissions = (entry.mode & 0o7777) as u16;
+        let result = match entry.mode & S_IFMT {
+            S_IFDIR => match vfs().create_dir("/", &path, permissions) {
+                Err(VfsError::AlreadyExists) if is_dir(&path) => Ok(()),
+                result => result,
+            },
+            S_IFREG => write_file(&path, permissions, entry.data),
+            S_IFLNK => match str::from_utf8(entry.data) {
+                Ok(target) => vfs().symlink("/", target, &path),
+                Err(_) => return Err(CpioError::BadName.into()),
+            },
+            _ => continue,
+        };
+        result.map_err(|error| InitramfsError::File { path, error })?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn write_file(path: &str, permissions: u16, data: &[u8]) -> vfs::Result<()> {
+    let flags = OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE;
+    let file = vfs().open("/", path, flags, permissions)?;
+    let mut written = 0;
+    while written < data.len() {
+        written += file.write(&data[written..])?;
+    }
+    Ok(())
+}
+
+fn is_dir(path: &str) -> bool {
+    vfs()
+        .metadata("/", path, true)
+        .is_ok_and(|metadata| metadata.is_dir())
+}
@@ -0,0 +1,46 @@
+//! File systems, joined in a single tree by the VFS.
+
+pub mod dev;
+pub mod image;
+pub mod initramfs;
+pub mod p9;
+pub mod tmpfs;
+
+use alloc::sync::Arc;
+use vfs::{File, OpenFile, Vfs, VfsError};
+
+use crate::process::handle::{Handle, HandleError};
+
+static VFS: Vfs = Vfs::new();
+
+/// The mount table of the system, shared by every process.
+pub fn vfs() -> &'static Vfs {
+    &VFS
+}
+
+/// Mounts a tmpfs with `root_options` as the root, and the device nodes at `/dev`.
+pub fn init(root_options: &str) -> Result<(), VfsError> {
+    VFS.mount("/", Arc::new(tmpfs::TmpFs::with_options(root_options)?))?;
+    VFS.create_dir("/", "/dev", 0o755)?;
+    VFS.mount("/dev", Arc::new(dev::DevFs))?;
+    VFS.create_dir("/", "/tmp", 0o1777)?;
+    Ok(())
+}
+
+impl Handle for OpenFile {
+    fn read(&self, buffer: &mut [u8]) -> Result<usize, HandleError> {
+        Ok(File::read(self, buffer)?)
+    }
+
+    fn write(&self, buffer: &[u8]) -> Result<usize, HandleError> {
+        Ok(File::write(self, buffer)?)
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.inode().is_terminal()
+    }
+
+    fn as_file(&self) -> Option<&OpenFile> {
+        Some(self)
+    }
+}
@@ -0,0 +1,310 @@
+//! 9P2000.L client, the Linux dialect of the protocol, one request at a time over the virtio
+//! transport.
+
+use alloc::{string::String, vec, vec::Vec};
+use core::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+use p9::{message, Reader, Writer, HEADER_SIZE, NOFID, NOTAG, VERSION};
+use spin::{Mutex, MutexGuard};
+use vfs::{Result, VfsError};
+use virtio::p9::{Virtio9p, MAX_MESSAGE_SIZE};
+
+use crate::{memory::Dma, thread, thread::WaitQueue, time};
+
+pub use p9::Qid;
+
+/// Tag of the requests, never more than one in flight.
+const TAG: u16 = 0;
+/// Header of a read or write: the message header, the fid, the offset and the count.
+const IO_HEADER_SIZE: usize = HEADER_SIZE + 4 + 8 + 4;
+/// Longest wait for a response, the share is given up after it.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Flags of `lopen` and `lcreate`, those of Linux.
+pub mod open {
+    pub const RDONLY: u32 = 0;
+    pub const RDWR: u32 = 2;
+    pub const CREAT: u32 = 0o100;
+    pub const EXCL: u32 = 0o200;
+    pub const DIRECTORY: u32 = 0o200000;
+}
+
+/// `unlinkat` flag removing a directory.
+pub const AT_REMOVEDIR: u32 = 0x200;
+
+/// Fields `getattr` asks for: mode, links, owner, rdev, times, size and blocks.
+const GETATTR_BASIC: u64 = 0x7ff;
+/// `setattr` field setting the size.
+const SETATTR_SIZE: u32 = 0x8;
+
+/// What `getattr` returns, times as seconds and nanoseconds.
+#[derive(Debug, Clone)]
+pub struct Attributes {
+    pub qid: Qid,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub links: u64,
+    pub rdev: u64,
+    pub size: u64,
+    pub accessed: (u64, u64),
+    pub modified: (u64, u64),
+    pub changed: (u64, u64),
+}
+
+/// An entry of `readdir`, `offset` being where the next one starts.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub qid: Qid,
+    pub offset: u64,
+    pub kind: u8,
+    pub name: String,
+}
+
+struct Connection {
+    device: Virtio9p<Dma>,
+    response: Vec<u8>,
+}
+
+pub struct Client {
+    /// Only taken with `try_lock`: its holder waits for the device, the other threads sleep in
+    /// `idle` meanwhile.
+    connection: Mutex<Connection>,
+    idle: WaitQueue,
+    /// Largest message agreed with the server.
+    msize: u32,
+    next_fid: AtomicU32,
+}
+
+impl Client {
+    /// Agrees on the version and the message size with the server, which must leave room for
+    /// file data in a read or write.
+    pub fn new(device: Virtio9p<Dma>) -> Result<Self> {
+        let client = Self {
+            connection: Mutex::new(Connection {
+                device,
+                response: vec![0; MAX_MESSAGE_SIZE],
+            }),
+            idle: WaitQueue::new(),
+            msize: MAX_MESSAGE_SIZE as u32,
+            next_fid: AtomicU32::new(0),
+        };
+
+        let mut request = Writer::new(message::TVERSION, NOTAG);
+        request.u32(client.msize).str(VERSION);
+        let response = client.rpc(&mut request)?;
+        let mut reader = Reader::new(&response);
+        let msize = reader.u32()?.min(client.msize);
+        if reader.str()? != VERSION || msize as usize <= IO_HEADER_SIZE {
+            return Err(VfsError::NotSupported);
+        }
+        Ok(Self { msize, ..client })
+    }
+
+    /// Sends `request` and returns the body of the response, after its header. The other threads
+    /// run while the device works, the request fails with an I/O error after `TIMEOUT`.
+    fn rpc(&self, request: &mut Writer) -> Result<Vec<u8>> {
+        let kind = request.kind();
+        let mut connection = self.connection();
+        let Connection { device, response } = &mut *connection;
+        let deadline = time::uptime() + TIMEOUT;
+        let result = device.request(request.finish(), response, || {
+            thread::yield_now();
+            time::uptime() < deadline
+        });
+        let result = match result {
+            Ok(len) => p9::response(&response[..len], kind).map(<[u8]>::to_vec),
+            Err(_) => Err(VfsError::Io),
+        };
+
+        drop(connection);
+        self.idle.notify_one();
+        result
+    }
+
+    /// Waits until no other request is in flight.
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        let mut connection = None;
+        self.idle.wait_until(|| {
+            connection = self.connection.try_lock();
+            connection.is_some()
+        });
+        connection.expect("the wait ended without the connection")
+    }
+
+    fn fid(&self) -> u32 {
+        self.next_fid.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Most bytes of file data in a read or write response.
+    fn io_size(&self) -> usize {
+        self.msize as usize - IO_HEADER_SIZE
+    }
+
+    /// Attaches to the exported tree as root, returns the fid of its root.
+    pub fn attach(&self) -> Result<(u32, Qid)> {
+        let fid = self.fid();
+        let mut request = Writer::new(message::TATTACH, TAG);
+        request.u32(fid).u32(NOFID).str("root").str("").u32(0);
+        let qid = Reader::new(&self.rpc(&mut request)?).qid()?;
+        Ok((fid, qid))
+    }
+
+    /// Walks from `fid` through `names`, returns the new fid and the qid of the last name, or of
+    /// `fid` itself when there are no names.
+    pub fn walk(&self, fid: u32, names: &[&str]) -> Result<(u32, Option<Qid>)> {
+        let new_fid = self.fid();
+        let mut request = Writer::new(message::TWALK, TAG);
+        request.u32(fid).u32(new_fid).u16(names.len() as u16);
+        for name in names {
+            request.str(name);
+        }
+        let response = self.rpc(&mut request)?;
+        let mut reader = Reader::new(&response);
+        let count = reader.u16()? as usize;
+        // The walk stops at the first missing name, and doesn't create the fid then
+        if count < names.len() {
+            return Err(VfsError::NotFound);
+        }
+        let mut qid = None;
+        for _ in 0..count {
+            qid = Some(reader.qid()?);
+        }
+        Ok((new_fid, qid))
+    }
+
+    /// Opens `fid` for I/O with the Linux `flags`.
+    pub fn lopen(&self, fid: u32, flags: u32) -> Result<Qid> {
+        let mut request = Writer::new(message::TLOPEN, TAG);
+        request.u32(fid).u32(flags);
+        Reader::new(&self.rpc(&mut request)?).qid()
+    }
+
+    /// Creates the file `name` in the directory `fid`, which then stands for the new file, open
+    /// with `flags`.
+    pub fn lcreate(&self, fid: u32, name: &str, flags: u32, mode: u32) -> Result<Qid> {
+        let mut request = Writer::new(message::TLCREATE, TAG);
+        request.u32(fid).str(name).u32(flags).u32(mode).u32(0);
+        Reader::new(&self.rpc(&mut request)?).qid()
+    }
+
+    pub fn read(&self, fid: u32, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let count = buffer.len().min(self.io_size());
+        let mut request = Writer::new(message::TREAD, TAG);
+        request.u32(fid).u64(offset).u32(count as u32);
+        let response = self.rpc(&mut request)?;
+        let mut reader = Reader::new(&response);
+        let len = (reader.u32()? as usize).min(count);
+        buffer[..len].copy_from_slice(reader.take(len)?);
+        Ok(len)
+    }
+
+    pub fn write(&self, fid: u32, offset: u64, data: &[u8]) -> Result<usize> {
+        let data = &data[..data.len().min(self.io_size())];
+        let mut request = Writer::new(message::TWRITE, TAG);
+        request
+            .u32(fid)
+            .u64(offset)
+            .u32(data.len() as u32)
+            .bytes(data);
+        let written = Reader::new(&self.rpc(&mut request)?).u32()?;
+        Ok(written as usize)
+    }
+
+    /// Entries of the open directory `fid` from `offset`, empty after the last one.
+    pub fn readdir(&self, fid: u32, offset: u64) -> Result<Vec<Entry>> {
+        let mut request = Writer::new(message::TREADDIR, TAG);
+        request.u32(fid).u64(offset).u32(self.io_size() as u32);
+        let response = self.rpc(&mut request)?;
+        let mut reader = Reader::new(&response);
+        let count = reader.u32()? as usize;
+        let mut reader = Reader::new(reader.take(count)?);
+
+        let mut entries = Vec::new();
+        while !reader.is_empty() {
+            entries.push(Entry {
+                qid: reader.qid()?,
+                offset: reader.u64()?,
+                kind: reader.u8()?,
+                name: reader.str()?,
+            });
+        }
+        Ok(entries)
+    }
+
+    pub fn getattr(&self, fid: u32) -> Result<Attributes> {
+        let mut request = Writer::new(message::TGETATTR, TAG);
+        request.u32(fid).u64(GETATTR_BASIC);
+        let response = self.rpc(&mut request)?;
+        let mut reader = Reader::new(&response);
+        let _valid = reader.u64()?;
+        let qid = reader.qid()?;
+        let mode = reader.u32()?;
+        let uid = reader.u32()?;
+        let gid = reader.u32()?;
+        let links = reader.u64()?;
+        let rdev = reader.u64()?;
+        let size = reader.u64()?;
+        let _block_size = reader.u64()?;
+        let _blocks = reader.u64()?;
+        Ok(Attributes {
+            qid,
+            mode,
+            uid,
+            gid,
+            links,
+            rdev,
+            size,
+            accessed: reader.time()?,
+            modified: reader.time()?,
+            changed: reader.time()?,
+        })
+    }
+
+    pub fn set_size(&self, fid: u32, size: u64) -> Result<()> {
+        let mut request = Writer::new(message::TSETATTR, TAG);
+        request.u32(fid).u32(SETATTR_SIZE).u32(0).u32(0).u32(0);
+        request.u64(size).u64(0).u64(0).u64(0).u64(0);
+        self.rpc(&mut request).map(|_| ())
+    }
+
+    pub fn mkdir(&self, fid: u32, name: &str, mode: u32) -> Result<Qid> {
+        let mut request = Writer::new(message::TMKDIR, TAG);
+        request.u32(fid).str(name).u32(mode).u32(0);
+        Reader::new(&self.rpc(&mut request)?).qid()
+    }
+
+    pub fn symlink(&self, fid: u32, name: &str, target: &str) -> Result<Qid> {
+        let mut request = Writer::new(message::TSYMLINK, TAG);
+        request.u32(fid).str(name).str(target).u32(0);
+        Reader::new(&self.rpc(&mut request)?).qid()
+    }
+
+    pub fn readlink(&self, fid: u32) -> Result<String> {
+        let mut request = Writer::new(message::TREADLINK, TAG);
+        request.u32(fid);
+        Reader::new(&self.rpc(&mut request)?).str()
+    }
+
+    pub fn renameat(&self, fid: u32, name: &str, new_fid: u32, new_name: &str) -> Result<()> {
+        let mut request = Writer::new(message::TRENAMEAT, TAG);
+        request.u32(fid).str(name).u32(new_fid).str(new_name);
+        self.rpc(&mut request).map(|_| ())
+    }
+
+    pub fn unlinkat(&self, fid: u32, name: &str, flags: u32) -> Result<()> {
+        let mut request = Writer::new(message::TUNLINKAT, TAG);
+        request.u32(fid).str(name).u32(flags);
+        self.rpc(&mut request).map(|_| ())
+    }
+
+    /// Forgets `fid`, the server closes what it had open.
+    pub fn clunk(&self, fid: u32) -> Result<()> {
+        let mut request = Writer::new(message::TCLUNK, TAG);
+        request.u32(fid);
+        self.rpc(&mut request).map(|_| ())
+    }
+}
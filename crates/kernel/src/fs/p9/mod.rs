@@ -0,0 +1,354 @@
+//! Shares of the host through virtio 9P, like the `host0` share QEMU exports with `-virtfs`.
+//!
+//! Every inode holds a fid walked to its file. Files get a second fid on their first read or
+//! write, opened for both when the host allows it.
+
+mod client;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::{any::Any, time::Duration};
+
+use pci::{
+    access::{CSpaceAccess, CSpaceAccessMethod, IoCSpaceAccessMethod},
+    structures::{device::Device, register::bar::BaseAddressRegister},
+};
+use spin::Mutex;
+use vfs::{DirEntry, FileSystem, FileType, Inode, Metadata, Result, VfsError};
+use virtio::{legacy::LegacyTransport, p9::Virtio9p};
+
+use self::client::{open, Attributes, Client, Qid, AT_REMOVEDIR};
+
+use super::vfs;
+
+/// PCI command register bits enabling the I/O ports and the DMA of a device.
+const COMMAND_IO_SPACE: u32 = 1;
+const COMMAND_BUS_MASTER: u32 = 1 << 2;
+
+#[derive(Debug)]
+pub enum MountError {
+    /// No virtio 9P device has the tag.
+    NoDevice,
+    Device(virtio::Error),
+    File(VfsError),
+}
+
+impl From<virtio::Error> for MountError {
+    fn from(value: virtio::Error) -> Self {
+        MountError::Device(value)
+    }
+}
+
+impl From<VfsError> for MountError {
+    fn from(value: VfsError) -> Self {
+        MountError::File(value)
+    }
+}
+
+/// Mounts the share of the virtio 9P device tagged `tag` at `path`, creating the directory if
+/// needed.
+pub fn mount(tag: &str, path: &str) -> core::result::Result<(), MountError> {
+    let device = find_device(tag)?;
+    let fs = P9Fs::new(device)?;
+    match vfs().create_dir("/", path, 0o755) {
+        Ok(()) | Err(VfsError::AlreadyExists) => {}
+        Err(err) => return Err(err.into()),
+    }
+    vfs().mount(path, Arc::new(fs))?;
+    Ok(())
+}
+
+fn find_device(tag: &str) -> core::result::Result<Virtio9p<crate::memory::Dma>, MountError> {
+    for device in pci::scan_buses(CSpaceAccessMethod::Io) {
+        let device = match device {
+            Device::General(device)
+                if device.common.vendor_id == virtio::PCI_VENDOR_ID
+                    && device.common.device_id == virtio::p9::PCI_DEVICE_ID =>
+            {
+                device
+            }
+            _ => continue,
+        };
+        let base = match device.bars[0] {
+            BaseAddressRegister::IoSpace(bar) => bar.address() as u16,
+            BaseAddressRegister::Memory(_) => continue,
+        };
+
+        let access = IoCSpaceAccessMethod::new(device.common.location.clone());
+        let command = access.read(0x4) & 0xFFFF;
+        // The status half is left zero, its bits are cleared by writing ones
+        access.write(0x4, command | COMMAND_IO_SPACE | COMMAND_BUS_MASTER);
+
+        let device = Virtio9p::new(unsafe { LegacyTransport::new(base) })?;
+        if device.tag() == tag {
+            return Ok(device);
+        }
+    }
+    Err(MountError::NoDevice)
+}
+
+/// Type of a file from the mode bits of Linux.
+fn file_type(mode: u32) -> FileType {
+    match mode & 0o170000 {
+        0o010000 => FileType::Fifo,
+        0o020000 => FileType::CharDevice,
+        0o040000 => FileType::Directory,
+        0o060000 => FileType::BlockDevice,
+        0o120000 => FileType::Symlink,
+        0o140000 => FileType::Socket,
+        _ => FileType::Regular,
+    }
+}
+
+/// Type of a directory entry from its Linux `d_type`.
+fn entry_type(kind: u8) -> FileType {
+    match kind {
+        1 => FileType::Fifo,
+        2 => FileType::CharDevice,
+        4 => FileType::Directory,
+        6 => FileType::BlockDevice,
+        10 => FileType::Symlink,
+        12 => FileType::Socket,
+        _ => FileType::Regular,
+    }
+}
+
+fn time((seconds, nanoseconds): (u64, u64)) -> Duration {
+    Duration::new(seconds, nanoseconds as u32)
+}
+
+struct P9Inode {
+    client: Arc<Client>,
+    fid: u32,
+    qid: Qid,
+    /// Fid open for reading and maybe writing, and whether it writes.
+    io: Mutex<Option<(u32, bool)>>,
+    /// Listing of a directory, read again when it is listed from the start.
+    entries: Mutex<Vec<DirEntry>>,
+}
+
+impl P9Inode {
+    fn new(client: Arc<Client>, fid: u32, qid: Qid) -> Arc<Self> {
+        Arc::new(Self {
+            client,
+            fid,
+            qid,
+            io: Mutex::new(None),
+            entries: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Walks to the child `name`.
+    fn child(&self, name: &str) -> Result<Arc<Self>> {
+        let (fid, qid) = self.client.walk(self.fid, &[name])?;
+        Ok(Self::new(self.client.clone(), fid, qid.unwrap_or(self.qid)))
+    }
+
+    /// The fid open for I/O, for writing too with `write`.
+    fn io_fid(&self, write: bool) -> Result<u32> {
+        let mut io = self.io.lock();
+        match *io {
+            Some((fid, writes)) if writes || !write => return Ok(fid),
+            _ => {}
+        }
+
+        let (fid, _) = self.client.walk(self.fid, &[])?;
+        let writes = match self.client.lopen(fid, open::RDWR) {
+            Ok(_) => true,
+            Err(VfsError::PermissionDenied | VfsError::ReadOnly) if !write => {
+                match self.client.lopen(fid, open::RDONLY) {
+                    Ok(_) => false,
+                    Err(err) => {
+                        let _ = self.client.clunk(fid);
+                        return Err(err);
+                    }
+                }
+            }
+            Err(err) => {
+                let _ = self.client.clunk(fid);
+                return Err(err);
+            }
+        };
+        if let Some((old, _)) = io.replace((fid, writes)) {
+            let _ = self.client.clunk(old);
+        }
+        Ok(fid)
+    }
+
+    /// Reads the whole listing of the directory, without `.` and `..`.
+    fn list(&self) -> Result<Vec<DirEntry>> {
+        let (fid, _) = self.client.walk(self.fid, &[])?;
+        let result = self.list_open(fid);
+        let _ = self.client.clunk(fid);
+        result
+    }
+
+    fn list_open(&self, fid: u32) -> Result<Vec<DirEntry>> {
+        self.client.lopen(fid, open::RDONLY | open::DIRECTORY)?;
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        loop {
+            let batch = self.client.readdir(fid, offset)?;
+            let last = match batch.last() {
+                Some(last) => last.offset,
+                None => return Ok(entries),
+            };
+            entries.extend(
+                batch
+                    .into_iter()
+                    .filter(|entry| entry.name != "." && entry.name != "..")
+                    .map(|entry| DirEntry {
+                        name: entry.name,
+                        inode: entry.qid.path,
+                        file_type: entry_type(entry.kind),
+                    }),
+            );
+            offset = last;
+        }
+    }
+}
+
+impl Drop for P9Inode {
+    fn drop(&mut self) {
+        if let Some((fid, _)) = self.io.get_mut().take() {
+            let _ = self.client.clunk(fid);
+        }
+        let _ = self.client.clunk(self.fid);
+    }
+}
+
+impl Inode for P9Inode {
+    fn metadata(&self) -> Result<Metadata> {
+        let Attributes {
+            qid,
+            mode,
+            uid,
+            gid,
+            links,
+            rdev,
+            size,
+            accessed,
+            modified,
+            changed,
+        } = self.client.getattr(self.fid)?;
+        let mut metadata = Metadata::new(qid.path, file_type(mode), (mode & 0o7777) as u16, size);
+        metadata.links = links as u32;
+        metadata.uid = uid;
+        metadata.gid = gid;
+        metadata.device = rdev;
+        metadata.accessed = time(accessed);
+        metadata.modified = time(modified);
+        metadata.changed = time(changed);
+        Ok(metadata)
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let fid = self.io_fid(false)?;
+        self.client.read(fid, offset, buffer)
+    }
+
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> Result<usize> {
+        let fid = self.io_fid(true)?;
+        let mut written = 0;
+        while written < buffer.len() {
+            let count = self
+                .client
+                .write(fid, offset + written as u64, &buffer[written..])?;
+            if count == 0 {
+                break;
+            }
+            written += count;
+        }
+        Ok(written)
+    }
+
+    fn truncate(&self, size: u64) -> Result<()> {
+        self.client.set_size(self.fid, size)
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>> {
+        Ok(self.child(name)?)
+    }
+
+    fn read_dir(&self, index: usize) -> Result<Option<DirEntry>> {
+        let mut entries = self.entries.lock();
+        if index == 0 {
+            *entries = self.list()?;
+        }
+        Ok(entries.get(index).cloned())
+    }
+
+    fn create(&self, name: &str, file_type: FileType, mode: u16) -> Result<Arc<dyn Inode>> {
+        match file_type {
+            FileType::Regular => {
+                // The fid of the directory walked to itself becomes the open file
+                let (fid, _) = self.client.walk(self.fid, &[])?;
+                let flags = open::RDWR | open::CREAT | open::EXCL;
+                if let Err(err) = self.client.lcreate(fid, name, flags, u32::from(mode)) {
+                    let _ = self.client.clunk(fid);
+                    return Err(err);
+                }
+                let child = self.child(name)?;
+                *child.io.lock() = Some((fid, true));
+                Ok(child)
+            }
+            FileType::Directory => {
+                self.client.mkdir(self.fid, name, u32::from(mode))?;
+                Ok(self.child(name)?)
+            }
+            _ => Err(VfsError::NotSupported),
+        }
+    }
+
+    fn symlink(&self, name: &str, target: &str) -> Result<Arc<dyn Inode>> {
+        self.client.symlink(self.fid, name, target)?;
+        Ok(self.child(name)?)
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        self.client.unlinkat(self.fid, name, 0)
+    }
+
+    fn rmdir(&self, name: &str) -> Result<()> {
+        self.client.unlinkat(self.fid, name, AT_REMOVEDIR)
+    }
+
+    fn rename(&self, name: &str, new_dir: &Arc<dyn Inode>, new_name: &str) -> Result<()> {
+        let new_dir: Arc<dyn Any + Send + Sync> = new_dir.clone();
+        let new_dir = new_dir
+            .downcast::<P9Inode>()
+            .map_err(|_| VfsError::CrossDevice)?;
+        if !Arc::ptr_eq(&self.client, &new_dir.client) {
+            return Err(VfsError::CrossDevice);
+        }
+        self.client.renameat(self.fid, name, new_dir.fid, new_name)
+    }
+
+    fn read_link(&self) -> Result<String> {
+        self.client.readlink(self.fid)
+    }
+}
+
+/// A 9P share mounted in the VFS.
+pub struct P9Fs {
+    root: Arc<P9Inode>,
+}
+
+impl P9Fs {
+    pub fn new(device: Virtio9p<crate::memory::Dma>) -> Result<Self> {
+        let client = Arc::new(Client::new(device)?);
+        let (fid, qid) = client.attach()?;
+        Ok(Self {
+            root: P9Inode::new(client, fid, qid),
+        })
+    }
+}
+
+impl FileSystem for P9Fs {
+    fn name(&self) -> &'static str {
+        "9p"
+    }
+
+    fn root(&self) -> Result<Arc<dyn Inode>> {
+        Ok(self.root.clone())
+    }
+}
@@ -0,0 +1,522 @@
+//! In-memory file system, the contents of its files kept in frames.
+
+use core::{
+    ptr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use spin::Mutex;
+use x86::{
+    addr::VirtAddr,
+    structures::paging::{
+        frame::PhysFrame,
+        frame_alloc::FrameDeallocator,
+        page::{PageSize, Size4KiB},
+    },
+};
+
+use vfs::{DirEntry, FileSystem, FileType, Inode, Metadata, Result, VfsError};
+
+use crate::{memory, time};
+
+const PAGE_SIZE: u64 = Size4KiB::SIZE;
+
+/// Size of a tmpfs mounted without the `size` option.
+pub const DEFAULT_SIZE: u64 = 16 * 1024 * 1024;
+
+/// What the inodes of one tmpfs share.
+///
+/// The operations changing the directories hold `tree` while they lock several inodes, the others
+/// only ever lock one, so the order of the inode locks doesn't matter.
+struct Shared {
+    tree: Mutex<()>,
+    /// Pages the files can hold together.
+    max_pages: u64,
+    used_pages: AtomicU64,
+    next_inode: AtomicU64,
+}
+
+impl Shared {
+    /// Counts `count` more pages against the size of the file system.
+    fn reserve(&self, count: u64) -> Result<()> {
+        self.used_pages
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(count)
+                    .filter(|&used| used <= self.max_pages)
+            })
+            .map(|_| ())
+            .map_err(|_| VfsError::NoSpace)
+    }
+
+    /// Largest file, holes included.
+    fn max_size(&self) -> u64 {
+        self.max_pages * PAGE_SIZE
+    }
+
+    fn release(&self, count: u64) {
+        self.used_pages.fetch_sub(count, Ordering::Relaxed);
+    }
+}
+
+enum Contents {
+    /// Frames of a regular file by page, the holes have none and read as zeros.
+    File(BTreeMap<u64, PhysFrame>),
+    Directory(BTreeMap<String, Arc<TmpInode>>),
+    Symlink(String),
+}
+
+struct Node {
+    metadata: Metadata,
+    contents: Contents,
+}
+
+impl Node {
+    fn entries(&mut self) -> Result<&mut BTreeMap<String, Arc<TmpInode>>> {
+        match &mut self.contents {
+            Contents::Directory(entries) => Ok(entries),
+            _ => Err(VfsError::NotDirectory),
+        }
+    }
+
+    fn touch(&mut self) {
+        let now = time::uptime();
+        self.metadata.modified = now;
+        self.metadata.changed = now;
+    }
+}
+
+struct TmpInode {
+    fs: Arc<Shared>,
+    inode: u64,
+    file_type: FileType,
+    node: Mutex<Node>,
+}
+
+impl TmpInode {
+    fn new(fs: &Arc<Shared>, file_type: FileType, mode: u16, contents: Contents) -> Arc<Self> {
+        let inode = fs.next_inode.fetch_add(1, Ordering::Relaxed);
+        let mut metadata = Metadata::new(inode, file_type, mode & 0o7777, 0);
+        let now = time::uptime();
+        metadata.accessed = now;
+        metadata.modified = now;
+        metadata.changed = now;
+        if file_type == FileType::Directory {
+            metadata.links = 2;
+        }
+        if let Contents::Symlink(target) = &contents {
+            metadata.size = target.len() as u64;
+        }
+
+        Arc::new(Self {
+            fs: fs.clone(),
+            inode,
+            file_type,
+            node: Mutex::new(Node { metadata, contents }),
+        })
+    }
+
+    /// Adds `child` as `name`, a directory linking back to `node` with its `..`.
+    fn link(node: &mut Node, name: &str, child: Arc<TmpInode>) -> Result<Arc<TmpInode>> {
+        let is_dir = child.is_dir();
+        let entries = node.entries()?;
+        if entries.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        entries.insert(String::from(name), child.clone());
+        if is_dir {
+            node.metadata.links += 1;
+        }
+        node.touch();
+        Ok(child)
+    }
+
+    /// Checks that `child` can take the place of `replaced` in a rename, with `tree` held.
+    fn check_replace(child: &TmpInode, replaced: &TmpInode) -> Result<()> {
+        let mut replaced = replaced.node.lock();
+        match (child.is_dir(), &replaced.contents) {
+            (true, Contents::Directory(entries)) if entries.is_empty() => Ok(()),
+            (true, Contents::Directory(_)) => Err(VfsError::NotEmpty),
+            (true, _) => Err(VfsError::NotDirectory),
+            (false, Contents::Directory(_)) => Err(VfsError::IsDirectory),
+            (false, _) => {
+                replaced.metadata.links -= 1;
+                Ok(())
+            }
+        }
+    }
+
+    fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+}
+
+impl Drop for TmpInode {
+    fn drop(&mut self) {
+        let node = self.node.get_mut();
+        if let Contents::File(pages) = &mut node.contents {
+            free_pages(&self.fs, core::mem::take(pages).into_values());
+        }
+    }
+}
+
+impl Inode for TmpInode {
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(self.node.lock().metadata.clone())
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let mut node = self.node.lock();
+        let size = node.metadata.size;
+        let pages = match &node.contents {
+            Contents::File(pages) => pages,
+            Contents::Directory(_) => return Err(VfsError::IsDirectory),
+            Contents::Symlink(_) => return Err(VfsError::InvalidArgument),
+        };
+        if offset >= size {
+            return Ok(0);
+        }
+
+        let len = buffer.len().min((size - offset) as usize);
+        let physical_memory_offset = physical_memory_offset()?;
+        let mut done = 0;
+        while done < len {
+            let position = offset + done as u64;
+            let in_page = (position % PAGE_SIZE) as usize;
+            let count = (len - done).min(PAGE_SIZE as usize - in_page);
+            let target = &mut buffer[done..done + count];
+            match pages.get(&(position / PAGE_SIZE)) {
+                Some(frame) => {
+                    let source = page_ptr(physical_memory_offset, *frame);
+                    unsafe {
+                        ptr::copy_nonoverlapping(source.add(in_page), target.as_mut_ptr(), count)
+                    };
+                }
+                None => target.fill(0),
+            }
+            done += count;
+        }
+
+        node.metadata.accessed = time::uptime();
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> Result<usize> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        let end = offset
+            .checked_add(buffer.len() as u64)
+            .filter(|&end| end <= self.fs.max_size())
+            .ok_or(VfsError::FileTooLarge)?;
+
+        let mut node = self.node.lock();
+        let pages = match &mut node.contents {
+            Contents::File(pages) => pages,
+            Contents::Directory(_) => return Err(VfsError::IsDirectory),
+            Contents::Symlink(_) => return Err(VfsError::InvalidArgument),
+        };
+
+        for index in offset / PAGE_SIZE..=(end - 1) / PAGE_SIZE {
+            if pages.contains_key(&index) {
+                continue;
+            }
+            self.fs.reserve(1)?;
+            let frame = match memory::with(|memory| memory.allocate_zeroed()).flatten() {
+                Some(frame) => frame,
+                None => {
+                    self.fs.release(1);
+                    return Err(VfsError::NoSpace);
+                }
+            };
+            pages.insert(index, frame);
+        }
+
+        let physical_memory_offset = physical_memory_offset()?;
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done as u64;
+            let in_page = (position % PAGE_SIZE) as usize;
+            let count = (buffer.len() - done).min(PAGE_SIZE as usize - in_page);
+            let frame = pages[&(position / PAGE_SIZE)];
+            let target = page_ptr(physical_memory_offset, frame);
+            unsafe {
+                ptr::copy_nonoverlapping(buffer[done..].as_ptr(), target.add(in_page), count)
+            };
+            done += count;
+        }
+
+        node.metadata.size = node.metadata.size.max(end);
+        node.touch();
+        Ok(buffer.len())
+    }
+
+    fn truncate(&self, size: u64) -> Result<()> {
+        if size > self.fs.max_size() {
+            return Err(VfsError::FileTooLarge);
+        }
+        let mut node = self.node.lock();
+        let pages = match &mut node.contents {
+            Contents::File(pages) => pages,
+            Contents::Directory(_) => return Err(VfsError::IsDirectory),
+            Contents::Symlink(_) => return Err(VfsError::InvalidArgument),
+        };
+
+        let removed = pages.split_off(&size.div_ceil(PAGE_SIZE));
+        free_pages(&self.fs, removed.into_values());
+        // The end of the last page must read as zeros if the file grows again
+        let in_page = (size % PAGE_SIZE) as usize;
+        if let (true, Some(frame)) = (in_page != 0, pages.get(&(size / PAGE_SIZE))) {
+            let page = page_ptr(physical_memory_offset()?, *frame);
+            unsafe {
+                page.add(in_page)
+                    .write_bytes(0, PAGE_SIZE as usize - in_page)
+            };
+        }
+
+        node.metadata.size = size;
+        node.touch();
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>> {
+        let mut node = self.node.lock();
+        let child = node.entries()?.get(name).cloned();
+        child
+            .map(|child| child as Arc<dyn Inode>)
+            .ok_or(VfsError::NotFound)
+    }
+
+    fn read_dir(&self, index: usize) -> Result<Option<DirEntry>> {
+        let mut node = self.node.lock();
+        let entry = node
+            .entries()?
+            .iter()
+            .nth(index)
+            .map(|(name, child)| DirEntry {
+                name: name.clone(),
+                inode: child.inode,
+                file_type: child.file_type,
+            });
+        Ok(entry)
+    }
+
+    fn create(&self, name: &str, file_type: FileType, mode: u16) -> Result<Arc<dyn Inode>> {
+        let contents = match file_type {
+            FileType::Regular => Contents::File(BTreeMap::new()),
+            FileType::Directory => Contents::Directory(BTreeMap::new()),
+            _ => return Err(VfsError::NotSupported),
+        };
+        let _tree = self.fs.tree.lock();
+        let mut node = self.node.lock();
+        node.entries()?;
+        let child = TmpInode::new(&self.fs, file_type, mode, contents);
+        Ok(TmpInode::link(&mut node, name, child)?)
+    }
+
+    fn symlink(&self, name: &str, target: &str) -> Result<Arc<dyn Inode>> {
+        let _tree = self.fs.tree.lock();
+        let mut node = self.node.lock();
+        node.entries()?;
+        let contents = Contents::Symlink(String::from(target));
+        let child = TmpInode::new(&self.fs, FileType::Symlink, 0o777, contents);
+        Ok(TmpInode::link(&mut node, name, child)?)
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        let _tree = self.fs.tree.lock();
+        let mut node = self.node.lock();
+        let entries = node.entries()?;
+        let child = entries.get(name).ok_or(VfsError::NotFound)?;
+        if child.is_dir() {
+            return Err(VfsError::IsDirectory);
+        }
+
+        // Open files keep the inode, and its pages, until they are closed
+        let child = entries.remove(name).unwrap();
+        node.touch();
+        drop(node);
+        child.node.lock().metadata.links -= 1;
+        Ok(())
+    }
+
+    fn rmdir(&self, name: &str) -> Result<()> {
+        let _tree = self.fs.tree.lock();
+        let mut node = self.node.lock();
+        let entries = node.entries()?;
+        let child = entries.get(name).ok_or(VfsError::NotFound)?;
+        match &child.node.lock().contents {
+            Contents::Directory(children) if !children.is_empty() => {
+                return Err(VfsError::NotEmpty)
+            }
+            Contents::Directory(_) => {}
+            _ => return Err(VfsError::NotDirectory),
+        }
+
+        let child = entries.remove(name).unwrap();
+        node.metadata.links -= 1;
+        node.touch();
+        drop(node);
+        child.node.lock().metadata.links = 0;
+        Ok(())
+    }
+
+    fn rename(&self, name: &str, new_dir: &Arc<dyn Inode>, new_name: &str) -> Result<()> {
+        let new_dir: Arc<dyn core::any::Any + Send + Sync> = new_dir.clone();
+        let new_dir = new_dir
+            .downcast::<TmpInode>()
+            .map_err(|_| VfsError::CrossDevice)?;
+        if !Arc::ptr_eq(&self.fs, &new_dir.fs) {
+            return Err(VfsError::CrossDevice);
+        }
+
+        let _tree = self.fs.tree.lock();
+        if ptr::eq(self, &*new_dir) {
+            let mut node = self.node.lock();
+            let entries = node.entries()?;
+            let child = entries.get(name).cloned().ok_or(VfsError::NotFound)?;
+            if let Some(replaced) = entries.get(new_name) {
+                if Arc::ptr_eq(replaced, &child) {
+                    return Ok(());
+                }
+                TmpInode::check_replace(&child, replaced)?;
+            }
+
+            entries.remove(name);
+            let replaced = entries.insert(String::from(new_name), child);
+            if replaced.as_ref().is_some_and(|replaced| replaced.is_dir()) {
+                node.metadata.links -= 1;
+            }
+            node.touch();
+            drop(node);
+            drop(replaced);
+            return Ok(());
+        }
+
+        let mut source = self.node.lock();
+        let mut target = new_dir.node.lock();
+        let child = source
+            .entries()?
+            .get(name)
+            .cloned()
+            .ok_or(VfsError::NotFound)?;
+        if let Some(replaced) = target.entries()?.get(new_name) {
+            if Arc::ptr_eq(replaced, &child) {
+                return Ok(());
+            }
+            TmpInode::check_replace(&child, replaced)?;
+        }
+
+        source.entries()?.remove(name);
+        if child.is_dir() {
+            source.metadata.links -= 1;
+            target.metadata.links += 1;
+        }
+        let replaced = target.entries()?.insert(String::from(new_name), child);
+        if replaced.as_ref().is_some_and(|replaced| replaced.is_dir()) {
+            target.metadata.links -= 1;
+        }
+        source.touch();
+        target.touch();
+        drop((source, target));
+        drop(replaced);
+        Ok(())
+    }
+
+    fn read_link(&self) -> Result<String> {
+        match &self.node.lock().contents {
+            Contents::Symlink(target) => Ok(target.clone()),
+            _ => Err(VfsError::InvalidArgument),
+        }
+    }
+}
+
+/// Gives the frames of `pages` back to the frame allocator.
+fn free_pages(fs: &Shared, pages: impl Iterator<Item = PhysFrame>) {
+    let frames: Vec<PhysFrame> = pages.collect();
+    if frames.is_empty() {
+        return;
+    }
+    fs.release(frames.len() as u64);
+    memory::with(|memory| {
+        for frame in frames {
+            unsafe { memory.frame_allocator.deallocate_frame(frame) };
+        }
+    });
+}
+
+fn physical_memory_offset() -> Result<VirtAddr> {
+    memory::with(|memory| memory.physical_memory_offset).ok_or(VfsError::Io)
+}
+
+fn page_ptr(physical_memory_offset: VirtAddr, frame: PhysFrame) -> *mut u8 {
+    (physical_memory_offset + frame.start_address().as_u64()).as_mut_ptr()
+}
+
+/// A file system in memory, lost at shutdown. Its files hold at most the `size` given at mount,
+/// in whole pages, the directories and names live on the kernel heap.
+pub struct TmpFs {
+    root: Arc<TmpInode>,
+}
+
+impl TmpFs {
+    pub fn new(size: u64) -> Self {
+        let shared = Arc::new(Shared {
+            tree: Mutex::new(()),
+            max_pages: size.div_ceil(PAGE_SIZE),
+            used_pages: AtomicU64::new(0),
+            next_inode: AtomicU64::new(1),
+        });
+        let root = TmpInode::new(
+            &shared,
+            FileType::Directory,
+            0o755,
+            Contents::Directory(BTreeMap::new()),
+        );
+        Self { root }
+    }
+
+    /// Parses mount options like `size=4m`, separated by commas. The size takes a `k`, `m` or
+    /// `g` suffix.
+    pub fn with_options(options: &str) -> Result<Self> {
+        let mut size = DEFAULT_SIZE;
+        for option in options.split(',').filter(|option| !option.is_empty()) {
+            match option.split_once('=') {
+                Some(("size", value)) => {
+                    size = parse_size(value).ok_or(VfsError::InvalidArgument)?
+                }
+                _ => return Err(VfsError::InvalidArgument),
+            }
+        }
+        Ok(Self::new(size))
+    }
+
+    /// Bytes the files can hold together, and the bytes they use.
+    pub fn usage(&self) -> (u64, u64) {
+        let fs = &self.root.fs;
+        (
+            fs.max_size(),
+            fs.used_pages.load(Ordering::Relaxed) * PAGE_SIZE,
+        )
+    }
+}
+
+fn parse_size(value: &str) -> Option<u64> {
+    let (digits, unit) = match value.char_indices().last()? {
+        (index, 'k' | 'K') => (&value[..index], 1 << 10),
+        (index, 'm' | 'M') => (&value[..index], 1 << 20),
+        (index, 'g' | 'G') => (&value[..index], 1 << 30),
+        _ => (value, 1),
+    };
+    digits.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+impl FileSystem for TmpFs {
+    fn name(&self) -> &'static str {
+        "tmpfs"
+    }
+
+    fn root(&self) -> Result<Arc<dyn Inode>> {
+        Ok(self.root.clone())
+    }
+}
@@ -1,19 +1,35 @@
+use apic::lapic::{self, LocalApic};
+use conquer_once::spin::OnceCell;
 use lazy_static::lazy_static;
 use pc_keyboard::{layouts, HandleControl, Keyboard, ScancodeSet1};
 use pic::pic8259::ChainedPics;
-use spin::Mutex;
-use x86::instructions::{interrupts, port::Port};
+use x86::{
+    instructions::{interrupts, port::Port},
+    structures::paging::{
+        frame_alloc::FrameAllocator,
+        mapper::{MapToError, Mapper},
+        page::Size4KiB,
+    },
+};
+
+use crate::{memory, smp, sync::IrqMutex, thread, time};
 
 pub const PIC_1_OFFSET: u8 = 32;
 pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
-pub static PICS: Mutex<ChainedPics> =
-    spin::Mutex::new(ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET));
+pub static PICS: IrqMutex<ChainedPics> =
+    IrqMutex::named("pics", ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET));
+
+pub static LAPIC: OnceCell<LocalApic> = OnceCell::uninit();
 
 #[repr(u8)]
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard,
+    // IRQ8 is the RTC line, used by the second HPET comparator in legacy replacement mode
+    Alarm = PIC_2_OFFSET,
+    LapicTimer = PIC_2_OFFSET + 8,
+    Spurious = 0xFF,
 }
 
 impl InterruptIndex {
@@ -31,7 +47,29 @@ pub fn init() {
     interrupts::enable();
 }
 
-pub extern "x86-interrupt" fn keyboard_interrupt_handler() {
+/// Maps and enables the local APIC of the bootstrap processor.
+pub fn init_lapic(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<&'static LocalApic, MapToError<Size4KiB>> {
+    if let Ok(lapic) = LAPIC.try_get() {
+        return Ok(lapic);
+    }
+
+    let base = memory::map_mmio(
+        lapic::base_address(),
+        lapic::MMIO_SIZE,
+        mapper,
+        frame_allocator,
+    )?;
+    let lapic = unsafe { LocalApic::new(base) };
+    lapic.enable(InterruptIndex::Spurious.as_u8());
+
+    Ok(LAPIC.get_or_init(|| lapic))
+}
+
+/// Called by the `trap` stub of the keyboard vector.
+pub(crate) fn keyboard_interrupt() {
     let mut port = Port::new(0x60);
     let scancode: u8 = unsafe { port.read() };
     crate::task::keyboard::add_scancode(scancode);
@@ -41,3 +79,39 @@ pub extern "x86-interrupt" fn keyboard_interrupt_handler() {
             .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8())
     };
 }
+
+/// Called by the `trap` stub of the PIT vector.
+pub(crate) fn timer_interrupt() {
+    time::tick();
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8())
+    };
+
+    thread::preempt();
+}
+
+/// Called by the `trap` stub of the RTC alarm vector.
+pub(crate) fn alarm_interrupt() {
+    time::ring_alarm();
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::Alarm.as_u8())
+    };
+}
+
+/// Called by the `trap` stub of the local APIC timer vector.
+pub(crate) fn lapic_timer_interrupt() {
+    // The APs only use their timer for preemption
+    if smp::cpu_index() == 0 {
+        time::tick();
+    }
+
+    if let Ok(lapic) = LAPIC.try_get() {
+        lapic.end_of_interrupt();
+    }
+
+    thread::preempt();
+}
@@ -0,0 +1,253 @@
+//! Message channels: two connected endpoints exchanging small messages, which can carry handles
+//! from one process to another.
+
+use core::{
+    future,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
+
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use futures_util::task::AtomicWaker;
+
+use crate::{
+    process::handle::{Handle, HandleError},
+    sync::IrqMutex,
+    thread::WaitQueue,
+};
+
+use super::block_on;
+
+/// Largest payload of a message.
+pub const MAX_MESSAGE_SIZE: usize = 1024;
+/// Most handles a message can carry.
+pub const MAX_MESSAGE_HANDLES: usize = 8;
+/// Messages an endpoint holds before its peer blocks sending.
+pub const MAX_QUEUED_MESSAGES: usize = 64;
+
+pub struct Message {
+    pub data: Vec<u8>,
+    pub handles: Vec<Arc<dyn Handle>>,
+}
+
+struct State {
+    messages: VecDeque<Message>,
+    /// One of the endpoints is gone.
+    closed: bool,
+}
+
+/// Messages sent to one of the endpoints.
+struct Mailbox {
+    state: IrqMutex<State>,
+    readable: WaitQueue,
+    writable: WaitQueue,
+    read_waker: AtomicWaker,
+    write_waker: AtomicWaker,
+}
+
+impl Mailbox {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            state: IrqMutex::named(
+                "channel",
+                State {
+                    messages: VecDeque::new(),
+                    closed: false,
+                },
+            ),
+            readable: WaitQueue::new(),
+            writable: WaitQueue::new(),
+            read_waker: AtomicWaker::new(),
+            write_waker: AtomicWaker::new(),
+        })
+    }
+
+    fn notify_readers(&self) {
+        self.readable.notify_all();
+        self.read_waker.wake();
+    }
+
+    fn notify_writers(&self) {
+        self.writable.notify_all();
+        self.write_waker.wake();
+    }
+}
+
+/// Creates a channel and returns its two endpoints.
+pub fn channel() -> (Endpoint, Endpoint) {
+    let (first, second) = (Mailbox::new(), Mailbox::new());
+    (
+        Endpoint::new(first.clone(), second.clone()),
+        Endpoint::new(second, first),
+    )
+}
+
+/// One side of a channel, receiving what the other side sends.
+///
+/// Once the peer is closed, sending fails with `BrokenPipe` and receiving does once the queued
+/// messages are read.
+pub struct Endpoint {
+    incoming: Arc<Mailbox>,
+    outgoing: Arc<Mailbox>,
+    nonblocking: AtomicBool,
+}
+
+impl Endpoint {
+    fn new(incoming: Arc<Mailbox>, outgoing: Arc<Mailbox>) -> Self {
+        Self {
+            incoming,
+            outgoing,
+            nonblocking: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether `other` is one of the endpoints of the same channel.
+    fn same_channel(&self, other: &Endpoint) -> bool {
+        Arc::ptr_eq(&self.incoming, &other.incoming) || Arc::ptr_eq(&self.incoming, &other.outgoing)
+    }
+
+    /// Queues `message` on the peer without waiting.
+    pub fn try_send(&self, message: Message) -> Result<(), HandleError> {
+        self.push(&mut Some(message))
+    }
+
+    /// Queues `message` on the peer, waiting for room unless the endpoint is non-blocking.
+    pub fn send(&self, message: Message) -> Result<(), HandleError> {
+        let mut message = Some(message);
+        if self.nonblocking.load(Ordering::Relaxed) {
+            return self.push(&mut message);
+        }
+        block_on(&self.outgoing.writable, || self.push(&mut message))
+    }
+
+    /// Takes the message out of `message` once it is queued.
+    fn push(&self, message: &mut Option<Message>) -> Result<(), HandleError> {
+        let pending = match message.as_ref() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        if pending.data.len() > MAX_MESSAGE_SIZE || pending.handles.len() > MAX_MESSAGE_HANDLES {
+            return Err(HandleError::MessageTooLarge);
+        }
+        // The message would keep the channel alive once both endpoints are closed
+        let loops = pending.handles.iter().any(|handle| {
+            handle
+                .as_channel()
+                .is_some_and(|endpoint| self.same_channel(endpoint))
+        });
+        if loops {
+            return Err(HandleError::CannotTransfer);
+        }
+
+        {
+            let mut state = self.outgoing.state.lock();
+            if state.closed {
+                return Err(HandleError::BrokenPipe);
+            }
+            if state.messages.len() >= MAX_QUEUED_MESSAGES {
+                return Err(HandleError::WouldBlock);
+            }
+            state.messages.extend(message.take());
+        }
+
+        self.outgoing.notify_readers();
+        Ok(())
+    }
+
+    /// Takes the next message without waiting, if its payload and handles fit in `max_data` and
+    /// `max_handles`. Otherwise it stays queued and `BufferTooSmall` is returned.
+    pub fn try_receive(&self, max_data: usize, max_handles: usize) -> Result<Message, HandleError> {
+        let message = {
+            let mut state = self.incoming.state.lock();
+            let next = match state.messages.front() {
+                Some(next) => next,
+                None if state.closed => return Err(HandleError::BrokenPipe),
+                None => return Err(HandleError::WouldBlock),
+            };
+            if next.data.len() > max_data || next.handles.len() > max_handles {
+                return Err(HandleError::BufferTooSmall);
+            }
+            state.messages.pop_front().unwrap()
+        };
+
+        self.incoming.notify_writers();
+        Ok(message)
+    }
+
+    /// Takes the next message, waiting for one unless the endpoint is non-blocking.
+    pub fn receive(&self, max_data: usize, max_handles: usize) -> Result<Message, HandleError> {
+        if self.nonblocking.load(Ordering::Relaxed) {
+            return self.try_receive(max_data, max_handles);
+        }
+        block_on(&self.incoming.readable, || {
+            self.try_receive(max_data, max_handles)
+        })
+    }
+
+    pub fn poll_receive(
+        &self,
+        cx: &mut Context,
+        max_data: usize,
+        max_handles: usize,
+    ) -> Poll<Result<Message, HandleError>> {
+        self.incoming.read_waker.register(cx.waker());
+        match self.try_receive(max_data, max_handles) {
+            Err(HandleError::WouldBlock) => Poll::Pending,
+            result => Poll::Ready(result),
+        }
+    }
+
+    pub async fn receive_async(
+        &self,
+        max_data: usize,
+        max_handles: usize,
+    ) -> Result<Message, HandleError> {
+        future::poll_fn(|cx| self.poll_receive(cx, max_data, max_handles)).await
+    }
+
+    /// Queues the message of `message` once there is room, taking it out.
+    pub fn poll_send(
+        &self,
+        cx: &mut Context,
+        message: &mut Option<Message>,
+    ) -> Poll<Result<(), HandleError>> {
+        self.outgoing.write_waker.register(cx.waker());
+        match self.push(message) {
+            Err(HandleError::WouldBlock) => Poll::Pending,
+            result => Poll::Ready(result),
+        }
+    }
+
+    pub async fn send_async(&self, message: Message) -> Result<(), HandleError> {
+        let mut message = Some(message);
+        future::poll_fn(|cx| self.poll_send(cx, &mut message)).await
+    }
+}
+
+impl Handle for Endpoint {
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), HandleError> {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn as_channel(&self) -> Option<&Endpoint> {
+        Some(self)
+    }
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        // The undelivered messages may hold the last references to other objects, they are
+        // dropped out of the lock
+        let messages = {
+            let mut state = self.incoming.state.lock();
+            state.closed = true;
+            core::mem::take(&mut state.messages)
+        };
+        drop(messages);
+        self.outgoing.state.lock().closed = true;
+
+        self.incoming.notify_writers();
+        self.outgoing.notify_readers();
+    }
+}
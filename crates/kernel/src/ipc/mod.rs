@@ -0,0 +1,34 @@
+//! Communication between processes: byte pipes, message channels and shared memory, all used
+//! through handles.
+//!
+//! Threads block on the wait queues of the objects, kernel tasks can poll them instead.
+
+pub mod channel;
+pub mod pipe;
+pub mod shared_memory;
+
+use crate::{
+    process::{handle::HandleError, signal},
+    thread::WaitQueue,
+};
+
+pub use channel::{channel, Endpoint, Message};
+pub use pipe::{pipe, PipeReader, PipeWriter};
+pub use shared_memory::SharedMemoryHandle;
+
+/// Retries `attempt` each time `queue` is notified while it would block, until a signal
+/// interrupts the wait.
+fn block_on<T>(
+    queue: &WaitQueue,
+    mut attempt: impl FnMut() -> Result<T, HandleError>,
+) -> Result<T, HandleError> {
+    let mut result = Err(HandleError::WouldBlock);
+    queue.wait_until(|| {
+        result = match attempt() {
+            Err(HandleError::WouldBlock) if signal::interrupted() => Err(HandleError::Interrupted),
+            result => result,
+        };
+        !matches!(result, Err(HandleError::WouldBlock))
+    });
+    result
+}
@@ -0,0 +1,243 @@
+//! Pipes: a bounded buffer of bytes written at one end and read at the other.
+
+use core::{
+    future,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
+
+use alloc::{collections::VecDeque, sync::Arc};
+use futures_util::task::AtomicWaker;
+
+use crate::{
+    process::{
+        self,
+        handle::{Handle, HandleError},
+        signal::{self, Signal},
+    },
+    sync::IrqMutex,
+    thread::WaitQueue,
+};
+
+use super::block_on;
+
+/// Bytes a pipe holds before its writer blocks.
+pub const PIPE_CAPACITY: usize = 4096;
+
+struct State {
+    buffer: VecDeque<u8>,
+    reader_open: bool,
+    writer_open: bool,
+}
+
+struct Pipe {
+    state: IrqMutex<State>,
+    /// Threads waiting for data, and for room in the buffer.
+    readable: WaitQueue,
+    writable: WaitQueue,
+    /// Task polling each end, one at a time.
+    read_waker: AtomicWaker,
+    write_waker: AtomicWaker,
+}
+
+impl Pipe {
+    fn notify_readers(&self) {
+        self.readable.notify_all();
+        self.read_waker.wake();
+    }
+
+    fn notify_writers(&self) {
+        self.writable.notify_all();
+        self.write_waker.wake();
+    }
+}
+
+/// Creates a pipe and returns its two ends.
+pub fn pipe() -> (PipeReader, PipeWriter) {
+    let pipe = Arc::new(Pipe {
+        state: IrqMutex::named(
+            "pipe",
+            State {
+                buffer: VecDeque::new(),
+                reader_open: true,
+                writer_open: true,
+            },
+        ),
+        readable: WaitQueue::new(),
+        writable: WaitQueue::new(),
+        read_waker: AtomicWaker::new(),
+        write_waker: AtomicWaker::new(),
+    });
+
+    let reader = PipeReader {
+        pipe: pipe.clone(),
+        nonblocking: AtomicBool::new(false),
+    };
+    let writer = PipeWriter {
+        pipe,
+        nonblocking: AtomicBool::new(false),
+    };
+    (reader, writer)
+}
+
+/// Read end of a pipe, reading 0 bytes once the writer is closed and the buffer empty.
+pub struct PipeReader {
+    pipe: Arc<Pipe>,
+    nonblocking: AtomicBool,
+}
+
+impl PipeReader {
+    /// Reads what is buffered without waiting.
+    pub fn try_read(&self, buffer: &mut [u8]) -> Result<usize, HandleError> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        let read = {
+            let mut state = self.pipe.state.lock();
+            if state.buffer.is_empty() {
+                return match state.writer_open {
+                    true => Err(HandleError::WouldBlock),
+                    false => Ok(0),
+                };
+            }
+
+            let read = buffer.len().min(state.buffer.len());
+            for (byte, value) in buffer.iter_mut().zip(state.buffer.drain(..read)) {
+                *byte = value;
+            }
+            read
+        };
+
+        self.pipe.notify_writers();
+        Ok(read)
+    }
+
+    pub fn poll_read(
+        &self,
+        cx: &mut Context,
+        buffer: &mut [u8],
+    ) -> Poll<Result<usize, HandleError>> {
+        self.pipe.read_waker.register(cx.waker());
+        match self.try_read(buffer) {
+            Err(HandleError::WouldBlock) => Poll::Pending,
+            result => Poll::Ready(result),
+        }
+    }
+
+    pub async fn read_async(&self, buffer: &mut [u8]) -> Result<usize, HandleError> {
+        future::poll_fn(|cx| self.poll_read(cx, buffer)).await
+    }
+}
+
+impl Handle for PipeReader {
+    fn read(&self, buffer: &mut [u8]) -> Result<usize, HandleError> {
+        if self.nonblocking.load(Ordering::Relaxed) {
+            return self.try_read(buffer);
+        }
+        block_on(&self.pipe.readable, || self.try_read(buffer))
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), HandleError> {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        let buffer = {
+            let mut state = self.pipe.state.lock();
+            state.reader_open = false;
+            core::mem::take(&mut state.buffer)
+        };
+        drop(buffer);
+        self.pipe.notify_writers();
+    }
+}
+
+/// Write end of a pipe. Writing once the reader is closed fails and sends SIGPIPE to the writing
+/// process.
+pub struct PipeWriter {
+    pipe: Arc<Pipe>,
+    nonblocking: AtomicBool,
+}
+
+impl PipeWriter {
+    /// Writes what fits in the buffer without waiting.
+    pub fn try_write(&self, buffer: &[u8]) -> Result<usize, HandleError> {
+        let written = {
+            let mut state = self.pipe.state.lock();
+            if !state.reader_open {
+                return Err(HandleError::BrokenPipe);
+            }
+            if buffer.is_empty() {
+                return Ok(0);
+            }
+
+            let written = buffer.len().min(PIPE_CAPACITY - state.buffer.len());
+            if written == 0 {
+                return Err(HandleError::WouldBlock);
+            }
+            state.buffer.extend(&buffer[..written]);
+            written
+        };
+
+        self.pipe.notify_readers();
+        Ok(written)
+    }
+
+    pub fn poll_write(&self, cx: &mut Context, buffer: &[u8]) -> Poll<Result<usize, HandleError>> {
+        self.pipe.write_waker.register(cx.waker());
+        match self.try_write(buffer) {
+            Err(HandleError::WouldBlock) => Poll::Pending,
+            result => Poll::Ready(result),
+        }
+    }
+
+    pub async fn write_async(&self, buffer: &[u8]) -> Result<usize, HandleError> {
+        future::poll_fn(|cx| self.poll_write(cx, buffer)).await
+    }
+
+    /// Writes all of `buffer`, waiting for room as needed. Returns what was written before an
+    /// interruption.
+    fn write_all(&self, buffer: &[u8]) -> Result<usize, HandleError> {
+        let mut written = 0;
+        while written < buffer.len() {
+            match block_on(&self.pipe.writable, || self.try_write(&buffer[written..])) {
+                Ok(count) => written += count,
+                Err(_) if written > 0 => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(written)
+    }
+}
+
+impl Handle for PipeWriter {
+    fn write(&self, buffer: &[u8]) -> Result<usize, HandleError> {
+        let result = match self.nonblocking.load(Ordering::Relaxed) {
+            true => self.try_write(buffer),
+            false => self.write_all(buffer),
+        };
+
+        if result == Err(HandleError::BrokenPipe) {
+            if let Some(pid) = process::current() {
+                let _ = signal::send(pid, Signal::PIPE);
+            }
+        }
+        result
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<(), HandleError> {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        self.pipe.state.lock().writer_open = false;
+        self.pipe.notify_readers();
+    }
+}
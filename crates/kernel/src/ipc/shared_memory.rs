@@ -0,0 +1,22 @@
+//! Handles to shared memory objects, which processes map to exchange data without copying it.
+
+use alloc::sync::Arc;
+
+use crate::{memory::SharedMemory, process::handle::Handle};
+
+/// A shared memory object held by a descriptor, it can be sent over channels to share it.
+pub struct SharedMemoryHandle {
+    object: Arc<SharedMemory>,
+}
+
+impl SharedMemoryHandle {
+    pub fn new(object: Arc<SharedMemory>) -> Self {
+        Self { object }
+    }
+}
+
+impl Handle for SharedMemoryHandle {
+    fn as_shared_memory(&self) -> Option<&Arc<SharedMemory>> {
+        Some(&self.object)
+    }
+}
@@ -3,99 +3,151 @@
 #![feature(const_mut_refs)]
 
 pub mod allocator;
+pub mod fs;
 pub mod interrupts;
+pub mod ipc;
+pub mod loader;
 pub mod memory;
+pub mod percpu;
+pub mod process;
+pub mod smp;
+pub mod sync;
+pub mod syscall;
 pub mod task;
+pub mod thread;
+pub mod time;
+pub mod trap;
 pub mod tty;
+pub mod user;
 
 extern crate alloc;
 
 use core::fmt::Write;
+use core::ptr;
 
-use alloc::{format, string::ToString};
-use interrupts::PICS;
+use alloc::{boxed::Box, format, string::ToString, vec};
 use lazy_static::lazy_static;
 use qemu::QemuExitCode;
 use tty::TTY;
-use vga::println;
 use x86::{
     dt::gdt::{Descriptor, GlobalDescriptorTable},
     dt::idt::InterruptDescriptorTable,
     instructions::{self, load_tss},
-    segmentation::{SegmentSelector, CS},
+    registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags},
+    segmentation::{SegmentSelector, CS, DS, ES, SS},
     tss::TaskStateSegment,
 };
 
-use crate::interrupts::{keyboard_interrupt_handler, InterruptIndex};
-
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+const DOUBLE_FAULT_STACK_SIZE: usize = 4096 * 5;
 
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::default();
-        idt.divide_by_zero.set_handler(divide_by_zero_handler);
-        idt.breakpoint.set_handler(breakpoint_handler);
-        idt.double_fault
-            .set_handler(double_fault_handler)
-            .set_stack_index(DOUBLE_FAULT_IST_INDEX);
-        idt[InterruptIndex::Timer.as_u8()].set_handler(timer_interrupt_handler);
-        idt[InterruptIndex::Keyboard.as_u8()].set_handler(keyboard_interrupt_handler);
+        trap::init_idt(&mut idt);
         idt
     };
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::default();
+    static ref GDT: (GlobalDescriptorTable, Selectors) = {
+        let tss = unsafe { &mut *ptr::addr_of_mut!(TSS) };
         tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+            static mut STACK: [u8; DOUBLE_FAULT_STACK_SIZE] = [0; DOUBLE_FAULT_STACK_SIZE];
 
             let stack_start = unsafe { (&STACK) as *const _ as u64 };
-            stack_start + STACK_SIZE as u64
+            stack_start + DOUBLE_FAULT_STACK_SIZE as u64
         };
 
-        tss
+        build_gdt(tss)
     };
-    static ref GDT: (GlobalDescriptorTable, SegmentSelector, SegmentSelector) = {
-        let mut gdt = GlobalDescriptorTable::default();
-        let code_selector = gdt.append(Descriptor::kernel_code_segment());
-        let tss_selector = gdt.append(Descriptor::tss_segment(&TSS));
-        (gdt, code_selector, tss_selector)
+}
+
+// Mutable as the kernel stack of the running thread is written to it on each switch
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
+
+/// Segment selectors, the same on every CPU.
+#[derive(Clone, Copy)]
+pub struct Selectors {
+    pub kernel_code: SegmentSelector,
+    pub kernel_data: SegmentSelector,
+    pub user_code: SegmentSelector,
+    pub user_data: SegmentSelector,
+    pub tss: SegmentSelector,
+}
+
+/// The descriptors are in the order expected by `syscall`/`sysret`: kernel data right after
+/// kernel code, and user data right before user code.
+fn build_gdt(tss: &'static TaskStateSegment) -> (GlobalDescriptorTable, Selectors) {
+    let mut gdt = GlobalDescriptorTable::default();
+    let selectors = Selectors {
+        kernel_code: gdt.append(Descriptor::kernel_code_segment()),
+        kernel_data: gdt.append(Descriptor::kernel_data_segment()),
+        user_data: gdt.append(Descriptor::user_data_segment()),
+        user_code: gdt.append(Descriptor::user_code_segment()),
+        tss: gdt.append(Descriptor::tss_segment(tss)),
     };
+    (gdt, selectors)
 }
 
-pub fn init() {
-    GDT.0.load();
+fn load_gdt(gdt: &'static GlobalDescriptorTable, selectors: &Selectors) {
+    gdt.load();
     unsafe {
-        CS::set_reg(GDT.1);
-        load_tss(GDT.2);
+        CS::set_reg(selectors.kernel_code);
+        SS::set_reg(selectors.kernel_data);
+        DS::set_reg(selectors.kernel_data);
+        ES::set_reg(selectors.kernel_data);
+        load_tss(selectors.tss);
     }
+}
+
+/// Lets user programs use SSE, the kernel itself is built without it and doesn't touch the
+/// registers, which the scheduler saves for each thread.
+fn enable_sse() {
+    unsafe {
+        Cr0::write((Cr0::read() - Cr0Flags::EMULATE_COPROCESSOR) | Cr0Flags::MONITOR_COPROCESSOR);
+        Cr4::write(Cr4::read() | Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT_ENABLE);
+    }
+}
 
+pub fn init() {
+    load_gdt(&GDT.0, &GDT.1);
     IDT.load();
+    enable_sse();
+    syscall::init();
     interrupts::init();
 }
 
-pub fn hlt_loop() -> ! {
-    loop {
-        instructions::hlt();
+/// Loads a GDT with its own TSS and the shared IDT on an application processor.
+pub(crate) fn init_ap() {
+    let stack = vec![0u8; DOUBLE_FAULT_STACK_SIZE].leak();
+    let mut tss = TaskStateSegment::default();
+    tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+        stack.as_ptr() as u64 + DOUBLE_FAULT_STACK_SIZE as u64;
+    let tss = Box::leak(Box::new(tss));
+    if let Some(percpu) = percpu::current() {
+        percpu.set_tss(tss);
     }
-}
 
-extern "x86-interrupt" fn divide_by_zero_handler() {
-    panic!("EXCEPTION: DIVIDE BY ZERO");
+    let (gdt, selectors) = build_gdt(tss);
+    let gdt: &'static GlobalDescriptorTable = Box::leak(Box::new(gdt));
+    load_gdt(gdt, &selectors);
+
+    IDT.load();
+    enable_sse();
+    syscall::init();
 }
 
-extern "x86-interrupt" fn breakpoint_handler() {
-    println!("EXCEPTION: BREAKPOINT");
+pub fn selectors() -> &'static Selectors {
+    &GDT.1
 }
 
-extern "x86-interrupt" fn double_fault_handler() {
-    panic!("EXCEPTION: DOUBLE FAULT");
+/// TSS of the BSP, loaded before the per-CPU blocks exist.
+pub(crate) fn bsp_tss() -> *mut TaskStateSegment {
+    ptr::addr_of_mut!(TSS)
 }
 
-extern "x86-interrupt" fn timer_interrupt_handler() {
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8())
-    };
+pub fn hlt_loop() -> ! {
+    loop {
+        instructions::hlt();
+    }
 }
 
 #[derive(Debug)]
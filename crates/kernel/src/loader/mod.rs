@@ -0,0 +1,248 @@
+//! Loads ELF64 executables into a new address space, `process::spawn` starts them in ring 3.
+
+pub mod programs;
+
+use core::arch::x86_64::_rdtsc;
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use elf::{header::ElfType, program::SegmentFlags, program::SegmentType, ElfError, ElfFile};
+use x86::{
+    addr::VirtAddr,
+    structures::paging::{
+        page::{Page, PageSize, Size4KiB},
+        page_table::PageTableFlags,
+    },
+};
+
+use crate::memory::{self, AddressSpace, MapError, Memory, USER_END};
+
+/// Top of the user stack, a page below the end of the user half is left unmapped.
+pub const USER_STACK_TOP: u64 = USER_END - Size4KiB::SIZE;
+pub const USER_STACK_SIZE: u64 = 64 * 1024;
+
+const PAGE_SIZE: u64 = Size4KiB::SIZE;
+
+// Auxiliary vector entries
+const AT_NULL: u64 = 0;
+const AT_PHDR: u64 = 3;
+const AT_PHENT: u64 = 4;
+const AT_PHNUM: u64 = 5;
+const AT_PAGESZ: u64 = 6;
+const AT_ENTRY: u64 = 9;
+const AT_RANDOM: u64 = 25;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Elf(ElfError),
+    NotExecutable,
+    InvalidSegment,
+    Map(MapError),
+    MemoryUnavailable,
+}
+
+impl From<ElfError> for LoadError {
+    fn from(value: ElfError) -> Self {
+        LoadError::Elf(value)
+    }
+}
+
+impl From<MapError> for LoadError {
+    fn from(value: MapError) -> Self {
+        LoadError::Map(value)
+    }
+}
+
+/// A program ready to be started.
+#[derive(Debug)]
+pub struct Program {
+    pub address_space: AddressSpace,
+    pub entry: VirtAddr,
+    pub stack_pointer: VirtAddr,
+    /// End of the highest segment rounded up to a page, where the heap grown by `brk` starts.
+    pub brk: VirtAddr,
+}
+
+/// Maps the `PT_LOAD` segments of `bytes` and a stack holding `args` and `env`.
+pub fn load(bytes: &[u8], args: &[&str], env: &[&str]) -> Result<Program, LoadError> {
+    let elf = ElfFile::parse(bytes)?;
+    if elf.header().elf_type() != ElfType::Executable {
+        return Err(LoadError::NotExecutable);
+    }
+
+    memory::with(|memory| {
+        let mut space = AddressSpace::new(memory)?;
+        let mapped = map_segments(&elf, &mut space, memory).and_then(|brk| {
+            let stack_pointer = map_stack(&elf, &mut space, memory, args, env)?;
+            Ok((brk, stack_pointer))
+        });
+        let (brk, stack_pointer) = match mapped {
+            Ok(mapped) => mapped,
+            Err(err) => {
+                // Without areas, nothing needs the memory lock released
+                drop(space.destroy(memory));
+                return Err(err);
+            }
+        };
+
+        Ok(Program {
+            address_space: space,
+            entry: VirtAddr::new(elf.entry()),
+            stack_pointer,
+            brk,
+        })
+    })
+    .ok_or(LoadError::MemoryUnavailable)?
+}
+
+/// Maps the segments and returns the end of the last mapped page.
+fn map_segments(
+    elf: &ElfFile,
+    space: &mut AddressSpace,
+    memory: &mut Memory,
+) -> Result<VirtAddr, LoadError> {
+    // Segments can share a page, which gets the permissions of both
+    let mut pages: BTreeMap<u64, SegmentFlags> = BTreeMap::new();
+    for program in elf.program_headers().flatten() {
+        if program.segment_type() != SegmentType::Load || program.memsz == 0 {
+            continue;
+        }
+
+        let end = program
+            .vaddr
+            .checked_add(program.memsz)
+            .filter(|&end| end <= USER_END)
+            .ok_or(LoadError::InvalidSegment)?;
+
+        let first = program.vaddr & !(PAGE_SIZE - 1);
+        for page in (first..end).step_by(PAGE_SIZE as usize) {
+            *pages.entry(page).or_insert(SegmentFlags::empty()) |= program.segment_flags();
+        }
+    }
+
+    for (&page, &flags) in &pages {
+        let mut table_flags = PageTableFlags::empty();
+        if flags.contains(SegmentFlags::WRITE) {
+            table_flags |= PageTableFlags::WRITABLE;
+        }
+        if !flags.contains(SegmentFlags::EXECUTE) {
+            table_flags |= PageTableFlags::NO_EXECUTE;
+        }
+        space.map(
+            memory,
+            Page::new_containing_address(VirtAddr::new(page)),
+            table_flags,
+        )?;
+    }
+
+    // The pages are zeroed, which covers the part of the segments past the file data
+    for program in elf.program_headers().flatten() {
+        if program.segment_type() != SegmentType::Load {
+            continue;
+        }
+        let data = elf
+            .segment_data(&program)
+            .ok_or(LoadError::InvalidSegment)?;
+        space.write(memory, VirtAddr::new(program.vaddr), data)?;
+    }
+
+    let end = pages.keys().next_back().map_or(0, |&page| page + PAGE_SIZE);
+    Ok(VirtAddr::new(end))
+}
+
+/// Maps the stack and lays out `argc`, `argv`, `envp` and the auxiliary vector at its top, as the
+/// System V ABI expects. Returns the initial stack pointer.
+fn map_stack(
+    elf: &ElfFile,
+    space: &mut AddressSpace,
+    memory: &mut Memory,
+    args: &[&str],
+    env: &[&str],
+) -> Result<VirtAddr, LoadError> {
+    let bottom = USER_STACK_TOP - USER_STACK_SIZE;
+    for page in (bottom..USER_STACK_TOP).step_by(PAGE_SIZE as usize) {
+        let page = Page::new_containing_address(VirtAddr::new(page));
+        space.map(
+            memory,
+            page,
+            PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE,
+        )?;
+    }
+
+    // The strings go at the top, the pointers to them below
+    let mut top = USER_STACK_TOP;
+    let mut push_bytes = |bytes: &[u8]| -> Result<u64, LoadError> {
+        top -= bytes.len() as u64;
+        space.write(memory, VirtAddr::new(top), bytes)?;
+        Ok(top)
+    };
+
+    let mut push_str = |s: &str| -> Result<u64, LoadError> {
+        push_bytes(&[0])?;
+        push_bytes(s.as_bytes())
+    };
+    let argv = args
+        .iter()
+        .map(|arg| push_str(arg))
+        .collect::<Result<Vec<_>, _>>()?;
+    let envp = env
+        .iter()
+        .map(|var| push_str(var))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Seed for the stack protector of libc, not meant to be unpredictable
+    let seed = unsafe { _rdtsc() }.to_le_bytes();
+    let mut random = [0u8; 16];
+    random[..8].copy_from_slice(&seed);
+    random[8..].copy_from_slice(&seed.map(|byte| byte.rotate_left(3)));
+    let random = push_bytes(&random)?;
+
+    let header = elf.header();
+    let auxv = [
+        (AT_PHDR, program_headers_address(elf).unwrap_or(0)),
+        (AT_PHENT, u64::from(header.phentsize)),
+        (AT_PHNUM, u64::from(header.phnum)),
+        (AT_PAGESZ, PAGE_SIZE),
+        (AT_ENTRY, elf.entry()),
+        (AT_RANDOM, random),
+        (AT_NULL, 0),
+    ];
+
+    let mut words = Vec::new();
+    words.push(args.len() as u64);
+    words.extend(&argv);
+    words.push(0);
+    words.extend(&envp);
+    words.push(0);
+    for (key, value) in auxv {
+        words.extend([key, value]);
+    }
+
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let stack_pointer = (top - bytes.len() as u64) & !0xF;
+    if stack_pointer < bottom {
+        return Err(LoadError::InvalidSegment);
+    }
+    space.write(memory, VirtAddr::new(stack_pointer), &bytes)?;
+
+    Ok(VirtAddr::new(stack_pointer))
+}
+
+/// Address of the program headers once loaded, for `AT_PHDR`.
+fn program_headers_address(elf: &ElfFile) -> Option<u64> {
+    let phoff = elf.header().phoff;
+    if let Some(phdr) = elf
+        .program_headers()
+        .flatten()
+        .find(|program| program.segment_type() == SegmentType::ProgramHeader)
+    {
+        return Some(phdr.vaddr);
+    }
+
+    elf.program_headers()
+        .flatten()
+        .find(|program| {
+            program.segment_type() == SegmentType::Load
+                && (program.offset..program.offset + program.filesz).contains(&phoff)
+        })
+        .map(|program| program.vaddr + phoff - program.offset)
+}
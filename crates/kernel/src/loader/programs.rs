@@ -0,0 +1,46 @@
+use crate::process::Personality;
+
+/// A program embedded in the kernel image.
+pub struct Embedded {
+    pub name: &'static str,
+    pub bytes: &'static [u8],
+    /// Nothing in the ELF file tells a Linux program apart, so the table says it.
+    pub personality: Personality,
+}
+
+/// Programs embedded in the kernel image, built from the sources in `programs/` by
+/// `programs/build.sh`. The ones the build script copies are empty when they weren't built.
+static PROGRAMS: &[Embedded] = &[
+    Embedded {
+        name: "hello",
+        bytes: include_bytes!("../../../../programs/hello"),
+        personality: Personality::Native,
+    },
+    Embedded {
+        name: "linux-hello",
+        bytes: include_bytes!("../../../../programs/linux-hello"),
+        personality: Personality::Linux,
+    },
+    Embedded {
+        name: "musl-hello",
+        bytes: include_bytes!(concat!(env!("OUT_DIR"), "/musl-hello")),
+        personality: Personality::Linux,
+    },
+    Embedded {
+        name: "userland-hello",
+        bytes: include_bytes!(concat!(env!("OUT_DIR"), "/userland-hello")),
+        personality: Personality::Native,
+    },
+];
+
+fn built() -> impl Iterator<Item = &'static Embedded> {
+    PROGRAMS.iter().filter(|program| !program.bytes.is_empty())
+}
+
+pub fn find(name: &str) -> Option<&'static Embedded> {
+    built().find(|program| program.name == name)
+}
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    built().map(|program| program.name)
+}
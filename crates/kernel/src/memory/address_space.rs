@@ -0,0 +1,427 @@
+use alloc::vec::Vec;
+use x86::{
+    addr::{PhysAddr, VirtAddr},
+    registers::control::{Cr3, Cr3Flags},
+    structures::paging::{
+        frame::PhysFrame,
+        frame_alloc::FrameDeallocator,
+        mapper::{FlagUpdateError, MapToError, Mapper, Translate, TranslateResult, UnmapError},
+        page::{Page, PageSize, Size4KiB},
+        page_table::{PageTable, PageTableFlags},
+    },
+};
+
+use super::{
+    area::{Area, Areas, Backing},
+    Memory, USER_END,
+};
+
+/// Top level entry of the kernel image, which also holds the usual load address of the programs
+/// built for other systems, 0x400000. Each address space gets its own copy of the tables under
+/// it, the kernel doesn't change its mappings there after the boot.
+const LOW_ENTRY: usize = 0;
+
+#[derive(Debug)]
+pub enum MapError {
+    FrameAllocationFailed,
+    /// The page is outside of the user half or shares its top level entry with the kernel.
+    KernelRegion,
+    /// The page is mapped, or part of an area.
+    AlreadyMapped,
+    NotMapped,
+    /// A shared memory object is empty or too large, or an area goes past its end.
+    InvalidSize,
+}
+
+impl From<UnmapError> for MapError {
+    fn from(value: UnmapError) -> Self {
+        match value {
+            UnmapError::ParentEntryHugePage => MapError::KernelRegion,
+            UnmapError::PageNotMapped => MapError::NotMapped,
+        }
+    }
+}
+
+impl From<FlagUpdateError> for MapError {
+    fn from(value: FlagUpdateError) -> Self {
+        match value {
+            FlagUpdateError::ParentEntryHugePage => MapError::KernelRegion,
+            FlagUpdateError::PageNotMapped => MapError::NotMapped,
+        }
+    }
+}
+
+impl From<MapToError<Size4KiB>> for MapError {
+    fn from(value: MapToError<Size4KiB>) -> Self {
+        match value {
+            MapToError::FrameAllocationFailed => MapError::FrameAllocationFailed,
+            MapToError::ParentEntryHugePage => MapError::KernelRegion,
+            MapToError::PageAlreadyMapped(_) => MapError::AlreadyMapped,
+        }
+    }
+}
+
+/// Page tables of a user program.
+///
+/// The top level entries present in the kernel page tables are shared, so the kernel stays mapped
+/// in every address space. User pages can only be mapped under the other entries, and next to the
+/// kernel pages under `LOW_ENTRY`.
+///
+/// The pages mapped with `map_area` are recorded as areas, the others are the ones of the program
+/// image, its stack and its heap.
+#[derive(Debug)]
+pub struct AddressSpace {
+    level_4_frame: PhysFrame,
+    areas: Areas,
+}
+
+impl AddressSpace {
+    /// Makes an address space sharing the kernel mappings.
+    ///
+    /// The top level entries of the kernel are copied now, the tables under them are shared. So
+    /// the kernel must not fill an unused top level entry once programs run, the address spaces
+    /// made before wouldn't see it: what it maps after the boot goes under entries it already has.
+    pub fn new(memory: &mut Memory) -> Result<Self, MapError> {
+        let level_4_frame = memory
+            .allocate_zeroed()
+            .ok_or(MapError::FrameAllocationFailed)?;
+
+        let kernel = unsafe { memory.table(memory.kernel_level_4_frame()) };
+        let table = unsafe { memory.table(level_4_frame) };
+        for (entry, kernel_entry) in table.iter_mut().zip(kernel.iter()) {
+            if !kernel_entry.is_unused() {
+                entry.set_frame(kernel_entry.frame().unwrap(), kernel_entry.flags());
+            }
+        }
+
+        let space = Self {
+            level_4_frame,
+            areas: Areas::default(),
+        };
+        if let Ok(kernel_low) = kernel[LOW_ENTRY].frame() {
+            match copy_tables(memory, kernel_low, 3) {
+                Some(copy) => table[LOW_ENTRY].set_frame(copy, kernel[LOW_ENTRY].flags()),
+                None => {
+                    table[LOW_ENTRY].set_unused();
+                    let _ = space.destroy(memory);
+                    return Err(MapError::FrameAllocationFailed);
+                }
+            }
+        }
+
+        Ok(space)
+    }
+
+    pub fn level_4_frame(&self) -> PhysFrame {
+        self.level_4_frame
+    }
+
+    pub fn areas(&self) -> &Areas {
+        &self.areas
+    }
+
+    /// Maps `page` to a new zeroed frame.
+    pub fn map(
+        &mut self,
+        memory: &mut Memory,
+        page: Page,
+        flags: PageTableFlags,
+    ) -> Result<PhysFrame, MapError> {
+        self.check_user_page(memory, page)?;
+
+        let frame = memory
+            .allocate_zeroed()
+            .ok_or(MapError::FrameAllocationFailed)?;
+        let mut mapper = unsafe { memory.mapper_for(self.level_4_frame) };
+        let flush = unsafe {
+            mapper.map_to(
+                page,
+                frame,
+                flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE,
+                &mut memory.frame_allocator,
+            )?
+        };
+        flush.flush();
+        Ok(frame)
+    }
+
+    /// Removes the mapping of `page` and frees its frame. It must have been mapped with `map`, the
+    /// pages of the areas go with `unmap_areas`.
+    pub fn unmap(&mut self, memory: &mut Memory, page: Page) -> Result<(), MapError> {
+        self.check_user_page(memory, page)?;
+        let start = page.start_address().as_u64();
+        if self.areas.overlaps(start, start + Size4KiB::SIZE) {
+            return Err(MapError::AlreadyMapped);
+        }
+
+        let mut mapper = unsafe { memory.mapper_for(self.level_4_frame) };
+        let (frame, flush) = mapper.unmap(page)?;
+        flush.flush();
+        unsafe { memory.frame_allocator.deallocate_frame(frame) };
+        Ok(())
+    }
+
+    /// Maps the pages of `area` and records it, undoing it all if a page fails. It must not overlap
+    /// the other areas.
+    pub fn map_area(&mut self, memory: &mut Memory, area: Area) -> Result<(), MapError> {
+        if area.is_empty() || self.areas.overlaps(area.start, area.end) {
+            return Err(MapError::AlreadyMapped);
+        }
+        if let Backing::Shared { object, offset } = &area.backing {
+            if offset
+                .checked_add(area.len())
+                .map_or(true, |end| end > object.size())
+            {
+                return Err(MapError::InvalidSize);
+            }
+        }
+
+        for addr in (area.start..area.end).step_by(Size4KiB::SIZE as usize) {
+            if let Err(err) = self.map_area_page(memory, &area, addr) {
+                self.unmap_pages(memory, &area, area.start, addr);
+                return Err(err);
+            }
+        }
+        self.areas.insert(area);
+        Ok(())
+    }
+
+    /// Unmaps what the areas have between `start` and `end` and returns the parts removed. The
+    /// pages outside of areas are left alone.
+    ///
+    /// The areas may hold the last reference to a shared memory object, they must be dropped once
+    /// the memory lock is released.
+    #[must_use]
+    pub fn unmap_areas(&mut self, memory: &mut Memory, start: u64, end: u64) -> Vec<Area> {
+        let removed = self.areas.remove(start, end);
+        for area in &removed {
+            self.unmap_pages(memory, area, area.start, area.end);
+        }
+        removed
+    }
+
+    /// Changes the flags of the pages from `start` to `end`, which must all be in areas.
+    pub fn protect(
+        &mut self,
+        memory: &mut Memory,
+        start: u64,
+        end: u64,
+        flags: PageTableFlags,
+    ) -> Result<(), MapError> {
+        if !self.areas.covers(start, end) {
+            return Err(MapError::NotMapped);
+        }
+
+        let mut mapper = unsafe { memory.mapper_for(self.level_4_frame) };
+        for area in self.areas.range_mut(start, end) {
+            area.flags = flags;
+            for addr in (area.start..area.end).step_by(Size4KiB::SIZE as usize) {
+                let page = Page::new_containing_address(VirtAddr::new(addr));
+                unsafe { mapper.update_flags(page, flags | PageTableFlags::PRESENT)? }.flush();
+            }
+        }
+        Ok(())
+    }
+
+    fn map_area_page(
+        &mut self,
+        memory: &mut Memory,
+        area: &Area,
+        addr: u64,
+    ) -> Result<(), MapError> {
+        let page = Page::new_containing_address(VirtAddr::new(addr));
+        self.check_user_page(memory, page)?;
+
+        let frame = match &area.backing {
+            Backing::Anonymous => memory
+                .allocate_zeroed()
+                .ok_or(MapError::FrameAllocationFailed)?,
+            Backing::Shared { object, offset } => object
+                .frame(offset + (addr - area.start))
+                .ok_or(MapError::InvalidSize)?,
+        };
+
+        // The flags of the page decide the access, the tables above allow everything
+        let table_flags =
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+        let mut mapper = unsafe { memory.mapper_for(self.level_4_frame) };
+        let result = unsafe {
+            mapper.map_to_with_table_flags(
+                page,
+                frame,
+                area.flags | PageTableFlags::PRESENT,
+                table_flags,
+                &mut memory.frame_allocator,
+            )
+        };
+        match result {
+            Ok(flush) => {
+                flush.flush();
+                Ok(())
+            }
+            Err(err) => {
+                if let Backing::Anonymous = area.backing {
+                    unsafe { memory.frame_allocator.deallocate_frame(frame) };
+                }
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Unmaps the pages of `area` from `start` to `end`, freeing the frames it owns.
+    fn unmap_pages(&mut self, memory: &mut Memory, area: &Area, start: u64, end: u64) {
+        let mut mapper = unsafe { memory.mapper_for(self.level_4_frame) };
+        for addr in (start..end).step_by(Size4KiB::SIZE as usize) {
+            let page = Page::new_containing_address(VirtAddr::new(addr));
+            let (frame, flush) = match mapper.unmap(page) {
+                Ok(unmapped) => unmapped,
+                Err(_) => continue,
+            };
+            flush.flush();
+            if let Backing::Anonymous = area.backing {
+                unsafe { memory.frame_allocator.deallocate_frame(frame) };
+            }
+        }
+    }
+
+    /// Physical address and flags of the page containing `addr`.
+    pub fn translate(
+        &self,
+        memory: &mut Memory,
+        addr: VirtAddr,
+    ) -> Option<(PhysAddr, PageTableFlags)> {
+        let mapper = unsafe { memory.mapper_for(self.level_4_frame) };
+        match mapper.translate(addr) {
+            TranslateResult::Mapped {
+                frame,
+                offset,
+                flags,
+            } => Some((frame.start_address() + offset, flags)),
+            _ => None,
+        }
+    }
+
+    /// Copies `bytes` to mapped memory at `addr`, whatever the permissions of the pages are.
+    pub fn write(&self, memory: &mut Memory, addr: VirtAddr, bytes: &[u8]) -> Result<(), MapError> {
+        let mut done = 0;
+        while done < bytes.len() {
+            let addr = addr + done as u64;
+            let (phys, _) = self.translate(memory, addr).ok_or(MapError::NotMapped)?;
+            let len = (Size4KiB::SIZE - addr.as_u64() % Size4KiB::SIZE) as usize;
+            let len = len.min(bytes.len() - done);
+
+            let ptr: *mut u8 = (memory.physical_memory_offset + phys.as_u64()).as_mut_ptr();
+            unsafe { ptr.copy_from_nonoverlapping(bytes[done..].as_ptr(), len) };
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// Frees the user pages and the page tables, and returns the areas to be dropped once the
+    /// memory lock is released.
+    ///
+    /// The address space must not be active on any CPU.
+    #[must_use]
+    pub fn destroy(mut self, memory: &mut Memory) -> Vec<Area> {
+        // The frames of shared memory objects are not the address space's to free
+        let areas = self.areas.take();
+        for area in &areas {
+            if let Backing::Shared { .. } = area.backing {
+                self.unmap_pages(memory, area, area.start, area.end);
+            }
+        }
+
+        let kernel = unsafe { memory.table(memory.kernel_level_4_frame()) };
+        let level_4 = unsafe { memory.table(self.level_4_frame) };
+        for (i, (entry, kernel_entry)) in level_4.iter().zip(kernel.iter()).enumerate() {
+            let frame = match entry.frame() {
+                Ok(frame) => frame,
+                Err(_) => continue,
+            };
+            // The other entries copied from the kernel are shared with every address space
+            if kernel_entry.is_unused() {
+                free_table(memory, frame, None, 3);
+            } else if i == LOW_ENTRY {
+                free_table(memory, frame, Some(kernel_entry.frame().unwrap()), 3);
+            }
+        }
+
+        unsafe { memory.frame_allocator.deallocate_frame(self.level_4_frame) };
+        areas
+    }
+
+    /// Switches the running CPU to this address space.
+    pub fn activate(&self) {
+        unsafe { Cr3::write(self.level_4_frame, Cr3Flags::empty()) };
+    }
+
+    fn check_user_page(&self, memory: &mut Memory, page: Page) -> Result<(), MapError> {
+        let addr = page.start_address().as_u64();
+        let kernel = unsafe { memory.table(memory.kernel_level_4_frame()) };
+        let index = usize::from(page.p4_index());
+        if addr >= USER_END || (index != LOW_ENTRY && !kernel[index].is_unused()) {
+            return Err(MapError::KernelRegion);
+        }
+
+        // Under `LOW_ENTRY`, the pages of the kernel image are in the copied tables
+        match self.translate(memory, page.start_address()) {
+            Some((_, flags)) if !flags.contains(PageTableFlags::USER_ACCESSIBLE) => {
+                Err(MapError::KernelRegion)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Copies a page table of the given level and the tables below it, not the frames they map.
+fn copy_tables(memory: &mut Memory, frame: PhysFrame, level: u8) -> Option<PhysFrame> {
+    let copy = memory.allocate_zeroed()?;
+    let source: &PageTable = unsafe { memory.table(frame) };
+    let table = unsafe { memory.table(copy) };
+    for (entry, source_entry) in table.iter_mut().zip(source.iter()) {
+        match source_entry.frame() {
+            Ok(child) if level > 1 => match copy_tables(memory, child, level - 1) {
+                Some(child) => entry.set_frame(child, source_entry.flags()),
+                None => {
+                    free_table(memory, copy, Some(frame), level);
+                    return None;
+                }
+            },
+            // Pages and huge pages
+            _ if !source_entry.is_unused() => {
+                let frame = PhysFrame::containing_address(source_entry.addr());
+                entry.set_frame(frame, source_entry.flags());
+            }
+            _ => {}
+        }
+    }
+    Some(copy)
+}
+
+/// Frees a page table of the given level, the tables below it and the frames they map.
+///
+/// With `kernel`, the table is a copy of that kernel table and the frames the kernel maps are
+/// kept.
+fn free_table(memory: &mut Memory, frame: PhysFrame, kernel: Option<PhysFrame>, level: u8) {
+    let table = unsafe { memory.table(frame) };
+    let kernel = kernel.map(|kernel| unsafe { &*memory.table(kernel) });
+    for (i, entry) in table.iter().enumerate() {
+        let child = match entry.frame() {
+            Ok(child) => child,
+            // Unused, or a huge page of the kernel
+            Err(_) => continue,
+        };
+        let kernel_child = kernel
+            .map(|kernel| &kernel[i])
+            .filter(|entry| !entry.is_unused());
+
+        if level > 1 {
+            let kernel_child = kernel_child.and_then(|entry| entry.frame().ok());
+            free_table(memory, child, kernel_child, level - 1);
+        } else if kernel_child.is_none() {
+            unsafe { memory.frame_allocator.deallocate_frame(child) };
+        }
+    }
+
+    unsafe { memory.frame_allocator.deallocate_frame(frame) };
+}
@@ -0,0 +1,147 @@
+//! Virtual memory areas: the ranges of an address space mapped by `mmap`, with their protection
+//! and the memory backing them.
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use x86::structures::paging::page_table::PageTableFlags;
+
+use super::SharedMemory;
+
+#[derive(Debug, Clone)]
+pub enum Backing {
+    /// Zeroed frames owned by the address space.
+    Anonymous,
+    /// Frames of a shared memory object, the area starting `offset` bytes into it.
+    Shared {
+        object: Arc<SharedMemory>,
+        offset: u64,
+    },
+}
+
+/// Pages from `start` to `end`, both page aligned, mapped with `flags`.
+#[derive(Debug, Clone)]
+pub struct Area {
+    pub start: u64,
+    pub end: u64,
+    pub flags: PageTableFlags,
+    pub backing: Backing,
+}
+
+impl Area {
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Cuts the area at `addr` and returns the part after it.
+    fn split_off(&mut self, addr: u64) -> Area {
+        let mut tail = self.clone();
+        tail.start = addr;
+        if let Backing::Shared { offset, .. } = &mut tail.backing {
+            *offset += addr - self.start;
+        }
+        self.end = addr;
+        tail
+    }
+}
+
+/// Areas of an address space, which don't overlap.
+///
+/// Only the address space changes them, so that they stay in line with its page tables.
+#[derive(Debug, Default)]
+pub struct Areas {
+    /// Indexed by start address.
+    areas: BTreeMap<u64, Area>,
+}
+
+impl Areas {
+    pub fn iter(&self) -> impl Iterator<Item = &Area> {
+        self.areas.values()
+    }
+
+    /// Area containing `addr`.
+    pub fn find(&self, addr: u64) -> Option<&Area> {
+        self.areas
+            .range(..=addr)
+            .next_back()
+            .map(|(_, area)| area)
+            .filter(|area| addr < area.end)
+    }
+
+    /// Lowest address from `start` where `len` bytes fit before `end` without overlapping an area.
+    pub fn find_free(&self, len: u64, start: u64, end: u64) -> Option<u64> {
+        let mut candidate = start;
+        for area in self.areas.values() {
+            if area.end <= candidate {
+                continue;
+            }
+            if area.start >= candidate.checked_add(len)? {
+                break;
+            }
+            candidate = area.end;
+        }
+        (candidate.checked_add(len)? <= end).then_some(candidate)
+    }
+
+    /// Whether an area overlaps the range from `start` to `end`.
+    pub fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.find(start).is_some() || self.areas.range(start..end).next().is_some()
+    }
+
+    /// Whether the range from `start` to `end` is in areas, without holes.
+    pub fn covers(&self, start: u64, end: u64) -> bool {
+        let mut addr = start;
+        while addr < end {
+            match self.find(addr) {
+                Some(area) => addr = area.end,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Adds an area, which must not overlap the others.
+    pub(super) fn insert(&mut self, area: Area) {
+        debug_assert!(!self.overlaps(area.start, area.end));
+        self.areas.insert(area.start, area);
+    }
+
+    /// Takes out what is between `start` and `end`, cutting the areas crossing the bounds.
+    pub(super) fn remove(&mut self, start: u64, end: u64) -> Vec<Area> {
+        self.split_at(start);
+        self.split_at(end);
+        let starts: Vec<u64> = self
+            .areas
+            .range(start..end)
+            .map(|(&start, _)| start)
+            .collect();
+        starts
+            .into_iter()
+            .filter_map(|start| self.areas.remove(&start))
+            .collect()
+    }
+
+    /// Areas between `start` and `end`, cutting the ones crossing the bounds.
+    pub(super) fn range_mut(&mut self, start: u64, end: u64) -> impl Iterator<Item = &mut Area> {
+        self.split_at(start);
+        self.split_at(end);
+        self.areas.range_mut(start..end).map(|(_, area)| area)
+    }
+
+    /// Takes out all the areas.
+    pub(super) fn take(&mut self) -> Vec<Area> {
+        core::mem::take(&mut self.areas).into_values().collect()
+    }
+
+    /// Makes `addr` the bound of two areas if it falls inside one.
+    fn split_at(&mut self, addr: u64) {
+        let area = match self.areas.range_mut(..addr).next_back() {
+            Some((_, area)) if addr < area.end => area,
+            _ => return,
+        };
+        let tail = area.split_off(addr);
+        self.areas.insert(addr, tail);
+    }
+}
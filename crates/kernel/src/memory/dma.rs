@@ -0,0 +1,37 @@
+use core::ptr::NonNull;
+
+use x86::{
+    addr::PhysAddr,
+    structures::paging::{
+        frame::PhysFrame,
+        frame_alloc::FrameDeallocator,
+        page::{PageSize, Size4KiB},
+    },
+};
+
+/// Memory of the virtio devices, frames following each other reached through the mapping of the
+/// physical memory.
+pub struct Dma;
+
+unsafe impl virtio::Hal for Dma {
+    fn dma_alloc(pages: usize) -> Option<(u64, NonNull<u8>)> {
+        super::with(|memory| {
+            let frame = memory.frame_allocator.allocate_contiguous(pages)?;
+            let paddr = frame.start_address().as_u64();
+            let ptr: *mut u8 = (memory.physical_memory_offset + paddr).as_mut_ptr();
+            unsafe { ptr.write_bytes(0, pages * Size4KiB::SIZE as usize) };
+            Some((paddr, NonNull::new(ptr)?))
+        })
+        .flatten()
+    }
+
+    unsafe fn dma_dealloc(paddr: u64, _vaddr: NonNull<u8>, pages: usize) {
+        super::with(|memory| {
+            for i in 0..pages as u64 {
+                let frame =
+                    PhysFrame::containing_address(PhysAddr::new(paddr + i * Size4KiB::SIZE));
+                unsafe { memory.frame_allocator.deallocate_frame(frame) };
+            }
+        });
+    }
+}
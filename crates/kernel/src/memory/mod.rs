@@ -1,13 +1,113 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use alloc::vec::Vec;
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use conquer_once::spin::OnceCell;
 use x86::{
-    addr::{PhysAddr, VirtAddr},
+    addr::{align_up, PhysAddr, VirtAddr},
     registers::control::Cr3,
     structures::paging::{
-        frame::PhysFrame, frame_alloc::FrameAllocator, mapper::offset_page_table::OffsetPageTable,
-        page::Size4KiB, page_table::PageTable,
+        frame::PhysFrame,
+        frame_alloc::{FrameAllocator, FrameDeallocator},
+        mapper::{
+            offset_page_table::OffsetPageTable, MapToError, Mapper, Translate, TranslateResult,
+        },
+        page::{Page, PageSize, Size4KiB},
+        page_table::{PageTable, PageTableFlags},
     },
 };
 
+use crate::sync::IrqMutex;
+
+pub use address_space::{AddressSpace, MapError};
+pub use area::{Area, Areas, Backing};
+pub use dma::Dma;
+pub use shared::{SharedMemory, MAX_SHARED_MEMORY_SIZE};
+pub use stack::{KernelStack, STACKS_START};
+
+mod address_space;
+mod area;
+mod dma;
+mod shared;
+mod stack;
+
+pub const MMIO_START: u64 = 0x_5555_5555_0000;
+/// End of the lower half of the address space, user code only gets addresses below it.
+pub const USER_END: u64 = 0x_8000_0000_0000;
+
+static NEXT_MMIO: AtomicU64 = AtomicU64::new(MMIO_START);
+static MEMORY: OnceCell<IrqMutex<Memory>> = OnceCell::uninit();
+
+/// Page tables and frame allocator shared by the kernel once the boot is done.
+pub struct Memory {
+    pub mapper: OffsetPageTable<'static>,
+    pub frame_allocator: BootInfoFrameAllocator,
+    pub physical_memory_offset: VirtAddr,
+    kernel_level_4_frame: PhysFrame,
+}
+
+impl Memory {
+    /// Allocates a zeroed frame.
+    pub fn allocate_zeroed(&mut self) -> Option<PhysFrame> {
+        let frame = self.frame_allocator.allocate_frame()?;
+        let ptr: *mut u8 =
+            (self.physical_memory_offset + frame.start_address().as_u64()).as_mut_ptr();
+        unsafe { ptr.write_bytes(0, Size4KiB::SIZE as usize) };
+        Some(frame)
+    }
+
+    /// Top level table of the kernel, the one active during the boot.
+    pub fn kernel_level_4_frame(&self) -> PhysFrame {
+        self.kernel_level_4_frame
+    }
+
+    /// Flags of the page containing `addr` in the active address space, `None` if it isn't mapped.
+    pub fn page_flags(&self, addr: VirtAddr) -> Option<PageTableFlags> {
+        let (level_4_frame, _) = Cr3::read();
+        match unsafe { self.mapper_for(level_4_frame) }.translate(addr) {
+            TranslateResult::Mapped { flags, .. } => Some(flags),
+            _ => None,
+        }
+    }
+
+    /// The table must not be referenced elsewhere while the result is used.
+    unsafe fn table(&self, frame: PhysFrame) -> &'static mut PageTable {
+        let virt = self.physical_memory_offset + frame.start_address().as_u64();
+        unsafe { &mut *virt.as_mut_ptr::<PageTable>() }
+    }
+
+    /// The tables must not be referenced elsewhere while the result is used.
+    unsafe fn mapper_for(&self, level_4_frame: PhysFrame) -> OffsetPageTable<'static> {
+        unsafe { OffsetPageTable::new(self.table(level_4_frame), self.physical_memory_offset) }
+    }
+}
+
+/// Hands the page tables and the frame allocator over to the kernel, for the syscalls and the
+/// loaders that map memory after the boot.
+pub fn install(
+    mapper: OffsetPageTable<'static>,
+    frame_allocator: BootInfoFrameAllocator,
+    physical_memory_offset: VirtAddr,
+) {
+    MEMORY.init_once(|| {
+        IrqMutex::named(
+            "memory",
+            Memory {
+                mapper,
+                frame_allocator,
+                physical_memory_offset,
+                kernel_level_4_frame: Cr3::read().0,
+            },
+        )
+    });
+}
+
+/// Runs `f` with the kernel memory, `None` before `install`.
+pub fn with<R>(f: impl FnOnce(&mut Memory) -> R) -> Option<R> {
+    let memory = MEMORY.try_get().ok()?;
+    Some(f(&mut memory.lock()))
+}
+
 unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
     let (level_4_table_frame, _) = Cr3::read();
     let phys = level_4_table_frame.start_address();
@@ -22,9 +122,55 @@ pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static>
     OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
 
+/// Maps a region of device memory as uncacheable and returns its virtual address.
+pub fn map_mmio(
+    phys_addr: PhysAddr,
+    size: u64,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<VirtAddr, MapToError<Size4KiB>> {
+    let start_frame: PhysFrame = PhysFrame::containing_address(phys_addr);
+    let offset = phys_addr.as_u64() - start_frame.start_address().as_u64();
+    let length = align_up(offset + size, Size4KiB::SIZE);
+
+    let start = VirtAddr::new(NEXT_MMIO.fetch_add(length, Ordering::Relaxed));
+    let start_page: Page = Page::new_containing_address(start);
+
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::WRITE_THROUGH
+        | PageTableFlags::NO_CACHE;
+
+    for i in 0..length / Size4KiB::SIZE {
+        let page = start_page + i;
+        let frame = PhysFrame::containing_address(start_frame.start_address() + i * Size4KiB::SIZE);
+        unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+    }
+
+    Ok(start + offset)
+}
+
+/// Unmaps a region mapped by `map_mmio`, its virtual addresses aren't reused.
+pub fn unmap_mmio(virt_addr: VirtAddr, size: u64, mapper: &mut impl Mapper<Size4KiB>) {
+    let start_page: Page = Page::new_containing_address(virt_addr);
+    let offset = virt_addr.as_u64() - start_page.start_address().as_u64();
+    let length = align_up(offset + size, Size4KiB::SIZE);
+
+    for i in 0..length / Size4KiB::SIZE {
+        // The frames are the device's, not the allocator's
+        if let Ok((_, flush)) = mapper.unmap(start_page + i) {
+            flush.flush();
+        }
+    }
+}
+
 pub struct BootInfoFrameAllocator {
     memory_map: &'static MemoryMap,
     next: usize,
+    /// Start of the frame taken out of order by `allocate_below`, left out of the usable ones.
+    reserved: Option<PhysAddr>,
+    // Frames given back, reused before the ones never allocated
+    free: Vec<PhysFrame>,
 }
 
 impl BootInfoFrameAllocator {
@@ -32,23 +178,90 @@ impl BootInfoFrameAllocator {
         BootInfoFrameAllocator {
             memory_map,
             next: 0,
+            reserved: None,
+            free: Vec::new(),
         }
     }
 
+    /// Allocates `count` frames following each other in physical memory, for the devices. The
+    /// frames skipped to find them go to the free list.
+    pub fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrame> {
+        let mut start = None;
+        let mut len = 0;
+        let mut previous: Option<PhysFrame> = None;
+        for (index, frame) in self.usable_frames().enumerate().skip(self.next) {
+            let follows = previous.is_some_and(|previous| {
+                previous.start_address().as_u64() + Size4KiB::SIZE == frame.start_address().as_u64()
+            });
+            if !follows {
+                start = Some((index, frame));
+                len = 0;
+            }
+            previous = Some(frame);
+            len += 1;
+
+            if len == count {
+                let (start_index, start_frame) = start?;
+                let skipped: Vec<_> = self
+                    .usable_frames()
+                    .skip(self.next)
+                    .take(start_index - self.next)
+                    .collect();
+                self.free.extend(skipped);
+                self.next = index + 1;
+                return Some(start_frame);
+            }
+        }
+        None
+    }
+
+    /// Allocates the first free frame below `limit`, for the code which must run from low memory.
+    /// It works before the heap is initialized, and for a single frame: the frames before it stay
+    /// free, it is left out of the usable ones instead.
+    pub fn allocate_below(&mut self, limit: PhysAddr) -> Option<PhysFrame> {
+        if self.reserved.is_some() {
+            return None;
+        }
+        let frame = self
+            .usable_frames()
+            .skip(self.next)
+            .find(|frame| frame.start_address() < limit)?;
+        self.reserved = Some(frame.start_address());
+        Some(frame)
+    }
+
+    /// Number of frames given back and not reused yet.
+    pub fn free_count(&self) -> usize {
+        self.free.len()
+    }
+
     fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
+        let reserved = self.reserved;
         self.memory_map
             .iter()
             .filter(|r| r.region_type == MemoryRegionType::Usable)
             .map(|r| r.range.start_addr()..r.range.end_addr())
             .flat_map(|r| r.step_by(4096))
             .map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
+            .filter(move |frame| Some(frame.start_address()) != reserved)
     }
 }
 
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        if let Some(frame) = self.free.pop() {
+            return Some(frame);
+        }
+
         let frame = self.usable_frames().nth(self.next);
         self.next += 1;
         frame
     }
 }
+
+impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
+    /// The free list lives on the heap, so frames can only be given back once it is initialized.
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        self.free.push(frame);
+    }
+}
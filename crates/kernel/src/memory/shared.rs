@@ -0,0 +1,66 @@
+use alloc::{sync::Arc, vec::Vec};
+use x86::structures::paging::{
+    frame::PhysFrame,
+    frame_alloc::FrameDeallocator,
+    page::{PageSize, Size4KiB},
+};
+
+use super::{MapError, Memory};
+
+/// Largest shared memory object, its frames are listed on the kernel heap.
+pub const MAX_SHARED_MEMORY_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Zeroed frames that several address spaces can map, freed with the last reference to the
+/// object: the handles to it and the areas mapping it.
+///
+/// The frames are freed through `memory::with`, so the last reference must not be dropped with
+/// the memory lock held.
+#[derive(Debug)]
+pub struct SharedMemory {
+    frames: Vec<PhysFrame>,
+}
+
+impl SharedMemory {
+    /// Allocates `size` bytes rounded up to whole pages.
+    pub fn new(memory: &mut Memory, size: u64) -> Result<Arc<Self>, MapError> {
+        if size == 0 || size > MAX_SHARED_MEMORY_SIZE {
+            return Err(MapError::InvalidSize);
+        }
+
+        let count = size.div_ceil(Size4KiB::SIZE) as usize;
+        let mut frames = Vec::with_capacity(count);
+        for _ in 0..count {
+            match memory.allocate_zeroed() {
+                Some(frame) => frames.push(frame),
+                None => {
+                    for frame in frames {
+                        unsafe { memory.frame_allocator.deallocate_frame(frame) };
+                    }
+                    return Err(MapError::FrameAllocationFailed);
+                }
+            }
+        }
+        Ok(Arc::new(Self { frames }))
+    }
+
+    /// Size in bytes, a multiple of the page size.
+    pub fn size(&self) -> u64 {
+        self.frames.len() as u64 * Size4KiB::SIZE
+    }
+
+    /// Frame holding the byte at `offset`.
+    pub fn frame(&self, offset: u64) -> Option<PhysFrame> {
+        self.frames.get((offset / Size4KiB::SIZE) as usize).copied()
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        let frames = core::mem::take(&mut self.frames);
+        super::with(|memory| {
+            for frame in frames {
+                unsafe { memory.frame_allocator.deallocate_frame(frame) };
+            }
+        });
+    }
+}
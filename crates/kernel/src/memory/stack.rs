@@ -0,0 +1,124 @@
+//! Stacks of the kernel threads, each above an unmapped guard page so that an overflow faults
+//! instead of writing over the stack below.
+
+use core::{
+    slice,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use x86::{
+    addr::VirtAddr,
+    structures::paging::{
+        frame_alloc::{FrameAllocator, FrameDeallocator},
+        mapper::{MapToError, Mapper},
+        page::{Page, PageSize, Size4KiB},
+        page_table::PageTableFlags,
+    },
+};
+
+use crate::thread::STACK_SIZE;
+
+/// The region is one top level entry, made when the first stack is mapped during the boot. The
+/// address spaces copy the kernel entries once, so it must exist before the first one.
+pub const STACKS_START: u64 = 0x_6600_0000_0000;
+const STACKS_END: u64 = 0x_6680_0000_0000;
+
+const PAGES: u64 = STACK_SIZE as u64 / Size4KiB::SIZE;
+/// A guard page and the stack.
+const SLOT_SIZE: u64 = (PAGES + 1) * Size4KiB::SIZE;
+
+/// The slots aren't reused: without shootdowns, another CPU could still have a freed stack in its
+/// TLB.
+static NEXT_SLOT: AtomicU64 = AtomicU64::new(STACKS_START);
+
+/// A kernel stack, unmapped and its frames freed when dropped.
+pub struct KernelStack {
+    bottom: VirtAddr,
+}
+
+impl KernelStack {
+    /// Maps a stack with the tables of the boot, before `install`. A full region fails with
+    /// `FrameAllocationFailed`.
+    pub fn map(
+        mapper: &mut impl Mapper<Size4KiB>,
+        frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    ) -> Result<Self, MapToError<Size4KiB>> {
+        let bottom = next_slot().ok_or(MapToError::FrameAllocationFailed)?;
+        map_pages(bottom, mapper, frame_allocator)?;
+        Ok(Self { bottom })
+    }
+
+    /// Maps a stack with the kernel memory, `None` before `install` or without memory left.
+    pub fn new() -> Option<Self> {
+        let bottom = next_slot()?;
+        super::with(|memory| {
+            match map_pages(bottom, &mut memory.mapper, &mut memory.frame_allocator) {
+                Ok(()) => Some(Self { bottom }),
+                Err(_) => {
+                    unmap_pages(bottom, &mut memory.mapper, &mut memory.frame_allocator);
+                    None
+                }
+            }
+        })
+        .flatten()
+    }
+
+    pub fn top(&self) -> VirtAddr {
+        self.bottom + STACK_SIZE as u64
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.bottom.as_mut_ptr(), STACK_SIZE) }
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        super::with(|memory| {
+            unmap_pages(self.bottom, &mut memory.mapper, &mut memory.frame_allocator)
+        });
+    }
+}
+
+/// Bottom of the stack of a new slot, the guard page being below it.
+fn next_slot() -> Option<VirtAddr> {
+    let slot = NEXT_SLOT.fetch_add(SLOT_SIZE, Ordering::Relaxed);
+    match slot + SLOT_SIZE <= STACKS_END {
+        true => Some(VirtAddr::new(slot + Size4KiB::SIZE)),
+        false => None,
+    }
+}
+
+fn pages(bottom: VirtAddr) -> impl Iterator<Item = Page> {
+    let start: Page = Page::new_containing_address(bottom);
+    (0..PAGES).map(move |i| start + i)
+}
+
+fn map_pages(
+    bottom: VirtAddr,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    for page in pages(bottom) {
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+    }
+    Ok(())
+}
+
+/// Unmaps the pages of the stack that are mapped, which is all of them but after a failed map.
+fn unmap_pages(
+    bottom: VirtAddr,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameDeallocator<Size4KiB>,
+) {
+    for page in pages(bottom) {
+        if let Ok((frame, flush)) = mapper.unmap(page) {
+            flush.flush();
+            unsafe { frame_allocator.deallocate_frame(frame) };
+        }
+    }
+}
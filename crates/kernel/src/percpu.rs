@@ -0,0 +1,160 @@
+use core::{
+    arch::{asm, x86_64::__cpuid},
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering},
+};
+
+use alloc::boxed::Box;
+use x86::{
+    addr::VirtAddr,
+    instructions::interrupts,
+    registers::model_specific::{GsBase, KernelGsBase},
+    tss::TaskStateSegment,
+};
+
+/// Number of slots of the per-CPU variables, the CPUs past it are not started.
+pub const MAX_CPUS: usize = 64;
+
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// Per-CPU block reached through the GS base while in the kernel. In ring 3 it waits in the
+/// kernel GS base, the entries from user code `swapgs` it in and out.
+///
+/// The first field points to the block itself so that `gs:[0]` gives its address.
+#[repr(C)]
+pub struct PerCpu {
+    self_ptr: *const PerCpu,
+    pub index: usize,
+    pub apic_id: u8,
+    /// Top of the kernel stack the syscall entry switches to.
+    pub kernel_stack: AtomicU64,
+    /// Saves the user stack pointer during the syscall entry.
+    pub user_stack: AtomicU64,
+    current_thread: AtomicU64,
+    tss: AtomicPtr<TaskStateSegment>,
+}
+
+impl PerCpu {
+    pub fn current_thread(&self) -> u64 {
+        self.current_thread.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_current_thread(&self, id: u64) {
+        self.current_thread.store(id, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_tss(&self, tss: *mut TaskStateSegment) {
+        self.tss.store(tss, Ordering::Relaxed);
+    }
+
+    /// Sets the stack the CPU switches to when it enters the kernel from ring 3.
+    pub fn set_kernel_stack(&self, top: VirtAddr) {
+        self.kernel_stack.store(top.as_u64(), Ordering::Relaxed);
+
+        let tss = self.tss.load(Ordering::Relaxed);
+        if !tss.is_null() {
+            // The TSS is packed, the field isn't aligned
+            unsafe {
+                ptr::addr_of_mut!((*tss).privilege_stack_table)
+                    .cast::<u64>()
+                    .write_unaligned(top.as_u64());
+            }
+        }
+    }
+}
+
+/// Allocates the block of the running CPU and points the GS base to it, the user GS base is 0.
+///
+/// The BSP must call it before the APs are started, and each AP before using per-CPU data.
+pub fn init(index: usize) {
+    assert!(index < MAX_CPUS, "CPU index {} out of range", index);
+
+    // The initial APIC id, the local APIC may not be mapped yet
+    let apic_id = (__cpuid(1).ebx >> 24) as u8;
+    let block = Box::leak(Box::new(PerCpu {
+        self_ptr: core::ptr::null(),
+        index,
+        apic_id,
+        kernel_stack: AtomicU64::new(0),
+        user_stack: AtomicU64::new(0),
+        current_thread: AtomicU64::new(0),
+        // The BSP loads its TSS before the heap exists, the APs register theirs in `init_ap`
+        tss: AtomicPtr::new(match index {
+            0 => crate::bsp_tss(),
+            _ => ptr::null_mut(),
+        }),
+    }));
+    block.self_ptr = block;
+
+    let base = VirtAddr::new(block as *const PerCpu as u64);
+    unsafe {
+        GsBase::write(base);
+        KernelGsBase::write(VirtAddr::new(0));
+    }
+    INITIALIZED.store(true, Ordering::Release);
+}
+
+/// Block of the running CPU, `None` before `init`.
+///
+/// The caller can migrate to another CPU unless interrupts are disabled.
+pub fn current() -> Option<&'static PerCpu> {
+    if !INITIALIZED.load(Ordering::Acquire) {
+        return None;
+    }
+
+    let ptr: *const PerCpu;
+    unsafe {
+        asm!("mov {}, gs:[0]", out(reg) ptr, options(nostack, preserves_flags, readonly));
+    }
+    unsafe { ptr.as_ref() }
+}
+
+/// Index of the running CPU, 0 before `init`.
+pub fn index() -> usize {
+    current().map_or(0, |percpu| percpu.index)
+}
+
+/// A variable with one instance per CPU, declared with `percpu!`.
+///
+/// Each instance is only reached from its CPU with interrupts disabled.
+pub struct PerCpuVar<T> {
+    values: [T; MAX_CPUS],
+}
+
+unsafe impl<T: Send> Sync for PerCpuVar<T> {}
+
+impl<T> PerCpuVar<T> {
+    pub const fn new(values: [T; MAX_CPUS]) -> Self {
+        Self { values }
+    }
+
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        interrupts::without_interrupts(|| f(&self.values[index()]))
+    }
+}
+
+impl<T: Copy> PerCpuVar<T> {
+    pub fn get(&self) -> T {
+        self.with(|value| *value)
+    }
+}
+
+/// Declares a static with one instance per CPU.
+///
+/// ```ignore
+/// percpu! {
+///     static COUNTER: Cell<u64> = Cell::new(0);
+/// }
+///
+/// COUNTER.with(|counter| counter.set(counter.get() + 1));
+/// ```
+#[macro_export]
+macro_rules! percpu {
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $init:expr;) => {
+        $(#[$attr])*
+        $vis static $name: $crate::percpu::PerCpuVar<$ty> = {
+            const INIT: $ty = $init;
+            $crate::percpu::PerCpuVar::new([INIT; $crate::percpu::MAX_CPUS])
+        };
+    };
+}
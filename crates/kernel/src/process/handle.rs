@@ -0,0 +1,142 @@
+use core::fmt::Write;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use vfs::{OpenFile, VfsError};
+
+use crate::{ipc::channel::Endpoint, memory::SharedMemory, tty::TTY};
+
+/// Number of descriptors a process can have open.
+pub const MAX_HANDLES: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandleError {
+    BadDescriptor,
+    NotSupported,
+    TooManyHandles,
+    /// The handle is non-blocking and the operation would have to wait.
+    WouldBlock,
+    /// The other end of a pipe or a channel is closed.
+    BrokenPipe,
+    /// A signal arrived while waiting.
+    Interrupted,
+    /// The message is larger than a channel allows.
+    MessageTooLarge,
+    /// The next message doesn't fit in the buffers given to receive it.
+    BufferTooSmall,
+    /// A channel can't carry its own endpoints.
+    CannotTransfer,
+    /// An error of the file system of an open file.
+    File(VfsError),
+}
+
+impl From<VfsError> for HandleError {
+    fn from(value: VfsError) -> Self {
+        HandleError::File(value)
+    }
+}
+
+/// Something a file descriptor refers to.
+pub trait Handle: Send + Sync {
+    fn read(&self, _buffer: &mut [u8]) -> Result<usize, HandleError> {
+        Err(HandleError::NotSupported)
+    }
+
+    fn write(&self, _buffer: &[u8]) -> Result<usize, HandleError> {
+        Err(HandleError::NotSupported)
+    }
+
+    fn is_terminal(&self) -> bool {
+        false
+    }
+
+    /// Makes `read` and `write` fail with `WouldBlock` instead of waiting.
+    fn set_nonblocking(&self, _nonblocking: bool) -> Result<(), HandleError> {
+        Err(HandleError::NotSupported)
+    }
+
+    fn as_channel(&self) -> Option<&Endpoint> {
+        None
+    }
+
+    fn as_shared_memory(&self) -> Option<&Arc<SharedMemory>> {
+        None
+    }
+
+    fn as_file(&self) -> Option<&OpenFile> {
+        None
+    }
+}
+
+/// Output to the terminal, there is no console input for user programs yet.
+pub struct Console;
+
+impl Handle for Console {
+    fn read(&self, _buffer: &mut [u8]) -> Result<usize, HandleError> {
+        Ok(0)
+    }
+
+    fn write(&self, buffer: &[u8]) -> Result<usize, HandleError> {
+        let _ = TTY.lock().write_str(&String::from_utf8_lossy(buffer));
+        Ok(buffer.len())
+    }
+
+    fn is_terminal(&self) -> bool {
+        true
+    }
+}
+
+/// File descriptor table of a process, a descriptor is an index in it.
+#[derive(Clone, Default)]
+pub struct HandleTable {
+    handles: Vec<Option<Arc<dyn Handle>>>,
+}
+
+impl HandleTable {
+    /// A table with the console as stdin, stdout and stderr.
+    pub fn with_console() -> Self {
+        let console: Arc<dyn Handle> = Arc::new(Console);
+        Self {
+            handles: alloc::vec![Some(console.clone()), Some(console.clone()), Some(console)],
+        }
+    }
+
+    pub fn get(&self, fd: u64) -> Result<Arc<dyn Handle>, HandleError> {
+        usize::try_from(fd)
+            .ok()
+            .and_then(|fd| self.handles.get(fd))
+            .and_then(Option::clone)
+            .ok_or(HandleError::BadDescriptor)
+    }
+
+    /// Stores `handle` at the lowest free descriptor.
+    pub fn insert(&mut self, handle: Arc<dyn Handle>) -> Result<u64, HandleError> {
+        let fd = match self.handles.iter().position(Option::is_none) {
+            Some(fd) => fd,
+            None if self.handles.len() < MAX_HANDLES => {
+                self.handles.push(None);
+                self.handles.len() - 1
+            }
+            None => return Err(HandleError::TooManyHandles),
+        };
+
+        self.handles[fd] = Some(handle);
+        Ok(fd as u64)
+    }
+
+    pub fn remove(&mut self, fd: u64) -> Result<Arc<dyn Handle>, HandleError> {
+        usize::try_from(fd)
+            .ok()
+            .and_then(|fd| self.handles.get_mut(fd))
+            .and_then(Option::take)
+            .ok_or(HandleError::BadDescriptor)
+    }
+
+    pub fn len(&self) -> usize {
+        self.handles.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
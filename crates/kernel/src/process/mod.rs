@@ -0,0 +1,421 @@
+//! User processes: a program running in its own address space, with its descriptors and its
+//! place in the parent/child tree.
+
+pub mod handle;
+pub mod signal;
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use x86::addr::VirtAddr;
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+use crate::{
+    loader::{self, LoadError},
+    memory::{self, AddressSpace},
+    sync::IrqMutex,
+    thread::{self, SpawnError, ThreadId, WaitQueue},
+    tty, user,
+};
+
+use handle::HandleTable;
+use signal::{Signal, SignalState};
+
+static TABLE: IrqMutex<ProcessTable> = IrqMutex::named("processes", ProcessTable::new());
+/// Notified each time a process exits, the waiting parents check their children again.
+static EXITED: WaitQueue = WaitQueue::new();
+static NEXT_PID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pid(u64);
+
+impl Pid {
+    /// Parent of the processes the kernel starts and waits for itself, which stay zombies until
+    /// `wait` like the children of a process.
+    pub const KERNEL: Pid = Pid(0);
+
+    fn new() -> Self {
+        Self(NEXT_PID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for Pid {
+    fn from(value: u64) -> Self {
+        Self(value)
+    }
+}
+
+impl fmt::Display for Pid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    Running,
+    /// Exited with the code, kept until the parent waits for it.
+    Zombie(i32),
+}
+
+/// System call ABI a process was built for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Personality {
+    Native,
+    /// The subset of the Linux x86_64 system calls in `syscall::linux`.
+    Linux,
+}
+
+/// Heap of a process grown with `brk`, from the end of its segments.
+#[derive(Debug, Clone, Copy)]
+pub struct ProgramBreak {
+    pub start: VirtAddr,
+    pub end: VirtAddr,
+}
+
+#[derive(Debug)]
+pub enum ProcessError {
+    Load(LoadError),
+    NoChild,
+    NoProcess,
+    Interrupted,
+    Spawn(SpawnError),
+}
+
+impl From<LoadError> for ProcessError {
+    fn from(value: LoadError) -> Self {
+        ProcessError::Load(value)
+    }
+}
+
+impl From<SpawnError> for ProcessError {
+    fn from(value: SpawnError) -> Self {
+        ProcessError::Spawn(value)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcessInfo {
+    pub pid: Pid,
+    pub parent: Option<Pid>,
+    pub name: String,
+    pub state: ProcessState,
+    pub thread: Option<ThreadId>,
+    pub handles: usize,
+}
+
+pub struct Process {
+    pid: Pid,
+    // None for the processes started by the kernel, and the orphans
+    parent: Option<Pid>,
+    name: String,
+    state: ProcessState,
+    thread: Option<ThreadId>,
+    address_space: Option<AddressSpace>,
+    handles: HandleTable,
+    personality: Personality,
+    program_break: ProgramBreak,
+    signals: SignalState,
+    /// Absolute path the relative paths start from.
+    cwd: String,
+}
+
+impl Process {
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    pub fn parent(&self) -> Option<Pid> {
+        self.parent
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn handles(&mut self) -> &mut HandleTable {
+        &mut self.handles
+    }
+
+    pub fn personality(&self) -> Personality {
+        self.personality
+    }
+
+    pub fn program_break(&mut self) -> &mut ProgramBreak {
+        &mut self.program_break
+    }
+
+    pub fn signals(&mut self) -> &mut SignalState {
+        &mut self.signals
+    }
+
+    pub fn cwd(&self) -> &str {
+        &self.cwd
+    }
+
+    pub fn set_cwd(&mut self, cwd: String) {
+        self.cwd = cwd;
+    }
+
+    /// `None` once the process exited.
+    pub fn address_space(&mut self) -> Option<&mut AddressSpace> {
+        self.address_space.as_mut()
+    }
+
+    fn info(&self) -> ProcessInfo {
+        ProcessInfo {
+            pid: self.pid,
+            parent: self.parent,
+            name: self.name.clone(),
+            state: self.state,
+            thread: self.thread,
+            handles: self.handles.len(),
+        }
+    }
+}
+
+struct ProcessTable {
+    processes: BTreeMap<Pid, Process>,
+    threads: BTreeMap<ThreadId, Pid>,
+}
+
+impl ProcessTable {
+    const fn new() -> Self {
+        Self {
+            processes: BTreeMap::new(),
+            threads: BTreeMap::new(),
+        }
+    }
+}
+
+/// Starts `bytes` as a new process, a child of `parent` which it inherits the descriptors and the
+/// working directory from. `handles` replaces the inherited descriptors.
+pub fn spawn(
+    name: &str,
+    bytes: &[u8],
+    args: &[&str],
+    env: &[&str],
+    personality: Personality,
+    parent: Option<Pid>,
+    handles: Option<HandleTable>,
+) -> Result<Pid, ProcessError> {
+    let program = loader::load(bytes, args, env)?;
+    let (entry, stack_pointer) = (program.entry, program.stack_pointer);
+    let program_break = ProgramBreak {
+        start: program.brk,
+        end: program.brk,
+    };
+    let level_4_frame = program.address_space.level_4_frame();
+
+    let pid = Pid::new();
+    {
+        let mut table = TABLE.lock();
+        let parent_process = parent.and_then(|parent| table.processes.get(&parent));
+        let handles = handles.unwrap_or_else(|| {
+            parent_process.map_or_else(HandleTable::with_console, |parent| parent.handles.clone())
+        });
+        let cwd = parent_process.map_or_else(|| String::from("/"), |parent| parent.cwd.clone());
+        table.processes.insert(
+            pid,
+            Process {
+                pid,
+                parent,
+                name: String::from(name),
+                state: ProcessState::Running,
+                thread: None,
+                address_space: Some(program.address_space),
+                handles,
+                personality,
+                program_break,
+                signals: SignalState::new(),
+                cwd,
+            },
+        );
+    }
+
+    let spawned = thread::spawn(name, move || {
+        attach(pid);
+        thread::set_address_space(level_4_frame);
+        unsafe { user::enter_user_mode(entry, stack_pointer) }
+    });
+    if let Err(err) = spawned {
+        // Dropped out of the table lock, like the process of `exit`
+        let process = TABLE.lock().processes.remove(&pid);
+        if let Some(address_space) = process.and_then(|process| process.address_space) {
+            let areas = memory::with(|memory| address_space.destroy(memory));
+            drop(areas);
+        }
+        return Err(err.into());
+    }
+
+    Ok(pid)
+}
+
+/// Records the running thread as the one of `pid`.
+fn attach(pid: Pid) {
+    let current = thread::current().expect("threads are not initialized");
+    let mut table = TABLE.lock();
+    table.threads.insert(current, pid);
+    if let Some(process) = table.processes.get_mut(&pid) {
+        process.thread = Some(current);
+    }
+}
+
+/// The process of the running thread, `None` for kernel threads.
+pub fn current() -> Option<Pid> {
+    let thread = thread::current()?;
+    TABLE.lock().threads.get(&thread).copied()
+}
+
+/// Runs `f` with the process of the running thread.
+pub fn with_current<R>(f: impl FnOnce(&mut Process) -> R) -> Option<R> {
+    let thread = thread::current()?;
+    let mut table = TABLE.lock();
+    let pid = *table.threads.get(&thread)?;
+    table.processes.get_mut(&pid).map(f)
+}
+
+/// Replaces the program of the current process, only returns on failure.
+pub fn exec(name: &str, bytes: &[u8], args: &[&str], personality: Personality) -> ProcessError {
+    let pid = match current() {
+        Some(pid) => pid,
+        None => return ProcessError::NoProcess,
+    };
+    let program = match loader::load(bytes, args, &[]) {
+        Ok(program) => program,
+        Err(err) => return err.into(),
+    };
+
+    let level_4_frame = program.address_space.level_4_frame();
+    let old = {
+        let mut table = TABLE.lock();
+        let process = table.processes.get_mut(&pid).expect("the process vanished");
+        process.name = String::from(name);
+        process.personality = personality;
+        process.program_break = ProgramBreak {
+            start: program.brk,
+            end: program.brk,
+        };
+        process.signals.reset_handlers();
+        process.address_space.replace(program.address_space)
+    };
+
+    thread::set_address_space(level_4_frame);
+    thread::set_fs_base(VirtAddr::new(0));
+    if let Some(old) = old {
+        let areas = memory::with(|memory| old.destroy(memory));
+        drop(areas);
+    }
+
+    unsafe { user::enter_user_mode(program.entry, program.stack_pointer) }
+}
+
+/// Ends the current process with `code`, or the current thread if it isn't part of a process.
+///
+/// The address space is freed right away, the parent is sent SIGCHLD and gets the code by waiting
+/// for the process.
+pub fn exit(code: i32) -> ! {
+    let pid = match current() {
+        Some(pid) => pid,
+        None => thread::exit(),
+    };
+
+    // Nothing of the process may be in use once the address space is freed
+    if let Some(kernel) = memory::with(|memory| memory.kernel_level_4_frame()) {
+        thread::set_address_space(kernel);
+    }
+
+    let (address_space, handles) = {
+        let mut table = TABLE.lock();
+        let table = &mut *table;
+
+        let process = table.processes.get_mut(&pid).expect("the process vanished");
+        process.state = ProcessState::Zombie(code);
+        let address_space = process.address_space.take();
+        let handles = core::mem::take(&mut process.handles);
+        let parent = process.parent;
+        if let Some(thread) = process.thread.take() {
+            table.threads.remove(&thread);
+        }
+
+        // Orphans are reaped when they exit, the zombies right now
+        table.processes.retain(|_, child| {
+            if child.parent != Some(pid) {
+                return true;
+            }
+            child.parent = None;
+            child.state == ProcessState::Running
+        });
+
+        match parent.and_then(|parent| table.processes.get_mut(&parent)) {
+            Some(parent) => parent.signals.raise(Signal::CHLD),
+            None if parent == Some(Pid::KERNEL) => {}
+            None => {
+                table.processes.remove(&pid);
+            }
+        }
+
+        (address_space, handles)
+    };
+
+    tty::release_foreground(pid);
+    drop(handles);
+    if let Some(address_space) = address_space {
+        // Dropped out of the memory lock, the areas may free shared memory
+        let areas = memory::with(|memory| address_space.destroy(memory));
+        drop(areas);
+    }
+
+    EXITED.notify_all();
+    thread::exit()
+}
+
+/// Blocks until a child of `parent` exits, `target` or any of them, and reaps it. A signal to act
+/// on interrupts the wait.
+pub fn wait(parent: Pid, target: Option<Pid>) -> Result<(Pid, i32), ProcessError> {
+    let mut result = None;
+    EXITED.wait_until(|| {
+        result = match try_reap(parent, target) {
+            None if signal::interrupted() => Some(Err(ProcessError::Interrupted)),
+            result => result,
+        };
+        result.is_some()
+    });
+    result.expect("woken without a result")
+}
+
+fn try_reap(parent: Pid, target: Option<Pid>) -> Option<Result<(Pid, i32), ProcessError>> {
+    let mut table = TABLE.lock();
+    let mut children = table.processes.values().filter(|process| {
+        process.parent == Some(parent) && target.map_or(true, |target| process.pid == target)
+    });
+
+    let mut found = false;
+    let zombie = children.find_map(|child| {
+        found = true;
+        match child.state {
+            ProcessState::Zombie(code) => Some((child.pid, code)),
+            ProcessState::Running => None,
+        }
+    });
+
+    match zombie {
+        Some((pid, code)) => {
+            table.processes.remove(&pid);
+            Some(Ok((pid, code)))
+        }
+        None if !found => Some(Err(ProcessError::NoChild)),
+        None => None,
+    }
+}
+
+pub fn processes() -> Vec<ProcessInfo> {
+    TABLE.lock().processes.values().map(Process::info).collect()
+}
@@ -0,0 +1,462 @@
+//! Signals: asynchronous notifications of a process, acted on when it next returns to ring 3.
+//!
+//! A caught signal runs its handler on the user stack, under a `SignalFrame` holding the
+//! interrupted registers. The handler returns to the restorer given with the handler, which calls
+//! `sigreturn` to resume the interrupted code.
+
+use core::{mem::size_of, ptr, slice};
+
+use x86::{
+    instructions::{
+        fpu::{self, FxSaveArea},
+        interrupts,
+    },
+    registers::rflags::RFlags,
+};
+
+use crate::{
+    memory::USER_END,
+    syscall::{user_ptr::UserSlice, SyscallError},
+    thread,
+    trap::TrapFrame,
+};
+
+use super::{Pid, ProcessError, ProcessState, TABLE};
+
+/// Signals are numbered from 1 to `NSIG - 1`, as on Linux.
+pub const NSIG: usize = 64;
+
+/// Bytes below the user stack pointer the handler frame leaves alone, the red zone of the SysV ABI.
+const RED_ZONE: u64 = 128;
+
+/// RFLAGS bits user code can change through `sigreturn`.
+const USER_FLAGS: u64 = RFlags::CARRY_FLAG.bits()
+    | RFlags::PARITY_FLAG.bits()
+    | RFlags::AUXILIARY_CARRY_FLAG.bits()
+    | RFlags::ZERO_FLAG.bits()
+    | RFlags::SIGN_FLAG.bits()
+    | RFlags::TRAP_FLAG.bits()
+    | RFlags::DIRECTION_FLAG.bits()
+    | RFlags::OVERFLOW_FLAG.bits();
+
+/// Interrupts enabled and the reserved bit 1.
+const BASE_FLAGS: u64 = 0x202;
+
+/// Offset of MXCSR in the `fxsave` area, its upper half is reserved.
+const MXCSR_OFFSET: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Signal(u8);
+
+impl Signal {
+    pub const HUP: Signal = Signal(1);
+    pub const INT: Signal = Signal(2);
+    pub const QUIT: Signal = Signal(3);
+    pub const ILL: Signal = Signal(4);
+    pub const TRAP: Signal = Signal(5);
+    pub const ABRT: Signal = Signal(6);
+    pub const BUS: Signal = Signal(7);
+    pub const FPE: Signal = Signal(8);
+    pub const KILL: Signal = Signal(9);
+    pub const USR1: Signal = Signal(10);
+    pub const SEGV: Signal = Signal(11);
+    pub const USR2: Signal = Signal(12);
+    pub const PIPE: Signal = Signal(13);
+    pub const ALRM: Signal = Signal(14);
+    pub const TERM: Signal = Signal(15);
+    pub const CHLD: Signal = Signal(17);
+    pub const CONT: Signal = Signal(18);
+    pub const URG: Signal = Signal(23);
+    pub const WINCH: Signal = Signal(28);
+
+    /// `None` for 0 and past the last signal.
+    pub fn new(number: u64) -> Option<Self> {
+        (1..NSIG as u64)
+            .contains(&number)
+            .then_some(Self(number as u8))
+    }
+
+    pub fn number(self) -> u8 {
+        self.0
+    }
+
+    /// SIGKILL always terminates the process.
+    pub fn can_catch(self) -> bool {
+        self != Self::KILL
+    }
+
+    /// Exit code of a process terminated by the signal, as shells report it.
+    pub fn exit_code(self) -> i32 {
+        128 + i32::from(self.0)
+    }
+
+    fn default_action(self) -> DefaultAction {
+        match self {
+            Self::CHLD | Self::CONT | Self::URG | Self::WINCH => DefaultAction::Ignore,
+            _ => DefaultAction::Terminate,
+        }
+    }
+
+    fn bit(self) -> u64 {
+        1 << (self.0 - 1)
+    }
+}
+
+enum DefaultAction {
+    Terminate,
+    Ignore,
+}
+
+/// Set of signals, bit `n - 1` standing for signal `n` like the `sigset_t` of Linux.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SignalSet(u64);
+
+impl SignalSet {
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(self) -> u64 {
+        self.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, signal: Signal) -> bool {
+        self.0 & signal.bit() != 0
+    }
+
+    pub fn insert(&mut self, signal: Signal) {
+        self.0 |= signal.bit();
+    }
+
+    pub fn remove(&mut self, signal: Signal) {
+        self.0 &= !signal.bit();
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    /// The signal with the lowest number.
+    pub fn first(self) -> Option<Signal> {
+        (self.0 != 0).then(|| Signal(self.0.trailing_zeros() as u8 + 1))
+    }
+
+    /// The signals that can be blocked, SIGKILL can't.
+    fn blockable(self) -> Self {
+        let mut set = self;
+        set.remove(Signal::KILL);
+        set
+    }
+}
+
+/// What a process does with a signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Default,
+    Ignore,
+    /// Calls `handler(signal)` with `mask` blocked on top of the current mask and the signal
+    /// itself. The handler returns to `restorer`, which must call `sigreturn`.
+    Handler {
+        handler: u64,
+        mask: SignalSet,
+        restorer: u64,
+    },
+}
+
+#[derive(Debug)]
+pub enum SignalError {
+    /// SIGKILL can't be caught, ignored or blocked.
+    Uncatchable,
+    /// The handler or the restorer isn't a user address.
+    BadAddress,
+}
+
+/// Signals of a process.
+#[derive(Debug, Clone)]
+pub struct SignalState {
+    pending: SignalSet,
+    blocked: SignalSet,
+    actions: [Action; NSIG],
+}
+
+impl SignalState {
+    pub fn new() -> Self {
+        Self {
+            pending: SignalSet::empty(),
+            blocked: SignalSet::empty(),
+            actions: [Action::Default; NSIG],
+        }
+    }
+
+    pub fn pending(&self) -> SignalSet {
+        self.pending
+    }
+
+    pub fn blocked(&self) -> SignalSet {
+        self.blocked
+    }
+
+    pub fn set_blocked(&mut self, blocked: SignalSet) {
+        self.blocked = blocked.blockable();
+    }
+
+    pub fn action(&self, signal: Signal) -> Action {
+        self.actions[usize::from(signal.0)]
+    }
+
+    /// Replaces the action of `signal` and returns the previous one.
+    pub fn set_action(&mut self, signal: Signal, action: Action) -> Result<Action, SignalError> {
+        if !signal.can_catch() {
+            return Err(SignalError::Uncatchable);
+        }
+        if let Action::Handler {
+            handler, restorer, ..
+        } = action
+        {
+            if handler >= USER_END || restorer >= USER_END {
+                return Err(SignalError::BadAddress);
+            }
+        }
+
+        let old = core::mem::replace(&mut self.actions[usize::from(signal.0)], action);
+        // Ignoring a signal discards it
+        if self.ignores(signal) {
+            self.pending.remove(signal);
+        }
+        Ok(old)
+    }
+
+    /// Makes `signal` pending, unless the process ignores it.
+    pub fn raise(&mut self, signal: Signal) {
+        if !self.ignores(signal) {
+            self.pending.insert(signal);
+        }
+    }
+
+    /// The handlers are gone with the program on `exec`, the ignored signals stay ignored.
+    pub fn reset_handlers(&mut self) {
+        for action in self.actions.iter_mut() {
+            if let Action::Handler { .. } = action {
+                *action = Action::Default;
+            }
+        }
+    }
+
+    fn ignores(&self, signal: Signal) -> bool {
+        match self.action(signal) {
+            Action::Ignore => true,
+            Action::Default => matches!(signal.default_action(), DefaultAction::Ignore),
+            Action::Handler { .. } => false,
+        }
+    }
+
+    /// Removes the next pending signal that isn't blocked.
+    fn take(&mut self) -> Option<Signal> {
+        let signal = self.pending.difference(self.blocked).first()?;
+        self.pending.remove(signal);
+        Some(signal)
+    }
+}
+
+impl Default for SignalState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pushed on the user stack under the handler of a signal, `restorer` being its return address.
+#[repr(C)]
+struct SignalFrame {
+    restorer: u64,
+    signal: u64,
+    registers: TrapFrame,
+    blocked: u64,
+    fpu: FxSaveArea,
+}
+
+/// Makes `signal` pending for the process `pid`, its thread is woken to interrupt a blocking wait.
+pub fn send(pid: Pid, signal: Signal) -> Result<(), ProcessError> {
+    let thread = {
+        let mut table = TABLE.lock();
+        match table.processes.get_mut(&pid) {
+            Some(process) if process.state == ProcessState::Running => {
+                process.signals.raise(signal);
+                process.thread
+            }
+            _ => return Err(ProcessError::NoProcess),
+        }
+    };
+
+    if let Some(thread) = thread {
+        thread::wake(thread);
+    }
+    Ok(())
+}
+
+/// Whether the current process has a signal to act on, blocking waits give up on it.
+pub fn interrupted() -> bool {
+    super::with_current(|process| {
+        let signals = &process.signals;
+        !signals.pending.difference(signals.blocked).is_empty()
+    })
+    .unwrap_or(false)
+}
+
+/// Sends a signal caused by the running process, like a fault. The process is terminated when
+/// it blocks or ignores the signal, it would only run into the fault again.
+pub fn force(signal: Signal) {
+    super::with_current(|process| {
+        let signals = &mut process.signals;
+        let caught = matches!(signals.action(signal), Action::Handler { .. });
+        if !caught || signals.blocked.contains(signal) {
+            signals.actions[usize::from(signal.0)] = Action::Default;
+            signals.blocked.remove(signal);
+        }
+        signals.pending.insert(signal);
+    });
+}
+
+/// Acts on the pending signals of the current process before `frame` returns to ring 3.
+pub fn deliver(frame: &mut TrapFrame) {
+    loop {
+        let next = super::with_current(|process| {
+            let signal = process.signals.take()?;
+            Some((
+                signal,
+                process.signals.action(signal),
+                process.signals.blocked,
+            ))
+        })
+        .flatten();
+        let (signal, action, blocked) = match next {
+            Some(next) => next,
+            None => return,
+        };
+
+        match action {
+            Action::Ignore => {}
+            Action::Default => match signal.default_action() {
+                DefaultAction::Ignore => {}
+                DefaultAction::Terminate => terminate(signal),
+            },
+            Action::Handler {
+                handler,
+                mask,
+                restorer,
+            } => {
+                if push_frame(frame, signal, handler, restorer, blocked).is_err() {
+                    terminate(Signal::SEGV);
+                }
+                super::with_current(|process| {
+                    let mut blocked = blocked.union(mask);
+                    blocked.insert(signal);
+                    process.signals.set_blocked(blocked);
+                });
+                return;
+            }
+        }
+    }
+}
+
+/// Resumes the code interrupted by a signal from the frame the handler returned above.
+///
+/// The restorer calls `sigreturn` with the stack pointer right after the popped return address.
+pub fn sigreturn(frame: &mut TrapFrame) {
+    if restore_frame(frame).is_err() {
+        force(Signal::SEGV);
+    }
+}
+
+fn push_frame(
+    frame: &mut TrapFrame,
+    signal: Signal,
+    handler: u64,
+    restorer: u64,
+    blocked: SignalSet,
+) -> Result<(), SyscallError> {
+    let mut signal_frame = SignalFrame {
+        restorer,
+        signal: u64::from(signal.0),
+        registers: *frame,
+        blocked: blocked.bits(),
+        fpu: FxSaveArea::new(),
+    };
+    // The registers of the interrupted code are still loaded
+    unsafe { fpu::fxsave(&mut signal_frame.fpu) };
+
+    // The handler is entered as if called, with RSP + 8 aligned on 16 bytes
+    let size = size_of::<SignalFrame>() as u64;
+    let top = frame
+        .rsp
+        .checked_sub(RED_ZONE + size)
+        .ok_or(SyscallError::BadAddress)?;
+    let address = (top & !0xF) - 8;
+
+    let bytes = unsafe {
+        slice::from_raw_parts(
+            &signal_frame as *const SignalFrame as *const u8,
+            size_of::<SignalFrame>(),
+        )
+    };
+    UserSlice::new(address, size)?.write(bytes)?;
+
+    frame.rip = handler;
+    frame.rsp = address;
+    frame.rdi = u64::from(signal.0);
+    frame.rflags &= !(RFlags::DIRECTION_FLAG.bits() | RFlags::TRAP_FLAG.bits());
+    Ok(())
+}
+
+fn restore_frame(frame: &mut TrapFrame) -> Result<(), SyscallError> {
+    let address = frame.rsp.checked_sub(8).ok_or(SyscallError::BadAddress)?;
+    let bytes = UserSlice::new(address, size_of::<SignalFrame>() as u64)?.read()?;
+    let mut signal_frame = unsafe { ptr::read_unaligned(bytes.as_ptr() as *const SignalFrame) };
+
+    let saved = signal_frame.registers;
+    if saved.rip >= USER_END {
+        return Err(SyscallError::BadAddress);
+    }
+
+    // Everything but the selectors and the privileged flags comes from the frame
+    *frame = TrapFrame {
+        vector: frame.vector,
+        error_code: frame.error_code,
+        cs: frame.cs,
+        ss: frame.ss,
+        rflags: (saved.rflags & USER_FLAGS) | BASE_FLAGS,
+        ..saved
+    };
+
+    // Reserved MXCSR bits make `fxrstor` fault
+    let area = unsafe {
+        slice::from_raw_parts_mut(&mut signal_frame.fpu as *mut FxSaveArea as *mut u8, 512)
+    };
+    area[MXCSR_OFFSET + 2..MXCSR_OFFSET + 4].fill(0);
+    unsafe { fpu::fxrstor(&signal_frame.fpu) };
+
+    super::with_current(|process| {
+        process
+            .signals
+            .set_blocked(SignalSet::from_bits(signal_frame.blocked))
+    });
+    Ok(())
+}
+
+/// Ends the current process as killed by `signal`.
+fn terminate(signal: Signal) -> ! {
+    // As from a syscall, the trap handlers run with the interrupts disabled
+    interrupts::enable();
+    super::exit(signal.exit_code())
+}
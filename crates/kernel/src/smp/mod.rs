@@ -0,0 +1,205 @@
+mod trampoline;
+
+use core::{
+    mem,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use acpi::{Acpi, AcpiError};
+use alloc::{vec, vec::Vec};
+use apic::lapic::LocalApic;
+use conquer_once::spin::OnceCell;
+use vga::println;
+use x86::{
+    addr::PhysAddr,
+    structures::paging::{
+        frame::PhysFrame,
+        frame_alloc::FrameAllocator,
+        mapper::{MapToError, Mapper},
+        page::Size4KiB,
+    },
+};
+
+use crate::{
+    interrupts::{self, InterruptIndex, LAPIC},
+    memory::{BootInfoFrameAllocator, KernelStack},
+    percpu::{self, MAX_CPUS},
+    thread, time,
+};
+
+use trampoline::Trampoline;
+
+/// The startup IPI vector is a page number, the trampoline must start below 1 MiB.
+const TRAMPOLINE_LIMIT: u64 = 0x10_0000;
+
+const INIT_DELAY: Duration = Duration::from_millis(10);
+const STARTUP_DELAY: Duration = Duration::from_micros(200);
+const STARTUP_TIMEOUT_MILLIS: u64 = 100;
+
+static CPUS: OnceCell<Vec<Cpu>> = OnceCell::uninit();
+static ONLINE: AtomicUsize = AtomicUsize::new(1);
+
+#[derive(Debug)]
+pub enum SmpError {
+    Acpi(AcpiError),
+    Map(MapToError<Size4KiB>),
+    MadtNotFound,
+    TrampolineUnavailable,
+    PageTableUnreachable,
+}
+
+impl From<AcpiError> for SmpError {
+    fn from(value: AcpiError) -> Self {
+        SmpError::Acpi(value)
+    }
+}
+
+impl From<MapToError<Size4KiB>> for SmpError {
+    fn from(value: MapToError<Size4KiB>) -> Self {
+        SmpError::Map(value)
+    }
+}
+
+/// Data of one processor, the BSP has the index 0.
+#[derive(Debug)]
+pub struct Cpu {
+    pub index: usize,
+    pub apic_id: u8,
+    online: AtomicBool,
+}
+
+impl Cpu {
+    fn new(index: usize, apic_id: u8) -> Self {
+        Self {
+            index,
+            apic_id,
+            online: AtomicBool::new(index == 0),
+        }
+    }
+
+    pub fn is_bsp(&self) -> bool {
+        self.index == 0
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.online.load(Ordering::Acquire)
+    }
+}
+
+/// Takes the frame the APs start from, the first free one below 1 MiB.
+pub fn reserve_trampoline(
+    frame_allocator: &mut BootInfoFrameAllocator,
+) -> Result<PhysFrame, SmpError> {
+    frame_allocator
+        .allocate_below(PhysAddr::new(TRAMPOLINE_LIMIT))
+        .ok_or(SmpError::TrampolineUnavailable)
+}
+
+/// Starts the processors listed in the MADT and returns the number of online CPUs.
+///
+/// Threads must be initialized, the APs join the scheduler once started.
+pub fn init(
+    trampoline: PhysFrame,
+    acpi: &Acpi,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<usize, SmpError> {
+    let madt = acpi.madt()?.ok_or(SmpError::MadtNotFound)?;
+    if !Trampoline::page_table_reachable() {
+        return Err(SmpError::PageTableUnreachable);
+    }
+
+    let lapic = interrupts::init_lapic(mapper, frame_allocator)?;
+    let bsp_id = lapic.id();
+
+    let mut cpus = vec![Cpu::new(0, bsp_id)];
+    for processor in madt.processors() {
+        if processor.apic_id == bsp_id {
+            continue;
+        }
+
+        if cpus.len() == MAX_CPUS {
+            println!("WARNING: only {} CPUs are supported", MAX_CPUS);
+            break;
+        }
+        cpus.push(Cpu::new(cpus.len(), processor.apic_id));
+    }
+    let cpus = CPUS.get_or_init(|| cpus);
+
+    let trampoline =
+        unsafe { Trampoline::install(trampoline, acpi.phys_offset(), mapper, frame_allocator)? };
+
+    // The APs reuse the timer count measured on the BSP
+    time::lapic_timer_count(lapic);
+
+    for cpu in &cpus[1..] {
+        let stack = KernelStack::map(mapper, frame_allocator)?;
+        start_ap(cpu, lapic, &trampoline, stack);
+    }
+
+    Ok(ONLINE.load(Ordering::Acquire))
+}
+
+/// Runs the INIT-SIPI-SIPI sequence and waits for the AP to come online.
+fn start_ap(cpu: &'static Cpu, lapic: &LocalApic, trampoline: &Trampoline, stack: KernelStack) {
+    trampoline.prepare(stack.top(), ap_main, cpu as *const Cpu as u64);
+    // The AP runs on it for good, even one that comes online after the timeout
+    mem::forget(stack);
+
+    lapic.send_init(cpu.apic_id);
+    time::delay(INIT_DELAY);
+
+    for _ in 0..2 {
+        lapic.send_startup(cpu.apic_id, trampoline.vector());
+        time::delay(STARTUP_DELAY);
+        if cpu.is_online() {
+            return;
+        }
+    }
+
+    for _ in 0..STARTUP_TIMEOUT_MILLIS {
+        if cpu.is_online() {
+            return;
+        }
+        time::delay(Duration::from_millis(1));
+    }
+
+    println!(
+        "WARNING: CPU {} (APIC {}) did not start",
+        cpu.index, cpu.apic_id
+    );
+}
+
+extern "C" fn ap_main(cpu: u64) -> ! {
+    let cpu = unsafe { &*(cpu as *const Cpu) };
+
+    percpu::init(cpu.index);
+    crate::init_ap();
+    let lapic = LAPIC.try_get().expect("the LAPIC is not initialized");
+    lapic.enable(InterruptIndex::Spurious.as_u8());
+    time::start_lapic_timer(lapic);
+    thread::init_cpu(cpu.index);
+
+    cpu.online.store(true, Ordering::Release);
+    ONLINE.fetch_add(1, Ordering::AcqRel);
+
+    thread::run_idle()
+}
+
+pub fn cpus() -> &'static [Cpu] {
+    CPUS.try_get().map_or(&[], Vec::as_slice)
+}
+
+pub fn online_count() -> usize {
+    ONLINE.load(Ordering::Acquire)
+}
+
+/// The CPU running the caller, `None` until `init` is done.
+pub fn current() -> Option<&'static Cpu> {
+    cpus().get(cpu_index())
+}
+
+pub fn cpu_index() -> usize {
+    percpu::index()
+}
@@ -0,0 +1,222 @@
+use core::{arch::global_asm, ptr};
+
+use x86::{
+    addr::{PhysAddr, VirtAddr},
+    registers::control::Cr3,
+    structures::paging::{
+        frame::PhysFrame,
+        frame_alloc::FrameAllocator,
+        mapper::{MapToError, Mapper},
+        page::{Page, PageSize, Size4KiB},
+        page_table::PageTableFlags,
+    },
+};
+
+// Started by the SIPI in real mode at the start of its frame, with CS set to the frame segment.
+// The code goes through protected mode to long mode, using the page tables of the BSP, then jumps
+// to the kernel entry on the stack prepared for this AP. The addresses are patched at runtime.
+global_asm!(
+    r#"
+.code16
+.global ap_trampoline_start
+ap_trampoline_start:
+    cli
+    cld
+    mov %cs, %ax
+    mov %ax, %ds
+    xor %ebx, %ebx
+    mov %cs, %bx
+    shl $4, %ebx
+    lgdtl (ap_trampoline_gdtr - ap_trampoline_start)
+    mov %cr0, %eax
+    or $1, %eax
+    mov %eax, %cr0
+    ljmpl *(ap_trampoline_far32 - ap_trampoline_start)
+
+.code32
+.global ap_trampoline_protected_mode
+ap_trampoline_protected_mode:
+    mov $0x10, %ax
+    mov %ax, %ds
+    mov %ax, %es
+    mov %ax, %ss
+    mov %cr4, %eax
+    or $(1 << 5), %eax
+    mov %eax, %cr4
+    mov (ap_trampoline_cr3 - ap_trampoline_start)(%ebx), %eax
+    mov %eax, %cr3
+    mov $0xC0000080, %ecx
+    rdmsr
+    or $((1 << 8) | (1 << 11)), %eax
+    wrmsr
+    mov %cr0, %eax
+    or $((1 << 31) | (1 << 16)), %eax
+    mov %eax, %cr0
+    ljmpl *(ap_trampoline_far64 - ap_trampoline_start)(%ebx)
+
+.code64
+.global ap_trampoline_long_mode
+ap_trampoline_long_mode:
+    mov %ebx, %ebx
+    xor %eax, %eax
+    mov %ax, %ds
+    mov %ax, %es
+    mov %ax, %ss
+    mov %ax, %fs
+    mov %ax, %gs
+    mov (ap_trampoline_stack - ap_trampoline_start)(%rbx), %rsp
+    mov (ap_trampoline_argument - ap_trampoline_start)(%rbx), %rdi
+    mov (ap_trampoline_entry - ap_trampoline_start)(%rbx), %rax
+    call *%rax
+    ud2
+
+.balign 8
+.global ap_trampoline_gdt
+ap_trampoline_gdt:
+    .quad 0
+    .quad 0x00AF9A000000FFFF
+    .quad 0x00CF92000000FFFF
+    .quad 0x00CF9A000000FFFF
+.global ap_trampoline_gdtr
+ap_trampoline_gdtr:
+    .word 4 * 8 - 1
+    .long 0
+.global ap_trampoline_far32
+ap_trampoline_far32:
+    .long 0
+    .word 0x18
+.global ap_trampoline_far64
+ap_trampoline_far64:
+    .long 0
+    .word 0x08
+.balign 8
+.global ap_trampoline_cr3
+ap_trampoline_cr3:
+    .quad 0
+.global ap_trampoline_stack
+ap_trampoline_stack:
+    .quad 0
+.global ap_trampoline_entry
+ap_trampoline_entry:
+    .quad 0
+.global ap_trampoline_argument
+ap_trampoline_argument:
+    .quad 0
+.global ap_trampoline_end
+ap_trampoline_end:
+"#,
+    options(att_syntax)
+);
+
+extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_protected_mode: u8;
+    static ap_trampoline_long_mode: u8;
+    static ap_trampoline_gdt: u8;
+    static ap_trampoline_gdtr: u8;
+    static ap_trampoline_far32: u8;
+    static ap_trampoline_far64: u8;
+    static ap_trampoline_cr3: u8;
+    static ap_trampoline_stack: u8;
+    static ap_trampoline_entry: u8;
+    static ap_trampoline_argument: u8;
+    static ap_trampoline_end: u8;
+}
+
+/// Offset of a trampoline label from the start of the trampoline.
+fn offset(label: &u8) -> u64 {
+    let start = unsafe { &ap_trampoline_start } as *const u8 as u64;
+    label as *const u8 as u64 - start
+}
+
+pub struct Trampoline {
+    frame: PhysFrame,
+    virt: VirtAddr,
+}
+
+impl Trampoline {
+    /// Copies the trampoline to `frame` and identity maps it, paging is enabled from there.
+    ///
+    /// `frame` must be below 1 MiB and the whole physical memory mapped at `phys_offset`.
+    pub unsafe fn install(
+        frame: PhysFrame,
+        phys_offset: VirtAddr,
+        mapper: &mut impl Mapper<Size4KiB>,
+        frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    ) -> Result<Self, MapToError<Size4KiB>> {
+        let size = offset(unsafe { &ap_trampoline_end });
+        assert!(
+            size <= Size4KiB::SIZE,
+            "the AP trampoline doesn't fit in a frame"
+        );
+
+        let virt = phys_offset + frame.start_address().as_u64();
+        unsafe {
+            ptr::copy_nonoverlapping(
+                &ap_trampoline_start as *const u8,
+                virt.as_mut_ptr::<u8>(),
+                size as usize,
+            )
+        };
+
+        let page: Page =
+            Page::new_containing_address(VirtAddr::new(frame.start_address().as_u64()));
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        match unsafe { mapper.map_to(page, frame, flags, frame_allocator) } {
+            Ok(flush) => flush.flush(),
+            Err(MapToError::PageAlreadyMapped(mapped))
+                if mapped.start_address() == frame.start_address() => {}
+            Err(err) => return Err(err),
+        }
+
+        let trampoline = Self { frame, virt };
+        let base = frame.start_address().as_u64();
+        unsafe {
+            trampoline.write(
+                &ap_trampoline_gdtr,
+                2,
+                (base + offset(&ap_trampoline_gdt)) as u32,
+            );
+            trampoline.write(
+                &ap_trampoline_far32,
+                0,
+                (base + offset(&ap_trampoline_protected_mode)) as u32,
+            );
+            trampoline.write(
+                &ap_trampoline_far64,
+                0,
+                (base + offset(&ap_trampoline_long_mode)) as u32,
+            );
+        }
+
+        Ok(trampoline)
+    }
+
+    /// Vector of the startup IPI, the page number of the trampoline.
+    pub fn vector(&self) -> u8 {
+        (self.frame.start_address().as_u64() / Size4KiB::SIZE) as u8
+    }
+
+    /// Sets what the next started AP runs, `entry` is called with `argument` on `stack_top`.
+    ///
+    /// The page tables must be below 4 GiB since CR3 is loaded from protected mode.
+    pub fn prepare(&self, stack_top: VirtAddr, entry: extern "C" fn(u64) -> !, argument: u64) {
+        let (page_table, _) = Cr3::read();
+        unsafe {
+            self.write(&ap_trampoline_cr3, 0, page_table.start_address().as_u64());
+            self.write(&ap_trampoline_stack, 0, stack_top.as_u64());
+            self.write(&ap_trampoline_entry, 0, entry as usize as u64);
+            self.write(&ap_trampoline_argument, 0, argument);
+        }
+    }
+
+    pub fn page_table_reachable() -> bool {
+        let (page_table, _) = Cr3::read();
+        page_table.start_address() < PhysAddr::new(1 << 32)
+    }
+
+    unsafe fn write<T>(&self, label: &u8, field_offset: u64, value: T) {
+        let ptr = (self.virt + offset(label) + field_offset).as_mut_ptr::<T>();
+        unsafe { ptr::write_unaligned(ptr, value) };
+    }
+}
@@ -0,0 +1,196 @@
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+#[cfg(debug_assertions)]
+use core::{panic::Location, sync::atomic::AtomicUsize};
+
+use x86::instructions::interrupts;
+
+#[cfg(debug_assertions)]
+use super::lockdep;
+
+/// Spins before a contended lock is reported as a possible deadlock.
+#[cfg(debug_assertions)]
+const DEADLOCK_SPINS: u64 = 1 << 28;
+
+/// A spin lock that masks interrupts on the local CPU while it is held.
+///
+/// It can be shared between interrupt handlers and normal code: a handler can't interrupt a holder
+/// on the same CPU, and holders on other CPUs just make it spin. Nested guards must be dropped in
+/// the reverse order they were taken, the outermost one restores the interrupts.
+pub struct IrqMutex<T: ?Sized> {
+    name: &'static str,
+    locked: AtomicBool,
+    // Location of the current holder, reported when a lock seems stuck
+    #[cfg(debug_assertions)]
+    holder: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for IrqMutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for IrqMutex<T> {}
+
+impl<T> IrqMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self::named("", value)
+    }
+
+    /// Creates a mutex whose name is used by the lock diagnostics.
+    pub const fn named(name: &'static str, value: T) -> Self {
+        Self {
+            name,
+            locked: AtomicBool::new(false),
+            #[cfg(debug_assertions)]
+            holder: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> IrqMutex<T> {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> IrqMutexGuard<'_, T> {
+        #[cfg(debug_assertions)]
+        let location = Location::caller();
+        let interrupts_enabled = interrupts::are_enabled();
+        interrupts::disable();
+
+        #[cfg(debug_assertions)]
+        lockdep::acquire(self.id(), self.name, location);
+
+        #[cfg(debug_assertions)]
+        let mut spins = 0u64;
+        while !self.try_acquire() {
+            core::hint::spin_loop();
+
+            #[cfg(debug_assertions)]
+            {
+                spins += 1;
+                if spins == DEADLOCK_SPINS {
+                    lockdep::report_stuck(self.id(), self.name, location, self.holder());
+                }
+            }
+        }
+
+        #[cfg(debug_assertions)]
+        self.set_holder(location);
+
+        IrqMutexGuard {
+            mutex: self,
+            interrupts_enabled,
+        }
+    }
+
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<IrqMutexGuard<'_, T>> {
+        let interrupts_enabled = interrupts::are_enabled();
+        interrupts::disable();
+
+        if !self.try_acquire() {
+            if interrupts_enabled {
+                interrupts::enable();
+            }
+            return None;
+        }
+
+        #[cfg(debug_assertions)]
+        self.set_holder(Location::caller());
+
+        Some(IrqMutexGuard {
+            mutex: self,
+            interrupts_enabled,
+        })
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    fn try_acquire(&self) -> bool {
+        self.locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    #[cfg(debug_assertions)]
+    fn id(&self) -> usize {
+        self as *const Self as *const u8 as usize
+    }
+
+    #[cfg(debug_assertions)]
+    fn set_holder(&self, location: &'static Location<'static>) {
+        let ptr = location as *const Location<'static> as usize;
+        self.holder.store(ptr, Ordering::Relaxed);
+        lockdep::acquired(self.id(), self.name, location);
+    }
+
+    #[cfg(debug_assertions)]
+    fn holder(&self) -> Option<&'static Location<'static>> {
+        let ptr = self.holder.load(Ordering::Relaxed) as *const Location<'static>;
+        unsafe { ptr.as_ref() }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for IrqMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => f
+                .debug_struct("IrqMutex")
+                .field("name", &self.name)
+                .field("value", &&*guard)
+                .finish(),
+            None => f
+                .debug_struct("IrqMutex")
+                .field("name", &self.name)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+pub struct IrqMutexGuard<'a, T: ?Sized> {
+    mutex: &'a IrqMutex<T>,
+    interrupts_enabled: bool,
+}
+
+impl<T: ?Sized> Deref for IrqMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for IrqMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for IrqMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.locked.store(false, Ordering::Release);
+
+        #[cfg(debug_assertions)]
+        lockdep::released(self.mutex.id());
+
+        if self.interrupts_enabled {
+            interrupts::enable();
+        }
+    }
+}
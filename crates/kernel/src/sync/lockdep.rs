@@ -0,0 +1,209 @@
+use core::{cell::RefCell, fmt, panic::Location};
+
+use spin::Mutex;
+use vga::println;
+
+use crate::percpu;
+
+/// Locks held at once by a CPU that are tracked, the deeper ones are ignored.
+const MAX_HELD: usize = 16;
+/// Distinct lock orderings remembered, the checker stops learning new ones past it.
+const MAX_EDGES: usize = 256;
+
+#[derive(Clone, Copy)]
+struct LockRef {
+    id: usize,
+    name: &'static str,
+    location: &'static Location<'static>,
+}
+
+impl fmt::Display for LockRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            "" => write!(f, "IrqMutex@{:#x}", self.id)?,
+            name => write!(f, "{}", name)?,
+        }
+        write!(f, " (taken at {})", self.location)
+    }
+}
+
+/// Stack of the locks held by a CPU, interrupts are masked while an `IrqMutex` is held so the
+/// holder can't move to another CPU.
+struct Held {
+    locks: [Option<LockRef>; MAX_HELD],
+    len: usize,
+}
+
+impl Held {
+    const fn new() -> Self {
+        Self {
+            locks: [None; MAX_HELD],
+            len: 0,
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = LockRef> + '_ {
+        self.locks[..self.len].iter().flatten().copied()
+    }
+}
+
+/// An edge `before -> after` means `after` was taken while `before` was held.
+#[derive(Clone, Copy)]
+struct Edge {
+    before: LockRef,
+    after: LockRef,
+}
+
+struct Graph {
+    edges: [Option<Edge>; MAX_EDGES],
+    len: usize,
+}
+
+impl Graph {
+    const fn new() -> Self {
+        Self {
+            edges: [None; MAX_EDGES],
+            len: 0,
+        }
+    }
+
+    fn edges(&self) -> impl Iterator<Item = Edge> + '_ {
+        self.edges[..self.len].iter().flatten().copied()
+    }
+
+    fn contains(&self, before: usize, after: usize) -> bool {
+        self.edges()
+            .any(|edge| edge.before.id == before && edge.after.id == after)
+    }
+
+    fn insert(&mut self, edge: Edge) {
+        if self.len < MAX_EDGES {
+            self.edges[self.len] = Some(edge);
+            self.len += 1;
+        }
+    }
+
+    /// Searches a chain of edges from `from` to `to` and returns its first edge.
+    fn path(&self, from: usize, to: usize) -> Option<Edge> {
+        let mut visited = [false; MAX_EDGES];
+        let mut stack = [(0, None); MAX_EDGES];
+        stack[0] = (from, None::<Edge>);
+        let mut len = 1;
+
+        while len > 0 {
+            len -= 1;
+            let (node, first) = stack[len];
+
+            for (i, edge) in self.edges().enumerate() {
+                if visited[i] || edge.before.id != node {
+                    continue;
+                }
+                visited[i] = true;
+
+                let first = first.or(Some(edge));
+                if edge.after.id == to {
+                    return first;
+                }
+                stack[len] = (edge.after.id, first);
+                len += 1;
+            }
+        }
+
+        None
+    }
+}
+
+percpu! {
+    static HELD: RefCell<Held> = RefCell::new(Held::new());
+}
+
+static GRAPH: Mutex<Graph> = Mutex::new(Graph::new());
+
+/// Checks the lock about to be taken against the locks held by the CPU.
+///
+/// Order inversions are reported once. Recursive locking is reported every time since the CPU
+/// then spins forever, it isn't a panic as the panic handler may need the same lock to print.
+pub fn acquire(id: usize, name: &'static str, location: &'static Location<'static>) {
+    let lock = LockRef { id, name, location };
+
+    HELD.with(|held| {
+        let held = held.borrow();
+        if let Some(holder) = held.iter().find(|holder| holder.id == id) {
+            println!("LOCKDEP: recursive locking of {}", holder);
+            println!("LOCKDEP: taken again at {}", location);
+            return;
+        }
+
+        let mut graph = GRAPH.lock();
+        for holder in held.iter() {
+            if graph.contains(holder.id, id) {
+                continue;
+            }
+
+            if let Some(edge) = graph.path(id, holder.id) {
+                println!("LOCKDEP: possible deadlock, lock order inversion");
+                println!("LOCKDEP: {} is taken while holding {}", lock, holder);
+                println!(
+                    "LOCKDEP: but {} was taken while holding {}",
+                    edge.after, edge.before
+                );
+            }
+
+            graph.insert(Edge {
+                before: holder,
+                after: lock,
+            });
+        }
+    });
+}
+
+/// Records a lock taken by the CPU.
+pub fn acquired(id: usize, name: &'static str, location: &'static Location<'static>) {
+    HELD.with(|held| {
+        let mut held = held.borrow_mut();
+        if held.len < MAX_HELD {
+            let len = held.len;
+            held.locks[len] = Some(LockRef { id, name, location });
+            held.len += 1;
+        }
+    });
+}
+
+pub fn released(id: usize) {
+    HELD.with(|held| {
+        let mut held = held.borrow_mut();
+        let len = held.len;
+        if let Some(i) = held.locks[..len].iter().rposition(|lock| match lock {
+            Some(lock) => lock.id == id,
+            None => false,
+        }) {
+            held.locks.copy_within(i + 1..len, i);
+            held.locks[len - 1] = None;
+            held.len -= 1;
+        }
+    });
+}
+
+/// Reports a lock the CPU has been spinning on for a long time.
+pub fn report_stuck(
+    id: usize,
+    name: &'static str,
+    location: &'static Location<'static>,
+    holder: Option<&'static Location<'static>>,
+) {
+    let lock = LockRef { id, name, location };
+    println!(
+        "LOCKDEP: possible deadlock, CPU {} is stuck on {}",
+        percpu::index(),
+        lock
+    );
+    if let Some(holder) = holder {
+        println!("LOCKDEP: the lock was last taken at {}", holder);
+    }
+
+    HELD.with(|held| {
+        for lock in held.borrow().iter() {
+            println!("LOCKDEP: the CPU holds {}", lock);
+        }
+    });
+}
@@ -0,0 +1,92 @@
+pub mod irq_mutex;
+#[cfg(debug_assertions)]
+mod lockdep;
+pub mod mpsc;
+pub mod mutex;
+pub mod notify;
+pub mod oneshot;
+pub mod rwlock;
+pub mod semaphore;
+
+use core::task::Waker;
+
+use alloc::collections::VecDeque;
+
+pub use irq_mutex::{IrqMutex, IrqMutexGuard};
+pub use mutex::{Mutex, MutexGuard};
+pub use notify::Notify;
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{Semaphore, SemaphorePermit};
+
+/// FIFO list of the tasks waiting on a primitive.
+///
+/// Each waiting future keeps the id it was registered with. When a woken future is dropped
+/// before it could use the resource, it must hand the wakeup over with `wake_one`.
+pub(crate) struct WaitList {
+    next_id: u64,
+    waiters: VecDeque<(u64, Waker)>,
+}
+
+impl WaitList {
+    pub const fn new() -> Self {
+        Self {
+            next_id: 0,
+            waiters: VecDeque::new(),
+        }
+    }
+
+    /// Registers or updates the waker of a waiting future.
+    pub fn register(&mut self, id: &mut Option<u64>, waker: &Waker) {
+        if let Some(id) = *id {
+            if let Some((_, current)) = self.waiters.iter_mut().find(|(i, _)| *i == id) {
+                if !current.will_wake(waker) {
+                    *current = waker.clone();
+                }
+                return;
+            }
+        }
+
+        let new_id = self.next_id;
+        self.next_id += 1;
+        self.waiters.push_back((new_id, waker.clone()));
+        *id = Some(new_id);
+    }
+
+    /// Removes a waiter, returns `false` if it was already woken.
+    pub fn remove(&mut self, id: u64) -> bool {
+        match self.waiters.iter().position(|(i, _)| *i == id) {
+            Some(index) => {
+                self.waiters.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        self.waiters.iter().any(|(i, _)| *i == id)
+    }
+
+    pub fn wake_one(&mut self) -> bool {
+        self.wake_oldest().is_some()
+    }
+
+    /// Wakes the waiter registered first, returns its id.
+    pub fn wake_oldest(&mut self) -> Option<u64> {
+        let (id, waker) = self.waiters.pop_front()?;
+        waker.wake();
+        Some(id)
+    }
+
+    pub fn wake_all(&mut self) {
+        while self.wake_one() {}
+    }
+
+    pub fn len(&self) -> usize {
+        self.waiters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+}
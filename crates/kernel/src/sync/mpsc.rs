@@ -0,0 +1,256 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use alloc::{collections::VecDeque, sync::Arc};
+use futures_util::Stream;
+use x86::instructions::interrupts;
+
+use super::WaitList;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+    Closed(T),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Closed,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    capacity: usize,
+    senders: usize,
+    receiver_dropped: bool,
+    receiver_waker: Option<Waker>,
+    send_waiters: WaitList,
+}
+
+impl<T> State<T> {
+    fn is_full(&self) -> bool {
+        self.queue.len() >= self.capacity
+    }
+
+    fn push(&mut self, value: T) {
+        self.queue.push_back(value);
+        if let Some(waker) = self.receiver_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+struct Shared<T> {
+    state: spin::Mutex<State<T>>,
+}
+
+impl<T> Shared<T> {
+    fn with_state<R>(&self, f: impl FnOnce(&mut State<T>) -> R) -> R {
+        interrupts::without_interrupts(|| f(&mut self.state.lock()))
+    }
+}
+
+/// Creates a bounded channel, the buffer is allocated upfront so that `try_send` never allocates.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "mpsc channel capacity must be greater than 0");
+
+    let shared = Arc::new(Shared {
+        state: spin::Mutex::new(State {
+            queue: VecDeque::with_capacity(capacity),
+            capacity,
+            senders: 1,
+            receiver_dropped: false,
+            receiver_waker: None,
+            send_waiters: WaitList::new(),
+        }),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Waits for a free slot, the value is given back if the receiver was dropped.
+    pub fn send(&self, value: T) -> Send<'_, T> {
+        Send {
+            sender: self,
+            value: Some(value),
+            waiter: None,
+        }
+    }
+
+    /// Sends without waiting nor allocating, can be called from interrupt handlers.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.shared.with_state(|state| {
+            if state.receiver_dropped {
+                return Err(TrySendError::Closed(value));
+            }
+
+            if state.is_full() {
+                return Err(TrySendError::Full(value));
+            }
+
+            state.push(value);
+            Ok(())
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.with_state(|state| state.receiver_dropped)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.with_state(|state| state.senders += 1);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.with_state(|state| {
+            state.senders -= 1;
+            if state.senders == 0 {
+                if let Some(waker) = state.receiver_waker.take() {
+                    waker.wake();
+                }
+            }
+        });
+    }
+}
+
+pub struct Send<'a, T> {
+    sender: &'a Sender<T>,
+    value: Option<T>,
+    waiter: Option<u64>,
+}
+
+// The value is only moved around, it is never pinned
+impl<T> Unpin for Send<'_, T> {}
+
+impl<T> Future for Send<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+
+        this.sender.shared.with_state(|state| {
+            let value = this.value.take().expect("Send polled after completion");
+
+            if state.receiver_dropped {
+                return Poll::Ready(Err(SendError(value)));
+            }
+
+            if state.is_full() {
+                this.value = Some(value);
+                state.send_waiters.register(&mut this.waiter, cx.waker());
+                return Poll::Pending;
+            }
+
+            if let Some(id) = this.waiter.take() {
+                state.send_waiters.remove(id);
+            }
+            state.push(value);
+            Poll::Ready(Ok(()))
+        })
+    }
+}
+
+impl<T> Drop for Send<'_, T> {
+    fn drop(&mut self) {
+        if let (Some(_), Some(id)) = (&self.value, self.waiter) {
+            self.sender.shared.with_state(|state| {
+                // The free slot was meant for us, pass it to the next sender
+                if !state.send_waiters.remove(id) && !state.is_full() {
+                    state.send_waiters.wake_one();
+                }
+            });
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Resolves to `None` once all the senders are dropped and the buffer is empty.
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.shared
+            .with_state(|state| match state.queue.pop_front() {
+                Some(value) => {
+                    state.send_waiters.wake_one();
+                    Ok(value)
+                }
+                None if state.senders == 0 => Err(TryRecvError::Closed),
+                None => Err(TryRecvError::Empty),
+            })
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<T>> {
+        self.shared.with_state(|state| {
+            if let Some(value) = state.queue.pop_front() {
+                state.send_waiters.wake_one();
+                return Poll::Ready(Some(value));
+            }
+
+            if state.senders == 0 {
+                return Poll::Ready(None);
+            }
+
+            state.receiver_waker = Some(cx.waker().clone());
+            Poll::Pending
+        })
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.with_state(|state| {
+            state.receiver_dropped = true;
+            state.send_waiters.wake_all();
+        });
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+pub struct Recv<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<T> Future for Recv<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        self.receiver.poll_recv(cx)
+    }
+}
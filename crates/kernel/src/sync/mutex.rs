@@ -0,0 +1,156 @@
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use x86::instructions::interrupts;
+
+use super::WaitList;
+
+struct State {
+    locked: bool,
+    waiters: WaitList,
+}
+
+/// A mutex whose lock can be held across `.await` points.
+///
+/// Waiting tasks are parked by the executor instead of spinning.
+pub struct Mutex<T: ?Sized> {
+    state: spin::Mutex<State>,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: spin::Mutex::new(State {
+                locked: false,
+                waiters: WaitList::new(),
+            }),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    fn with_state<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        interrupts::without_interrupts(|| f(&mut self.state.lock()))
+    }
+
+    pub fn lock(&self) -> Lock<'_, T> {
+        Lock {
+            mutex: self,
+            waiter: None,
+            acquired: false,
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.with_state(|state| match state.locked {
+            true => None,
+            false => {
+                state.locked = true;
+                Some(MutexGuard { mutex: self })
+            }
+        })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    fn unlock(&self) {
+        self.with_state(|state| {
+            state.locked = false;
+            state.waiters.wake_one();
+        });
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+pub struct Lock<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+    waiter: Option<u64>,
+    acquired: bool,
+}
+
+impl<'a, T: ?Sized> Future for Lock<'a, T> {
+    type Output = MutexGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mutex = this.mutex;
+
+        mutex.with_state(|state| {
+            if !state.locked {
+                if let Some(id) = this.waiter.take() {
+                    state.waiters.remove(id);
+                }
+                state.locked = true;
+                this.acquired = true;
+                return Poll::Ready(MutexGuard { mutex });
+            }
+
+            state.waiters.register(&mut this.waiter, cx.waker());
+            Poll::Pending
+        })
+    }
+}
+
+impl<T: ?Sized> Drop for Lock<'_, T> {
+    fn drop(&mut self) {
+        if self.acquired {
+            return;
+        }
+
+        if let Some(id) = self.waiter {
+            self.mutex.with_state(|state| {
+                // The wakeup was meant for us, pass it to the next waiter
+                if !state.waiters.remove(id) && !state.locked {
+                    state.waiters.wake_one();
+                }
+            });
+        }
+    }
+}
+
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
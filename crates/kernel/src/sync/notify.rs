@@ -0,0 +1,130 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use alloc::vec::Vec;
+
+use x86::instructions::interrupts;
+
+use super::WaitList;
+
+struct State {
+    permit: bool,
+    waiters: WaitList,
+    /// Waiters woken by `notify_one` which didn't complete yet.
+    notified: Vec<u64>,
+}
+
+impl State {
+    fn notify_one(&mut self) {
+        match self.waiters.wake_oldest() {
+            Some(id) => self.notified.push(id),
+            None => self.permit = true,
+        }
+    }
+
+    /// Forgets the `notify_one` which woke the waiter `id`, returns whether there was one.
+    fn take_notified(&mut self, id: u64) -> bool {
+        let len = self.notified.len();
+        self.notified.retain(|&notified| notified != id);
+        self.notified.len() < len
+    }
+}
+
+/// Wakes up tasks waiting for an event, can be used from interrupt handlers.
+pub struct Notify {
+    state: spin::Mutex<State>,
+}
+
+impl Notify {
+    pub const fn new() -> Self {
+        Self {
+            state: spin::Mutex::new(State {
+                permit: false,
+                waiters: WaitList::new(),
+                notified: Vec::new(),
+            }),
+        }
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        interrupts::without_interrupts(|| f(&mut self.state.lock()))
+    }
+
+    /// Wakes the oldest waiter, or lets the next call to `notified` complete immediately.
+    pub fn notify_one(&self) {
+        self.with_state(State::notify_one);
+    }
+
+    /// Wakes all the current waiters without storing a permit.
+    pub fn notify_waiters(&self) {
+        self.with_state(|state| state.waiters.wake_all());
+    }
+
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            waiter: None,
+            done: false,
+        }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    waiter: Option<u64>,
+    done: bool,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let this = &mut *self;
+        if this.done {
+            return Poll::Ready(());
+        }
+
+        this.notify.with_state(|state| {
+            let woken = match this.waiter {
+                Some(id) => {
+                    state.take_notified(id);
+                    !state.waiters.contains(id)
+                }
+                None => core::mem::take(&mut state.permit),
+            };
+
+            if woken {
+                this.done = true;
+                return Poll::Ready(());
+            }
+
+            state.waiters.register(&mut this.waiter, cx.waker());
+            // Room for every waiter, `notify_one` doesn't allocate in an interrupt handler
+            let waiters = state.waiters.len();
+            state.notified.reserve(waiters);
+            Poll::Pending
+        })
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        if let (false, Some(id)) = (self.done, self.waiter) {
+            self.notify.with_state(|state| {
+                // A `notify_one` meant for us goes to the next waiter, or stays as the permit
+                if !state.waiters.remove(id) && state.take_notified(id) {
+                    state.notify_one();
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use alloc::sync::Arc;
+use x86::instructions::interrupts;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Closed,
+}
+
+struct State<T> {
+    value: Option<T>,
+    sender_dropped: bool,
+    receiver_dropped: bool,
+    waker: Option<Waker>,
+}
+
+struct Shared<T> {
+    state: spin::Mutex<State<T>>,
+}
+
+impl<T> Shared<T> {
+    fn with_state<R>(&self, f: impl FnOnce(&mut State<T>) -> R) -> R {
+        interrupts::without_interrupts(|| f(&mut self.state.lock()))
+    }
+}
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: spin::Mutex::new(State {
+            value: None,
+            sender_dropped: false,
+            receiver_dropped: false,
+            waker: None,
+        }),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends the value without blocking nor allocating, can be called from interrupt handlers.
+    ///
+    /// The value is given back if the receiver was dropped.
+    pub fn send(self, value: T) -> Result<(), T> {
+        self.shared.with_state(|state| {
+            if state.receiver_dropped {
+                return Err(value);
+            }
+
+            state.value = Some(value);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+            Ok(())
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.with_state(|state| state.receiver_dropped)
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.with_state(|state| {
+            state.sender_dropped = true;
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.shared.with_state(|state| match state.value.take() {
+            Some(value) => Ok(value),
+            None if state.sender_dropped => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        })
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.shared.with_state(|state| {
+            if let Some(value) = state.value.take() {
+                return Poll::Ready(Ok(value));
+            }
+
+            if state.sender_dropped {
+                return Poll::Ready(Err(RecvError));
+            }
+
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        })
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared
+            .with_state(|state| state.receiver_dropped = true);
+    }
+}
@@ -0,0 +1,237 @@
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use x86::instructions::interrupts;
+
+use super::WaitList;
+
+struct State {
+    readers: usize,
+    writer: bool,
+    read_waiters: WaitList,
+    write_waiters: WaitList,
+}
+
+impl State {
+    // Writers are preferred so that a stream of readers can't starve them
+    fn can_read(&self) -> bool {
+        !self.writer && self.write_waiters.is_empty()
+    }
+
+    fn can_write(&self) -> bool {
+        !self.writer && self.readers == 0
+    }
+
+    fn wake(&mut self) {
+        if self.write_waiters.is_empty() {
+            self.read_waiters.wake_all();
+        } else if self.readers == 0 {
+            self.write_waiters.wake_one();
+        }
+    }
+}
+
+pub struct RwLock<T: ?Sized> {
+    state: spin::Mutex<State>,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: spin::Mutex::new(State {
+                readers: 0,
+                writer: false,
+                read_waiters: WaitList::new(),
+                write_waiters: WaitList::new(),
+            }),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    fn with_state<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        interrupts::without_interrupts(|| f(&mut self.state.lock()))
+    }
+
+    pub fn read(&self) -> Read<'_, T> {
+        Read {
+            lock: self,
+            waiter: None,
+            acquired: false,
+        }
+    }
+
+    pub fn write(&self) -> Write<'_, T> {
+        Write {
+            lock: self,
+            waiter: None,
+            acquired: false,
+        }
+    }
+
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        self.with_state(|state| match state.can_read() {
+            true => {
+                state.readers += 1;
+                Some(RwLockReadGuard { lock: self })
+            }
+            false => None,
+        })
+    }
+
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        self.with_state(|state| match state.can_write() {
+            true => {
+                state.writer = true;
+                Some(RwLockWriteGuard { lock: self })
+            }
+            false => None,
+        })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+pub struct Read<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    waiter: Option<u64>,
+    acquired: bool,
+}
+
+impl<'a, T: ?Sized> Future for Read<'a, T> {
+    type Output = RwLockReadGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let lock = this.lock;
+
+        lock.with_state(|state| {
+            if state.can_read() {
+                if let Some(id) = this.waiter.take() {
+                    state.read_waiters.remove(id);
+                }
+                state.readers += 1;
+                this.acquired = true;
+                return Poll::Ready(RwLockReadGuard { lock });
+            }
+
+            state.read_waiters.register(&mut this.waiter, cx.waker());
+            Poll::Pending
+        })
+    }
+}
+
+impl<T: ?Sized> Drop for Read<'_, T> {
+    fn drop(&mut self) {
+        if let (false, Some(id)) = (self.acquired, self.waiter) {
+            self.lock.with_state(|state| state.read_waiters.remove(id));
+        }
+    }
+}
+
+pub struct Write<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    waiter: Option<u64>,
+    acquired: bool,
+}
+
+impl<'a, T: ?Sized> Future for Write<'a, T> {
+    type Output = RwLockWriteGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let lock = this.lock;
+
+        lock.with_state(|state| {
+            if state.can_write() {
+                if let Some(id) = this.waiter.take() {
+                    state.write_waiters.remove(id);
+                }
+                state.writer = true;
+                this.acquired = true;
+                return Poll::Ready(RwLockWriteGuard { lock });
+            }
+
+            state.write_waiters.register(&mut this.waiter, cx.waker());
+            Poll::Pending
+        })
+    }
+}
+
+impl<T: ?Sized> Drop for Write<'_, T> {
+    fn drop(&mut self) {
+        if let (false, Some(id)) = (self.acquired, self.waiter) {
+            self.lock.with_state(|state| {
+                if !state.write_waiters.remove(id) || state.write_waiters.is_empty() {
+                    state.wake();
+                }
+            });
+        }
+    }
+}
+
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.with_state(|state| {
+            state.readers -= 1;
+            state.wake();
+        });
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.with_state(|state| {
+            state.writer = false;
+            state.wake();
+        });
+    }
+}
@@ -0,0 +1,129 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use x86::instructions::interrupts;
+
+use super::WaitList;
+
+struct State {
+    permits: usize,
+    waiters: WaitList,
+}
+
+pub struct Semaphore {
+    state: spin::Mutex<State>,
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            state: spin::Mutex::new(State {
+                permits,
+                waiters: WaitList::new(),
+            }),
+        }
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        interrupts::without_interrupts(|| f(&mut self.state.lock()))
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.with_state(|state| state.permits)
+    }
+
+    pub fn acquire(&self) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            waiter: None,
+            acquired: false,
+        }
+    }
+
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.with_state(|state| match state.permits {
+            0 => None,
+            _ => {
+                state.permits -= 1;
+                Some(SemaphorePermit { semaphore: self })
+            }
+        })
+    }
+
+    /// Adds permits and wakes as many waiters, can be called from interrupt handlers.
+    pub fn add_permits(&self, permits: usize) {
+        self.with_state(|state| {
+            state.permits += permits;
+            for _ in 0..permits {
+                if !state.waiters.wake_one() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    waiter: Option<u64>,
+    acquired: bool,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = SemaphorePermit<'a>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let semaphore = this.semaphore;
+
+        semaphore.with_state(|state| {
+            if state.permits > 0 {
+                if let Some(id) = this.waiter.take() {
+                    state.waiters.remove(id);
+                }
+                state.permits -= 1;
+                this.acquired = true;
+                return Poll::Ready(SemaphorePermit { semaphore });
+            }
+
+            state.waiters.register(&mut this.waiter, cx.waker());
+            Poll::Pending
+        })
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        if self.acquired {
+            return;
+        }
+
+        if let Some(id) = self.waiter {
+            self.semaphore.with_state(|state| {
+                if !state.waiters.remove(id) && state.permits > 0 {
+                    state.waiters.wake_one();
+                }
+            });
+        }
+    }
+}
+
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl SemaphorePermit<'_> {
+    /// Consumes the permit without giving it back to the semaphore.
+    pub fn forget(self) {
+        core::mem::forget(self);
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(1);
+    }
+}
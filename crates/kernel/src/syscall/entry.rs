@@ -0,0 +1,89 @@
+use core::{arch::global_asm, mem::offset_of};
+
+use crate::{
+    percpu::PerCpu,
+    trap::{TrapFrame, SYSCALL_VECTOR},
+};
+
+/// Selectors of the iretq frame built by the entry, the ones `sysret` loads.
+pub(super) const USER_CODE: u64 = 0x23;
+pub(super) const USER_DATA: u64 = 0x1B;
+
+/// The syscall entry saves the same frame as the exception stubs.
+pub type SyscallFrame = TrapFrame;
+
+extern "C" {
+    pub(super) fn syscall_entry();
+}
+
+// `syscall` leaves RSP untouched and masks the interrupts through SFMASK: the user stack is swapped
+// for the kernel stack of the thread before they are enabled again. The frame is 22 words, so the
+// stack stays 16 bytes aligned for the call.
+//
+// A signal frame can change RCX and R11, which `sysret` can't restore: the entry returns with
+// `iretq` when they no longer hold the return address and RFLAGS.
+global_asm!(
+    ".global syscall_entry",
+    "syscall_entry:",
+    "swapgs",
+    "mov gs:[{user_stack}], rsp",
+    "mov rsp, gs:[{kernel_stack}]",
+    "push {user_data}",
+    "push qword ptr gs:[{user_stack}]",
+    "push r11",
+    "push {user_code}",
+    "push rcx",
+    "push 0",
+    "push {vector}",
+    "push rdi",
+    "push rsi",
+    "push rdx",
+    "push rcx",
+    "push rax",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "push rbx",
+    "push rbp",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov rdi, rsp",
+    "sti",
+    "call {dispatch}",
+    "cli",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbp",
+    "pop rbx",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rax",
+    "pop rcx",
+    "pop rdx",
+    "pop rsi",
+    "pop rdi",
+    "add rsp, 16",
+    "cmp rcx, [rsp]",
+    "jne 2f",
+    "cmp r11, [rsp + 16]",
+    "jne 2f",
+    "mov rsp, [rsp + 24]",
+    "swapgs",
+    "sysretq",
+    "2:",
+    "swapgs",
+    "iretq",
+    user_stack = const offset_of!(PerCpu, user_stack),
+    kernel_stack = const offset_of!(PerCpu, kernel_stack),
+    user_code = const USER_CODE,
+    user_data = const USER_DATA,
+    vector = const SYSCALL_VECTOR,
+    dispatch = sym super::dispatch,
+);
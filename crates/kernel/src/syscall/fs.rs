@@ -0,0 +1,269 @@
+//! File system calls, and the layouts of `struct stat` and of the directory entries shared with
+//! the Linux personality.
+
+use alloc::{string::String, vec::Vec};
+use vfs::{File, FileType, Metadata, OpenFile, OpenFlags, SeekFrom};
+
+use crate::{
+    fs,
+    process::{self, handle::Handle},
+};
+
+use super::{current_handle, read_string, user_ptr::UserSlice, SyscallError};
+
+/// `unlink` flag removing a directory instead of a file.
+pub const REMOVE_DIR: u64 = 1;
+
+/// `seek` origins.
+pub mod whence {
+    pub const SET: u64 = 0;
+    pub const CURRENT: u64 = 1;
+    pub const END: u64 = 2;
+}
+
+/// Size of `struct stat` on Linux x86_64.
+pub const STAT_SIZE: usize = 144;
+
+/// `open(path, len, flags, mode)`, opens a file with `vfs::OpenFlags` and returns its
+/// descriptor.
+pub(super) fn sys_open(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [path, len, flags, mode, ..] = *args;
+    let path = read_string(path, len)?;
+    let flags = OpenFlags::from_bits(flags as u32).ok_or(SyscallError::InvalidArgument)?;
+    open(&cwd()?, &path, flags, mode as u16)
+}
+
+/// `seek(fd, offset, whence)`, moves the offset of a file and returns it.
+pub(super) fn sys_seek(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [fd, offset, origin, ..] = *args;
+    let position = match origin {
+        whence::SET => SeekFrom::Start(offset),
+        whence::CURRENT => SeekFrom::Current(offset as i64),
+        whence::END => SeekFrom::End(offset as i64),
+        _ => return Err(SyscallError::InvalidArgument),
+    };
+    let handle = current_handle(fd)?;
+    let file = handle.as_file().ok_or(SyscallError::NotSeekable)?;
+    Ok(file.seek(position)?)
+}
+
+/// `stat(path, len, stat, nofollow)`, stores the `struct stat` of a path. A symlink is followed
+/// unless `nofollow` is set.
+pub(super) fn sys_stat(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [path, len, stat, nofollow, ..] = *args;
+    let path = read_string(path, len)?;
+    let stat = UserSlice::new(stat, STAT_SIZE as u64)?;
+    let metadata = fs::vfs().metadata(&cwd()?, &path, nofollow == 0)?;
+    stat.write(&stat_bytes(&metadata))?;
+    Ok(0)
+}
+
+/// `fstat(fd, stat)`, descriptors other than files get a made up `struct stat`.
+pub(super) fn sys_fstat(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [fd, stat, ..] = *args;
+    let stat = UserSlice::new(stat, STAT_SIZE as u64)?;
+    let metadata = handle_metadata(&*current_handle(fd)?)?;
+    stat.write(&stat_bytes(&metadata))?;
+    Ok(0)
+}
+
+/// `read_dir(fd, buffer, len)`, stores the next entries of a directory as Linux
+/// `struct linux_dirent64` and returns their size, 0 after the last one.
+pub(super) fn sys_read_dir(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [fd, buffer, len, ..] = *args;
+    let buffer = UserSlice::new(buffer, len)?;
+    let handle = current_handle(fd)?;
+    let file = handle.as_file().ok_or(SyscallError::NotDirectory)?;
+
+    let entries = read_dirents(file, buffer.len())?;
+    buffer.write(&entries)?;
+    Ok(entries.len() as u64)
+}
+
+/// `mkdir(path, len, mode)`
+pub(super) fn sys_mkdir(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [path, len, mode, ..] = *args;
+    let path = read_string(path, len)?;
+    fs::vfs().create_dir(&cwd()?, &path, mode as u16)?;
+    Ok(0)
+}
+
+/// `unlink(path, len, flags)`, removes a file, or an empty directory with `REMOVE_DIR`.
+pub(super) fn sys_unlink(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [path, len, flags, ..] = *args;
+    let path = read_string(path, len)?;
+    match flags {
+        0 => fs::vfs().remove_file(&cwd()?, &path)?,
+        REMOVE_DIR => fs::vfs().remove_dir(&cwd()?, &path)?,
+        _ => return Err(SyscallError::InvalidArgument),
+    }
+    Ok(0)
+}
+
+/// `symlink(target, target_len, path, len)`, creates a symlink at `path` to `target`.
+pub(super) fn sys_symlink(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [target, target_len, path, len, ..] = *args;
+    let target = read_string(target, target_len)?;
+    let path = read_string(path, len)?;
+    fs::vfs().symlink(&cwd()?, &target, &path)?;
+    Ok(0)
+}
+
+/// `read_link(path, len, buffer, buffer_len)`, stores the target of a symlink, cut to the buffer,
+/// and returns its length.
+pub(super) fn sys_read_link(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [path, len, buffer, buffer_len, ..] = *args;
+    let path = read_string(path, len)?;
+    let buffer = UserSlice::new(buffer, buffer_len)?;
+    let target = fs::vfs().read_link(&cwd()?, &path)?;
+
+    let len = target.len().min(buffer.len());
+    buffer.write(&target.as_bytes()[..len])?;
+    Ok(len as u64)
+}
+
+/// `chdir(path, len)`
+pub(super) fn sys_chdir(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [path, len, ..] = *args;
+    let path = read_string(path, len)?;
+    chdir(&cwd()?, &path)?;
+    Ok(0)
+}
+
+/// `getcwd(buffer, len)`, stores the working directory without a NUL and returns its length.
+pub(super) fn sys_getcwd(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [buffer, len, ..] = *args;
+    let buffer = UserSlice::new(buffer, len)?;
+    let cwd = cwd()?;
+    if cwd.len() > buffer.len() {
+        return Err(SyscallError::BufferTooSmall);
+    }
+    buffer.write(cwd.as_bytes())?;
+    Ok(cwd.len() as u64)
+}
+
+/// `rename(old, old_len, new, new_len)`, moves a file in its file system.
+pub(super) fn sys_rename(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [old, old_len, new, new_len, ..] = *args;
+    let old = read_string(old, old_len)?;
+    let new = read_string(new, new_len)?;
+    fs::vfs().rename(&cwd()?, &old, &new)?;
+    Ok(0)
+}
+
+/// `truncate(fd, size)`, sets the size of a file open for writing.
+pub(super) fn sys_truncate(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [fd, size, ..] = *args;
+    let handle = current_handle(fd)?;
+    let file = handle.as_file().ok_or(SyscallError::InvalidArgument)?;
+    file.truncate(size)?;
+    Ok(0)
+}
+
+/// Working directory of the current process.
+pub(super) fn cwd() -> Result<String, SyscallError> {
+    process::with_current(|process| String::from(process.cwd()))
+        .ok_or(SyscallError::InvalidArgument)
+}
+
+/// Opens `path` from `cwd` and returns the new descriptor.
+pub(super) fn open(
+    cwd: &str,
+    path: &str,
+    flags: OpenFlags,
+    mode: u16,
+) -> Result<u64, SyscallError> {
+    let file = fs::vfs().open(cwd, path, flags, mode)?;
+    let fd = process::with_current(|process| process.handles().insert(file))
+        .ok_or(SyscallError::InvalidArgument)??;
+    Ok(fd)
+}
+
+/// Makes the directory at `path` the working directory.
+pub(super) fn chdir(cwd: &str, path: &str) -> Result<(), SyscallError> {
+    let dir = fs::vfs().resolve(cwd, path, true)?;
+    if !dir.inode.metadata()?.is_dir() {
+        return Err(SyscallError::NotDirectory);
+    }
+    process::with_current(|process| process.set_cwd(dir.path)).ok_or(SyscallError::InvalidArgument)
+}
+
+/// Metadata of the file of `handle`, made up for the terminal and the pipes.
+pub(super) fn handle_metadata(handle: &dyn Handle) -> Result<Metadata, SyscallError> {
+    if let Some(file) = handle.as_file() {
+        return Ok(file.metadata()?);
+    }
+    Ok(match handle.is_terminal() {
+        true => Metadata::new(0, FileType::CharDevice, 0o620, 0),
+        false => Metadata::new(0, FileType::Fifo, 0o600, 0),
+    })
+}
+
+/// `struct stat` of Linux x86_64.
+pub(super) fn stat_bytes(metadata: &Metadata) -> [u8; STAT_SIZE] {
+    let file_type = match metadata.file_type {
+        FileType::Fifo => 0o010000,
+        FileType::CharDevice => 0o020000,
+        FileType::Directory => 0o040000,
+        FileType::BlockDevice => 0o060000,
+        FileType::Regular => 0o100000,
+        FileType::Symlink => 0o120000,
+        FileType::Socket => 0o140000,
+    };
+    let mode = file_type | u32::from(metadata.mode & 0o7777);
+
+    let mut stat = [0u8; STAT_SIZE];
+    let mut put =
+        |offset: usize, bytes: &[u8]| stat[offset..][..bytes.len()].copy_from_slice(bytes);
+    put(8, &metadata.inode.to_le_bytes());
+    put(16, &u64::from(metadata.links).to_le_bytes());
+    put(24, &mode.to_le_bytes());
+    put(28, &metadata.uid.to_le_bytes());
+    put(32, &metadata.gid.to_le_bytes());
+    put(40, &metadata.device.to_le_bytes());
+    put(48, &metadata.size.to_le_bytes());
+    put(56, &4096u64.to_le_bytes());
+    put(64, &metadata.size.div_ceil(512).to_le_bytes());
+    let times = [metadata.accessed, metadata.modified, metadata.changed];
+    for (i, time) in times.iter().enumerate() {
+        put(72 + i * 16, &time.as_secs().to_le_bytes());
+        put(80 + i * 16, &u64::from(time.subsec_nanos()).to_le_bytes());
+    }
+    stat
+}
+
+/// The next entries of the directory `file` as `struct linux_dirent64`, as many as fit in `len`
+/// bytes. The first one has to fit.
+pub(super) fn read_dirents(file: &OpenFile, len: usize) -> Result<Vec<u8>, SyscallError> {
+    let mut entries = Vec::new();
+    while let Some(entry) = file.read_dir()? {
+        // Inode, offset of the next entry, record length, type, and the name with its NUL
+        let record_len = (19 + entry.name.len() + 1).next_multiple_of(8);
+        if entries.len() + record_len > len {
+            file.seek(SeekFrom::Current(-1))?;
+            if entries.is_empty() {
+                return Err(SyscallError::InvalidArgument);
+            }
+            break;
+        }
+
+        let file_type: u8 = match entry.file_type {
+            FileType::Fifo => 1,
+            FileType::CharDevice => 2,
+            FileType::Directory => 4,
+            FileType::BlockDevice => 6,
+            FileType::Regular => 8,
+            FileType::Symlink => 10,
+            FileType::Socket => 12,
+        };
+        let next = file.seek(SeekFrom::Current(0))?;
+        let start = entries.len();
+        entries.extend(entry.inode.to_le_bytes());
+        entries.extend(next.to_le_bytes());
+        entries.extend((record_len as u16).to_le_bytes());
+        entries.push(file_type);
+        entries.extend(entry.name.as_bytes());
+        entries.resize(start + record_len, 0);
+    }
+    Ok(entries)
+}
@@ -0,0 +1,692 @@
+//! Linux x86_64 personality, the subset of its system calls that static musl programs need.
+//!
+//! The registers are the same as for the native calls, only the numbers, the structures and the
+//! error codes differ. Errors are returned as negated `errno` values.
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use x86::{
+    addr::VirtAddr,
+    registers::model_specific::FsBase,
+    structures::paging::{
+        page::{Page, PageSize, Size4KiB},
+        page_table::PageTableFlags,
+    },
+};
+
+use vfs::{File, OpenFlags};
+
+use crate::{
+    fs, ipc,
+    memory::{self, AddressSpace, Backing, MapError, Memory},
+    process::{self, handle::Handle},
+    thread, time,
+};
+
+use super::{
+    user_ptr::{self, UserSlice},
+    SyscallError, MAX_READ, MMAP_START,
+};
+
+pub mod number {
+    pub const READ: u64 = 0;
+    pub const WRITE: u64 = 1;
+    pub const OPEN: u64 = 2;
+    pub const CLOSE: u64 = 3;
+    pub const STAT: u64 = 4;
+    pub const FSTAT: u64 = 5;
+    pub const LSTAT: u64 = 6;
+    pub const LSEEK: u64 = 8;
+    pub const MMAP: u64 = 9;
+    pub const MPROTECT: u64 = 10;
+    pub const MUNMAP: u64 = 11;
+    pub const BRK: u64 = 12;
+    pub const IOCTL: u64 = 16;
+    pub const WRITEV: u64 = 20;
+    pub const PIPE: u64 = 22;
+    pub const GETPID: u64 = 39;
+    pub const EXIT: u64 = 60;
+    pub const UNAME: u64 = 63;
+    pub const TRUNCATE: u64 = 76;
+    pub const FTRUNCATE: u64 = 77;
+    pub const GETCWD: u64 = 79;
+    pub const CHDIR: u64 = 80;
+    pub const RENAME: u64 = 82;
+    pub const MKDIR: u64 = 83;
+    pub const RMDIR: u64 = 84;
+    pub const UNLINK: u64 = 87;
+    pub const SYMLINK: u64 = 88;
+    pub const READLINK: u64 = 89;
+    pub const ARCH_PRCTL: u64 = 158;
+    pub const GETDENTS64: u64 = 217;
+    pub const SET_TID_ADDRESS: u64 = 218;
+    pub const CLOCK_GETTIME: u64 = 228;
+    pub const EXIT_GROUP: u64 = 231;
+    pub const OPENAT: u64 = 257;
+    pub const MKDIRAT: u64 = 258;
+    pub const NEWFSTATAT: u64 = 262;
+    pub const UNLINKAT: u64 = 263;
+    pub const RENAMEAT: u64 = 264;
+    pub const SYMLINKAT: u64 = 266;
+    pub const READLINKAT: u64 = 267;
+    pub const PIPE2: u64 = 293;
+    pub const RENAMEAT2: u64 = 316;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum Errno {
+    /// EPERM
+    NotPermitted = 1,
+    /// ENOENT
+    NoEntry = 2,
+    /// ESRCH
+    NoProcess = 3,
+    /// EINTR
+    Interrupted = 4,
+    /// EIO
+    Io = 5,
+    /// ENOEXEC
+    ExecFormat = 8,
+    /// EBADF
+    BadDescriptor = 9,
+    /// ECHILD
+    NoChild = 10,
+    /// EAGAIN
+    Again = 11,
+    /// ENOMEM
+    NoMemory = 12,
+    /// EACCES
+    Access = 13,
+    /// EFAULT
+    Fault = 14,
+    /// EBUSY
+    Busy = 16,
+    /// EEXIST
+    Exists = 17,
+    /// EXDEV
+    CrossDevice = 18,
+    /// ENODEV
+    NoDevice = 19,
+    /// ENOTDIR
+    NotDirectory = 20,
+    /// EISDIR
+    IsDirectory = 21,
+    /// EINVAL
+    Invalid = 22,
+    /// ENOTTY
+    NotTerminal = 25,
+    /// EFBIG
+    FileTooBig = 27,
+    /// ENOSPC
+    NoSpace = 28,
+    /// ESPIPE
+    IllegalSeek = 29,
+    /// EROFS
+    ReadOnly = 30,
+    /// EPIPE
+    BrokenPipe = 32,
+    /// ERANGE
+    Range = 34,
+    /// ENAMETOOLONG
+    NameTooLong = 36,
+    /// ENOSYS
+    NoSys = 38,
+    /// ENOTEMPTY
+    NotEmpty = 39,
+    /// ELOOP
+    Loop = 40,
+}
+
+impl From<SyscallError> for Errno {
+    fn from(value: SyscallError) -> Self {
+        match value {
+            SyscallError::UnknownSyscall => Errno::NoSys,
+            SyscallError::BadAddress => Errno::Fault,
+            SyscallError::InvalidArgument | SyscallError::NotSupported => Errno::Invalid,
+            SyscallError::BadHandle => Errno::BadDescriptor,
+            SyscallError::OutOfMemory => Errno::NoMemory,
+            SyscallError::NoChild => Errno::NoChild,
+            SyscallError::NotFound => Errno::NoEntry,
+            SyscallError::InvalidExecutable => Errno::ExecFormat,
+            SyscallError::NoProcess => Errno::NoProcess,
+            SyscallError::WouldBlock => Errno::Again,
+            SyscallError::BrokenPipe => Errno::BrokenPipe,
+            SyscallError::Interrupted => Errno::Interrupted,
+            SyscallError::BufferTooSmall => Errno::Invalid,
+            SyscallError::NotDirectory => Errno::NotDirectory,
+            SyscallError::IsDirectory => Errno::IsDirectory,
+            SyscallError::AlreadyExists => Errno::Exists,
+            SyscallError::NotEmpty => Errno::NotEmpty,
+            SyscallError::NameTooLong => Errno::NameTooLong,
+            SyscallError::TooManySymlinks => Errno::Loop,
+            SyscallError::ReadOnly => Errno::ReadOnly,
+            SyscallError::NoSpace => Errno::NoSpace,
+            SyscallError::FileTooLarge => Errno::FileTooBig,
+            SyscallError::Busy => Errno::Busy,
+            SyscallError::CrossDevice => Errno::CrossDevice,
+            SyscallError::NotSeekable => Errno::IllegalSeek,
+            SyscallError::PermissionDenied => Errno::Access,
+            SyscallError::Io => Errno::Io,
+        }
+    }
+}
+
+const IOV_MAX: u64 = 1024;
+const PATH_MAX: usize = 4096;
+
+const O_ACCMODE: u64 = 0o3;
+const O_WRONLY: u64 = 0o1;
+const O_RDWR: u64 = 0o2;
+const O_CREAT: u64 = 0o100;
+const O_EXCL: u64 = 0o200;
+const O_TRUNC: u64 = 0o1000;
+const O_APPEND: u64 = 0o2000;
+const O_NONBLOCK: u64 = 0o4000;
+const O_DIRECTORY: u64 = 0o200000;
+const O_NOFOLLOW: u64 = 0o400000;
+// Descriptors are not inherited across `exec` anyway
+const O_CLOEXEC: u64 = 0o2000000;
+
+/// `dirfd` of the `*at` calls standing for the working directory.
+const AT_FDCWD: i32 = -100;
+const AT_SYMLINK_NOFOLLOW: u64 = 0x100;
+const AT_REMOVEDIR: u64 = 0x200;
+const AT_EMPTY_PATH: u64 = 0x1000;
+
+const MAP_SHARED: u64 = 0x01;
+const MAP_PRIVATE: u64 = 0x02;
+const MAP_FIXED: u64 = 0x10;
+const MAP_ANONYMOUS: u64 = 0x20;
+
+const TCGETS: u64 = 0x5401;
+
+const ARCH_SET_FS: u64 = 0x1002;
+const ARCH_GET_FS: u64 = 0x1003;
+
+// Clocks of `clock_gettime`, all of them count from boot
+const CLOCK_REALTIME: u64 = 0;
+const CLOCK_MONOTONIC: u64 = 1;
+const CLOCK_MONOTONIC_RAW: u64 = 4;
+const CLOCK_REALTIME_COARSE: u64 = 5;
+const CLOCK_MONOTONIC_COARSE: u64 = 6;
+const CLOCK_BOOTTIME: u64 = 7;
+
+/// Runs the Linux system call `number` and returns the value for RAX.
+pub(super) fn dispatch(number: u64, args: &[u64; 6]) -> u64 {
+    let result = match number {
+        number::READ => super::sys_read(args).map_err(Errno::from),
+        number::WRITE => super::sys_write(args).map_err(Errno::from),
+        number::CLOSE => super::sys_close(args).map_err(Errno::from),
+        number::PIPE => sys_pipe2(&[args[0], 0, 0, 0, 0, 0]),
+        number::PIPE2 => sys_pipe2(args),
+        number::MMAP => sys_mmap(args),
+        number::MPROTECT => sys_mprotect(args),
+        number::MUNMAP => sys_munmap(args),
+        number::BRK => sys_brk(args),
+        number::IOCTL => sys_ioctl(args),
+        number::WRITEV => sys_writev(args),
+        number::GETPID | number::SET_TID_ADDRESS => sys_getpid(args),
+        number::EXIT | number::EXIT_GROUP => process::exit(args[0] as i32),
+        number::UNAME => sys_uname(args),
+        number::ARCH_PRCTL => sys_arch_prctl(args),
+        number::CLOCK_GETTIME => sys_clock_gettime(args),
+        number::OPEN => sys_openat(&[AT_FDCWD as u64, args[0], args[1], args[2], 0, 0]),
+        number::OPENAT => sys_openat(args),
+        number::LSEEK => super::fs::sys_seek(args).map_err(Errno::from),
+        number::FSTAT => super::fs::sys_fstat(args).map_err(Errno::from),
+        number::STAT => sys_newfstatat(&[AT_FDCWD as u64, args[0], args[1], 0, 0, 0]),
+        number::LSTAT => {
+            sys_newfstatat(&[AT_FDCWD as u64, args[0], args[1], AT_SYMLINK_NOFOLLOW, 0, 0])
+        }
+        number::NEWFSTATAT => sys_newfstatat(args),
+        number::GETDENTS64 => super::fs::sys_read_dir(args).map_err(Errno::from),
+        number::MKDIR => sys_mkdirat(&[AT_FDCWD as u64, args[0], args[1], 0, 0, 0]),
+        number::MKDIRAT => sys_mkdirat(args),
+        number::UNLINK => sys_unlinkat(&[AT_FDCWD as u64, args[0], 0, 0, 0, 0]),
+        number::RMDIR => sys_unlinkat(&[AT_FDCWD as u64, args[0], AT_REMOVEDIR, 0, 0, 0]),
+        number::UNLINKAT => sys_unlinkat(args),
+        number::SYMLINK => sys_symlinkat(&[args[0], AT_FDCWD as u64, args[1], 0, 0, 0]),
+        number::SYMLINKAT => sys_symlinkat(args),
+        number::READLINK => sys_readlinkat(&[AT_FDCWD as u64, args[0], args[1], args[2], 0, 0]),
+        number::READLINKAT => sys_readlinkat(args),
+        number::RENAME => sys_renameat(&[AT_FDCWD as u64, args[0], AT_FDCWD as u64, args[1], 0, 0]),
+        number::RENAMEAT => sys_renameat(args),
+        number::RENAMEAT2 => sys_renameat2(args),
+        number::TRUNCATE => sys_truncate(args),
+        number::FTRUNCATE => super::fs::sys_truncate(args).map_err(Errno::from),
+        number::CHDIR => sys_chdir(args),
+        number::GETCWD => sys_getcwd(args),
+        _ => Err(Errno::NoSys),
+    };
+
+    match result {
+        Ok(value) => value,
+        Err(errno) => (errno as u64).wrapping_neg(),
+    }
+}
+
+/// `writev(fd, iov, iovcnt)`, the buffers are written with a single write of at most `MAX_READ`
+/// bytes.
+fn sys_writev(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [fd, iov, count, ..] = *args;
+    if count > IOV_MAX {
+        return Err(Errno::Invalid);
+    }
+    let handle = current_handle(fd)?;
+
+    let mut bytes = Vec::new();
+    for vec in UserSlice::new(iov, count * 16)?.read()?.chunks_exact(16) {
+        let base = u64::from_le_bytes(vec[..8].try_into().unwrap());
+        let len = u64::from_le_bytes(vec[8..].try_into().unwrap());
+        let len = len.min(MAX_READ - bytes.len() as u64);
+        bytes.extend(UserSlice::new(base, len)?.read()?);
+        if bytes.len() as u64 == MAX_READ {
+            break;
+        }
+    }
+
+    let written = handle.write(&bytes).map_err(SyscallError::from)?;
+    Ok(written as u64)
+}
+
+/// `openat(dirfd, path, flags, mode)`
+fn sys_openat(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [dirfd, path, flags, mode, ..] = *args;
+    let path = read_path(path)?;
+
+    let mut open_flags = match flags & O_ACCMODE {
+        O_WRONLY => OpenFlags::WRITE,
+        O_RDWR => OpenFlags::READ | OpenFlags::WRITE,
+        0 => OpenFlags::READ,
+        _ => return Err(Errno::Invalid),
+    };
+    let mapping = [
+        (O_CREAT, OpenFlags::CREATE),
+        (O_EXCL, OpenFlags::EXCLUSIVE),
+        (O_TRUNC, OpenFlags::TRUNCATE),
+        (O_APPEND, OpenFlags::APPEND),
+        (O_DIRECTORY, OpenFlags::DIRECTORY),
+        (O_NOFOLLOW, OpenFlags::NO_FOLLOW),
+    ];
+    for (flag, open_flag) in mapping {
+        if flags & flag != 0 {
+            open_flags |= open_flag;
+        }
+    }
+
+    let cwd = at_dir(dirfd, &path)?;
+    Ok(super::fs::open(&cwd, &path, open_flags, mode as u16)?)
+}
+
+/// `newfstatat(dirfd, path, statbuf, flags)`, also `stat` and `lstat`.
+fn sys_newfstatat(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [dirfd, path, stat, flags, ..] = *args;
+    let path = read_path(path)?;
+    if path.is_empty() && flags & AT_EMPTY_PATH != 0 {
+        return Ok(super::fs::sys_fstat(&[dirfd, stat, 0, 0, 0, 0])?);
+    }
+
+    let stat = UserSlice::new(stat, super::fs::STAT_SIZE as u64)?;
+    let cwd = at_dir(dirfd, &path)?;
+    let follow = flags & AT_SYMLINK_NOFOLLOW == 0;
+    let metadata = fs::vfs()
+        .metadata(&cwd, &path, follow)
+        .map_err(SyscallError::from)?;
+    stat.write(&super::fs::stat_bytes(&metadata))?;
+    Ok(0)
+}
+
+/// `mkdirat(dirfd, path, mode)`, also `mkdir`.
+fn sys_mkdirat(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [dirfd, path, mode, ..] = *args;
+    let path = read_path(path)?;
+    let cwd = at_dir(dirfd, &path)?;
+    fs::vfs()
+        .create_dir(&cwd, &path, mode as u16)
+        .map_err(SyscallError::from)?;
+    Ok(0)
+}
+
+/// `unlinkat(dirfd, path, flags)`, also `unlink` and `rmdir`.
+fn sys_unlinkat(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [dirfd, path, flags, ..] = *args;
+    let path = read_path(path)?;
+    let cwd = at_dir(dirfd, &path)?;
+    let result = match flags {
+        0 => fs::vfs().remove_file(&cwd, &path),
+        AT_REMOVEDIR => fs::vfs().remove_dir(&cwd, &path),
+        _ => return Err(Errno::Invalid),
+    };
+    result.map_err(SyscallError::from)?;
+    Ok(0)
+}
+
+/// `symlinkat(target, newdirfd, linkpath)`, also `symlink`.
+fn sys_symlinkat(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [target, dirfd, path, ..] = *args;
+    let target = read_path(target)?;
+    let path = read_path(path)?;
+    let cwd = at_dir(dirfd, &path)?;
+    fs::vfs()
+        .symlink(&cwd, &target, &path)
+        .map_err(SyscallError::from)?;
+    Ok(0)
+}
+
+/// `readlinkat(dirfd, path, buf, bufsiz)`, also `readlink`. The target is cut to the buffer and
+/// not NUL terminated.
+fn sys_readlinkat(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [dirfd, path, buffer, len, ..] = *args;
+    let path = read_path(path)?;
+    let buffer = UserSlice::new(buffer, len)?;
+    let cwd = at_dir(dirfd, &path)?;
+    let target = fs::vfs()
+        .read_link(&cwd, &path)
+        .map_err(SyscallError::from)?;
+
+    let len = target.len().min(buffer.len());
+    buffer.write(&target.as_bytes()[..len])?;
+    Ok(len as u64)
+}
+
+/// `renameat(olddirfd, oldpath, newdirfd, newpath)`, also `rename`.
+fn sys_renameat(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [old_dirfd, old, new_dirfd, new, ..] = *args;
+    let old = read_path(old)?;
+    let new = read_path(new)?;
+    let old_cwd = at_dir(old_dirfd, &old)?;
+    let new_cwd = at_dir(new_dirfd, &new)?;
+    // The VFS takes a single working directory, the new path is made absolute from its own
+    let new = match new.starts_with('/') {
+        true => new,
+        false => vfs::path::join(&new_cwd, &new),
+    };
+    fs::vfs()
+        .rename(&old_cwd, &old, &new)
+        .map_err(SyscallError::from)?;
+    Ok(0)
+}
+
+/// `renameat2(olddirfd, oldpath, newdirfd, newpath, flags)`, without any flag.
+fn sys_renameat2(args: &[u64; 6]) -> Result<u64, Errno> {
+    if args[4] != 0 {
+        return Err(Errno::Invalid);
+    }
+    sys_renameat(args)
+}
+
+/// `truncate(path, length)`
+fn sys_truncate(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [path, size, ..] = *args;
+    let path = read_path(path)?;
+    fs::vfs()
+        .truncate(&super::fs::cwd()?, &path, size)
+        .map_err(SyscallError::from)?;
+    Ok(0)
+}
+
+/// `chdir(path)`
+fn sys_chdir(args: &[u64; 6]) -> Result<u64, Errno> {
+    let path = read_path(args[0])?;
+    super::fs::chdir(&super::fs::cwd()?, &path)?;
+    Ok(0)
+}
+
+/// `getcwd(buf, size)`, returns the length of the path with its NUL.
+fn sys_getcwd(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [buffer, len, ..] = *args;
+    let buffer = UserSlice::new(buffer, len)?;
+    let mut cwd = super::fs::cwd()?.into_bytes();
+    cwd.push(0);
+    if cwd.len() > buffer.len() {
+        return Err(Errno::Range);
+    }
+    buffer.write(&cwd)?;
+    Ok(cwd.len() as u64)
+}
+
+fn read_path(addr: u64) -> Result<String, Errno> {
+    let bytes = user_ptr::read_c_string(addr, PATH_MAX)?;
+    String::from_utf8(bytes).map_err(|_| Errno::Invalid)
+}
+
+/// Directory the relative `path` of a `*at` call starts from.
+fn at_dir(dirfd: u64, path: &str) -> Result<String, Errno> {
+    if path.starts_with('/') || dirfd as i32 == AT_FDCWD {
+        return Ok(super::fs::cwd()?);
+    }
+    let handle = current_handle(dirfd)?;
+    let file = handle.as_file().ok_or(Errno::NotDirectory)?;
+    if !file.metadata().map_err(SyscallError::from)?.is_dir() {
+        return Err(Errno::NotDirectory);
+    }
+    Ok(String::from(file.path()))
+}
+
+/// `pipe2(fds, flags)`, the descriptors are stored as two `int`.
+fn sys_pipe2(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [fds, flags, ..] = *args;
+    if flags & !(O_NONBLOCK | O_CLOEXEC) != 0 {
+        return Err(Errno::Invalid);
+    }
+    let fds = UserSlice::new(fds, 8)?;
+    fds.write(&[0; 8])?;
+
+    let (reader, writer) = ipc::pipe();
+    if flags & O_NONBLOCK != 0 {
+        reader.set_nonblocking(true).map_err(SyscallError::from)?;
+        writer.set_nonblocking(true).map_err(SyscallError::from)?;
+    }
+    let [reader, writer] = super::install_pair(Arc::new(reader), Arc::new(writer))?;
+    fds.write(&[(reader as i32).to_le_bytes(), (writer as i32).to_le_bytes()].concat())?;
+    Ok(0)
+}
+
+/// `mmap(addr, length, prot, flags, fd, offset)`, only anonymous mappings. The fixed ones must be
+/// in the region where the kernel places the others.
+fn sys_mmap(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [addr, len, prot, flags, ..] = *args;
+    if flags & MAP_ANONYMOUS == 0 {
+        return Err(Errno::NoDevice);
+    }
+    // Without `fork`, a shared anonymous mapping is only seen by its process anyway
+    if flags & (MAP_SHARED | MAP_PRIVATE) == 0 {
+        return Err(Errno::Invalid);
+    }
+
+    // The protection bits are the same as the native ones
+    let fixed = (flags & MAP_FIXED != 0).then_some(addr);
+    Ok(super::map_area(fixed, len, prot, Backing::Anonymous)?)
+}
+
+/// `munmap(addr, length)`, the pages that `mmap` didn't map are skipped.
+fn sys_munmap(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [addr, len, ..] = *args;
+    if len == 0 {
+        return Err(Errno::Invalid);
+    }
+    let (start, end) = super::page_bounds(addr, len)?;
+    super::unmap_areas(start, end)?;
+    Ok(0)
+}
+
+/// `mprotect(addr, len, prot)`, the pages must all have been mapped by `mmap`.
+fn sys_mprotect(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [addr, len, prot, ..] = *args;
+    let (start, end) = super::page_bounds(addr, len)?;
+    super::protect_areas(start, end, prot).map_err(|err| match err {
+        SyscallError::BadAddress => Errno::NoMemory,
+        err => err.into(),
+    })?;
+    Ok(0)
+}
+
+/// `brk(addr)`, moves the end of the heap and returns it, or the current end on failure.
+fn sys_brk(args: &[u64; 6]) -> Result<u64, Errno> {
+    let requested = args[0];
+    let program_break =
+        process::with_current(|process| *process.program_break()).ok_or(Errno::NoMemory)?;
+    let (start, current) = (program_break.start.as_u64(), program_break.end.as_u64());
+    if requested < start || requested > MMAP_START {
+        return Ok(current);
+    }
+
+    let old_end = current.next_multiple_of(Size4KiB::SIZE);
+    let new_end = requested.next_multiple_of(Size4KiB::SIZE);
+    let result = if new_end > old_end {
+        map_range(old_end, new_end)
+    } else {
+        unmap_range(new_end, old_end)
+    };
+    if result.is_err() {
+        return Ok(current);
+    }
+
+    process::with_current(|process| process.program_break().end = VirtAddr::new(requested));
+    Ok(requested)
+}
+
+/// `ioctl(fd, request, arg)`, only `TCGETS` on the terminal.
+fn sys_ioctl(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [fd, request, arg, ..] = *args;
+    let handle = current_handle(fd)?;
+    if request != TCGETS || !handle.is_terminal() {
+        return Err(Errno::NotTerminal);
+    }
+
+    // struct termios of the kernel: c_iflag, c_oflag, c_cflag, c_lflag, c_line and 19 c_cc
+    let mut termios = [0u8; 36];
+    termios[0..4].copy_from_slice(&0x0100u32.to_le_bytes()); // ICRNL
+    termios[4..8].copy_from_slice(&0x0005u32.to_le_bytes()); // OPOST | ONLCR
+    termios[8..12].copy_from_slice(&0x00BFu32.to_le_bytes()); // B38400 | CS8 | CREAD
+    termios[12..16].copy_from_slice(&0x803Bu32.to_le_bytes()); // ISIG | ICANON | ECHO...
+    let c_cc = &mut termios[17..];
+    c_cc[0] = 0x03; // VINTR, ^C
+    c_cc[1] = 0x1C; // VQUIT, ^\
+    c_cc[2] = 0x7F; // VERASE
+    c_cc[3] = 0x15; // VKILL, ^U
+    c_cc[4] = 0x04; // VEOF, ^D
+    c_cc[6] = 1; // VMIN
+
+    UserSlice::new(arg, termios.len() as u64)?.write(&termios)?;
+    Ok(0)
+}
+
+/// `getpid()`, also used for `set_tid_address` as processes have a single thread.
+fn sys_getpid(_args: &[u64; 6]) -> Result<u64, Errno> {
+    let pid = process::current().ok_or(Errno::NoSys)?;
+    Ok(pid.as_u64())
+}
+
+/// `uname(buf)`, six NUL terminated fields of 65 bytes.
+fn sys_uname(args: &[u64; 6]) -> Result<u64, Errno> {
+    const FIELD: usize = 65;
+    let fields = [
+        "KerwanOS",
+        "kerwanos",
+        env!("CARGO_PKG_VERSION"),
+        "#1",
+        "x86_64",
+        "(none)",
+    ];
+
+    let mut utsname = [0u8; FIELD * 6];
+    for (i, field) in fields.iter().enumerate() {
+        utsname[i * FIELD..][..field.len()].copy_from_slice(field.as_bytes());
+    }
+
+    UserSlice::new(args[0], utsname.len() as u64)?.write(&utsname)?;
+    Ok(0)
+}
+
+/// `arch_prctl(code, addr)`, musl sets its thread pointer with it before `main`.
+fn sys_arch_prctl(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [code, addr, ..] = *args;
+    match code {
+        ARCH_SET_FS => {
+            if addr >= memory::USER_END {
+                return Err(Errno::NotPermitted);
+            }
+            thread::set_fs_base(VirtAddr::new(addr));
+            Ok(0)
+        }
+        ARCH_GET_FS => {
+            let fs_base = FsBase::read();
+            UserSlice::new(addr, 8)?.write(&fs_base.as_u64().to_le_bytes())?;
+            Ok(0)
+        }
+        _ => Err(Errno::Invalid),
+    }
+}
+
+/// `clock_gettime(clockid, tp)`, there is no wall clock so the realtime clocks start at boot too.
+fn sys_clock_gettime(args: &[u64; 6]) -> Result<u64, Errno> {
+    let [clock, tp, ..] = *args;
+    match clock {
+        CLOCK_REALTIME
+        | CLOCK_MONOTONIC
+        | CLOCK_MONOTONIC_RAW
+        | CLOCK_REALTIME_COARSE
+        | CLOCK_MONOTONIC_COARSE
+        | CLOCK_BOOTTIME => {}
+        _ => return Err(Errno::Invalid),
+    }
+
+    let uptime = time::uptime();
+    let mut timespec = [0u8; 16];
+    timespec[..8].copy_from_slice(&uptime.as_secs().to_le_bytes());
+    timespec[8..].copy_from_slice(&u64::from(uptime.subsec_nanos()).to_le_bytes());
+    UserSlice::new(tp, timespec.len() as u64)?.write(&timespec)?;
+    Ok(0)
+}
+
+fn current_handle(fd: u64) -> Result<Arc<dyn Handle>, Errno> {
+    process::with_current(|process| process.handles().get(fd))
+        .ok_or(Errno::BadDescriptor)?
+        .map_err(|_| Errno::BadDescriptor)
+}
+
+/// Maps zeroed writable pages from `start` to `end`, undoing it all if a page fails.
+fn map_range(start: u64, end: u64) -> Result<(), Errno> {
+    let pages = page_range(start, end);
+    let flags = PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    with_address_space(|space, memory| {
+        for (i, page) in pages.clone().enumerate() {
+            if let Err(err) = space.map(memory, page, flags) {
+                for page in pages.clone().take(i) {
+                    let _ = space.unmap(memory, page);
+                }
+                return Err(SyscallError::from(err).into());
+            }
+        }
+        Ok(())
+    })
+}
+
+fn unmap_range(start: u64, end: u64) -> Result<(), Errno> {
+    with_address_space(|space, memory| {
+        for page in page_range(start, end) {
+            match space.unmap(memory, page) {
+                Ok(()) | Err(MapError::NotMapped) => {}
+                Err(_) => return Err(Errno::Invalid),
+            }
+        }
+        Ok(())
+    })
+}
+
+fn page_range(start: u64, end: u64) -> impl Iterator<Item = Page> + Clone {
+    (start..end)
+        .step_by(Size4KiB::SIZE as usize)
+        .map(|addr| Page::new_containing_address(VirtAddr::new(addr)))
+}
+
+fn with_address_space(
+    f: impl FnOnce(&mut AddressSpace, &mut Memory) -> Result<(), Errno>,
+) -> Result<(), Errno> {
+    process::with_current(|process| {
+        let space = process.address_space().ok_or(Errno::Fault)?;
+        memory::with(|memory| f(space, memory)).unwrap_or(Err(Errno::NoMemory))
+    })
+    .unwrap_or(Err(Errno::NoSys))
+}
@@ -0,0 +1,827 @@
+//! System calls entered from ring 3 with `syscall`.
+//!
+//! The number goes in RAX and the arguments in RDI, RSI, RDX, R10, R8 and R9. The result comes
+//! back in RAX, a negative value being the code of a `SyscallError`. RCX and R11 are clobbered,
+//! the other registers are preserved.
+
+mod entry;
+pub mod fs;
+pub mod linux;
+pub mod user_ptr;
+
+use core::time::Duration;
+
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use vfs::VfsError;
+use x86::{
+    addr::VirtAddr,
+    registers::{
+        model_specific::{Efer, EferFlags, LStar, SFMask, Star},
+        rflags::RFlags,
+    },
+    structures::paging::{
+        page::{PageSize, Size4KiB},
+        page_table::PageTableFlags,
+    },
+};
+
+use crate::{
+    ipc::{
+        self,
+        channel::{Message, MAX_MESSAGE_HANDLES, MAX_MESSAGE_SIZE},
+        SharedMemoryHandle,
+    },
+    loader::{self, LoadError},
+    memory::{self, AddressSpace, Area, Backing, MapError, Memory, SharedMemory, USER_END},
+    process::{
+        self,
+        handle::{Handle, HandleError},
+        signal::{self, Action, Signal, SignalError, SignalSet},
+        Personality, Pid, ProcessError,
+    },
+    thread,
+};
+
+pub use entry::SyscallFrame;
+use user_ptr::UserSlice;
+
+pub mod number {
+    pub const WRITE: u64 = 0;
+    pub const EXIT: u64 = 1;
+    pub const SLEEP: u64 = 2;
+    pub const YIELD: u64 = 3;
+    pub const MMAP: u64 = 4;
+    pub const SPAWN: u64 = 5;
+    pub const EXEC: u64 = 6;
+    pub const WAIT: u64 = 7;
+    pub const GETPID: u64 = 8;
+    pub const SIGACTION: u64 = 9;
+    pub const SIGPROCMASK: u64 = 10;
+    pub const KILL: u64 = 11;
+    pub const SIGRETURN: u64 = 12;
+    pub const READ: u64 = 13;
+    pub const CLOSE: u64 = 14;
+    pub const PIPE: u64 = 15;
+    pub const CHANNEL: u64 = 16;
+    pub const SEND: u64 = 17;
+    pub const RECEIVE: u64 = 18;
+    pub const SET_NONBLOCKING: u64 = 19;
+    pub const MUNMAP: u64 = 20;
+    pub const MPROTECT: u64 = 21;
+    pub const SHM_CREATE: u64 = 22;
+    pub const SHM_MAP: u64 = 23;
+    pub const OPEN: u64 = 24;
+    pub const SEEK: u64 = 25;
+    pub const STAT: u64 = 26;
+    pub const FSTAT: u64 = 27;
+    pub const READ_DIR: u64 = 28;
+    pub const MKDIR: u64 = 29;
+    pub const UNLINK: u64 = 30;
+    pub const SYMLINK: u64 = 31;
+    pub const READ_LINK: u64 = 32;
+    pub const CHDIR: u64 = 33;
+    pub const GETCWD: u64 = 34;
+    pub const RENAME: u64 = 35;
+    pub const TRUNCATE: u64 = 36;
+}
+
+pub mod prot {
+    pub const READ: u64 = 1;
+    pub const WRITE: u64 = 1 << 1;
+    pub const EXEC: u64 = 1 << 2;
+}
+
+/// `wait` argument to wait for any child.
+pub const ANY_CHILD: u64 = u64::MAX;
+
+/// `sigaction` handlers standing for the default action and for ignoring the signal.
+pub const SIG_DFL: u64 = 0;
+pub const SIG_IGN: u64 = 1;
+
+/// `sigprocmask` operations.
+pub mod mask {
+    pub const BLOCK: u64 = 0;
+    pub const UNBLOCK: u64 = 1;
+    pub const SET: u64 = 2;
+}
+
+/// Limits on the arguments given to `spawn` and `exec`.
+const MAX_ARGS: u64 = 64;
+const MAX_ARG_LEN: u64 = 4096;
+
+/// Largest `read` or `write` done at once, programs call it again for the rest.
+const MAX_READ: u64 = 64 * 1024;
+
+/// Region where `mmap` places the areas it maps, fixed ones included.
+const MMAP_START: u64 = 0x_6000_0000_0000;
+const MMAP_END: u64 = 0x_7000_0000_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum SyscallError {
+    UnknownSyscall = 1,
+    BadAddress = 2,
+    InvalidArgument = 3,
+    BadHandle = 4,
+    OutOfMemory = 5,
+    NoChild = 6,
+    NotFound = 7,
+    InvalidExecutable = 8,
+    NotSupported = 9,
+    NoProcess = 10,
+    WouldBlock = 11,
+    BrokenPipe = 12,
+    Interrupted = 13,
+    BufferTooSmall = 14,
+    NotDirectory = 15,
+    IsDirectory = 16,
+    AlreadyExists = 17,
+    NotEmpty = 18,
+    NameTooLong = 19,
+    TooManySymlinks = 20,
+    ReadOnly = 21,
+    NoSpace = 22,
+    FileTooLarge = 23,
+    Busy = 24,
+    CrossDevice = 25,
+    NotSeekable = 26,
+    PermissionDenied = 27,
+    Io = 28,
+}
+
+impl From<MapError> for SyscallError {
+    fn from(value: MapError) -> Self {
+        match value {
+            MapError::FrameAllocationFailed => SyscallError::OutOfMemory,
+            MapError::KernelRegion | MapError::NotMapped => SyscallError::BadAddress,
+            MapError::AlreadyMapped | MapError::InvalidSize => SyscallError::InvalidArgument,
+        }
+    }
+}
+
+impl From<HandleError> for SyscallError {
+    fn from(value: HandleError) -> Self {
+        match value {
+            HandleError::BadDescriptor => SyscallError::BadHandle,
+            HandleError::NotSupported => SyscallError::NotSupported,
+            HandleError::TooManyHandles => SyscallError::OutOfMemory,
+            HandleError::WouldBlock => SyscallError::WouldBlock,
+            HandleError::BrokenPipe => SyscallError::BrokenPipe,
+            HandleError::Interrupted => SyscallError::Interrupted,
+            HandleError::BufferTooSmall => SyscallError::BufferTooSmall,
+            HandleError::MessageTooLarge | HandleError::CannotTransfer => {
+                SyscallError::InvalidArgument
+            }
+            HandleError::File(err) => err.into(),
+        }
+    }
+}
+
+impl From<VfsError> for SyscallError {
+    fn from(value: VfsError) -> Self {
+        match value {
+            VfsError::NotFound => SyscallError::NotFound,
+            VfsError::NotDirectory => SyscallError::NotDirectory,
+            VfsError::IsDirectory => SyscallError::IsDirectory,
+            VfsError::AlreadyExists => SyscallError::AlreadyExists,
+            VfsError::NotEmpty => SyscallError::NotEmpty,
+            VfsError::InvalidArgument => SyscallError::InvalidArgument,
+            VfsError::NameTooLong => SyscallError::NameTooLong,
+            VfsError::TooManySymlinks => SyscallError::TooManySymlinks,
+            VfsError::ReadOnly => SyscallError::ReadOnly,
+            VfsError::NoSpace => SyscallError::NoSpace,
+            VfsError::FileTooLarge => SyscallError::FileTooLarge,
+            VfsError::NotSupported => SyscallError::NotSupported,
+            VfsError::Busy => SyscallError::Busy,
+            VfsError::CrossDevice => SyscallError::CrossDevice,
+            VfsError::BadDescriptor => SyscallError::BadHandle,
+            VfsError::NotSeekable => SyscallError::NotSeekable,
+            VfsError::PermissionDenied => SyscallError::PermissionDenied,
+            VfsError::Io => SyscallError::Io,
+        }
+    }
+}
+
+impl From<ProcessError> for SyscallError {
+    fn from(value: ProcessError) -> Self {
+        match value {
+            ProcessError::Load(LoadError::Map(err)) => err.into(),
+            ProcessError::Load(LoadError::MemoryUnavailable) => SyscallError::OutOfMemory,
+            ProcessError::Load(_) => SyscallError::InvalidExecutable,
+            ProcessError::NoChild => SyscallError::NoChild,
+            ProcessError::NoProcess => SyscallError::InvalidArgument,
+            ProcessError::Interrupted => SyscallError::Interrupted,
+            ProcessError::Spawn(_) => SyscallError::OutOfMemory,
+        }
+    }
+}
+
+impl From<SignalError> for SyscallError {
+    fn from(value: SignalError) -> Self {
+        match value {
+            SignalError::Uncatchable => SyscallError::InvalidArgument,
+            SignalError::BadAddress => SyscallError::BadAddress,
+        }
+    }
+}
+
+impl SyscallError {
+    /// Value returned in RAX.
+    pub fn to_return_value(self) -> u64 {
+        (self as u64).wrapping_neg()
+    }
+}
+
+type Handler = fn(&[u64; 6]) -> Result<u64, SyscallError>;
+
+/// Handlers indexed by syscall number.
+const SYSCALLS: [Handler; 37] = [
+    sys_write,
+    sys_exit,
+    sys_sleep,
+    sys_yield,
+    sys_mmap,
+    sys_spawn,
+    sys_exec,
+    sys_wait,
+    sys_getpid,
+    sys_sigaction,
+    sys_sigprocmask,
+    sys_kill,
+    sys_sigreturn,
+    sys_read,
+    sys_close,
+    sys_pipe,
+    sys_channel,
+    sys_send,
+    sys_receive,
+    sys_set_nonblocking,
+    sys_munmap,
+    sys_mprotect,
+    sys_shm_create,
+    sys_shm_map,
+    fs::sys_open,
+    fs::sys_seek,
+    fs::sys_stat,
+    fs::sys_fstat,
+    fs::sys_read_dir,
+    fs::sys_mkdir,
+    fs::sys_unlink,
+    fs::sys_symlink,
+    fs::sys_read_link,
+    fs::sys_chdir,
+    fs::sys_getcwd,
+    fs::sys_rename,
+    fs::sys_truncate,
+];
+
+/// Enables `syscall` on the running CPU, called with its GDT loaded.
+pub fn init() {
+    let selectors = crate::selectors();
+    debug_assert_eq!(u64::from(selectors.user_code.0), entry::USER_CODE);
+    debug_assert_eq!(u64::from(selectors.user_data.0), entry::USER_DATA);
+    unsafe {
+        Efer::write(Efer::read() | EferFlags::SYSTEM_CALL_EXTENSIONS);
+        // `sysret` loads user data right after the kernel data, then user code
+        Star::write(selectors.kernel_code, selectors.kernel_data);
+        LStar::write(VirtAddr::new(entry::syscall_entry as *const () as u64));
+        SFMask::write(
+            RFlags::INTERRUPT_FLAG
+                | RFlags::TRAP_FLAG
+                | RFlags::DIRECTION_FLAG
+                | RFlags::ALIGMENT_CHECK,
+        );
+    }
+}
+
+extern "C" fn dispatch(frame: &mut SyscallFrame) {
+    let args = [
+        frame.rdi, frame.rsi, frame.rdx, frame.r10, frame.r8, frame.r9,
+    ];
+    let personality =
+        process::with_current(|process| process.personality()).unwrap_or(Personality::Native);
+    frame.rax = match personality {
+        Personality::Native if frame.rax == number::SIGRETURN => {
+            signal::sigreturn(frame);
+            frame.rax
+        }
+        Personality::Native => {
+            let result = match SYSCALLS.get(frame.rax as usize) {
+                Some(handler) => handler(&args),
+                None => Err(SyscallError::UnknownSyscall),
+            };
+            match result {
+                Ok(value) => value,
+                Err(err) => err.to_return_value(),
+            }
+        }
+        Personality::Linux => linux::dispatch(frame.rax, &args),
+    };
+
+    signal::deliver(frame);
+
+    // `sysret` faults in ring 0 on a non-canonical return address
+    if frame.rip >= USER_END {
+        process::exit(-1);
+    }
+}
+
+/// `read(fd, buffer, len)`, returns 0 at the end of the input.
+fn sys_read(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [fd, addr, len, ..] = *args;
+    let handle = current_handle(fd)?;
+    let buffer = UserSlice::new(addr, len.min(MAX_READ))?;
+
+    let mut bytes = vec![0; buffer.len()];
+    let read = handle.read(&mut bytes)?;
+    buffer.write(&bytes[..read])?;
+    Ok(read as u64)
+}
+
+/// `write(fd, buffer, len)`, writes at most `MAX_READ` bytes.
+fn sys_write(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [fd, addr, len, ..] = *args;
+    let handle = current_handle(fd)?;
+
+    let bytes = UserSlice::new(addr, len.min(MAX_READ))?.read()?;
+    Ok(handle.write(&bytes)? as u64)
+}
+
+/// `exit(code)`, ends the calling process.
+fn sys_exit(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    process::exit(args[0] as i32)
+}
+
+/// `sleep(nanoseconds)`, a signal to act on cuts it short.
+fn sys_sleep(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    match thread::sleep_while(Duration::from_nanos(args[0]), || !signal::interrupted()) {
+        true => Ok(0),
+        false => Err(SyscallError::Interrupted),
+    }
+}
+
+fn sys_yield(_args: &[u64; 6]) -> Result<u64, SyscallError> {
+    thread::yield_now();
+    Ok(0)
+}
+
+/// `mmap(len, prot)`, maps zeroed memory and returns its address.
+fn sys_mmap(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [len, prot, ..] = *args;
+    map_area(None, len, prot, Backing::Anonymous)
+}
+
+/// `munmap(addr, len)`, the pages of the range that `mmap` didn't map are left alone.
+fn sys_munmap(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [addr, len, ..] = *args;
+    let (start, end) = page_bounds(addr, len)?;
+    unmap_areas(start, end)?;
+    Ok(0)
+}
+
+/// `mprotect(addr, len, prot)`, changes the protection of pages all mapped by `mmap`.
+fn sys_mprotect(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [addr, len, prot, ..] = *args;
+    let (start, end) = page_bounds(addr, len)?;
+    protect_areas(start, end, prot)?;
+    Ok(0)
+}
+
+/// `shm_create(size)`, creates a zeroed shared memory object of `size` bytes rounded up to whole
+/// pages and returns its descriptor.
+fn sys_shm_create(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let object = memory::with(|memory| SharedMemory::new(memory, args[0]))
+        .ok_or(SyscallError::OutOfMemory)??;
+    let handle = Arc::new(SharedMemoryHandle::new(object));
+    let fd = process::with_current(|process| process.handles().insert(handle))
+        .ok_or(SyscallError::InvalidArgument)??;
+    Ok(fd)
+}
+
+/// `shm_map(fd, offset, len, prot)`, maps `len` bytes of a shared memory object from `offset`, a
+/// multiple of the page size, and returns their address. The mapping keeps the object alive once
+/// its descriptors are closed.
+fn sys_shm_map(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [fd, offset, len, prot, ..] = *args;
+    if offset % Size4KiB::SIZE != 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let handle = current_handle(fd)?;
+    let object = handle.as_shared_memory().ok_or(SyscallError::BadHandle)?;
+
+    // `handle` outlives the mapping attempt, a failed one doesn't drop the last reference
+    let backing = Backing::Shared {
+        object: object.clone(),
+        offset,
+    };
+    map_area(None, len, prot, backing)
+}
+
+/// Flags of the pages mapped with `prot`. Without any of its bits they are out of reach of the
+/// user code, but still mapped so that the area keeps its frames.
+pub(super) fn prot_flags(prot: u64) -> Result<PageTableFlags, SyscallError> {
+    if prot & !(prot::READ | prot::WRITE | prot::EXEC) != 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    let mut flags = PageTableFlags::empty();
+    if prot != 0 {
+        flags |= PageTableFlags::USER_ACCESSIBLE;
+    }
+    if prot & prot::WRITE != 0 {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if prot & prot::EXEC == 0 {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+    Ok(flags)
+}
+
+/// Bounds of the pages from `addr`, which must be page aligned, to `addr + len`.
+pub(super) fn page_bounds(addr: u64, len: u64) -> Result<(u64, u64), SyscallError> {
+    if addr % Size4KiB::SIZE != 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let end = addr
+        .checked_add(len)
+        .and_then(|end| end.checked_next_multiple_of(Size4KiB::SIZE))
+        .filter(|&end| end <= USER_END)
+        .ok_or(SyscallError::InvalidArgument)?;
+    Ok((addr, end))
+}
+
+/// Maps an area of `len` bytes in the current process and returns its address. It goes at `fixed`
+/// in place of what the areas had there, or where it fits in the `mmap` region.
+pub(super) fn map_area(
+    fixed: Option<u64>,
+    len: u64,
+    prot: u64,
+    backing: Backing,
+) -> Result<u64, SyscallError> {
+    let flags = prot_flags(prot)?;
+    if len == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let len = len
+        .checked_next_multiple_of(Size4KiB::SIZE)
+        .ok_or(SyscallError::InvalidArgument)?;
+    if let Some(addr) = fixed {
+        let end = addr.checked_add(len);
+        if addr % Size4KiB::SIZE != 0 || addr < MMAP_START || end.map_or(true, |end| end > MMAP_END)
+        {
+            return Err(SyscallError::InvalidArgument);
+        }
+    }
+
+    let mut replaced = Vec::new();
+    let result = with_address_space(|space, memory| {
+        let start = match fixed {
+            Some(addr) => {
+                replaced = space.unmap_areas(memory, addr, addr + len);
+                addr
+            }
+            None => space
+                .areas()
+                .find_free(len, MMAP_START, MMAP_END)
+                .ok_or(SyscallError::OutOfMemory)?,
+        };
+        let area = Area {
+            start,
+            end: start + len,
+            flags,
+            backing,
+        };
+        space.map_area(memory, area)?;
+        Ok(start)
+    });
+
+    // Out of the memory lock, the areas may hold the last reference to a shared memory object
+    drop(replaced);
+    result
+}
+
+/// Unmaps the areas of the current process between `start` and `end`.
+pub(super) fn unmap_areas(start: u64, end: u64) -> Result<(), SyscallError> {
+    let mut removed = Vec::new();
+    with_address_space(|space, memory| {
+        removed = space.unmap_areas(memory, start, end);
+        Ok(())
+    })?;
+    drop(removed);
+    Ok(())
+}
+
+/// Changes the protection of the pages of the current process between `start` and `end`.
+pub(super) fn protect_areas(start: u64, end: u64, prot: u64) -> Result<(), SyscallError> {
+    let flags = prot_flags(prot)?;
+    with_address_space(|space, memory| Ok(space.protect(memory, start, end, flags)?))
+}
+
+fn with_address_space<R>(
+    f: impl FnOnce(&mut AddressSpace, &mut Memory) -> Result<R, SyscallError>,
+) -> Result<R, SyscallError> {
+    process::with_current(|process| {
+        let space = process
+            .address_space()
+            .ok_or(SyscallError::InvalidArgument)?;
+        memory::with(|memory| f(space, memory)).unwrap_or(Err(SyscallError::OutOfMemory))
+    })
+    .unwrap_or(Err(SyscallError::InvalidArgument))
+}
+
+/// `spawn(name, name_len, argv, argc)`, starts an embedded program as a child and returns its pid.
+///
+/// `argv` points to `argc` pairs of address and length.
+fn sys_spawn(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [name, name_len, argv, argc, ..] = *args;
+    let name = read_string(name, name_len)?;
+    let program = loader::programs::find(&name).ok_or(SyscallError::NotFound)?;
+    let argv = read_args(argv, argc)?;
+    let argv: Vec<&str> = argv.iter().map(String::as_str).collect();
+
+    let pid = process::spawn(
+        &name,
+        program.bytes,
+        &argv,
+        &[],
+        program.personality,
+        process::current(),
+        None,
+    )?;
+    Ok(pid.as_u64())
+}
+
+/// `exec(name, name_len, argv, argc)`, replaces the program of the calling process.
+fn sys_exec(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [name, name_len, argv, argc, ..] = *args;
+    let name = read_string(name, name_len)?;
+    let program = loader::programs::find(&name).ok_or(SyscallError::NotFound)?;
+    let argv = read_args(argv, argc)?;
+    let argv: Vec<&str> = argv.iter().map(String::as_str).collect();
+
+    Err(process::exec(&name, program.bytes, &argv, program.personality).into())
+}
+
+/// `wait(pid, status)`, reaps a child once it exited and stores its exit code at `status` unless
+/// it's null. `pid` can be `ANY_CHILD`.
+fn sys_wait(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [pid, status, ..] = *args;
+    let parent = process::current().ok_or(SyscallError::NoChild)?;
+    let target = (pid != ANY_CHILD).then(|| Pid::from(pid));
+
+    let status = match status {
+        0 => None,
+        addr => Some(UserSlice::new(addr, 4)?),
+    };
+    let (child, code) = process::wait(parent, target)?;
+    if let Some(status) = status {
+        status.write(&code.to_le_bytes())?;
+    }
+    Ok(child.as_u64())
+}
+
+fn sys_getpid(_args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let pid = process::current().ok_or(SyscallError::InvalidArgument)?;
+    Ok(pid.as_u64())
+}
+
+/// `sigaction(signal, handler, mask, restorer)`, sets the action of a signal and returns the
+/// previous handler. `handler` can be `SIG_DFL` or `SIG_IGN`.
+fn sys_sigaction(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [signal, handler, mask, restorer, ..] = *args;
+    let signal = Signal::new(signal).ok_or(SyscallError::InvalidArgument)?;
+    let action = match handler {
+        SIG_DFL => Action::Default,
+        SIG_IGN => Action::Ignore,
+        handler => Action::Handler {
+            handler,
+            mask: SignalSet::from_bits(mask),
+            restorer,
+        },
+    };
+
+    let old = process::with_current(|process| process.signals().set_action(signal, action))
+        .ok_or(SyscallError::InvalidArgument)??;
+    Ok(match old {
+        Action::Default => SIG_DFL,
+        Action::Ignore => SIG_IGN,
+        Action::Handler { handler, .. } => handler,
+    })
+}
+
+/// `sigprocmask(how, set)`, changes the blocked signals and returns the previous mask.
+fn sys_sigprocmask(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [how, set, ..] = *args;
+    let set = SignalSet::from_bits(set);
+    process::with_current(|process| {
+        let signals = process.signals();
+        let old = signals.blocked();
+        let blocked = match how {
+            mask::BLOCK => old.union(set),
+            mask::UNBLOCK => old.difference(set),
+            mask::SET => set,
+            _ => return Err(SyscallError::InvalidArgument),
+        };
+        signals.set_blocked(blocked);
+        Ok(old.bits())
+    })
+    .ok_or(SyscallError::InvalidArgument)?
+}
+
+/// `kill(pid, signal)`
+fn sys_kill(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [pid, signal, ..] = *args;
+    let signal = Signal::new(signal).ok_or(SyscallError::InvalidArgument)?;
+    signal::send(Pid::from(pid), signal).map_err(|_| SyscallError::NoProcess)?;
+    Ok(0)
+}
+
+/// Placeholder of the SIGRETURN slot, `dispatch` handles it as it restores the whole frame.
+fn sys_sigreturn(_args: &[u64; 6]) -> Result<u64, SyscallError> {
+    Err(SyscallError::UnknownSyscall)
+}
+
+/// `close(fd)`
+fn sys_close(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let handle = process::with_current(|process| process.handles().remove(args[0]))
+        .ok_or(SyscallError::BadHandle)??;
+
+    // Dropped out of the process lock, the last reference may have work to do
+    drop(handle);
+    Ok(0)
+}
+
+/// `pipe(fds)`, stores the descriptors of the read and the write end at `fds`.
+fn sys_pipe(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let fds = UserSlice::new(args[0], 16)?;
+    // Checked before the descriptors are allocated, there is nothing to undo past them
+    fds.write(&[0; 16])?;
+
+    let (reader, writer) = ipc::pipe();
+    let [reader, writer] = install_pair(Arc::new(reader), Arc::new(writer))?;
+    fds.write(&[reader.to_le_bytes(), writer.to_le_bytes()].concat())?;
+    Ok(0)
+}
+
+/// `channel(fds)`, stores the descriptors of the two endpoints of a new channel at `fds`.
+fn sys_channel(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let fds = UserSlice::new(args[0], 16)?;
+    fds.write(&[0; 16])?;
+
+    let (first, second) = ipc::channel();
+    let [first, second] = install_pair(Arc::new(first), Arc::new(second))?;
+    fds.write(&[first.to_le_bytes(), second.to_le_bytes()].concat())?;
+    Ok(0)
+}
+
+/// Adds two handles to the current process, or none of them.
+pub(super) fn install_pair(
+    first: Arc<dyn Handle>,
+    second: Arc<dyn Handle>,
+) -> Result<[u64; 2], SyscallError> {
+    let installed = process::with_current(|process| {
+        let handles = process.handles();
+        let first = handles.insert(first)?;
+        match handles.insert(second) {
+            Ok(second) => Ok([first, second]),
+            Err(err) => {
+                let _ = handles.remove(first);
+                Err(err)
+            }
+        }
+    })
+    .ok_or(SyscallError::InvalidArgument)??;
+    Ok(installed)
+}
+
+/// `send(fd, data, len, handles, count)`, sends a message on a channel. The `count` descriptors
+/// at `handles` move with it and are closed in the sender.
+fn sys_send(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [fd, data, len, handles, count, ..] = *args;
+    if len > MAX_MESSAGE_SIZE as u64 || count > MAX_MESSAGE_HANDLES as u64 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let data = UserSlice::new(data, len)?.read()?;
+    let fds = read_fds(handles, count)?;
+
+    let mut sorted = fds.clone();
+    sorted.sort_unstable();
+    sorted.dedup();
+    if sorted.len() != fds.len() || fds.contains(&fd) {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    let (endpoint, handles) = process::with_current(|process| {
+        let table = process.handles();
+        let endpoint = table.get(fd)?;
+        let handles = fds
+            .iter()
+            .map(|&fd| table.get(fd))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok::<_, HandleError>((endpoint, handles))
+    })
+    .ok_or(SyscallError::BadHandle)??;
+    let channel = endpoint.as_channel().ok_or(SyscallError::BadHandle)?;
+
+    channel.send(Message { data, handles })?;
+
+    let moved = process::with_current(|process| {
+        fds.iter()
+            .map(|&fd| process.handles().remove(fd))
+            .collect::<Vec<_>>()
+    });
+    drop(moved);
+    Ok(0)
+}
+
+/// `receive(fd, buffer, len, handles, capacity, count)`, takes the next message of a channel
+/// and returns the length of its data. The descriptors of the handles it carried are written at
+/// `handles` and their number at `count` unless it's null.
+///
+/// A message larger than the buffers stays queued and `BufferTooSmall` is returned.
+fn sys_receive(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [fd, buffer, len, handles, capacity, count] = *args;
+    let buffer = UserSlice::new(buffer, len.min(MAX_MESSAGE_SIZE as u64))?;
+    let capacity = capacity.min(MAX_MESSAGE_HANDLES as u64);
+    let fds = UserSlice::new(handles, capacity * 8)?;
+    let count = match count {
+        0 => None,
+        addr => Some(UserSlice::new(addr, 8)?),
+    };
+
+    let endpoint = current_handle(fd)?;
+    let channel = endpoint.as_channel().ok_or(SyscallError::BadHandle)?;
+    let message = channel.receive(buffer.len(), capacity as usize)?;
+
+    // The handles are lost if the table is full, like the rest of the message
+    let installed = process::with_current(|process| {
+        let table = process.handles();
+        let mut installed = Vec::new();
+        for handle in message.handles {
+            match table.insert(handle) {
+                Ok(fd) => installed.push(fd),
+                Err(err) => {
+                    for fd in installed {
+                        let _ = table.remove(fd);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        Ok(installed)
+    })
+    .ok_or(SyscallError::InvalidArgument)??;
+
+    buffer.write(&message.data)?;
+    let bytes: Vec<u8> = installed.iter().flat_map(|fd| fd.to_le_bytes()).collect();
+    fds.write(&bytes)?;
+    if let Some(count) = count {
+        count.write(&(installed.len() as u64).to_le_bytes())?;
+    }
+    Ok(message.data.len() as u64)
+}
+
+/// `set_nonblocking(fd, enabled)`, makes the operations on a pipe or a channel fail with
+/// `WouldBlock` instead of waiting.
+fn sys_set_nonblocking(args: &[u64; 6]) -> Result<u64, SyscallError> {
+    let [fd, enabled, ..] = *args;
+    current_handle(fd)?.set_nonblocking(enabled != 0)?;
+    Ok(0)
+}
+
+fn current_handle(fd: u64) -> Result<Arc<dyn Handle>, SyscallError> {
+    let handle = process::with_current(|process| process.handles().get(fd))
+        .ok_or(SyscallError::BadHandle)??;
+    Ok(handle)
+}
+
+fn read_fds(addr: u64, count: u64) -> Result<Vec<u64>, SyscallError> {
+    let bytes = UserSlice::new(addr, count * 8)?.read()?;
+    Ok(bytes
+        .chunks_exact(8)
+        .map(|fd| u64::from_le_bytes(fd.try_into().unwrap()))
+        .collect())
+}
+
+fn read_string(addr: u64, len: u64) -> Result<String, SyscallError> {
+    if len > MAX_ARG_LEN {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let bytes = UserSlice::new(addr, len)?.read()?;
+    String::from_utf8(bytes).map_err(|_| SyscallError::InvalidArgument)
+}
+
+fn read_args(argv: u64, argc: u64) -> Result<Vec<String>, SyscallError> {
+    if argc > MAX_ARGS {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let pairs = UserSlice::new(argv, argc * 16)?.read()?;
+    pairs
+        .chunks_exact(16)
+        .map(|pair| {
+            let addr = u64::from_le_bytes(pair[..8].try_into().unwrap());
+            let len = u64::from_le_bytes(pair[8..].try_into().unwrap());
+            read_string(addr, len)
+        })
+        .collect()
+}
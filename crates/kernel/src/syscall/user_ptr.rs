@@ -0,0 +1,92 @@
+use core::slice;
+
+use alloc::vec::Vec;
+use x86::{addr::VirtAddr, structures::paging::page_table::PageTableFlags};
+
+use crate::memory::{self, USER_END};
+
+use super::SyscallError;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// A buffer in user memory, checked to be mapped with user access before it is touched.
+#[derive(Debug, Clone, Copy)]
+pub struct UserSlice {
+    addr: u64,
+    len: u64,
+}
+
+impl UserSlice {
+    pub fn new(addr: u64, len: u64) -> Result<Self, SyscallError> {
+        let end = addr.checked_add(len).ok_or(SyscallError::BadAddress)?;
+        if end > USER_END {
+            return Err(SyscallError::BadAddress);
+        }
+        Ok(Self { addr, len })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Copies the buffer into kernel memory.
+    pub fn read(&self) -> Result<Vec<u8>, SyscallError> {
+        self.check(PageTableFlags::USER_ACCESSIBLE)?;
+        let bytes = unsafe { slice::from_raw_parts(self.addr as *const u8, self.len()) };
+        Ok(bytes.to_vec())
+    }
+
+    /// Copies `bytes` to the start of the buffer, which must be large enough.
+    pub fn write(&self, bytes: &[u8]) -> Result<(), SyscallError> {
+        if bytes.len() > self.len() {
+            return Err(SyscallError::InvalidArgument);
+        }
+        self.check(PageTableFlags::USER_ACCESSIBLE | PageTableFlags::WRITABLE)?;
+        let buffer = unsafe { slice::from_raw_parts_mut(self.addr as *mut u8, bytes.len()) };
+        buffer.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn check(&self, flags: PageTableFlags) -> Result<(), SyscallError> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let first = self.addr & !(PAGE_SIZE - 1);
+        let last = (self.addr + self.len - 1) & !(PAGE_SIZE - 1);
+        memory::with(|memory| {
+            (first..=last).step_by(PAGE_SIZE as usize).all(|page| {
+                memory
+                    .page_flags(VirtAddr::new(page))
+                    .is_some_and(|page_flags| page_flags.contains(flags | PageTableFlags::PRESENT))
+            })
+        })
+        .filter(|&mapped| mapped)
+        .map(|_| ())
+        .ok_or(SyscallError::BadAddress)
+    }
+}
+
+/// Copies the NUL terminated string at `addr`, without the NUL, failing past `max` bytes.
+pub fn read_c_string(addr: u64, max: usize) -> Result<Vec<u8>, SyscallError> {
+    let mut string = Vec::new();
+    let mut addr = addr;
+    loop {
+        // A page at a time, the next one may not be mapped when the string ends before
+        let chunk = UserSlice::new(addr, PAGE_SIZE - addr % PAGE_SIZE)?.read()?;
+        let end = chunk.iter().position(|&byte| byte == 0);
+        string.extend_from_slice(&chunk[..end.unwrap_or(chunk.len())]);
+
+        if string.len() > max {
+            return Err(SyscallError::InvalidArgument);
+        }
+        if end.is_some() {
+            return Ok(string);
+        }
+        addr += chunk.len() as u64;
+    }
+}
@@ -1,26 +1,60 @@
-use core::task::{Context, Poll, Waker};
+use core::{
+    mem::{self, ManuallyDrop},
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+    task::{Context, Waker},
+};
 
 use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
-use crossbeam_queue::ArrayQueue;
+use crossbeam_queue::SegQueue;
 use x86::instructions::interrupts;
 
-use super::{Task, TaskId};
+use crate::{
+    thread::{self, ThreadId, WaitQueue},
+    time,
+};
+
+use super::{
+    run_queue::RunQueue, stats, JoinError, Priority, Spawner, Task, TaskId, TaskStats, SPAWNER,
+};
+
+/// The first executor running as a thread, the one whose panicking tasks are recovered.
+static RUNNING: AtomicPtr<Executor> = AtomicPtr::new(ptr::null_mut());
+
+/// Executors running as threads block here when they have nothing to do.
+static WORK: WaitQueue = WaitQueue::new();
+
+/// Wakes the executor threads waiting for work.
+pub(super) fn notify() {
+    WORK.notify_all();
+}
 
 struct TaskWaker {
     task_id: TaskId,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+    priority: Priority,
+    run_queue: Arc<RunQueue>,
+    // Set while the task sits in the run queue, so repeated wakeups queue it only once
+    queued: AtomicBool,
+    stats: Arc<TaskStats>,
 }
 
 impl TaskWaker {
-    fn new(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
-        Waker::from(Arc::new(TaskWaker {
-            task_id,
-            task_queue,
-        }))
+    fn new(task: &Task, run_queue: Arc<RunQueue>) -> Arc<Self> {
+        Arc::new(TaskWaker {
+            task_id: task.id,
+            priority: task.priority(),
+            run_queue,
+            queued: AtomicBool::new(false),
+            stats: task.stats.clone(),
+        })
     }
 
     fn wake_task(&self) {
-        self.task_queue.push(self.task_id).expect("task_queue full");
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            self.stats.record_wake();
+            self.run_queue.push(self.task_id, self.priority);
+            notify();
+        }
     }
 }
 
@@ -36,63 +70,183 @@ impl Wake for TaskWaker {
 
 pub struct Executor {
     tasks: BTreeMap<TaskId, Task>,
-    task_queue: Arc<ArrayQueue<TaskId>>,
-    waker_cache: BTreeMap<TaskId, Waker>,
+    run_queue: Arc<RunQueue>,
+    new_tasks: Arc<SegQueue<Task>>,
+    cancelled_tasks: Arc<SegQueue<TaskId>>,
+    waker_cache: BTreeMap<TaskId, Arc<TaskWaker>>,
+    // The task being polled
+    current: Option<TaskId>,
+    thread: Option<ThreadId>,
 }
 
 impl Executor {
     pub fn new() -> Self {
         Self {
             tasks: BTreeMap::new(),
-            task_queue: Arc::new(ArrayQueue::new(100)),
+            run_queue: Arc::new(RunQueue::new()),
+            new_tasks: Arc::new(SegQueue::new()),
+            cancelled_tasks: Arc::new(SegQueue::new()),
             waker_cache: BTreeMap::new(),
+            current: None,
+            thread: None,
         }
     }
 
+    pub fn spawner(&self) -> Spawner {
+        Spawner::new(self.new_tasks.clone(), self.cancelled_tasks.clone())
+    }
+
+    /// Runs the tasks forever, the first running executor backs `task::spawn`.
     pub fn run(&mut self) -> ! {
+        SPAWNER.get_or_init(|| self.spawner());
+        self.thread = thread::current();
+        if self.thread.is_some() {
+            let _ = RUNNING.compare_exchange(
+                ptr::null_mut(),
+                self,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
+        }
+
         loop {
+            self.spawn_new_tasks();
+            self.cancel_tasks();
             self.run_ready_tasks();
             self.sleep_if_idle();
         }
     }
 
+    fn spawn_new_tasks(&mut self) {
+        while let Some(task) = self.new_tasks.pop() {
+            self.spawn(task);
+        }
+    }
+
+    fn cancel_tasks(&mut self) {
+        while let Some(task_id) = self.cancelled_tasks.pop() {
+            if let Some(task) = self.remove(task_id) {
+                task.fail(JoinError::Cancelled);
+            }
+        }
+    }
+
+    fn remove(&mut self, task_id: TaskId) -> Option<Task> {
+        self.waker_cache.remove(&task_id);
+        stats::unregister(task_id);
+        self.tasks.remove(&task_id)
+    }
+
+    fn has_work(&self) -> bool {
+        !self.run_queue.is_empty() || !self.new_tasks.is_empty() || !self.cancelled_tasks.is_empty()
+    }
+
     fn sleep_if_idle(&self) {
+        if self.thread.is_some() {
+            WORK.wait_until(|| self.has_work());
+            return;
+        }
+
         interrupts::disable();
-        if self.task_queue.is_empty() {
+        if !self.has_work() {
             interrupts::enable_and_hlt();
         } else {
             interrupts::enable();
         }
     }
 
+    /// Polls the ready tasks of every level up to the level's budget, from the highest priority
+    /// to the lowest, so no task can starve the others by waking itself.
     fn run_ready_tasks(&mut self) {
-        while let Some(task_id) = self.task_queue.pop() {
-            let task = match self.tasks.get_mut(&task_id) {
-                Some(task) => task,
-                None => continue,
+        for priority in Priority::ALL {
+            for _ in 0..priority.budget() {
+                match self.run_queue.pop(priority) {
+                    Some(task_id) => self.poll_task(task_id),
+                    None => break,
+                }
+            }
+        }
+    }
+
+    fn poll_task(&mut self, task_id: TaskId) {
+        let (task, task_waker) =
+            match (self.tasks.get_mut(&task_id), self.waker_cache.get(&task_id)) {
+                (Some(task), Some(task_waker)) => (task, task_waker),
+                _ => return,
             };
 
-            let waker = self
-                .waker_cache
-                .entry(task_id)
-                .or_insert_with(|| TaskWaker::new(task_id, self.task_queue.clone()));
+        task_waker.queued.store(false, Ordering::Release);
+        let waker = Waker::from(task_waker.clone());
+        let mut context = Context::from_waker(&waker);
 
-            let mut context = Context::from_waker(waker);
-            match task.poll(&mut context) {
-                Poll::Ready(()) => {
-                    self.tasks.remove(&task_id);
-                    self.waker_cache.remove(&task_id);
-                }
-                Poll::Pending => {}
-            }
+        self.current = Some(task_id);
+        let start = time::uptime();
+        let poll = task.poll(&mut context);
+        task.stats.record_poll(time::uptime().saturating_sub(start));
+        self.current = None;
+
+        if poll.is_ready() {
+            self.remove(task_id);
         }
     }
 
     pub fn spawn(&mut self, task: Task) {
         let task_id = task.id;
+        // Killed while it was queued
+        if task.stats.is_cancelled() {
+            stats::unregister(task_id);
+            task.fail(JoinError::Cancelled);
+            return;
+        }
+
+        let task_waker = TaskWaker::new(&task, self.run_queue.clone());
+        stats::register(task_id, task.stats.clone());
         if self.tasks.insert(task_id, task).is_some() {
             panic!("a task with the id {} already exist", task_id.0);
         }
-        self.task_queue.push(task_id).expect("queue full");
+        task_waker.wake_task();
+        self.waker_cache.insert(task_id, task_waker);
+    }
+}
+
+/// Called by the panic handler, recovers the executor thread if the panic comes from one of its
+/// tasks, and returns otherwise.
+///
+/// The task is reported as panicked through its `JoinHandle`. The executor is moved to a new thread
+/// and the panicked one exits, so the abandoned frames are never resumed and its stack is freed.
+/// The future is leaked, dropping it could run destructors over a half modified state, and the
+/// locks the task held stay held. Panics raised with interrupts disabled are not recovered, the
+/// interrupted code may hold a lock of the kernel.
+///
+/// # Safety
+///
+/// Only the panic handler may call it, the frames of the caller are abandoned.
+pub unsafe fn resume_after_panic() {
+    let running = RUNNING.load(Ordering::Acquire);
+    if running.is_null() || !interrupts::are_enabled() {
+        return;
+    }
+
+    let executor = unsafe { &mut *running };
+    if executor.thread != thread::current() {
+        return;
+    }
+
+    let task_id = match executor.current.take() {
+        Some(task_id) => task_id,
+        None => return,
+    };
+
+    if let Some(task) = executor.remove(task_id) {
+        task.fail(JoinError::Panicked);
+        mem::forget(task);
+    }
+
+    // The frames holding the executor are never resumed, it is moved out rather than copied. Left
+    // undropped if no thread can take it, the panic then halts the CPU.
+    RUNNING.store(ptr::null_mut(), Ordering::Release);
+    let mut executor = ManuallyDrop::new(unsafe { ptr::read(running) });
+    if thread::spawn("executor", move || executor.run()).is_ok() {
+        thread::exit()
     }
 }
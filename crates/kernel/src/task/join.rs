@@ -0,0 +1,168 @@
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use alloc::{boxed::Box, sync::Arc};
+use spin::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinError {
+    Cancelled,
+    Panicked,
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Cancelled => write!(f, "task was cancelled"),
+            JoinError::Panicked => write!(f, "task panicked"),
+        }
+    }
+}
+
+struct JoinState<T> {
+    result: Option<Result<T, JoinError>>,
+    finished: bool,
+    aborted: bool,
+    join_waker: Option<Waker>,
+    task_waker: Option<Waker>,
+}
+
+impl<T> JoinState<T> {
+    fn complete(&mut self, result: Result<T, JoinError>) {
+        if self.finished {
+            return;
+        }
+
+        self.result = Some(result);
+        self.finished = true;
+        if let Some(waker) = self.join_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Lets the executor report the failure of a task that could not run to completion.
+pub(crate) trait TaskHandle: Send + Sync {
+    fn fail(&self, error: JoinError);
+}
+
+impl<T: Send> TaskHandle for Mutex<JoinState<T>> {
+    fn fail(&self, error: JoinError) {
+        self.lock().complete(Err(error));
+    }
+}
+
+pub struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Cancels the task, its future is dropped the next time the executor reaches it.
+    pub fn abort(&self) {
+        let mut state = self.state.lock();
+        if state.finished {
+            return;
+        }
+
+        state.aborted = true;
+        if let Some(waker) = state.task_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Lets the task run in the background, dropping the handle has the same effect.
+    pub fn detach(self) {}
+
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().finished
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut state = self.state.lock();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None if state.finished => panic!("JoinHandle polled after completion"),
+            None => {
+                state.join_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Wraps the future of a task to store its output in the shared state.
+pub(crate) struct JoinFuture<T> {
+    future: Option<Pin<Box<dyn Future<Output = T> + Send>>>,
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Future for JoinFuture<T> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let state = self.state.clone();
+
+        {
+            let mut state = state.lock();
+            if state.aborted {
+                state.complete(Err(JoinError::Cancelled));
+                drop(state);
+                self.future = None;
+                return Poll::Ready(());
+            }
+            state.task_waker = Some(cx.waker().clone());
+        }
+
+        let future = match self.future.as_mut() {
+            Some(future) => future,
+            None => return Poll::Ready(()),
+        };
+
+        match future.as_mut().poll(cx) {
+            Poll::Ready(output) => {
+                self.future = None;
+                state.lock().complete(Ok(output));
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub(crate) fn join<F>(
+    future: F,
+) -> (
+    JoinFuture<F::Output>,
+    JoinHandle<F::Output>,
+    Arc<dyn TaskHandle>,
+)
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let state = Arc::new(Mutex::new(JoinState {
+        result: None,
+        finished: false,
+        aborted: false,
+        join_waker: None,
+        task_waker: None,
+    }));
+
+    let future = JoinFuture {
+        future: Some(Box::pin(future)),
+        state: state.clone(),
+    };
+    let handle = JoinHandle {
+        state: state.clone(),
+    };
+
+    (future, handle, state)
+}
@@ -9,6 +9,11 @@ use futures_util::{task::AtomicWaker, Stream, StreamExt};
 pub use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
 use vga::{print, println};
 
+use crate::tty;
+
+/// Character decoded for Ctrl-C with `HandleControl::MapLettersToUnicode`.
+const CTRL_C: char = '\u{3}';
+
 static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
 static WAKER: AtomicWaker = AtomicWaker::new();
 
@@ -46,6 +51,51 @@ impl Stream for ScancodeStream {
     }
 }
 
+/// Keys decoded from the scancodes, with the control letters mapped to their control characters.
+///
+/// Ctrl-C goes to the TTY, which sends SIGINT to the foreground process. It is only yielded when
+/// there is no foreground process.
+pub struct KeyStream {
+    scancodes: ScancodeStream,
+    keyboard: Keyboard<layouts::Azerty, ScancodeSet1>,
+}
+
+impl KeyStream {
+    pub fn new() -> Self {
+        Self {
+            scancodes: ScancodeStream::new(),
+            keyboard: Keyboard::new(
+                ScancodeSet1::new(),
+                layouts::Azerty,
+                HandleControl::MapLettersToUnicode,
+            ),
+        }
+    }
+}
+
+impl Stream for KeyStream {
+    type Item = DecodedKey;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<DecodedKey>> {
+        let this = self.get_mut();
+        loop {
+            let scancode = match Pin::new(&mut this.scancodes).poll_next(cx) {
+                Poll::Ready(Some(scancode)) => scancode,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            if let Ok(Some(key_event)) = this.keyboard.add_byte(scancode) {
+                match this.keyboard.process_keyevent(key_event) {
+                    Some(DecodedKey::Unicode(CTRL_C)) if tty::interrupt() => {}
+                    Some(key) => return Poll::Ready(Some(key)),
+                    None => {}
+                }
+            }
+        }
+    }
+}
+
 pub(crate) fn add_scancode(scancode: u8) {
     if let Ok(queue) = SCANCODE_QUEUE.try_get() {
         if queue.push(scancode).is_err() {
@@ -1,36 +1,147 @@
 pub mod executor;
+pub mod join;
 pub mod keyboard;
+mod run_queue;
 pub mod simple_executor;
+pub mod spawner;
+pub mod stats;
 
 use core::{
+    fmt,
     future::Future,
     pin::Pin,
     sync::atomic::{AtomicU64, Ordering},
     task::{Context, Poll},
 };
 
-use alloc::boxed::Box;
+use alloc::{boxed::Box, string::String, sync::Arc};
+use conquer_once::spin::OnceCell;
+
+use join::TaskHandle;
+pub use join::{JoinError, JoinHandle};
+pub use spawner::Spawner;
+pub use stats::{TaskInfo, TaskStats};
+
+static SPAWNER: OnceCell<Spawner> = OnceCell::uninit();
+
+/// Scheduling class of a task, higher priorities get a bigger share of the polls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl Priority {
+    pub const COUNT: usize = 3;
+    pub const ALL: [Priority; Self::COUNT] = [Priority::High, Priority::Normal, Priority::Low];
+
+    /// Maximum number of polls given to the level in one pass of the executor.
+    pub fn budget(self) -> usize {
+        match self {
+            Priority::High => 16,
+            Priority::Normal => 4,
+            Priority::Low => 1,
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        };
+        f.pad(name)
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct TaskId(u64);
+pub struct TaskId(u64);
 
 impl TaskId {
     fn new() -> Self {
         static NEXT_ID: AtomicU64 = AtomicU64::new(0);
         TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
     }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for TaskId {
+    fn from(value: u64) -> Self {
+        TaskId(value)
+    }
+}
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
 
 pub struct Task {
     id: TaskId,
-    future: Pin<Box<dyn Future<Output = ()>>>,
+    future: Pin<Box<dyn Future<Output = ()> + Send>>,
+    handle: Option<Arc<dyn TaskHandle>>,
+    stats: Arc<TaskStats>,
 }
 
 impl Task {
-    pub fn new(future: impl Future<Output = ()> + 'static) -> Self {
+    pub fn new(future: impl Future<Output = ()> + Send + 'static) -> Self {
         Self {
             id: TaskId::new(),
             future: Box::pin(future),
+            handle: None,
+            stats: Arc::default(),
+        }
+    }
+
+    /// Creates a task whose output and failures are reported through a `JoinHandle`.
+    pub fn with_handle<F>(future: F) -> (Self, JoinHandle<F::Output>)
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (future, join_handle, handle) = join::join(future);
+        let task = Self {
+            id: TaskId::new(),
+            future: Box::pin(future),
+            handle: Some(handle),
+            stats: Arc::default(),
+        };
+        (task, join_handle)
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        Arc::get_mut(&mut self.stats)
+            .expect("task stats are shared before the task is spawned")
+            .set_name(name.into());
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        Arc::get_mut(&mut self.stats)
+            .expect("task stats are shared before the task is spawned")
+            .set_priority(priority);
+        self
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.stats.priority()
+    }
+
+    fn fail(&self, error: JoinError) {
+        if let Some(handle) = &self.handle {
+            handle.fail(error);
         }
     }
 
@@ -38,3 +149,63 @@ impl Task {
         self.future.as_mut().poll(context)
     }
 }
+
+/// Spawns a task on the running executor, can be called from tasks and interrupt handlers.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (task, handle) = Task::with_handle(future);
+    spawner().spawn(task);
+    handle
+}
+
+pub fn spawn_named<F>(name: impl Into<String>, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (task, handle) = Task::with_handle(future);
+    spawner().spawn(task.with_name(name));
+    handle
+}
+
+/// Cancels a task, its `JoinHandle` resolves to `JoinError::Cancelled`.
+///
+/// Returns `false` if no live task has this id.
+pub fn kill(id: TaskId) -> bool {
+    if !stats::cancel(id) {
+        return false;
+    }
+
+    spawner().cancel(id);
+    true
+}
+
+/// Gives the other ready tasks a chance to run before resuming the current one.
+pub async fn yield_now() {
+    YieldNow { yielded: false }.await
+}
+
+struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+fn spawner() -> &'static Spawner {
+    SPAWNER.try_get().expect("no executor is running")
+}
@@ -0,0 +1,28 @@
+use crossbeam_queue::SegQueue;
+
+use super::{Priority, TaskId};
+
+/// One growable FIFO queue per priority level.
+pub(super) struct RunQueue {
+    queues: [SegQueue<TaskId>; Priority::COUNT],
+}
+
+impl RunQueue {
+    pub fn new() -> Self {
+        Self {
+            queues: [SegQueue::new(), SegQueue::new(), SegQueue::new()],
+        }
+    }
+
+    pub fn push(&self, task_id: TaskId, priority: Priority) {
+        self.queues[priority as usize].push(task_id);
+    }
+
+    pub fn pop(&self, priority: Priority) -> Option<TaskId> {
+        self.queues[priority as usize].pop()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(SegQueue::is_empty)
+    }
+}
@@ -0,0 +1,34 @@
+use alloc::sync::Arc;
+use crossbeam_queue::SegQueue;
+
+use super::{executor, stats, Task, TaskId};
+
+#[derive(Clone)]
+pub struct Spawner {
+    new_tasks: Arc<SegQueue<Task>>,
+    cancelled_tasks: Arc<SegQueue<TaskId>>,
+}
+
+impl Spawner {
+    pub(super) fn new(
+        new_tasks: Arc<SegQueue<Task>>,
+        cancelled_tasks: Arc<SegQueue<TaskId>>,
+    ) -> Self {
+        Self {
+            new_tasks,
+            cancelled_tasks,
+        }
+    }
+
+    /// The task is listed, and can be killed, before its executor takes it.
+    pub fn spawn(&self, task: Task) {
+        stats::register(task.id, task.stats.clone());
+        self.new_tasks.push(task);
+        executor::notify();
+    }
+
+    pub fn cancel(&self, task_id: TaskId) {
+        self.cancelled_tasks.push(task_id);
+        executor::notify();
+    }
+}
@@ -0,0 +1,118 @@
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use spin::Mutex;
+
+use crate::time;
+
+use super::{Priority, TaskId};
+
+static REGISTRY: Mutex<BTreeMap<TaskId, Arc<TaskStats>>> = Mutex::new(BTreeMap::new());
+
+/// Counters updated by the executor, and by the task waker for the wake time.
+#[derive(Debug, Default)]
+pub struct TaskStats {
+    name: Option<String>,
+    priority: Priority,
+    polls: AtomicU64,
+    poll_time: AtomicU64,
+    // Nanoseconds since boot, 0 until the task is woken for the first time
+    last_wake: AtomicU64,
+    // Set by `kill`, so that a task still queued for its executor is never polled
+    cancelled: AtomicBool,
+}
+
+impl TaskStats {
+    pub(super) fn set_name(&mut self, name: String) {
+        self.name = Some(name);
+    }
+
+    pub(super) fn set_priority(&mut self, priority: Priority) {
+        self.priority = priority;
+    }
+
+    pub(super) fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    pub(super) fn record_poll(&self, duration: Duration) {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        self.poll_time
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn record_wake(&self) {
+        let now = time::uptime().as_nanos() as u64;
+        self.last_wake.store(now.max(1), Ordering::Relaxed);
+    }
+
+    pub(super) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub id: TaskId,
+    pub name: Option<String>,
+    pub priority: Priority,
+    pub polls: u64,
+    pub poll_time: Duration,
+    pub last_wake: Option<Duration>,
+}
+
+impl TaskInfo {
+    fn new(id: TaskId, stats: &TaskStats) -> Self {
+        let last_wake = match stats.last_wake.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(Duration::from_nanos(nanos)),
+        };
+
+        Self {
+            id,
+            name: stats.name.clone(),
+            priority: stats.priority,
+            polls: stats.polls.load(Ordering::Relaxed),
+            poll_time: Duration::from_nanos(stats.poll_time.load(Ordering::Relaxed)),
+            last_wake,
+        }
+    }
+}
+
+pub(super) fn register(id: TaskId, stats: Arc<TaskStats>) {
+    REGISTRY.lock().insert(id, stats);
+}
+
+pub(super) fn unregister(id: TaskId) {
+    REGISTRY.lock().remove(&id);
+}
+
+/// Marks the task cancelled, `false` if no live task has this id.
+pub(super) fn cancel(id: TaskId) -> bool {
+    match REGISTRY.lock().get(&id) {
+        Some(stats) => {
+            stats.cancelled.store(true, Ordering::Release);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Snapshot of the live tasks of all the executors.
+pub fn tasks() -> Vec<TaskInfo> {
+    REGISTRY
+        .lock()
+        .iter()
+        .map(|(id, stats)| TaskInfo::new(*id, stats))
+        .collect()
+}
+
+pub fn task(id: TaskId) -> Option<TaskInfo> {
+    REGISTRY
+        .lock()
+        .get(&id)
+        .map(|stats| TaskInfo::new(id, stats))
+}
@@ -0,0 +1,176 @@
+mod scheduler;
+mod switch;
+pub mod wait_queue;
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+use spin::Mutex;
+
+use x86::{addr::VirtAddr, structures::paging::frame::PhysFrame};
+
+use crate::time;
+
+pub use scheduler::{init, init_cpu, preempt, run_idle};
+pub use wait_queue::WaitQueue;
+
+pub const STACK_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThreadId(u64);
+
+impl ThreadId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        ThreadId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for ThreadId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    Ready,
+    Running,
+    Blocked,
+    Sleeping,
+    Dead,
+}
+
+#[derive(Debug, Clone)]
+pub struct ThreadInfo {
+    pub id: ThreadId,
+    pub name: String,
+    pub state: ThreadState,
+}
+
+struct Packet<T> {
+    result: Mutex<Option<T>>,
+    finished: AtomicBool,
+    joiners: WaitQueue,
+}
+
+pub struct JoinHandle<T> {
+    id: ThreadId,
+    packet: Arc<Packet<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.packet.finished.load(Ordering::Acquire)
+    }
+
+    /// Blocks until the thread returns and gives back its result.
+    pub fn join(self) -> T {
+        self.packet.joiners.wait_until(|| self.is_finished());
+        self.packet
+            .result
+            .lock()
+            .take()
+            .expect("the thread result was already taken")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// No memory was left to map the stack of the thread.
+    StackUnavailable,
+}
+
+/// Starts a kernel thread with its own stack, scheduled round-robin with the others.
+pub fn spawn<F, T>(name: impl Into<String>, f: F) -> Result<JoinHandle<T>, SpawnError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let packet = Arc::new(Packet {
+        result: Mutex::new(None),
+        finished: AtomicBool::new(false),
+        joiners: WaitQueue::new(),
+    });
+
+    let thread_packet = packet.clone();
+    let entry = Box::new(move || {
+        let result = f();
+        *thread_packet.result.lock() = Some(result);
+        thread_packet.finished.store(true, Ordering::Release);
+        thread_packet.joiners.notify_all();
+    });
+
+    let id = scheduler::spawn(name.into(), entry)?;
+    Ok(JoinHandle { id, packet })
+}
+
+/// Id of the running thread, `None` before `init`.
+pub fn current() -> Option<ThreadId> {
+    scheduler::current()
+}
+
+pub fn is_initialized() -> bool {
+    scheduler::is_initialized()
+}
+
+pub fn yield_now() {
+    scheduler::yield_now();
+}
+
+/// Blocks the current thread for at least `duration`, with the resolution of the timer tick.
+pub fn sleep(duration: Duration) {
+    sleep_while(duration, || true);
+}
+
+/// Sleeps like `sleep` while `condition` holds, it is checked each time the thread is woken.
+/// Returns `false` if the sleep was cut short.
+pub fn sleep_while(duration: Duration, mut condition: impl FnMut() -> bool) -> bool {
+    let nanos_per_tick = 1_000_000_000 / time::TICK_FREQUENCY;
+    let ticks = (duration.as_nanos() as u64).div_ceil(nanos_per_tick).max(1);
+    let deadline = time::ticks() + ticks;
+    while time::ticks() < deadline {
+        if !condition() {
+            return false;
+        }
+        scheduler::sleep_until(deadline);
+    }
+    true
+}
+
+/// Makes the thread `id` ready if it is blocked or sleeping, its next block or sleep returns
+/// right away otherwise.
+pub fn wake(id: ThreadId) {
+    scheduler::wake(id);
+}
+
+/// Switches the current thread to the page tables at `level_4_frame`.
+pub fn set_address_space(level_4_frame: PhysFrame) {
+    scheduler::set_address_space(level_4_frame);
+}
+
+/// Sets the FS base of the current thread, used by user programs for thread local storage.
+pub fn set_fs_base(addr: VirtAddr) {
+    scheduler::set_fs_base(addr);
+}
+
+/// Terminates the current thread, its stack is freed once another thread runs.
+pub fn exit() -> ! {
+    scheduler::exit()
+}
+
+pub fn threads() -> Vec<ThreadInfo> {
+    scheduler::threads()
+}
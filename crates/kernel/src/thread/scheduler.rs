@@ -0,0 +1,456 @@
+use core::{cell::Cell, mem};
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    format,
+    string::String,
+    vec::Vec,
+};
+use conquer_once::spin::OnceCell;
+use spin::{Mutex, MutexGuard};
+use x86::{
+    addr::VirtAddr,
+    instructions::{
+        fpu::{self, FxSaveArea},
+        interrupts,
+    },
+    registers::{
+        control::{Cr3, Cr3Flags},
+        model_specific::FsBase,
+    },
+    structures::paging::{
+        frame::PhysFrame,
+        frame_alloc::FrameAllocator,
+        mapper::{MapToError, Mapper},
+        page::Size4KiB,
+    },
+};
+
+use crate::{memory::KernelStack, percpu, smp, time};
+
+use super::{switch, SpawnError, ThreadId, ThreadInfo, ThreadState};
+
+/// Number of timer ticks a thread runs before being preempted.
+const TIME_SLICE: u64 = 2;
+
+pub(super) type Entry = Box<dyn FnOnce() + Send>;
+
+static SCHEDULER: OnceCell<Mutex<Scheduler>> = OnceCell::uninit();
+static KERNEL_ADDRESS_SPACE: OnceCell<PhysFrame> = OnceCell::uninit();
+
+percpu! {
+    /// Ticks since the running thread was scheduled.
+    static SLICE: Cell<u64> = Cell::new(0);
+}
+
+struct Thread {
+    id: ThreadId,
+    name: String,
+    state: ThreadState,
+    rsp: u64,
+    // Set when the thread is woken while not blocked yet, its next block returns immediately
+    wake_pending: bool,
+    // None for the threads running on the boot stack of a CPU
+    stack: Option<KernelStack>,
+    // Top level page table loaded while the thread runs
+    address_space: PhysFrame,
+    // Thread local storage of user programs
+    fs_base: VirtAddr,
+    // Boxed for the alignment `fxsave` needs
+    fpu: Box<FxSaveArea>,
+}
+
+impl Thread {
+    fn new(name: String, state: ThreadState, rsp: u64, stack: Option<KernelStack>) -> Box<Self> {
+        // Boxed so the saved stack pointer doesn't move while a switch writes it
+        Box::new(Self {
+            id: ThreadId::new(),
+            name,
+            state,
+            rsp,
+            wake_pending: false,
+            stack,
+            address_space: *KERNEL_ADDRESS_SPACE
+                .try_get()
+                .expect("threads are not initialized"),
+            fs_base: VirtAddr::new(0),
+            fpu: Box::default(),
+        })
+    }
+
+    fn stack_top(&self) -> Option<VirtAddr> {
+        self.stack.as_ref().map(KernelStack::top)
+    }
+}
+
+struct CpuState {
+    current: ThreadId,
+    idle: ThreadId,
+}
+
+struct Scheduler {
+    threads: BTreeMap<ThreadId, Box<Thread>>,
+    /// Shared by the CPUs, whichever is free first runs the next thread. Everything here is under
+    /// the one scheduler lock, queues per CPU would still take it.
+    ready: VecDeque<ThreadId>,
+    sleeping: BTreeSet<(u64, ThreadId)>,
+    dead: Vec<ThreadId>,
+    cpus: BTreeMap<usize, CpuState>,
+}
+
+impl Scheduler {
+    fn create(&mut self, name: String, entry: Entry, mut stack: KernelStack) -> ThreadId {
+        let rsp = switch::init_stack(stack.as_mut_slice(), Box::into_raw(Box::new(entry)));
+
+        let thread = Thread::new(name, ThreadState::Ready, rsp, Some(stack));
+        let id = thread.id;
+        self.threads.insert(id, thread);
+        id
+    }
+
+    /// Registers the code running on a CPU as its current thread.
+    fn add_cpu(&mut self, index: usize, name: String, idle: Option<ThreadId>) -> ThreadId {
+        let thread = Thread::new(name, ThreadState::Running, 0, None);
+        let current = thread.id;
+        self.threads.insert(current, thread);
+
+        let idle = idle.unwrap_or(current);
+        self.cpus.insert(index, CpuState { current, idle });
+        set_current(current);
+        current
+    }
+
+    fn thread(&mut self, id: ThreadId) -> &mut Thread {
+        self.threads.get_mut(&id).expect("unknown thread")
+    }
+
+    fn cpu(&mut self) -> &mut CpuState {
+        self.cpus
+            .get_mut(&smp::cpu_index())
+            .expect("the CPU is not known to the scheduler")
+    }
+
+    fn current(&mut self) -> &mut Thread {
+        let current = self.cpu().current;
+        self.thread(current)
+    }
+
+    fn make_ready(&mut self, id: ThreadId) {
+        self.thread(id).state = ThreadState::Ready;
+        self.ready.push_back(id);
+    }
+
+    /// Picks the next thread to run, returns where to save the current context and the context to
+    /// resume if it isn't the current thread.
+    fn pick_next(&mut self) -> Option<(*mut u64, u64)> {
+        // Unmapping their stacks locks the memory inside the scheduler, never the other way round
+        for id in mem::take(&mut self.dead) {
+            self.threads.remove(&id);
+        }
+
+        let CpuState { current, idle } = *self.cpu();
+        let state = self.thread(current).state;
+        if state == ThreadState::Running && current != idle {
+            self.make_ready(current);
+        }
+
+        let next = self.ready.pop_front().unwrap_or(idle);
+        self.thread(next).state = ThreadState::Running;
+        SLICE.with(|slice| slice.set(0));
+        if next == current {
+            return None;
+        }
+
+        if state == ThreadState::Dead {
+            self.dead.push(current);
+        }
+        self.cpu().current = next;
+        set_current(next);
+        if let (Some(percpu), Some(top)) = (percpu::current(), self.thread(next).stack_top()) {
+            percpu.set_kernel_stack(top);
+        }
+        let address_space = self.thread(next).address_space;
+        if Cr3::read().0.start_address() != address_space.start_address() {
+            unsafe { Cr3::write(address_space, Cr3Flags::empty()) };
+        }
+
+        // Nothing uses the SSE registers in the kernel until the switch
+        unsafe {
+            fpu::fxsave(&mut self.thread(current).fpu);
+            fpu::fxrstor(&self.thread(next).fpu);
+            FsBase::write(self.thread(next).fs_base);
+        }
+
+        let old_rsp = &mut self.thread(current).rsp as *mut u64;
+        Some((old_rsp, self.thread(next).rsp))
+    }
+}
+
+/// Turns the running code into the `main` thread and creates the idle thread of the BSP.
+///
+/// Runs before the memory is installed, the stack of the idle thread is mapped with the tables of
+/// the boot. It is the first kernel stack, so the region of the stacks exists in every address
+/// space made afterwards.
+pub fn init(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    let stack = KernelStack::map(mapper, frame_allocator)?;
+    KERNEL_ADDRESS_SPACE.get_or_init(|| Cr3::read().0);
+    SCHEDULER.get_or_init(|| {
+        let mut scheduler = Scheduler {
+            threads: BTreeMap::new(),
+            ready: VecDeque::new(),
+            sleeping: BTreeSet::new(),
+            dead: Vec::new(),
+            cpus: BTreeMap::new(),
+        };
+
+        let idle = scheduler.create(String::from("idle0"), Box::new(|| run_idle()), stack);
+        scheduler.add_cpu(0, String::from("main"), Some(idle));
+
+        Mutex::new(scheduler)
+    });
+    Ok(())
+}
+
+/// Turns the running code into the idle thread of an AP, which then calls `run_idle`.
+pub fn init_cpu(index: usize) {
+    interrupts::without_interrupts(|| {
+        let mut scheduler = lock().expect("threads are not initialized");
+        scheduler.add_cpu(index, format!("idle{}", index), None);
+    });
+}
+
+pub fn is_initialized() -> bool {
+    SCHEDULER.is_initialized()
+}
+
+/// Locks the scheduler, interrupts must be disabled until the guard is dropped.
+fn lock() -> Option<MutexGuard<'static, Scheduler>> {
+    SCHEDULER.try_get().ok().map(Mutex::lock)
+}
+
+/// Releases the lock kept by a thread switching to the caller.
+fn unlock_after_switch() {
+    if let Ok(scheduler) = SCHEDULER.try_get() {
+        unsafe { scheduler.force_unlock() };
+    }
+}
+
+/// Switches to the next thread, must be called with interrupts disabled.
+///
+/// The lock stays held during the switch so that no other CPU resumes the current thread before
+/// its context is saved, the resumed thread releases it.
+fn schedule(mut scheduler: MutexGuard<'static, Scheduler>) {
+    if let Some((old_rsp, new_rsp)) = scheduler.pick_next() {
+        mem::forget(scheduler);
+        unsafe { switch::switch(old_rsp, new_rsp) };
+        unlock_after_switch();
+    }
+}
+
+pub fn spawn(name: String, entry: Entry) -> Result<ThreadId, SpawnError> {
+    // Mapped before locking the scheduler, which doesn't need to be held while frames are allocated
+    let stack = KernelStack::new().ok_or(SpawnError::StackUnavailable)?;
+    interrupts::without_interrupts(|| {
+        let mut scheduler = lock().expect("threads are not initialized");
+        let id = scheduler.create(name, entry, stack);
+        scheduler.ready.push_back(id);
+        Ok(id)
+    })
+}
+
+/// Records the running thread in the per-CPU block, read back without locking the scheduler.
+fn set_current(id: ThreadId) {
+    if let Some(percpu) = percpu::current() {
+        percpu.set_current_thread(id.as_u64());
+    }
+}
+
+pub fn current() -> Option<ThreadId> {
+    if !is_initialized() {
+        return None;
+    }
+
+    interrupts::without_interrupts(|| match percpu::current() {
+        Some(percpu) => Some(ThreadId(percpu.current_thread())),
+        None => lock().map(|mut scheduler| scheduler.cpu().current),
+    })
+}
+
+pub fn yield_now() {
+    interrupts::without_interrupts(|| {
+        if let Some(scheduler) = lock() {
+            schedule(scheduler);
+        }
+    });
+}
+
+/// Blocks the current thread until `wake` is called with its id.
+///
+/// Must be called with interrupts disabled, after the thread registered itself where it will be
+/// woken from. Without threads it returns immediately and the caller polls again.
+pub fn block_current() {
+    let mut scheduler = match lock() {
+        Some(scheduler) => scheduler,
+        None => return core::hint::spin_loop(),
+    };
+
+    let current = scheduler.current();
+    if mem::take(&mut current.wake_pending) {
+        return;
+    }
+
+    current.state = ThreadState::Blocked;
+    schedule(scheduler);
+}
+
+/// Makes a blocked thread ready again, or makes its next block return if it isn't blocked yet.
+pub fn wake(id: ThreadId) {
+    interrupts::without_interrupts(|| {
+        let mut scheduler = match lock() {
+            Some(scheduler) => scheduler,
+            None => return,
+        };
+
+        let thread = match scheduler.threads.get_mut(&id) {
+            Some(thread) => thread,
+            None => return,
+        };
+
+        match thread.state {
+            ThreadState::Blocked => scheduler.make_ready(id),
+            // The sleep ends early, `sleep_while` checks whether to go on
+            ThreadState::Sleeping => {
+                scheduler.sleeping.retain(|&(_, sleeper)| sleeper != id);
+                scheduler.make_ready(id);
+            }
+            ThreadState::Dead => {}
+            _ => thread.wake_pending = true,
+        }
+    });
+}
+
+/// Loads the page tables of the running thread, they are restored each time it is resumed.
+pub fn set_address_space(level_4_frame: PhysFrame) {
+    interrupts::without_interrupts(|| {
+        if let Some(mut scheduler) = lock() {
+            scheduler.current().address_space = level_4_frame;
+        }
+        unsafe { Cr3::write(level_4_frame, Cr3Flags::empty()) };
+    });
+}
+
+/// Sets the FS base of the running thread, restored each time it is resumed.
+pub fn set_fs_base(addr: VirtAddr) {
+    interrupts::without_interrupts(|| {
+        if let Some(mut scheduler) = lock() {
+            scheduler.current().fs_base = addr;
+        }
+        unsafe { FsBase::write(addr) };
+    });
+}
+
+pub fn sleep_until(tick: u64) {
+    interrupts::without_interrupts(|| {
+        let mut scheduler = match lock() {
+            Some(scheduler) => scheduler,
+            None => return,
+        };
+
+        let current = scheduler.current();
+        if mem::take(&mut current.wake_pending) {
+            return;
+        }
+
+        current.state = ThreadState::Sleeping;
+        let id = current.id;
+        scheduler.sleeping.insert((tick, id));
+        schedule(scheduler);
+    });
+}
+
+pub fn exit() -> ! {
+    interrupts::disable();
+    let mut scheduler = lock().expect("threads are not initialized");
+    scheduler.current().state = ThreadState::Dead;
+    schedule(scheduler);
+    unreachable!("a dead thread was resumed");
+}
+
+/// Wakes the sleeping threads and preempts the current one at the end of its time slice.
+///
+/// Called from the timer interrupt handlers, after the end of interrupt is signaled.
+pub fn preempt() {
+    let mut scheduler = match lock() {
+        Some(scheduler) => scheduler,
+        None => return,
+    };
+
+    // An AP can take its first timer interrupt before joining the scheduler
+    if !scheduler.cpus.contains_key(&smp::cpu_index()) {
+        return;
+    }
+
+    let now = time::ticks();
+    while let Some(&(tick, id)) = scheduler.sleeping.first() {
+        if tick > now {
+            break;
+        }
+        scheduler.sleeping.pop_first();
+        scheduler.make_ready(id);
+    }
+
+    let ready = !scheduler.ready.is_empty();
+    let slice = SLICE.with(|slice| {
+        slice.set(slice.get() + 1);
+        slice.get()
+    });
+    let CpuState { current, idle } = *scheduler.cpu();
+    if slice >= TIME_SLICE || (current == idle && ready) {
+        schedule(scheduler);
+    }
+}
+
+pub fn threads() -> Vec<ThreadInfo> {
+    interrupts::without_interrupts(|| {
+        let scheduler = match lock() {
+            Some(scheduler) => scheduler,
+            None => return Vec::new(),
+        };
+
+        scheduler
+            .threads
+            .values()
+            .map(|thread| ThreadInfo {
+                id: thread.id,
+                name: thread.name.clone(),
+                state: thread.state,
+            })
+            .collect()
+    })
+}
+
+/// Loop of the idle threads, halts until an interrupt makes a thread ready.
+pub fn run_idle() -> ! {
+    loop {
+        interrupts::disable();
+        let ready = lock().map_or(false, |scheduler| !scheduler.ready.is_empty());
+        if ready {
+            interrupts::enable();
+            yield_now();
+        } else {
+            interrupts::enable_and_hlt();
+        }
+    }
+}
+
+pub(super) extern "C" fn thread_start(entry: *mut Entry) -> ! {
+    unlock_after_switch();
+    let entry = unsafe { Box::from_raw(entry) };
+    interrupts::enable();
+    entry();
+    exit()
+}
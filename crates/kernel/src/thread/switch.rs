@@ -0,0 +1,65 @@
+use core::arch::global_asm;
+
+use super::scheduler::{thread_start, Entry};
+
+// Only the callee-saved registers need to be kept, the caller saved the others before calling
+// `thread_switch`. New threads start in `thread_trampoline` with their entry in r12.
+global_asm!(
+    ".global thread_switch",
+    "thread_switch:",
+    "push rbp",
+    "push rbx",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov [rdi], rsp",
+    "mov rsp, rsi",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbx",
+    "pop rbp",
+    "ret",
+    ".global thread_trampoline",
+    "thread_trampoline:",
+    "mov rdi, r12",
+    "call {start}",
+    "ud2",
+    start = sym thread_start,
+);
+
+extern "C" {
+    fn thread_switch(old_rsp: *mut u64, new_rsp: u64);
+    fn thread_trampoline();
+}
+
+/// Saves the current context in `old_rsp` and resumes the one saved in `new_rsp`.
+///
+/// Must be called with interrupts disabled, the resumed thread restores its own interrupt state.
+pub unsafe fn switch(old_rsp: *mut u64, new_rsp: u64) {
+    thread_switch(old_rsp, new_rsp);
+}
+
+/// Writes the initial frame popped by `thread_switch` and returns the stack pointer to resume.
+pub fn init_stack(stack: &mut [u8], entry: *mut Entry) -> u64 {
+    let top = (stack.as_mut_ptr() as u64 + stack.len() as u64) & !0xF;
+    let frame = [
+        0,                                     // r15
+        0,                                     // r14
+        0,                                     // r13
+        entry as u64,                          // r12
+        0,                                     // rbx
+        0,                                     // rbp
+        thread_trampoline as *const () as u64, // return address
+    ];
+
+    let rsp = top - 8 * frame.len() as u64;
+    let slots = rsp as *mut u64;
+    for (i, value) in frame.iter().enumerate() {
+        unsafe { slots.add(i).write(*value) };
+    }
+
+    rsp
+}
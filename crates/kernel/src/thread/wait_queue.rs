@@ -0,0 +1,71 @@
+use alloc::collections::VecDeque;
+use spin::Mutex;
+use x86::instructions::interrupts;
+
+use super::{scheduler, ThreadId};
+
+/// Threads blocked until a condition holds, woken by `notify_one` or `notify_all`.
+///
+/// The condition is checked with interrupts disabled, so notifying from an interrupt handler can't
+/// be lost between the check and the blocking. Notifications racing from other CPUs are kept as a
+/// pending wakeup of the thread.
+pub struct WaitQueue {
+    waiters: Mutex<VecDeque<ThreadId>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn wait_until(&self, mut condition: impl FnMut() -> bool) {
+        loop {
+            let done = interrupts::without_interrupts(|| {
+                // Registered before checking, a notify from another CPU in between isn't lost
+                let current = scheduler::current();
+                if let Some(current) = current {
+                    let mut waiters = self.waiters.lock();
+                    if !waiters.contains(&current) {
+                        waiters.push_back(current);
+                    }
+                }
+
+                if condition() {
+                    if let Some(current) = current {
+                        self.waiters.lock().retain(|&id| id != current);
+                    }
+                    return true;
+                }
+
+                scheduler::block_current();
+                false
+            });
+
+            if done {
+                return;
+            }
+        }
+    }
+
+    pub fn notify_one(&self) {
+        let waiter = interrupts::without_interrupts(|| self.waiters.lock().pop_front());
+        if let Some(id) = waiter {
+            scheduler::wake(id);
+        }
+    }
+
+    pub fn notify_all(&self) {
+        let waiters = interrupts::without_interrupts(|| core::mem::take(&mut *self.waiters.lock()));
+        for id in waiters {
+            scheduler::wake(id);
+        }
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
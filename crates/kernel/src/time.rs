@@ -0,0 +1,262 @@
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    task::Waker,
+    time::Duration,
+};
+
+use acpi::{Acpi, AcpiError};
+use apic::lapic::{LocalApic, TimerDivide, TimerMode};
+use conquer_once::spin::OnceCell;
+use futures_util::task::AtomicWaker;
+use hpet::Hpet;
+use pit::Pit;
+use vga::println;
+use x86::structures::paging::{
+    frame_alloc::FrameAllocator,
+    mapper::{MapToError, Mapper},
+    page::Size4KiB,
+};
+
+use crate::{
+    interrupts::{self, InterruptIndex, PICS},
+    memory,
+    sync::IrqMutex,
+};
+
+/// Number of timer interrupts per second, whatever the clock source is.
+pub const TICK_FREQUENCY: u64 = 100;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+const LAPIC_CALIBRATION_MICROS: u64 = 10_000;
+
+const HPET_TICK_TIMER: u8 = 0;
+const HPET_ALARM_TIMER: u8 = 1;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+static ALARMS: AtomicU64 = AtomicU64::new(0);
+static ALARM_WAKER: AtomicWaker = AtomicWaker::new();
+
+static SOURCE: OnceCell<ClockSource> = OnceCell::uninit();
+static HPET: OnceCell<IrqMutex<Hpet>> = OnceCell::uninit();
+static PIT: IrqMutex<Pit> = IrqMutex::named("pit", Pit::new());
+static LAPIC_TIMER_COUNT: OnceCell<u32> = OnceCell::uninit();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    Pit,
+    Hpet,
+    Lapic,
+}
+
+#[derive(Debug)]
+pub enum TimeError {
+    Acpi(AcpiError),
+    Map(MapToError<Size4KiB>),
+    HpetNotFound,
+    HpetUnavailable,
+    LegacyReplacementUnsupported,
+    PeriodicUnsupported,
+    InvalidPeriod,
+}
+
+impl From<AcpiError> for TimeError {
+    fn from(value: AcpiError) -> Self {
+        TimeError::Acpi(value)
+    }
+}
+
+impl From<MapToError<Size4KiB>> for TimeError {
+    fn from(value: MapToError<Size4KiB>) -> Self {
+        TimeError::Map(value)
+    }
+}
+
+/// Starts the periodic tick on the `preferred` source, falling back to the PIT.
+pub fn init(
+    preferred: ClockSource,
+    acpi: &Acpi,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> ClockSource {
+    let result = match preferred {
+        ClockSource::Pit => Ok(()),
+        ClockSource::Hpet => init_hpet(acpi, mapper, frame_allocator),
+        ClockSource::Lapic => init_lapic(mapper, frame_allocator),
+    };
+
+    let source = match result {
+        Ok(()) => preferred,
+        Err(err) => {
+            println!(
+                "WARNING: {:?} unavailable ({:?}); falling back to PIT",
+                preferred, err
+            );
+            ClockSource::Pit
+        }
+    };
+
+    if source == ClockSource::Pit {
+        PIT.lock().set_periodic(TICK_FREQUENCY);
+    }
+
+    SOURCE.get_or_init(|| source);
+    source
+}
+
+fn init_hpet(
+    acpi: &Acpi,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), TimeError> {
+    let table = acpi.hpet()?.ok_or(TimeError::HpetNotFound)?;
+    let base = memory::map_mmio(
+        table.base_address(),
+        hpet::MMIO_SIZE,
+        mapper,
+        frame_allocator,
+    )?;
+    let mut hpet = unsafe { Hpet::new(base) };
+
+    if let Err(err) = check_hpet(&mut hpet) {
+        memory::unmap_mmio(base, hpet::MMIO_SIZE, mapper);
+        return Err(err);
+    }
+
+    hpet.disable();
+    hpet.set_main_counter(0);
+
+    let period = hpet.frequency() / TICK_FREQUENCY;
+    hpet.timer(HPET_TICK_TIMER).set_periodic(period);
+
+    // Timer 0 replaces the PIT on IRQ0 and timer 1 the RTC on IRQ8
+    hpet.enable_legacy_replacement();
+    hpet.enable();
+
+    unsafe { PICS.lock().set_masked(InterruptIndex::Alarm.as_u8(), false) };
+
+    HPET.get_or_init(|| IrqMutex::named("hpet", hpet));
+    Ok(())
+}
+
+/// Checks that the HPET can take over the periodic tick of the PIT.
+fn check_hpet(hpet: &mut Hpet) -> Result<(), TimeError> {
+    // A zero period would divide by zero in `frequency`
+    let period = hpet.period();
+    if period == 0 || period > hpet::MAX_PERIOD {
+        return Err(TimeError::InvalidPeriod);
+    }
+
+    if !hpet.capabilities().legacy_replacement() {
+        return Err(TimeError::LegacyReplacementUnsupported);
+    }
+
+    if !hpet.timer(HPET_TICK_TIMER).supports_periodic() {
+        return Err(TimeError::PeriodicUnsupported);
+    }
+
+    Ok(())
+}
+
+fn init_lapic(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), TimeError> {
+    let lapic = interrupts::init_lapic(mapper, frame_allocator)?;
+    start_lapic_timer(lapic);
+
+    // The PIT keeps firing on IRQ0 and would count twice
+    unsafe { PICS.lock().set_masked(InterruptIndex::Timer.as_u8(), true) };
+
+    Ok(())
+}
+
+/// Count of the LAPIC timer for one tick, measured against the PIT on the first call.
+///
+/// The first call must happen on the BSP, before the APs are started.
+pub(crate) fn lapic_timer_count(lapic: &LocalApic) -> u32 {
+    *LAPIC_TIMER_COUNT.get_or_init(|| {
+        lapic.set_timer_divide(TimerDivide::By16);
+        lapic.start_timer(
+            InterruptIndex::LapicTimer.as_u8(),
+            TimerMode::OneShot,
+            u32::MAX,
+        );
+        PIT.lock().sleep_micros(LAPIC_CALIBRATION_MICROS);
+        let elapsed = u32::MAX - lapic.timer_current_count();
+        lapic.stop_timer();
+
+        let frequency = elapsed as u64 * (1_000_000 / LAPIC_CALIBRATION_MICROS);
+        (frequency / TICK_FREQUENCY) as u32
+    })
+}
+
+/// Starts the periodic LAPIC timer of the current CPU at the tick frequency.
+pub(crate) fn start_lapic_timer(lapic: &LocalApic) {
+    let initial_count = lapic_timer_count(lapic);
+    lapic.set_timer_divide(TimerDivide::By16);
+    lapic.start_timer(
+        InterruptIndex::LapicTimer.as_u8(),
+        TimerMode::Periodic,
+        initial_count,
+    );
+}
+
+/// Busy waits on the PIT, usable before the clock source is started.
+pub fn delay(duration: Duration) {
+    // A single PIT countdown lasts at most ~55ms
+    let mut micros = duration.as_micros() as u64;
+    while micros > 0 {
+        let step = micros.min(50_000);
+        PIT.lock().sleep_micros(step);
+        micros -= step;
+    }
+}
+
+pub fn source() -> Option<ClockSource> {
+    SOURCE.try_get().ok().copied()
+}
+
+pub(crate) fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Time elapsed since the clock source was started.
+///
+/// The HPET main counter is read directly, other sources are limited to the tick resolution.
+pub fn uptime() -> Duration {
+    let nanos = match HPET.try_get() {
+        Ok(hpet) => {
+            let hpet = hpet.lock();
+            hpet.ticks_to_nanos(hpet.main_counter())
+        }
+        Err(_) => ticks() * (NANOS_PER_SECOND / TICK_FREQUENCY),
+    };
+
+    Duration::from_nanos(nanos)
+}
+
+/// Arms a one-shot interrupt on the second HPET comparator.
+pub fn set_alarm(delay: Duration) -> Result<(), TimeError> {
+    let hpet = HPET.try_get().map_err(|_| TimeError::HpetUnavailable)?;
+    let mut hpet = hpet.lock();
+    let ticks = hpet.nanos_to_ticks(delay.as_nanos() as u64);
+    hpet.timer(HPET_ALARM_TIMER).set_one_shot(ticks);
+    Ok(())
+}
+
+pub(crate) fn ring_alarm() {
+    ALARMS.fetch_add(1, Ordering::Relaxed);
+    ALARM_WAKER.wake();
+}
+
+pub fn alarms() -> u64 {
+    ALARMS.load(Ordering::Relaxed)
+}
+
+pub fn register_alarm_waker(waker: &Waker) {
+    ALARM_WAKER.register(waker);
+}
@@ -0,0 +1,238 @@
+//! Exceptions and interrupts entered through assembly stubs that save the interrupted registers,
+//! so that the faults of user code become signals and the pending signals are delivered before
+//! returning to ring 3.
+//!
+//! Every vector goes through the stubs: the GS base of ring 3 is the user's, so the stubs swap in
+//! the per-CPU block when they interrupt user code, and swap it out again before returning.
+
+use core::arch::global_asm;
+
+use vga::println;
+use x86::{dt::idt::InterruptDescriptorTable, instructions::interrupts, PrivilegeLevel};
+
+use crate::{
+    interrupts::{self as irq, InterruptIndex},
+    process::signal::{self, Signal},
+    DOUBLE_FAULT_IST_INDEX,
+};
+
+/// `TrapFrame::vector` of the frames built by the syscall entry.
+pub const SYSCALL_VECTOR: u64 = 0x100;
+
+const DIVIDE_ERROR: u64 = 0;
+const BREAKPOINT: u64 = 3;
+const INVALID_OPCODE: u64 = 6;
+const DOUBLE_FAULT: u64 = 8;
+const GENERAL_PROTECTION_FAULT: u64 = 13;
+const PAGE_FAULT: u64 = 14;
+const X87_FLOATING_POINT: u64 = 16;
+const SIMD_FLOATING_POINT: u64 = 19;
+
+/// Registers of the interrupted code, in the reverse order they are pushed. The last five are the
+/// frame pushed by the CPU and popped by `iretq`.
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct TrapFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rax: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub vector: u64,
+    /// Pushed by the CPU for some exceptions, 0 otherwise.
+    pub error_code: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl TrapFrame {
+    /// Whether the interrupted code ran in ring 3.
+    pub fn is_user(&self) -> bool {
+        self.cs & 3 == 3
+    }
+}
+
+extern "C" {
+    fn trap_divide_error();
+    fn trap_breakpoint();
+    fn trap_invalid_opcode();
+    fn trap_double_fault();
+    fn trap_general_protection_fault();
+    fn trap_page_fault();
+    fn trap_x87_floating_point();
+    fn trap_simd_floating_point();
+    fn trap_timer();
+    fn trap_lapic_timer();
+    fn trap_keyboard();
+    fn trap_alarm();
+    fn trap_spurious();
+}
+
+/// Points the entries of the vectors the kernel handles to their stubs.
+pub fn init_idt(idt: &mut InterruptDescriptorTable) {
+    unsafe {
+        idt.divide_by_zero
+            .set_handler_addr(trap_divide_error as *const () as u64);
+        idt.breakpoint
+            .set_handler_addr(trap_breakpoint as *const () as u64)
+            .set_privilege_level(PrivilegeLevel::Ring3);
+        idt.invalid_opcode
+            .set_handler_addr(trap_invalid_opcode as *const () as u64);
+        idt.double_fault
+            .set_handler_addr(trap_double_fault as *const () as u64)
+            .set_stack_index(DOUBLE_FAULT_IST_INDEX);
+        idt.general_protection_fault
+            .set_handler_addr(trap_general_protection_fault as *const () as u64);
+        idt.page_fault
+            .set_handler_addr(trap_page_fault as *const () as u64);
+        idt.x87_floating_point
+            .set_handler_addr(trap_x87_floating_point as *const () as u64);
+        idt.simd_floating_point
+            .set_handler_addr(trap_simd_floating_point as *const () as u64);
+        idt[InterruptIndex::Timer.as_u8()].set_handler_addr(trap_timer as *const () as u64);
+        idt[InterruptIndex::LapicTimer.as_u8()]
+            .set_handler_addr(trap_lapic_timer as *const () as u64);
+        idt[InterruptIndex::Keyboard.as_u8()].set_handler_addr(trap_keyboard as *const () as u64);
+        idt[InterruptIndex::Alarm.as_u8()].set_handler_addr(trap_alarm as *const () as u64);
+        idt[InterruptIndex::Spurious.as_u8()].set_handler_addr(trap_spurious as *const () as u64);
+    }
+}
+
+// The CPU aligns the stack before pushing its 5 words, the error code and the vector make 7 and
+// the registers 22, so the stack is 16 bytes aligned for the call. The saved CS is 3 words above
+// the vector on entry, and 1 word above the RIP on return.
+global_asm!(
+    ".macro TRAP_STUB name, vector, error_code",
+    ".global \\name",
+    "\\name:",
+    ".if \\error_code == 0",
+    "push 0",
+    ".endif",
+    "push \\vector",
+    "jmp trap_common",
+    ".endm",
+    "TRAP_STUB trap_divide_error, {divide_error}, 0",
+    "TRAP_STUB trap_breakpoint, {breakpoint}, 0",
+    "TRAP_STUB trap_invalid_opcode, {invalid_opcode}, 0",
+    "TRAP_STUB trap_double_fault, {double_fault}, 1",
+    "TRAP_STUB trap_general_protection_fault, {general_protection_fault}, 1",
+    "TRAP_STUB trap_page_fault, {page_fault}, 1",
+    "TRAP_STUB trap_x87_floating_point, {x87_floating_point}, 0",
+    "TRAP_STUB trap_simd_floating_point, {simd_floating_point}, 0",
+    "TRAP_STUB trap_timer, {timer}, 0",
+    "TRAP_STUB trap_lapic_timer, {lapic_timer}, 0",
+    "TRAP_STUB trap_keyboard, {keyboard}, 0",
+    "TRAP_STUB trap_alarm, {alarm}, 0",
+    "TRAP_STUB trap_spurious, {spurious}, 0",
+    "trap_common:",
+    "test qword ptr [rsp + 24], 3",
+    "jz 1f",
+    "swapgs",
+    "1:",
+    "push rdi",
+    "push rsi",
+    "push rdx",
+    "push rcx",
+    "push rax",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "push rbx",
+    "push rbp",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "cld",
+    "mov rdi, rsp",
+    "call {dispatch}",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop rbp",
+    "pop rbx",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rax",
+    "pop rcx",
+    "pop rdx",
+    "pop rsi",
+    "pop rdi",
+    "add rsp, 16",
+    "test qword ptr [rsp + 8], 3",
+    "jz 2f",
+    "swapgs",
+    "2:",
+    "iretq",
+    divide_error = const DIVIDE_ERROR,
+    breakpoint = const BREAKPOINT,
+    invalid_opcode = const INVALID_OPCODE,
+    double_fault = const DOUBLE_FAULT,
+    general_protection_fault = const GENERAL_PROTECTION_FAULT,
+    page_fault = const PAGE_FAULT,
+    x87_floating_point = const X87_FLOATING_POINT,
+    simd_floating_point = const SIMD_FLOATING_POINT,
+    timer = const InterruptIndex::Timer as u8,
+    lapic_timer = const InterruptIndex::LapicTimer as u8,
+    keyboard = const InterruptIndex::Keyboard as u8,
+    alarm = const InterruptIndex::Alarm as u8,
+    spurious = const InterruptIndex::Spurious as u8,
+    dispatch = sym dispatch,
+);
+
+extern "C" fn dispatch(frame: &mut TrapFrame) {
+    const TIMER: u64 = InterruptIndex::Timer as u64;
+    const LAPIC_TIMER: u64 = InterruptIndex::LapicTimer as u64;
+    const KEYBOARD: u64 = InterruptIndex::Keyboard as u64;
+    const ALARM: u64 = InterruptIndex::Alarm as u64;
+    const SPURIOUS: u64 = InterruptIndex::Spurious as u64;
+
+    match frame.vector {
+        DIVIDE_ERROR => fault(frame, "DIVIDE BY ZERO", Signal::FPE),
+        BREAKPOINT => println!("EXCEPTION: BREAKPOINT"),
+        INVALID_OPCODE => fault(frame, "INVALID OPCODE", Signal::ILL),
+        DOUBLE_FAULT => panic!("EXCEPTION: DOUBLE FAULT\n{:#x?}", frame),
+        GENERAL_PROTECTION_FAULT => fault(frame, "GENERAL PROTECTION FAULT", Signal::SEGV),
+        PAGE_FAULT => fault(frame, "PAGE FAULT", Signal::SEGV),
+        X87_FLOATING_POINT => fault(frame, "X87 FLOATING POINT", Signal::FPE),
+        SIMD_FLOATING_POINT => fault(frame, "SIMD FLOATING POINT", Signal::FPE),
+        TIMER => irq::timer_interrupt(),
+        LAPIC_TIMER => irq::lapic_timer_interrupt(),
+        KEYBOARD => irq::keyboard_interrupt(),
+        ALARM => irq::alarm_interrupt(),
+        SPURIOUS => {}
+        vector => panic!("no handler for the vector {}", vector),
+    }
+
+    if frame.is_user() {
+        signal::deliver(frame);
+    }
+}
+
+/// Faults of the kernel are fatal, the ones of user code are sent to the process.
+fn fault(frame: &TrapFrame, name: &str, signal: Signal) {
+    if !frame.is_user() {
+        panic!("EXCEPTION: {}\n{:#x?}", name, frame);
+    }
+
+    // The user code ran with the interrupts enabled
+    interrupts::enable();
+    signal::force(signal);
+}
@@ -1,15 +1,28 @@
-use core::fmt::{self, Write};
+use core::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use lazy_static::lazy_static;
-use spin::Mutex;
 use vga::{Char, CharStyle, BUFFER_HEIGHT, BUFFER_WIDTH};
 
 pub use vga::Color;
 
+use crate::{
+    process::{
+        signal::{self, Signal},
+        Pid,
+    },
+    sync::IrqMutex,
+};
+
 lazy_static! {
-    pub static ref TTY: Mutex<Tty> = Mutex::new(Tty::default());
+    pub static ref TTY: IrqMutex<Tty> = IrqMutex::named("tty", Tty::default());
 }
 
+/// Pid of the process the keyboard signals go to, 0 when there is none.
+static FOREGROUND: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Debug, Default)]
 pub struct Tty {
     row: usize,
@@ -128,3 +141,25 @@ impl fmt::Write for Tty {
         Ok(())
     }
 }
+
+/// Sets the process Ctrl-C interrupts, `None` to leave it to the kernel.
+pub fn set_foreground(pid: Option<Pid>) {
+    FOREGROUND.store(pid.map_or(0, Pid::as_u64), Ordering::Relaxed);
+}
+
+pub fn foreground() -> Option<Pid> {
+    match FOREGROUND.load(Ordering::Relaxed) {
+        0 => None,
+        pid => Some(Pid::from(pid)),
+    }
+}
+
+/// Clears the foreground process if it is `pid`, once it exited.
+pub(crate) fn release_foreground(pid: Pid) {
+    let _ = FOREGROUND.compare_exchange(pid.as_u64(), 0, Ordering::Relaxed, Ordering::Relaxed);
+}
+
+/// Sends SIGINT to the foreground process for Ctrl-C, returns false when there is none.
+pub fn interrupt() -> bool {
+    foreground().is_some_and(|pid| signal::send(pid, Signal::INT).is_ok())
+}
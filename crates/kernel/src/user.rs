@@ -0,0 +1,42 @@
+use core::arch::asm;
+
+use x86::addr::VirtAddr;
+
+/// Initial RFLAGS of user code: interrupts enabled and the reserved bit 1.
+const USER_RFLAGS: u64 = 0x202;
+
+/// Leaves the kernel to run `entry` at CPL3 on `stack`.
+///
+/// The code comes back to the kernel through interrupts and exceptions, which switch to the kernel
+/// stack of the running thread. The kernel frames of the caller are never resumed. The per-CPU
+/// block is swapped out of the GS base like on every return to ring 3.
+///
+/// # Safety
+/// `entry` and `stack` must be mapped with user access in the active address space.
+pub unsafe fn enter_user_mode(entry: VirtAddr, stack: VirtAddr) -> ! {
+    let selectors = crate::selectors();
+    let code = u64::from(selectors.user_code.0);
+    let data = u64::from(selectors.user_data.0);
+
+    unsafe {
+        asm!(
+            "cli",
+            "mov ds, {data:x}",
+            "mov es, {data:x}",
+            "swapgs",
+            // Frame popped by iretq: SS, RSP, RFLAGS, CS, RIP
+            "push {data}",
+            "push {stack}",
+            "push {rflags}",
+            "push {code}",
+            "push {entry}",
+            "iretq",
+            data = in(reg) data,
+            code = in(reg) code,
+            stack = in(reg) stack.as_u64(),
+            entry = in(reg) entry.as_u64(),
+            rflags = in(reg) USER_RFLAGS,
+            options(noreturn),
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Messages of 9P2000.L, the Linux dialect of the 9P protocol, as the kernel client sends and
+//! receives them.
+//!
+//! A message is its size, its type and its tag, then the fields of the type, integers in little
+//! endian and strings prefixed by their length.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+
+use vfs::{Result, VfsError};
+
+/// No fid, for the `afid` of an attach without authentication.
+pub const NOFID: u32 = !0;
+/// Tag of the version request.
+pub const NOTAG: u16 = !0;
+pub const VERSION: &str = "9P2000.L";
+/// Size, type and tag.
+pub const HEADER_SIZE: usize = 7;
+
+/// Types of the requests, the responses are the next number.
+pub mod message {
+    pub const RLERROR: u8 = 7;
+    pub const TLOPEN: u8 = 12;
+    pub const TLCREATE: u8 = 14;
+    pub const TSYMLINK: u8 = 16;
+    pub const TREADLINK: u8 = 22;
+    pub const TGETATTR: u8 = 24;
+    pub const TSETATTR: u8 = 26;
+    pub const TREADDIR: u8 = 40;
+    pub const TMKDIR: u8 = 72;
+    pub const TRENAMEAT: u8 = 74;
+    pub const TUNLINKAT: u8 = 76;
+    pub const TVERSION: u8 = 100;
+    pub const TATTACH: u8 = 104;
+    pub const TWALK: u8 = 110;
+    pub const TREAD: u8 = 116;
+    pub const TWRITE: u8 = 118;
+    pub const TCLUNK: u8 = 120;
+}
+
+/// Identity of a file on the server. Its type and version aren't needed, `getattr` has them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Qid {
+    pub path: u64,
+}
+
+/// Builds a message, its size is filled in by `finish`.
+pub struct Writer(Vec<u8>);
+
+impl Writer {
+    pub fn new(kind: u8, tag: u16) -> Self {
+        let mut writer = Self(Vec::new());
+        writer.u32(0);
+        writer.u8(kind);
+        writer.u16(tag);
+        writer
+    }
+
+    pub fn kind(&self) -> u8 {
+        self.0[4]
+    }
+
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.0.push(value);
+        self
+    }
+
+    pub fn u16(&mut self, value: u16) -> &mut Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn u64(&mut self, value: u64) -> &mut Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn str(&mut self, value: &str) -> &mut Self {
+        self.u16(value.len() as u16);
+        self.0.extend_from_slice(value.as_bytes());
+        self
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.0.extend_from_slice(value);
+        self
+    }
+
+    /// The message with its size.
+    pub fn finish(&mut self) -> &[u8] {
+        let size = self.0.len() as u32;
+        self.0[..4].copy_from_slice(&size.to_le_bytes());
+        &self.0
+    }
+}
+
+/// Reads the fields of a response, a short response being an I/O error.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(VfsError::Io);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn str(&mut self) -> Result<String> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    pub fn qid(&mut self) -> Result<Qid> {
+        self.take(1 + 4)?;
+        Ok(Qid { path: self.u64()? })
+    }
+
+    pub fn time(&mut self) -> Result<(u64, u64)> {
+        Ok((self.u64()?, self.u64()?))
+    }
+}
+
+/// Body of `response`, the answer to a request of type `kind`. An `Rlerror` gives the error of
+/// its `errno`, another type or a wrong size an I/O error.
+pub fn response(response: &[u8], kind: u8) -> Result<&[u8]> {
+    let mut reader = Reader::new(response);
+    let size = reader.u32()? as usize;
+    let response_kind = reader.u8()?;
+    reader.u16()?;
+    let body = response.get(HEADER_SIZE..size).ok_or(VfsError::Io)?;
+    match response_kind {
+        message::RLERROR => Err(VfsError::from_errno(Reader::new(body).u32()?)),
+        response_kind if response_kind == kind.wrapping_add(1) => Ok(body),
+        _ => Err(VfsError::Io),
+    }
+}
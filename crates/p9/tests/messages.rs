@@ -0,0 +1,177 @@
+//! Tests of the 9P message codec and of the errors of `Rlerror`. They run on the host:
+//! `cargo test -p p9 --target x86_64-unknown-linux-gnu -Z build-std=std,panic_unwind`.
+
+use p9::{message, response, Qid, Reader, Writer, HEADER_SIZE, NOTAG, VERSION};
+use vfs::VfsError;
+
+const TAG: u16 = 0;
+
+/// Linux `errno` values of the errors.
+const EPERM: u32 = 1;
+const ENOENT: u32 = 2;
+const EIO: u32 = 5;
+const EACCES: u32 = 13;
+const ENOTEMPTY: u32 = 39;
+const EOPNOTSUPP: u32 = 95;
+
+/// A response of type `kind` with `body`.
+fn message(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut writer = Writer::new(kind, TAG);
+    writer.bytes(body);
+    writer.finish().to_vec()
+}
+
+fn rlerror(errno: u32) -> Vec<u8> {
+    message(message::RLERROR, &errno.to_le_bytes())
+}
+
+#[test]
+fn writes_little_endian_fields_after_the_header() {
+    let mut writer = Writer::new(message::TVERSION, NOTAG);
+    writer.u32(8192).str(VERSION);
+    assert_eq!(writer.kind(), message::TVERSION);
+    assert_eq!(
+        writer.finish(),
+        b"\x15\0\0\0\x64\xff\xff\x00\x20\0\0\x08\x009P2000.L"
+    );
+
+    let mut writer = Writer::new(message::TREAD, TAG);
+    writer
+        .u8(1)
+        .u16(0x0302)
+        .u32(0x07060504)
+        .u64(0x0f0e0d0c0b0a0908);
+    let bytes = writer.finish();
+    assert_eq!(bytes.len(), HEADER_SIZE + 15);
+    assert_eq!(&bytes[HEADER_SIZE..], (1..16).collect::<Vec<u8>>());
+}
+
+#[test]
+fn reads_what_the_writer_wrote() {
+    let mut writer = Writer::new(message::TGETATTR + 1, TAG);
+    writer
+        .u8(0xAB)
+        .u16(0xBEEF)
+        .u32(0xDEAD_BEEF)
+        .u64(u64::MAX - 1)
+        .str("")
+        .str("héllo")
+        // A qid: type, version and path
+        .u8(0x80)
+        .u32(7)
+        .u64(42)
+        .u64(1_700_000_000)
+        .u64(999_999_999)
+        .bytes(b"rest");
+    let message = writer.finish().to_vec();
+
+    let mut reader = Reader::new(&message);
+    assert_eq!(reader.u32().unwrap() as usize, message.len());
+    assert_eq!(reader.u8().unwrap(), message::TGETATTR + 1);
+    assert_eq!(reader.u16().unwrap(), TAG);
+    assert_eq!(reader.u8().unwrap(), 0xAB);
+    assert_eq!(reader.u16().unwrap(), 0xBEEF);
+    assert_eq!(reader.u32().unwrap(), 0xDEAD_BEEF);
+    assert_eq!(reader.u64().unwrap(), u64::MAX - 1);
+    assert_eq!(reader.str().unwrap(), "");
+    assert_eq!(reader.str().unwrap(), "héllo");
+    assert_eq!(reader.qid().unwrap(), Qid { path: 42 });
+    assert_eq!(reader.time().unwrap(), (1_700_000_000, 999_999_999));
+    assert_eq!(reader.take(4).unwrap(), b"rest");
+    assert!(reader.is_empty());
+}
+
+#[test]
+fn short_fields_are_io_errors() {
+    let mut reader = Reader::new(&[1, 2, 3]);
+    assert_eq!(reader.u32(), Err(VfsError::Io));
+    // A failed read consumes nothing
+    assert_eq!(reader.u16(), Ok(0x0201));
+    assert_eq!(reader.u16(), Err(VfsError::Io));
+    assert_eq!(reader.u8(), Ok(3));
+    assert_eq!(reader.u8(), Err(VfsError::Io));
+
+    // A string longer than what is left
+    assert_eq!(Reader::new(b"\x05\0abc").str(), Err(VfsError::Io));
+    assert_eq!(Reader::new(&[0; 12]).qid(), Err(VfsError::Io));
+}
+
+#[test]
+fn returns_the_body_of_the_response() {
+    let reply = message(message::TWALK + 1, b"\x01\x00qid");
+    assert_eq!(response(&reply, message::TWALK), Ok(&b"\x01\x00qid"[..]));
+
+    // Bytes after the size belong to no message
+    let mut padded = reply.clone();
+    padded.extend([0xFF; 8]);
+    assert_eq!(response(&padded, message::TWALK), Ok(&b"\x01\x00qid"[..]));
+}
+
+#[test]
+fn rejects_malformed_responses() {
+    // The response of another request
+    let reply = message(message::TREAD + 1, &[0; 4]);
+    assert_eq!(response(&reply, message::TWRITE), Err(VfsError::Io));
+    // A request instead of a response
+    assert_eq!(response(&reply, message::TREAD + 1), Err(VfsError::Io));
+
+    // Shorter than its size, or than a header
+    assert_eq!(response(&reply[..9], message::TREAD), Err(VfsError::Io));
+    assert_eq!(response(&reply[..4], message::TREAD), Err(VfsError::Io));
+    let mut small = reply.clone();
+    small[0] = 3;
+    assert_eq!(response(&small, message::TREAD), Err(VfsError::Io));
+
+    // An `Rlerror` without its errno
+    let empty_error = message(message::RLERROR, &[]);
+    assert_eq!(response(&empty_error, message::TREAD), Err(VfsError::Io));
+}
+
+#[test]
+fn maps_rlerror_to_its_error() {
+    for (errno, error) in [
+        (EPERM, VfsError::PermissionDenied),
+        (EACCES, VfsError::PermissionDenied),
+        (ENOENT, VfsError::NotFound),
+        (ENOTEMPTY, VfsError::NotEmpty),
+        (EOPNOTSUPP, VfsError::NotSupported),
+        (EIO, VfsError::Io),
+        // Unknown to the VFS
+        (110, VfsError::Io),
+        (u32::MAX, VfsError::Io),
+    ] {
+        assert_eq!(
+            response(&rlerror(errno), message::TLOPEN),
+            Err(error),
+            "errno {}",
+            errno
+        );
+    }
+}
+
+#[test]
+fn maps_every_errno_of_the_vfs_back() {
+    let errors = [
+        VfsError::NotFound,
+        VfsError::NotDirectory,
+        VfsError::IsDirectory,
+        VfsError::AlreadyExists,
+        VfsError::NotEmpty,
+        VfsError::InvalidArgument,
+        VfsError::NameTooLong,
+        VfsError::TooManySymlinks,
+        VfsError::ReadOnly,
+        VfsError::NoSpace,
+        VfsError::FileTooLarge,
+        VfsError::NotSupported,
+        VfsError::Busy,
+        VfsError::CrossDevice,
+        VfsError::BadDescriptor,
+        VfsError::NotSeekable,
+        VfsError::PermissionDenied,
+        VfsError::Io,
+    ];
+    for error in errors {
+        assert_eq!(VfsError::from_errno(error.errno() as u32), error);
+    }
+}
@@ -56,7 +56,7 @@ impl MemoryBaseAddressRegister {
     }
 
     pub fn address(&self) -> u32 {
-        self.0 >> 4
+        self.0 & !0xF
     }
 }
 
@@ -69,6 +69,6 @@ impl IoSpaceBaseAddressRegister {
     }
 
     pub fn address(&self) -> u32 {
-        self.0 >> 2
+        self.0 & !0x3
     }
 }
@@ -86,6 +86,21 @@ impl ChainedPics {
         self.pics.iter().any(|p| p.handles_interrupt(interrupt_id))
     }
 
+    pub unsafe fn set_masked(&mut self, interrupt_id: u8, masked: bool) {
+        if let Some(pic) = self
+            .pics
+            .iter_mut()
+            .find(|p| p.handles_interrupt(interrupt_id))
+        {
+            let line = interrupt_id - pic.offset;
+            let mask = pic.read_mask();
+            match masked {
+                true => pic.write_mask(mask | (1 << line)),
+                false => pic.write_mask(mask & !(1 << line)),
+            }
+        }
+    }
+
     pub unsafe fn notify_end_of_interrupt(&mut self, interrupt_id: u8) {
         if self.handles_interrupt(interrupt_id) {
             if self.pics[1].handles_interrupt(interrupt_id) {
@@ -0,0 +1,99 @@
+#![no_std]
+
+use x86::instructions::port::Port;
+
+pub const BASE_FREQUENCY: u64 = 1_193_182;
+
+const CHANNEL_0: u16 = 0x40;
+const CHANNEL_2: u16 = 0x42;
+const COMMAND: u16 = 0x43;
+const GATE: u16 = 0x61;
+
+const ACCESS_LOW_HIGH: u8 = 0b11 << 4;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy)]
+pub enum Mode {
+    InterruptOnTerminalCount = 0,
+    HardwareOneShot = 1,
+    RateGenerator = 2,
+    SquareWave = 3,
+}
+
+pub struct Pit {
+    channel_0: Port<u8>,
+    channel_2: Port<u8>,
+    command: Port<u8>,
+    gate: Port<u8>,
+}
+
+impl Pit {
+    pub const fn new() -> Self {
+        Self {
+            channel_0: Port::new(CHANNEL_0),
+            channel_2: Port::new(CHANNEL_2),
+            command: Port::new(COMMAND),
+            gate: Port::new(GATE),
+        }
+    }
+
+    /// Fires IRQ0 `frequency` times per second.
+    pub fn set_periodic(&mut self, frequency: u64) {
+        let divisor = divisor(frequency);
+        unsafe {
+            self.command
+                .write(ACCESS_LOW_HIGH | ((Mode::RateGenerator as u8) << 1));
+            self.channel_0.write(divisor as u8);
+            self.channel_0.write((divisor >> 8) as u8);
+        }
+    }
+
+    /// Fires IRQ0 a single time after `ticks` periods of the base frequency.
+    pub fn set_one_shot(&mut self, ticks: u16) {
+        unsafe {
+            self.command
+                .write(ACCESS_LOW_HIGH | ((Mode::InterruptOnTerminalCount as u8) << 1));
+            self.channel_0.write(ticks as u8);
+            self.channel_0.write((ticks >> 8) as u8);
+        }
+    }
+
+    /// Busy waits using channel 2, which is not wired to any interrupt.
+    ///
+    /// Used to calibrate other timers before any clock source is running.
+    pub fn sleep_micros(&mut self, micros: u64) {
+        let ticks = (BASE_FREQUENCY * micros / 1_000_000).clamp(1, u16::MAX as u64) as u16;
+
+        unsafe {
+            // Enable the gate of channel 2 but disconnect the speaker
+            let gate = self.gate.read();
+            self.gate.write((gate & !0b10) | 0b1);
+
+            self.command.write(
+                (0b10 << 6) | ACCESS_LOW_HIGH | ((Mode::InterruptOnTerminalCount as u8) << 1),
+            );
+            self.channel_2.write(ticks as u8);
+            self.channel_2.write((ticks >> 8) as u8);
+
+            // Restart the countdown by toggling the gate
+            let gate = self.gate.read();
+            self.gate.write(gate & !0b1);
+            self.gate.write(gate | 0b1);
+
+            // The output of channel 2 is reflected in bit 5 once the count reaches zero
+            while self.gate.read() & (1 << 5) == 0 {
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
+
+impl Default for Pit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn divisor(frequency: u64) -> u16 {
+    (BASE_FREQUENCY / frequency).clamp(1, u16::MAX as u64) as u16
+}
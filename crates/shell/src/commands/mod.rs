@@ -1,17 +1,308 @@
-use kernel::ExitCode;
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{fmt::Write, iter};
+use ext2::Ext2Fs;
+use fat::FatFs;
+use kernel::{
+    fs::{self, image::ImageDevice, tmpfs::TmpFs},
+    loader,
+    process::{self, signal::Signal, Pid},
+    task, thread, tty, ExitCode,
+};
+use vfs::{File, FileSystem, FileType, OpenFlags};
 
-pub fn run(cmd: &str) -> &str {
-    match cmd {
-        "hello" => hello_cmd(),
-        "shutdown" => shutdown_cmd(),
-        _ => "Command not found",
+/// The shell has no working directory of its own, relative paths start at the root.
+const CWD: &str = "/";
+
+pub fn run(cmd: &str) -> String {
+    let mut args = cmd.split_whitespace();
+    match args.next() {
+        Some("hello") => hello_cmd(),
+        Some("shutdown") => shutdown_cmd(),
+        Some("ps") => ps_cmd(),
+        Some("threads") => threads_cmd(),
+        Some("kill") => kill_cmd(args.next()),
+        Some("run") => run_cmd(args),
+        Some("signal") => signal_cmd(args.next(), args.next()),
+        Some("ls") => ls_cmd(args.next().unwrap_or(CWD)),
+        Some("cat") => cat_cmd(args.next()),
+        Some("mkdir") => mkdir_cmd(args.next()),
+        Some("rm") => rm_cmd(args.next()),
+        Some("mv") => mv_cmd(args.next(), args.next()),
+        Some("mounts") => mounts_cmd(),
+        Some("mount") => mount_cmd(args),
+        _ => "Command not found".to_string(),
     }
 }
 
-fn hello_cmd<'a>() -> &'a str {
-    "Hello world"
+fn hello_cmd() -> String {
+    "Hello world".to_string()
 }
 
-fn shutdown_cmd<'a>() -> &'a str {
+fn shutdown_cmd() -> String {
     kernel::exit(ExitCode::Success);
 }
+
+fn ps_cmd() -> String {
+    let mut out = format!(
+        "{:>4} {:>6} {:>8} {:>10} {:>10}  {}",
+        "ID", "PRIO", "POLLS", "TIME", "LAST WAKE", "NAME"
+    );
+
+    for info in task::stats::tasks() {
+        let last_wake = match info.last_wake {
+            Some(last_wake) => format!("{}ms", last_wake.as_millis()),
+            None => "-".to_string(),
+        };
+
+        let _ = write!(
+            out,
+            "\n{:>4} {:>6} {:>8} {:>10} {:>10}  {}",
+            info.id,
+            info.priority,
+            info.polls,
+            format!("{}us", info.poll_time.as_micros()),
+            last_wake,
+            info.name.as_deref().unwrap_or("-"),
+        );
+    }
+
+    let _ = write!(
+        out,
+        "\n\n{:>4} {:>5} {:>10}  {}",
+        "PID", "PPID", "STATE", "NAME"
+    );
+    for info in process::processes() {
+        let parent = match info.parent {
+            Some(parent) => parent.to_string(),
+            None => "-".to_string(),
+        };
+        let state = match info.state {
+            process::ProcessState::Running => "running".to_string(),
+            process::ProcessState::Zombie(code) => format!("exit {}", code),
+        };
+
+        let _ = write!(
+            out,
+            "\n{:>4} {:>5} {:>10}  {}",
+            info.pid, parent, state, info.name
+        );
+    }
+
+    out
+}
+
+fn threads_cmd() -> String {
+    let mut out = format!("{:>4} {:>8}  {}", "ID", "STATE", "NAME");
+    for info in thread::threads() {
+        let _ = write!(
+            out,
+            "\n{:>4} {:>8}  {}",
+            info.id,
+            format!("{:?}", info.state),
+            info.name
+        );
+    }
+
+    out
+}
+
+fn kill_cmd(id: Option<&str>) -> String {
+    let id = match id.and_then(|id| id.parse::<u64>().ok()) {
+        Some(id) => task::TaskId::from(id),
+        None => return "Usage: kill <id>".to_string(),
+    };
+
+    if task::kill(id) {
+        format!("Task {} killed", id)
+    } else {
+        format!("No task with the id {}", id)
+    }
+}
+
+fn run_cmd<'a>(mut args: impl Iterator<Item = &'a str>) -> String {
+    let name = match args.next() {
+        Some(name) => name,
+        None => {
+            let programs: Vec<_> = loader::programs::names().collect();
+            return format!(
+                "Usage: run <program> [args]\nPrograms: {}",
+                programs.join(" ")
+            );
+        }
+    };
+
+    let program = match loader::programs::find(name) {
+        Some(program) => program,
+        None => return format!("No program named {}", name),
+    };
+
+    let argv: Vec<&str> = iter::once(name).chain(args).collect();
+    match process::spawn(
+        name,
+        program.bytes,
+        &argv,
+        &[],
+        program.personality,
+        None,
+        None,
+    ) {
+        Ok(pid) => {
+            tty::set_foreground(Some(pid));
+            format!("Started {} as process {}", name, pid)
+        }
+        Err(err) => format!("Failed to start {}: {:?}", name, err),
+    }
+}
+
+fn signal_cmd(pid: Option<&str>, signal: Option<&str>) -> String {
+    const USAGE: &str = "Usage: signal <pid> [signal]";
+
+    let pid = match pid.and_then(|pid| pid.parse::<u64>().ok()) {
+        Some(pid) => Pid::from(pid),
+        None => return USAGE.to_string(),
+    };
+    let signal = match signal {
+        Some(signal) => match signal.parse::<u64>().ok().and_then(Signal::new) {
+            Some(signal) => signal,
+            None => return USAGE.to_string(),
+        },
+        None => Signal::TERM,
+    };
+
+    match process::signal::send(pid, signal) {
+        Ok(()) => format!("Sent signal {} to process {}", signal.number(), pid),
+        Err(_) => format!("No process with the pid {}", pid),
+    }
+}
+
+fn ls_cmd(path: &str) -> String {
+    let dir = match fs::vfs().open(CWD, path, OpenFlags::READ | OpenFlags::DIRECTORY, 0) {
+        Ok(dir) => dir,
+        Err(err) => return format!("{}: {}", path, err),
+    };
+
+    let mut names = Vec::new();
+    loop {
+        match dir.read_dir() {
+            Ok(Some(entry)) => {
+                let suffix = match entry.file_type {
+                    FileType::Directory => "/",
+                    FileType::Symlink => "@",
+                    _ => "",
+                };
+                names.push(format!("{}{}", entry.name, suffix));
+            }
+            Ok(None) => break,
+            Err(err) => return format!("{}: {}", path, err),
+        }
+    }
+    names.sort();
+    names.join("\n")
+}
+
+fn cat_cmd(path: Option<&str>) -> String {
+    let path = match path {
+        Some(path) => path,
+        None => return "Usage: cat <path>".to_string(),
+    };
+    let file = match fs::vfs().open(CWD, path, OpenFlags::READ, 0) {
+        Ok(file) => file,
+        Err(err) => return format!("{}: {}", path, err),
+    };
+
+    let mut contents = Vec::new();
+    let mut buffer = [0; 512];
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => contents.extend_from_slice(&buffer[..read]),
+            Err(err) => return format!("{}: {}", path, err),
+        }
+    }
+    String::from_utf8_lossy(&contents).into_owned()
+}
+
+fn mkdir_cmd(path: Option<&str>) -> String {
+    let path = match path {
+        Some(path) => path,
+        None => return "Usage: mkdir <path>".to_string(),
+    };
+    match fs::vfs().create_dir(CWD, path, 0o755) {
+        Ok(()) => String::new(),
+        Err(err) => format!("{}: {}", path, err),
+    }
+}
+
+fn rm_cmd(path: Option<&str>) -> String {
+    let path = match path {
+        Some(path) => path,
+        None => return "Usage: rm <path>".to_string(),
+    };
+    let result = match fs::vfs().metadata(CWD, path, false) {
+        Ok(metadata) if metadata.is_dir() => fs::vfs().remove_dir(CWD, path),
+        Ok(_) => fs::vfs().remove_file(CWD, path),
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(()) => String::new(),
+        Err(err) => format!("{}: {}", path, err),
+    }
+}
+
+fn mv_cmd(old: Option<&str>, new: Option<&str>) -> String {
+    let (old, new) = match (old, new) {
+        (Some(old), Some(new)) => (old, new),
+        _ => return "Usage: mv <old> <new>".to_string(),
+    };
+    match fs::vfs().rename(CWD, old, new) {
+        Ok(()) => String::new(),
+        Err(err) => format!("{}: {}", old, err),
+    }
+}
+
+fn mount_cmd<'a>(mut args: impl Iterator<Item = &'a str>) -> String {
+    const USAGE: &str =
+        "Usage: mount tmpfs <path> [options]\n       mount vfat|ext2 <path> <image>";
+
+    let (kind, path) = match (args.next(), args.next()) {
+        (Some(kind), Some(path)) => (kind, path),
+        _ => return USAGE.to_string(),
+    };
+    let fs: Arc<dyn FileSystem> = match (kind, args.next()) {
+        ("tmpfs", options) => match TmpFs::with_options(options.unwrap_or("")) {
+            Ok(tmpfs) => Arc::new(tmpfs),
+            Err(err) => return format!("tmpfs: {}", err),
+        },
+        (kind @ ("vfat" | "ext2"), Some(image)) => match image_fs(kind, image) {
+            Ok(fs) => fs,
+            Err(err) => return format!("{}: {}", image, err),
+        },
+        _ => return USAGE.to_string(),
+    };
+    match fs::vfs().mount(path, fs) {
+        Ok(()) => String::new(),
+        Err(err) => format!("{}: {}", path, err),
+    }
+}
+
+/// The file system of type `kind` in the image file `image`.
+fn image_fs(kind: &str, image: &str) -> Result<Arc<dyn FileSystem>, String> {
+    let device = Arc::new(ImageDevice::open(CWD, image).map_err(|err| err.to_string())?);
+    match kind {
+        "vfat" => Ok(FatFs::new(device).map_err(|err| err.to_string())?),
+        _ => Ok(Ext2Fs::new(device).map_err(|err| err.to_string())?),
+    }
+}
+
+fn mounts_cmd() -> String {
+    let mut out = format!("{:<6}  {}", "TYPE", "PATH");
+    for (path, name) in fs::vfs().mounts() {
+        let _ = write!(out, "\n{:<6}  {}", name, path);
+    }
+    out
+}
@@ -8,11 +8,11 @@ use alloc::{format, string::String};
 use core::fmt::Write;
 use futures_util::StreamExt;
 use kernel::{
-    task::keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1, ScancodeStream},
+    sync::Mutex,
+    task::keyboard::{DecodedKey, KeyStream},
     tty::{Color, TTY},
 };
 use lazy_static::lazy_static;
-use spin::Mutex;
 
 lazy_static! {
     static ref SHELL: Mutex<Shell> = Mutex::new(Shell::new());
@@ -35,18 +35,12 @@ impl Shell {
     }
 
     async fn handle_keypresses(&mut self) {
-        let mut scancodes = ScancodeStream::new();
-        let mut keyboard =
-            Keyboard::new(ScancodeSet1::new(), layouts::Azerty, HandleControl::Ignore);
+        let mut keys = KeyStream::new();
 
-        while let Some(scancode) = scancodes.next().await {
-            if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-                if let Some(key) = keyboard.process_keyevent(key_event) {
-                    match key {
-                        DecodedKey::Unicode(character) => self.handle_keypress(character),
-                        DecodedKey::RawKey(key) => {}
-                    }
-                }
+        while let Some(key) = keys.next().await {
+            match key {
+                DecodedKey::Unicode(character) => self.handle_keypress(character),
+                DecodedKey::RawKey(key) => {}
             }
         }
     }
@@ -58,6 +52,8 @@ impl Shell {
                 self.cmd.pop();
                 self.render();
             }
+            // The other control characters, like Ctrl-C without a foreground process
+            _ if char.is_control() => {}
             _ => {
                 self.cmd.push(char);
                 self.render();
@@ -80,5 +76,5 @@ impl Shell {
 }
 
 pub async fn init() {
-    SHELL.lock().init().await;
+    SHELL.lock().await.init().await;
 }
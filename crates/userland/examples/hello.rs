@@ -0,0 +1,32 @@
+//! A minimal program on the runtime, printing its arguments and `GREETING` and exiting with the
+//! number of arguments. Built from the root of the repository with
+//! `cargo build -p userland --example hello --target x86_64-kerwanos-user.json`, the binary lands in
+//! `target/x86_64-kerwanos-user/debug/examples/hello`. `programs/build.sh` embeds it in the kernel
+//! as `userland-hello`.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use userland::{entry, env, println};
+
+entry!(main);
+
+fn main() -> i32 {
+    let mut args = env::args();
+    let name = args.next().unwrap_or("?");
+    // Collected to exercise the heap
+    let args: Vec<&str> = args.collect();
+    println!("Hello from {} with {} arguments", name, args.len());
+    for arg in &args {
+        println!("  {}", arg);
+    }
+    match env::var("GREETING") {
+        Some(greeting) => println!("GREETING is {}", greeting),
+        None => println!("GREETING is unset"),
+    }
+    args.len() as i32
+}
@@ -0,0 +1,87 @@
+use core::{
+    ffi::CStr,
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+static ARGC: AtomicUsize = AtomicUsize::new(0);
+static ARGV: AtomicPtr<*const u8> = AtomicPtr::new(ptr::null_mut());
+static ENVP: AtomicPtr<*const u8> = AtomicPtr::new(ptr::null_mut());
+
+pub(crate) fn init(argc: usize, argv: *const *const u8, envp: *const *const u8) {
+    ARGC.store(argc, Ordering::Relaxed);
+    ARGV.store(argv.cast_mut(), Ordering::Relaxed);
+    ENVP.store(envp.cast_mut(), Ordering::Relaxed);
+}
+
+/// The arguments of the program, starting with its name.
+pub fn args() -> Args {
+    Args {
+        next: ARGV.load(Ordering::Relaxed),
+        remaining: ARGC.load(Ordering::Relaxed),
+    }
+}
+
+/// The environment of the program as `KEY=value` strings.
+pub fn vars() -> Vars {
+    Vars {
+        next: ENVP.load(Ordering::Relaxed),
+    }
+}
+
+pub struct Args {
+    next: *const *const u8,
+    remaining: usize,
+}
+
+impl Iterator for Args {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 || self.next.is_null() {
+            return None;
+        }
+
+        // The strings live on the initial stack which is never freed
+        let arg = unsafe { to_str(*self.next) };
+        self.next = unsafe { self.next.add(1) };
+        self.remaining -= 1;
+        Some(arg)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for Args {}
+
+pub struct Vars {
+    next: *const *const u8,
+}
+
+impl Iterator for Vars {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_null() || unsafe { (*self.next).is_null() } {
+            return None;
+        }
+
+        let var = unsafe { to_str(*self.next) };
+        self.next = unsafe { self.next.add(1) };
+        Some(var)
+    }
+}
+
+/// Looks up the environment variable `key`.
+pub fn var(key: &str) -> Option<&'static str> {
+    vars().find_map(|var| {
+        let (name, value) = var.split_once('=')?;
+        (name == key).then_some(value)
+    })
+}
+
+unsafe fn to_str(ptr: *const u8) -> &'static str {
+    CStr::from_ptr(ptr.cast()).to_str().unwrap_or("")
+}
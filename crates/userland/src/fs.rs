@@ -0,0 +1,223 @@
+//! Files and directories, see the `syscall::fs` module of the kernel for the layouts.
+use alloc::{string::String, vec, vec::Vec};
+
+use crate::syscall::{self, number, Result};
+
+/// Flags of [`open`], the `vfs::OpenFlags` of the kernel.
+pub mod flags {
+    pub const READ: u32 = 1;
+    pub const WRITE: u32 = 1 << 1;
+    pub const APPEND: u32 = 1 << 2;
+    pub const CREATE: u32 = 1 << 3;
+    pub const EXCLUSIVE: u32 = 1 << 4;
+    pub const TRUNCATE: u32 = 1 << 5;
+    pub const DIRECTORY: u32 = 1 << 6;
+    pub const NO_FOLLOW: u32 = 1 << 7;
+}
+
+/// Origin of a [`seek`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
+/// Type bits of [`Stat::mode`].
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+
+/// The fields of `struct stat` the kernel fills.
+#[derive(Debug, Clone, Copy)]
+pub struct Stat {
+    pub inode: u64,
+    pub links: u64,
+    /// Type and permission bits.
+    pub mode: u32,
+    pub size: u64,
+    /// Seconds since the Unix epoch.
+    pub modified: u64,
+}
+
+impl Stat {
+    fn parse(bytes: &[u8; STAT_SIZE]) -> Self {
+        let u64_at = |offset: usize| u64::from_le_bytes(bytes[offset..][..8].try_into().unwrap());
+        Self {
+            inode: u64_at(8),
+            links: u64_at(16),
+            mode: u32::from_le_bytes(bytes[24..28].try_into().unwrap()),
+            size: u64_at(48),
+            modified: u64_at(88),
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+}
+
+const STAT_SIZE: usize = 144;
+
+/// An entry returned by [`read_dir`].
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub inode: u64,
+    /// `d_type` of Linux, 4 for directories and 8 for regular files.
+    pub file_type: u8,
+    pub name: String,
+}
+
+/// Opens `path` with [`flags`], `mode` being the permissions of a created file.
+pub fn open(path: &str, flags: u32, mode: u16) -> Result<u64> {
+    let args = [
+        path.as_ptr() as u64,
+        path.len() as u64,
+        u64::from(flags),
+        u64::from(mode),
+        0,
+        0,
+    ];
+    unsafe { syscall::syscall(number::OPEN, args) }
+}
+
+/// Moves the offset of `fd` and returns it.
+pub fn seek(fd: u64, position: SeekFrom) -> Result<u64> {
+    let (offset, whence) = match position {
+        SeekFrom::Start(offset) => (offset, 0),
+        SeekFrom::Current(delta) => (delta as u64, 1),
+        SeekFrom::End(delta) => (delta as u64, 2),
+    };
+    unsafe { syscall::syscall(number::SEEK, [fd, offset, whence, 0, 0, 0]) }
+}
+
+/// Metadata of `path`, a final symlink followed unless `follow` is false.
+pub fn stat(path: &str, follow: bool) -> Result<Stat> {
+    let mut bytes = [0u8; STAT_SIZE];
+    let args = [
+        path.as_ptr() as u64,
+        path.len() as u64,
+        bytes.as_mut_ptr() as u64,
+        u64::from(!follow),
+        0,
+        0,
+    ];
+    unsafe { syscall::syscall(number::STAT, args) }?;
+    Ok(Stat::parse(&bytes))
+}
+
+pub fn fstat(fd: u64) -> Result<Stat> {
+    let mut bytes = [0u8; STAT_SIZE];
+    unsafe { syscall::syscall(number::FSTAT, [fd, bytes.as_mut_ptr() as u64, 0, 0, 0, 0]) }?;
+    Ok(Stat::parse(&bytes))
+}
+
+/// Every remaining entry of the directory `fd`.
+pub fn read_dir(fd: u64) -> Result<Vec<DirEntry>> {
+    let mut entries = Vec::new();
+    let mut buffer = vec![0u8; 4096];
+    loop {
+        let args = [fd, buffer.as_mut_ptr() as u64, buffer.len() as u64, 0, 0, 0];
+        let len = unsafe { syscall::syscall(number::READ_DIR, args) }? as usize;
+        if len == 0 {
+            return Ok(entries);
+        }
+
+        let mut records = &buffer[..len];
+        while !records.is_empty() {
+            let record_len = u16::from_le_bytes([records[16], records[17]]) as usize;
+            let name = &records[19..record_len];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+            entries.push(DirEntry {
+                inode: u64::from_le_bytes(records[..8].try_into().unwrap()),
+                file_type: records[18],
+                name: String::from_utf8_lossy(name).into_owned(),
+            });
+            records = &records[record_len..];
+        }
+    }
+}
+
+pub fn mkdir(path: &str, mode: u16) -> Result<()> {
+    let args = [
+        path.as_ptr() as u64,
+        path.len() as u64,
+        u64::from(mode),
+        0,
+        0,
+        0,
+    ];
+    unsafe { syscall::syscall(number::MKDIR, args) }.map(|_| ())
+}
+
+/// Removes a file or a symlink.
+pub fn unlink(path: &str) -> Result<()> {
+    let args = [path.as_ptr() as u64, path.len() as u64, 0, 0, 0, 0];
+    unsafe { syscall::syscall(number::UNLINK, args) }.map(|_| ())
+}
+
+/// Removes an empty directory.
+pub fn rmdir(path: &str) -> Result<()> {
+    let args = [path.as_ptr() as u64, path.len() as u64, 1, 0, 0, 0];
+    unsafe { syscall::syscall(number::UNLINK, args) }.map(|_| ())
+}
+
+/// Creates a symlink at `path` pointing to `target`.
+pub fn symlink(target: &str, path: &str) -> Result<()> {
+    let args = [
+        target.as_ptr() as u64,
+        target.len() as u64,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        0,
+        0,
+    ];
+    unsafe { syscall::syscall(number::SYMLINK, args) }.map(|_| ())
+}
+
+/// Moves `old` to `new`, replacing what is there.
+pub fn rename(old: &str, new: &str) -> Result<()> {
+    let args = [
+        old.as_ptr() as u64,
+        old.len() as u64,
+        new.as_ptr() as u64,
+        new.len() as u64,
+        0,
+        0,
+    ];
+    unsafe { syscall::syscall(number::RENAME, args) }.map(|_| ())
+}
+
+/// Sets the size of the file `fd`, open for writing.
+pub fn truncate(fd: u64, size: u64) -> Result<()> {
+    unsafe { syscall::syscall(number::TRUNCATE, [fd, size, 0, 0, 0, 0]) }.map(|_| ())
+}
+
+pub fn read_link(path: &str) -> Result<String> {
+    let mut buffer = vec![0u8; 4096];
+    let args = [
+        path.as_ptr() as u64,
+        path.len() as u64,
+        buffer.as_mut_ptr() as u64,
+        buffer.len() as u64,
+        0,
+        0,
+    ];
+    let len = unsafe { syscall::syscall(number::READ_LINK, args) }? as usize;
+    buffer.truncate(len);
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+pub fn chdir(path: &str) -> Result<()> {
+    let args = [path.as_ptr() as u64, path.len() as u64, 0, 0, 0, 0];
+    unsafe { syscall::syscall(number::CHDIR, args) }.map(|_| ())
+}
+
+pub fn getcwd() -> Result<String> {
+    let mut buffer = vec![0u8; 4096];
+    let args = [buffer.as_mut_ptr() as u64, buffer.len() as u64, 0, 0, 0, 0];
+    let len = unsafe { syscall::syscall(number::GETCWD, args) }? as usize;
+    buffer.truncate(len);
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
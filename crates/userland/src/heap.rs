@@ -0,0 +1,80 @@
+//! Global allocator on memory mapped from the kernel.
+//!
+//! The mappings aren't contiguous, so each one is a separate arena.
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr::{self, NonNull},
+};
+
+use linked_list_allocator::Heap;
+use spin::Mutex;
+
+use crate::memory::{self, prot, PAGE_SIZE};
+
+const ARENA_SIZE: usize = 64 * 1024;
+const MAX_ARENAS: usize = 64;
+
+#[global_allocator]
+static ALLOCATOR: Allocator = Allocator {
+    arenas: Mutex::new(Arenas {
+        heaps: [const { Heap::empty() }; MAX_ARENAS],
+        len: 0,
+    }),
+};
+
+struct Allocator {
+    arenas: Mutex<Arenas>,
+}
+
+struct Arenas {
+    heaps: [Heap; MAX_ARENAS],
+    len: usize,
+}
+
+impl Arenas {
+    fn grow(&mut self, layout: Layout) -> Option<&mut Heap> {
+        if self.len == MAX_ARENAS {
+            return None;
+        }
+
+        let size = (layout.size() + layout.align())
+            .max(ARENA_SIZE)
+            .checked_next_multiple_of(PAGE_SIZE)?;
+        let bottom = memory::mmap(size, prot::READ | prot::WRITE).ok()?;
+
+        let heap = &mut self.heaps[self.len];
+        unsafe { heap.init(bottom as usize, size) };
+        self.len += 1;
+        Some(heap)
+    }
+}
+
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut arenas = self.arenas.lock();
+        let len = arenas.len;
+        let found = arenas.heaps[..len]
+            .iter_mut()
+            .find_map(|heap| heap.allocate_first_fit(layout).ok());
+
+        match found {
+            Some(ptr) => ptr.as_ptr(),
+            None => arenas
+                .grow(layout)
+                .and_then(|heap| heap.allocate_first_fit(layout).ok())
+                .map_or(ptr::null_mut(), NonNull::as_ptr),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut arenas = self.arenas.lock();
+        let len = arenas.len;
+        let addr = ptr as usize;
+        if let Some(heap) = arenas.heaps[..len]
+            .iter_mut()
+            .find(|heap| (heap.bottom()..heap.top()).contains(&addr))
+        {
+            heap.deallocate(NonNull::new_unchecked(ptr), layout);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use core::fmt::{self, Write};
+
+use crate::syscall::{self, number, Result};
+
+pub const STDIN: u64 = 0;
+pub const STDOUT: u64 = 1;
+pub const STDERR: u64 = 2;
+
+/// Reads into `buffer` from the descriptor `fd`, returns how many bytes were read, 0 at the end.
+pub fn read(fd: u64, buffer: &mut [u8]) -> Result<usize> {
+    let args = [fd, buffer.as_mut_ptr() as u64, buffer.len() as u64, 0, 0, 0];
+    unsafe { syscall::syscall(number::READ, args) }.map(|read| read as usize)
+}
+
+/// Writes `bytes` to the descriptor `fd`, returns how many were written.
+pub fn write(fd: u64, bytes: &[u8]) -> Result<usize> {
+    let args = [fd, bytes.as_ptr() as u64, bytes.len() as u64, 0, 0, 0];
+    unsafe { syscall::syscall(number::WRITE, args) }.map(|written| written as usize)
+}
+
+/// Writes all of `bytes` to `fd`.
+pub fn write_all(fd: u64, mut bytes: &[u8]) -> Result<()> {
+    while !bytes.is_empty() {
+        let written = write(fd, bytes)?;
+        bytes = &bytes[written.min(bytes.len())..];
+    }
+    Ok(())
+}
+
+pub fn close(fd: u64) -> Result<()> {
+    unsafe { syscall::syscall(number::CLOSE, [fd, 0, 0, 0, 0, 0]) }.map(|_| ())
+}
+
+/// Makes reading and writing `fd` fail with `Error::WouldBlock` instead of waiting.
+pub fn set_nonblocking(fd: u64, nonblocking: bool) -> Result<()> {
+    let args = [fd, u64::from(nonblocking), 0, 0, 0, 0];
+    unsafe { syscall::syscall(number::SET_NONBLOCKING, args) }.map(|_| ())
+}
+
+struct Descriptor(u64);
+
+impl Write for Descriptor {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_all(self.0, s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments<'_>) {
+    let _ = Descriptor(STDOUT).write_fmt(args);
+}
+
+#[doc(hidden)]
+pub fn _eprint(args: fmt::Arguments<'_>) {
+    let _ = Descriptor(STDERR).write_fmt(args);
+}
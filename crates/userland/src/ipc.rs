@@ -0,0 +1,51 @@
+//! Pipes and message channels between processes, both used through descriptors.
+use crate::syscall::{self, number, Result};
+
+/// Largest payload of a channel message.
+pub const MAX_MESSAGE_SIZE: usize = 1024;
+/// Most descriptors a channel message can carry.
+pub const MAX_MESSAGE_HANDLES: usize = 8;
+
+/// Creates a pipe, returns the descriptors of its read and write ends.
+pub fn pipe() -> Result<(u64, u64)> {
+    let mut fds = [0u64; 2];
+    unsafe { syscall::syscall(number::PIPE, [fds.as_mut_ptr() as u64, 0, 0, 0, 0, 0]) }?;
+    Ok((fds[0], fds[1]))
+}
+
+/// Creates a channel, returns the descriptors of its two endpoints.
+pub fn channel() -> Result<(u64, u64)> {
+    let mut fds = [0u64; 2];
+    unsafe { syscall::syscall(number::CHANNEL, [fds.as_mut_ptr() as u64, 0, 0, 0, 0, 0]) }?;
+    Ok((fds[0], fds[1]))
+}
+
+/// Sends `data` on the channel `fd`, the descriptors of `handles` move to the receiver and are
+/// closed here.
+pub fn send(fd: u64, data: &[u8], handles: &[u64]) -> Result<()> {
+    let args = [
+        fd,
+        data.as_ptr() as u64,
+        data.len() as u64,
+        handles.as_ptr() as u64,
+        handles.len() as u64,
+        0,
+    ];
+    unsafe { syscall::syscall(number::SEND, args) }.map(|_| ())
+}
+
+/// Receives the next message of the channel `fd`, returns the length of its data and the number
+/// of descriptors stored in `handles`.
+pub fn receive(fd: u64, buffer: &mut [u8], handles: &mut [u64]) -> Result<(usize, usize)> {
+    let mut count = 0u64;
+    let args = [
+        fd,
+        buffer.as_mut_ptr() as u64,
+        buffer.len() as u64,
+        handles.as_mut_ptr() as u64,
+        handles.len() as u64,
+        &mut count as *mut u64 as u64,
+    ];
+    let len = unsafe { syscall::syscall(number::RECEIVE, args) }?;
+    Ok((len as usize, count as usize))
+}
@@ -0,0 +1,48 @@
+//! Runtime for programs running in ring 3 on KerwanOS.
+//!
+//! Programs are `#![no_std]` and `#![no_main]` binaries built for `x86_64-kerwanos-user.json`,
+//! they name their entry point with [`entry!`]:
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! use userland::{entry, env, println};
+//!
+//! entry!(main);
+//!
+//! fn main() -> i32 {
+//!     println!("Hello from {}", env::args().next().unwrap_or("?"));
+//!     0
+//! }
+//! ```
+//!
+//! Build them with `cargo build --target x86_64-kerwanos-user.json -Zbuild-std=core,alloc
+//! -Zbuild-std-features=compiler-builtins-mem` and add them to the program table of the kernel
+//! loader, they are linked as static executables at `0x4000_0000_0000`. In this repository, where
+//! `.cargo/config.toml` already sets `build-std`, the example in `examples/hello.rs` builds with
+//! `cargo build -p userland --example hello --target x86_64-kerwanos-user.json`.
+#![no_std]
+
+extern crate alloc;
+
+pub mod env;
+pub mod fs;
+pub mod heap;
+pub mod io;
+pub mod ipc;
+pub mod macros;
+pub mod memory;
+pub mod process;
+pub mod signal;
+mod start;
+pub mod syscall;
+pub mod thread;
+
+pub use process::exit;
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    eprintln!("{}", info);
+    exit(101)
+}
@@ -0,0 +1,33 @@
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::io::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => ($crate::io::_eprint(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    () => ($crate::eprint!("\n"));
+    ($($arg:tt)*) => ($crate::eprint!("{}\n", format_args!($($arg)*)));
+}
+
+/// Declares the entry point of the program, a `fn() -> i32` returning the exit code.
+#[macro_export]
+macro_rules! entry {
+    ($path:path) => {
+        #[export_name = "__userland_main"]
+        pub fn __userland_main() -> i32 {
+            let f: fn() -> i32 = $path;
+            f()
+        }
+    };
+}
@@ -0,0 +1,40 @@
+use crate::syscall::{self, number, Result};
+
+pub const PAGE_SIZE: usize = 4096;
+
+pub mod prot {
+    pub const READ: u64 = 1 << 0;
+    pub const WRITE: u64 = 1 << 1;
+    pub const EXEC: u64 = 1 << 2;
+}
+
+/// Maps `len` bytes of zeroed memory, rounded up to whole pages.
+pub fn mmap(len: usize, prot: u64) -> Result<*mut u8> {
+    let args = [len as u64, prot, 0, 0, 0, 0];
+    unsafe { syscall::syscall(number::MMAP, args) }.map(|addr| addr as *mut u8)
+}
+
+/// Unmaps the pages from `addr` to `addr + len` that `mmap` mapped.
+pub fn munmap(addr: *mut u8, len: usize) -> Result<()> {
+    let args = [addr as u64, len as u64, 0, 0, 0, 0];
+    unsafe { syscall::syscall(number::MUNMAP, args) }.map(|_| ())
+}
+
+/// Changes the protection of pages mapped by `mmap`, none of the bits makes them inaccessible.
+pub fn mprotect(addr: *mut u8, len: usize, prot: u64) -> Result<()> {
+    let args = [addr as u64, len as u64, prot, 0, 0, 0];
+    unsafe { syscall::syscall(number::MPROTECT, args) }.map(|_| ())
+}
+
+/// Creates a zeroed shared memory object of `size` bytes rounded up to whole pages, returns its
+/// descriptor. It can be sent over a channel for another process to map it.
+pub fn shm_create(size: usize) -> Result<u64> {
+    unsafe { syscall::syscall(number::SHM_CREATE, [size as u64, 0, 0, 0, 0, 0]) }
+}
+
+/// Maps `len` bytes of the shared memory object `fd` from `offset`, a multiple of `PAGE_SIZE`.
+/// The mapping stays valid once `fd` is closed.
+pub fn shm_map(fd: u64, offset: usize, len: usize, prot: u64) -> Result<*mut u8> {
+    let args = [fd, offset as u64, len as u64, prot, 0, 0];
+    unsafe { syscall::syscall(number::SHM_MAP, args) }.map(|addr| addr as *mut u8)
+}
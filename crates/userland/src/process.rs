@@ -0,0 +1,66 @@
+use alloc::vec::Vec;
+
+use crate::syscall::{self, number, Error, Result};
+
+/// Ends the program with `code`.
+pub fn exit(code: i32) -> ! {
+    let _ = unsafe { syscall::syscall(number::EXIT, [code as u64, 0, 0, 0, 0, 0]) };
+    unreachable!("the process survived exit")
+}
+
+pub fn getpid() -> u64 {
+    unsafe { syscall::syscall(number::GETPID, [0; 6]) }.unwrap_or(0)
+}
+
+/// Starts the embedded program `name` as a child, `args` should start with its name.
+pub fn spawn(name: &str, args: &[&str]) -> Result<u64> {
+    let argv = argv(args);
+    let call = [
+        name.as_ptr() as u64,
+        name.len() as u64,
+        argv.as_ptr() as u64,
+        args.len() as u64,
+        0,
+        0,
+    ];
+    unsafe { syscall::syscall(number::SPAWN, call) }
+}
+
+/// Replaces the running program with the embedded program `name`, only returns on failure.
+pub fn exec(name: &str, args: &[&str]) -> Error {
+    let argv = argv(args);
+    let call = [
+        name.as_ptr() as u64,
+        name.len() as u64,
+        argv.as_ptr() as u64,
+        args.len() as u64,
+        0,
+        0,
+    ];
+    match unsafe { syscall::syscall(number::EXEC, call) } {
+        Ok(_) => unreachable!("exec returned without an error"),
+        Err(err) => err,
+    }
+}
+
+/// Waits for the child `pid` to exit, or any child, and returns its pid and exit code.
+pub fn wait(pid: Option<u64>) -> Result<(u64, i32)> {
+    let mut code = 0i32;
+    let call = [
+        pid.unwrap_or(u64::MAX),
+        &mut code as *mut i32 as u64,
+        0,
+        0,
+        0,
+        0,
+    ];
+    let child = unsafe { syscall::syscall(number::WAIT, call) }?;
+    Ok((child, code))
+}
+
+/// The arguments as the address and length pairs the kernel expects.
+fn argv(args: &[&str]) -> Vec<[u64; 2]> {
+    args.iter()
+        .map(|arg| [arg.as_ptr() as u64, arg.len() as u64])
+        .collect()
+}
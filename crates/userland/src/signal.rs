@@ -0,0 +1,90 @@
+//! Signals, numbered as on Linux. A handler runs on the stack of the interrupted code and returns
+//! to it through the restorer of the runtime.
+use core::arch::global_asm;
+
+use crate::syscall::{self, number, Result};
+
+pub const HUP: u64 = 1;
+pub const INT: u64 = 2;
+pub const QUIT: u64 = 3;
+pub const ILL: u64 = 4;
+pub const ABRT: u64 = 6;
+pub const FPE: u64 = 8;
+pub const KILL: u64 = 9;
+pub const USR1: u64 = 10;
+pub const SEGV: u64 = 11;
+pub const USR2: u64 = 12;
+pub const PIPE: u64 = 13;
+pub const ALRM: u64 = 14;
+pub const TERM: u64 = 15;
+pub const CHLD: u64 = 17;
+
+/// `sigaction` values of `Handler::Default` and `Handler::Ignore`.
+const SIG_DFL: u64 = 0;
+const SIG_IGN: u64 = 1;
+
+mod how {
+    pub const BLOCK: u64 = 0;
+    pub const UNBLOCK: u64 = 1;
+    pub const SET: u64 = 2;
+}
+
+#[derive(Clone, Copy)]
+pub enum Handler {
+    Default,
+    Ignore,
+    /// Called with the signal number, the signal is blocked while it runs.
+    Function(extern "C" fn(u64)),
+}
+
+/// The bit of `signal` in a signal mask.
+pub const fn mask(signal: u64) -> u64 {
+    1 << (signal - 1)
+}
+
+extern "C" {
+    fn __userland_restorer();
+}
+
+// Handlers return here, with the stack pointer right above the frame the kernel pushed
+global_asm!(
+    ".global __userland_restorer",
+    "__userland_restorer:",
+    "mov eax, {sigreturn}",
+    "syscall",
+    "ud2",
+    sigreturn = const number::SIGRETURN,
+);
+
+/// Sets what the program does on `signal`, blocking the signals of `blocked` on top of it while a
+/// handler runs.
+pub fn set_handler(signal: u64, handler: Handler, blocked: u64) -> Result<()> {
+    let handler = match handler {
+        Handler::Default => SIG_DFL,
+        Handler::Ignore => SIG_IGN,
+        Handler::Function(function) => function as u64,
+    };
+    let restorer = __userland_restorer as u64;
+    let args = [signal, handler, blocked, restorer, 0, 0];
+    unsafe { syscall::syscall(number::SIGACTION, args) }.map(|_| ())
+}
+
+/// Adds `set` to the blocked signals, returns the previous mask.
+pub fn block(set: u64) -> Result<u64> {
+    unsafe { syscall::syscall(number::SIGPROCMASK, [how::BLOCK, set, 0, 0, 0, 0]) }
+}
+
+/// Removes `set` from the blocked signals, returns the previous mask.
+pub fn unblock(set: u64) -> Result<u64> {
+    unsafe { syscall::syscall(number::SIGPROCMASK, [how::UNBLOCK, set, 0, 0, 0, 0]) }
+}
+
+/// Replaces the blocked signals, returns the previous mask.
+pub fn set_mask(set: u64) -> Result<u64> {
+    unsafe { syscall::syscall(number::SIGPROCMASK, [how::SET, set, 0, 0, 0, 0]) }
+}
+
+/// Sends `signal` to the process `pid`.
+pub fn kill(pid: u64, signal: u64) -> Result<()> {
+    unsafe { syscall::syscall(number::KILL, [pid, signal, 0, 0, 0, 0]) }.map(|_| ())
+}
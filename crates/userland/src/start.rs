@@ -0,0 +1,28 @@
+use core::arch::global_asm;
+
+use crate::{env, process};
+
+// The kernel enters with `rsp` pointing at argc, followed by argv, envp and the auxiliary vector
+global_asm!(
+    ".global _start",
+    "_start:",
+    "xor rbp, rbp",
+    "mov rdi, rsp",
+    "and rsp, -16",
+    "call {start}",
+    "ud2",
+    start = sym start,
+);
+
+extern "Rust" {
+    fn __userland_main() -> i32;
+}
+
+unsafe extern "C" fn start(stack: *const u64) -> ! {
+    let argc = *stack as usize;
+    let argv = stack.add(1) as *const *const u8;
+    env::init(argc, argv, argv.add(argc + 1));
+
+    let code = __userland_main();
+    process::exit(code)
+}
@@ -0,0 +1,150 @@
+//! Raw system calls, see the `syscall` module of the kernel for the ABI.
+use core::{arch::asm, fmt};
+
+pub mod number {
+    pub const WRITE: u64 = 0;
+    pub const EXIT: u64 = 1;
+    pub const SLEEP: u64 = 2;
+    pub const YIELD: u64 = 3;
+    pub const MMAP: u64 = 4;
+    pub const SPAWN: u64 = 5;
+    pub const EXEC: u64 = 6;
+    pub const WAIT: u64 = 7;
+    pub const GETPID: u64 = 8;
+    pub const SIGACTION: u64 = 9;
+    pub const SIGPROCMASK: u64 = 10;
+    pub const KILL: u64 = 11;
+    pub const SIGRETURN: u64 = 12;
+    pub const READ: u64 = 13;
+    pub const CLOSE: u64 = 14;
+    pub const PIPE: u64 = 15;
+    pub const CHANNEL: u64 = 16;
+    pub const SEND: u64 = 17;
+    pub const RECEIVE: u64 = 18;
+    pub const SET_NONBLOCKING: u64 = 19;
+    pub const MUNMAP: u64 = 20;
+    pub const MPROTECT: u64 = 21;
+    pub const SHM_CREATE: u64 = 22;
+    pub const SHM_MAP: u64 = 23;
+    pub const OPEN: u64 = 24;
+    pub const SEEK: u64 = 25;
+    pub const STAT: u64 = 26;
+    pub const FSTAT: u64 = 27;
+    pub const READ_DIR: u64 = 28;
+    pub const MKDIR: u64 = 29;
+    pub const UNLINK: u64 = 30;
+    pub const SYMLINK: u64 = 31;
+    pub const READ_LINK: u64 = 32;
+    pub const CHDIR: u64 = 33;
+    pub const GETCWD: u64 = 34;
+    pub const RENAME: u64 = 35;
+    pub const TRUNCATE: u64 = 36;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    UnknownSyscall,
+    BadAddress,
+    InvalidArgument,
+    BadHandle,
+    OutOfMemory,
+    NoChild,
+    NotFound,
+    InvalidExecutable,
+    NotSupported,
+    NoProcess,
+    WouldBlock,
+    BrokenPipe,
+    Interrupted,
+    BufferTooSmall,
+    NotDirectory,
+    IsDirectory,
+    AlreadyExists,
+    NotEmpty,
+    NameTooLong,
+    TooManySymlinks,
+    ReadOnly,
+    NoSpace,
+    FileTooLarge,
+    Busy,
+    CrossDevice,
+    NotSeekable,
+    PermissionDenied,
+    Io,
+    Other(u64),
+}
+
+impl Error {
+    fn from_code(code: u64) -> Self {
+        match code {
+            1 => Error::UnknownSyscall,
+            2 => Error::BadAddress,
+            3 => Error::InvalidArgument,
+            4 => Error::BadHandle,
+            5 => Error::OutOfMemory,
+            6 => Error::NoChild,
+            7 => Error::NotFound,
+            8 => Error::InvalidExecutable,
+            9 => Error::NotSupported,
+            10 => Error::NoProcess,
+            11 => Error::WouldBlock,
+            12 => Error::BrokenPipe,
+            13 => Error::Interrupted,
+            14 => Error::BufferTooSmall,
+            15 => Error::NotDirectory,
+            16 => Error::IsDirectory,
+            17 => Error::AlreadyExists,
+            18 => Error::NotEmpty,
+            19 => Error::NameTooLong,
+            20 => Error::TooManySymlinks,
+            21 => Error::ReadOnly,
+            22 => Error::NoSpace,
+            23 => Error::FileTooLarge,
+            24 => Error::Busy,
+            25 => Error::CrossDevice,
+            26 => Error::NotSeekable,
+            27 => Error::PermissionDenied,
+            28 => Error::Io,
+            code => Error::Other(code),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Other(code) => write!(f, "error {}", code),
+            err => fmt::Debug::fmt(err, f),
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Performs the system call `number`, errors are returned by the kernel as negated codes.
+///
+/// # Safety
+/// The arguments must be valid for the call, the kernel checks user pointers but nothing it
+/// writes to is known by the compiler.
+pub unsafe fn syscall(number: u64, args: [u64; 6]) -> Result<u64> {
+    let ret: u64;
+    asm!(
+        "syscall",
+        inlateout("rax") number => ret,
+        in("rdi") args[0],
+        in("rsi") args[1],
+        in("rdx") args[2],
+        in("r10") args[3],
+        in("r8") args[4],
+        in("r9") args[5],
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack),
+    );
+
+    if (ret as i64) < 0 {
+        Err(Error::from_code(ret.wrapping_neg()))
+    } else {
+        Ok(ret)
+    }
+}
@@ -0,0 +1,14 @@
+use core::time::Duration;
+
+use crate::syscall::{self, number};
+
+/// Blocks the program for at least `duration`.
+pub fn sleep(duration: Duration) {
+    let nanos = duration.as_nanos().min(u64::MAX as u128) as u64;
+    let _ = unsafe { syscall::syscall(number::SLEEP, [nanos, 0, 0, 0, 0, 0]) };
+}
+
+/// Gives up the rest of the time slice.
+pub fn yield_now() {
+    let _ = unsafe { syscall::syscall(number::YIELD, [0; 6]) };
+}
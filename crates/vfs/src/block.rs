@@ -0,0 +1,83 @@
+use alloc::vec;
+
+use crate::{Result, VfsError};
+
+/// A disk, or anything addressed by fixed-size blocks, that file systems are built on.
+pub trait BlockDevice: Send + Sync {
+    /// Size of a block in bytes, a power of two.
+    fn block_size(&self) -> usize;
+
+    fn block_count(&self) -> u64;
+
+    /// Reads the blocks from `block` into `buffer`, a whole number of blocks.
+    fn read_blocks(&self, block: u64, buffer: &mut [u8]) -> Result<()>;
+
+    /// Writes `buffer`, a whole number of blocks, to the blocks from `block`.
+    fn write_blocks(&self, block: u64, buffer: &[u8]) -> Result<()>;
+
+    /// Writes back what the device caches.
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Reads at any byte `offset`, going through a block buffer for the partial blocks.
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<()> {
+        let size = self.block_size();
+        check_range(self, offset, buffer.len())?;
+        let mut block = vec![0; size];
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done as u64;
+            let start = (position % size as u64) as usize;
+            let index = position / size as u64;
+            let rest = &mut buffer[done..];
+            if start == 0 && rest.len() >= size {
+                let len = rest.len() - rest.len() % size;
+                self.read_blocks(index, &mut rest[..len])?;
+                done += len;
+            } else {
+                let len = rest.len().min(size - start);
+                self.read_blocks(index, &mut block)?;
+                rest[..len].copy_from_slice(&block[start..start + len]);
+                done += len;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes at any byte `offset`, reading the partial blocks first.
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> Result<()> {
+        let size = self.block_size();
+        check_range(self, offset, buffer.len())?;
+        let mut block = vec![0; size];
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done as u64;
+            let start = (position % size as u64) as usize;
+            let index = position / size as u64;
+            let rest = &buffer[done..];
+            if start == 0 && rest.len() >= size {
+                let len = rest.len() - rest.len() % size;
+                self.write_blocks(index, &rest[..len])?;
+                done += len;
+            } else {
+                let len = rest.len().min(size - start);
+                self.read_blocks(index, &mut block)?;
+                block[start..start + len].copy_from_slice(&rest[..len]);
+                self.write_blocks(index, &block)?;
+                done += len;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_range<D: BlockDevice + ?Sized>(device: &D, offset: u64, len: usize) -> Result<()> {
+    let end = offset
+        .checked_add(len as u64)
+        .ok_or(VfsError::InvalidArgument)?;
+    match end <= device.block_count() * device.block_size() as u64 {
+        true => Ok(()),
+        false => Err(VfsError::Io),
+    }
+}
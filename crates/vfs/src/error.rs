@@ -0,0 +1,93 @@
+use snafu::Snafu;
+
+pub type Result<T, E = VfsError> = core::result::Result<T, E>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Snafu)]
+pub enum VfsError {
+    #[snafu(display("No such file or directory"))]
+    NotFound,
+    #[snafu(display("Not a directory"))]
+    NotDirectory,
+    #[snafu(display("Is a directory"))]
+    IsDirectory,
+    #[snafu(display("File exists"))]
+    AlreadyExists,
+    #[snafu(display("Directory not empty"))]
+    NotEmpty,
+    #[snafu(display("Invalid argument"))]
+    InvalidArgument,
+    #[snafu(display("File name too long"))]
+    NameTooLong,
+    #[snafu(display("Too many levels of symbolic links"))]
+    TooManySymlinks,
+    #[snafu(display("Read-only file system"))]
+    ReadOnly,
+    #[snafu(display("No space left on device"))]
+    NoSpace,
+    #[snafu(display("File too large"))]
+    FileTooLarge,
+    #[snafu(display("Operation not supported"))]
+    NotSupported,
+    #[snafu(display("Device or resource busy"))]
+    Busy,
+    #[snafu(display("Invalid cross-device link"))]
+    CrossDevice,
+    #[snafu(display("Bad file descriptor"))]
+    BadDescriptor,
+    #[snafu(display("Illegal seek"))]
+    NotSeekable,
+    #[snafu(display("Permission denied"))]
+    PermissionDenied,
+    #[snafu(display("Input/output error"))]
+    Io,
+}
+
+impl VfsError {
+    /// The Linux `errno` value of the error, which every personality and tool maps from.
+    pub fn errno(self) -> i32 {
+        match self {
+            VfsError::NotFound => 2,
+            VfsError::Io => 5,
+            VfsError::BadDescriptor => 9,
+            VfsError::PermissionDenied => 13,
+            VfsError::Busy => 16,
+            VfsError::AlreadyExists => 17,
+            VfsError::CrossDevice => 18,
+            VfsError::NotDirectory => 20,
+            VfsError::IsDirectory => 21,
+            VfsError::InvalidArgument => 22,
+            VfsError::FileTooLarge => 27,
+            VfsError::NoSpace => 28,
+            VfsError::NotSeekable => 29,
+            VfsError::ReadOnly => 30,
+            VfsError::NameTooLong => 36,
+            VfsError::NotEmpty => 39,
+            VfsError::TooManySymlinks => 40,
+            VfsError::NotSupported => 95,
+        }
+    }
+
+    /// The error of a Linux `errno`, an I/O error when it has no match.
+    pub fn from_errno(errno: u32) -> Self {
+        match errno {
+            1 | 13 => VfsError::PermissionDenied,
+            2 => VfsError::NotFound,
+            9 => VfsError::BadDescriptor,
+            16 => VfsError::Busy,
+            17 => VfsError::AlreadyExists,
+            18 => VfsError::CrossDevice,
+            20 => VfsError::NotDirectory,
+            21 => VfsError::IsDirectory,
+            22 => VfsError::InvalidArgument,
+            27 => VfsError::FileTooLarge,
+            28 => VfsError::NoSpace,
+            29 => VfsError::NotSeekable,
+            30 => VfsError::ReadOnly,
+            36 => VfsError::NameTooLong,
+            39 => VfsError::NotEmpty,
+            40 => VfsError::TooManySymlinks,
+            95 => VfsError::NotSupported,
+            _ => VfsError::Io,
+        }
+    }
+}
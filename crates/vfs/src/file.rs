@@ -0,0 +1,163 @@
+use alloc::{string::String, sync::Arc};
+use bitflags::bitflags;
+use spin::Mutex;
+
+use crate::{DirEntry, FileType, Inode, Metadata, Result, VfsError};
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OpenFlags: u32 {
+        const READ = 1;
+        const WRITE = 1 << 1;
+        /// Writes go to the end of the file.
+        const APPEND = 1 << 2;
+        /// Creates a regular file if the path doesn't exist.
+        const CREATE = 1 << 3;
+        /// With `CREATE`, fails if the path exists.
+        const EXCLUSIVE = 1 << 4;
+        /// Empties a regular file opened for writing.
+        const TRUNCATE = 1 << 5;
+        /// Fails unless the path is a directory.
+        const DIRECTORY = 1 << 6;
+        /// Fails if the last component is a symlink.
+        const NO_FOLLOW = 1 << 7;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
+/// An open file, the state shared by the descriptors referring to it.
+pub trait File: Send + Sync {
+    fn read(&self, buffer: &mut [u8]) -> Result<usize>;
+
+    fn write(&self, buffer: &[u8]) -> Result<usize>;
+
+    /// Moves the offset and returns it.
+    fn seek(&self, position: SeekFrom) -> Result<u64>;
+
+    /// Next entry of a directory, `None` after the last one.
+    fn read_dir(&self) -> Result<Option<DirEntry>>;
+
+    fn metadata(&self) -> Result<Metadata>;
+
+    fn truncate(&self, size: u64) -> Result<()>;
+
+    fn inode(&self) -> &Arc<dyn Inode>;
+
+    /// Absolute path the file was opened with, symlinks resolved.
+    fn path(&self) -> &str;
+}
+
+/// An inode opened through the VFS, reading and writing at an offset. For directories the
+/// offset is the index of the next entry.
+///
+/// The offset isn't locked while the inode works, which can block: the descriptors sharing the
+/// file may read the same data if they read at the same time.
+pub struct OpenFile {
+    inode: Arc<dyn Inode>,
+    path: String,
+    flags: OpenFlags,
+    file_type: FileType,
+    offset: Mutex<u64>,
+}
+
+impl OpenFile {
+    pub fn new(inode: Arc<dyn Inode>, path: String, flags: OpenFlags) -> Result<Self> {
+        let file_type = inode.metadata()?.file_type;
+        Ok(Self {
+            inode,
+            path,
+            flags,
+            file_type,
+            offset: Mutex::new(0),
+        })
+    }
+
+    pub fn flags(&self) -> OpenFlags {
+        self.flags
+    }
+
+    fn is_seekable(&self) -> bool {
+        !matches!(self.file_type, FileType::Fifo | FileType::Socket)
+    }
+}
+
+impl File for OpenFile {
+    fn read(&self, buffer: &mut [u8]) -> Result<usize> {
+        if !self.flags.contains(OpenFlags::READ) {
+            return Err(VfsError::BadDescriptor);
+        }
+        if self.file_type == FileType::Directory {
+            return Err(VfsError::IsDirectory);
+        }
+
+        let offset = *self.offset.lock();
+        let read = self.inode.read_at(offset, buffer)?;
+        *self.offset.lock() = offset + read as u64;
+        Ok(read)
+    }
+
+    fn write(&self, buffer: &[u8]) -> Result<usize> {
+        if !self.flags.contains(OpenFlags::WRITE) {
+            return Err(VfsError::BadDescriptor);
+        }
+
+        let offset = match self.flags.contains(OpenFlags::APPEND) {
+            true => self.inode.metadata()?.size,
+            false => *self.offset.lock(),
+        };
+        let written = self.inode.write_at(offset, buffer)?;
+        *self.offset.lock() = offset + written as u64;
+        Ok(written)
+    }
+
+    fn seek(&self, position: SeekFrom) -> Result<u64> {
+        if !self.is_seekable() {
+            return Err(VfsError::NotSeekable);
+        }
+
+        let mut offset = self.offset.lock();
+        let new = match position {
+            SeekFrom::Start(new) => Some(new),
+            SeekFrom::Current(delta) => offset.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.inode.metadata()?.size.checked_add_signed(delta),
+        };
+        *offset = new
+            .filter(|&new| new <= i64::MAX as u64)
+            .ok_or(VfsError::InvalidArgument)?;
+        Ok(*offset)
+    }
+
+    fn read_dir(&self) -> Result<Option<DirEntry>> {
+        let offset = *self.offset.lock();
+        let entry = self.inode.read_dir(offset as usize)?;
+        if entry.is_some() {
+            *self.offset.lock() = offset + 1;
+        }
+        Ok(entry)
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        self.inode.metadata()
+    }
+
+    fn truncate(&self, size: u64) -> Result<()> {
+        if !self.flags.contains(OpenFlags::WRITE) {
+            return Err(VfsError::BadDescriptor);
+        }
+        self.inode.truncate(size)
+    }
+
+    fn inode(&self) -> &Arc<dyn Inode> {
+        &self.inode
+    }
+
+    fn path(&self) -> &str {
+        &self.path
+    }
+}
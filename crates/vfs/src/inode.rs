@@ -0,0 +1,149 @@
+use core::{any::Any, time::Duration};
+
+use alloc::{string::String, sync::Arc};
+
+use crate::{Result, VfsError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Regular,
+    Directory,
+    Symlink,
+    CharDevice,
+    BlockDevice,
+    Fifo,
+    Socket,
+}
+
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    /// Number of the inode, unique in its file system.
+    pub inode: u64,
+    pub file_type: FileType,
+    /// Permission bits.
+    pub mode: u16,
+    pub links: u32,
+    pub size: u64,
+    pub uid: u32,
+    pub gid: u32,
+    /// Device number of the device nodes.
+    pub device: u64,
+    /// Since the Unix epoch, or the boot for the file systems without a clock.
+    pub accessed: Duration,
+    pub modified: Duration,
+    pub changed: Duration,
+}
+
+impl Metadata {
+    /// Metadata with the times and the ownership zeroed.
+    pub fn new(inode: u64, file_type: FileType, mode: u16, size: u64) -> Self {
+        Self {
+            inode,
+            file_type,
+            mode,
+            links: 1,
+            size,
+            uid: 0,
+            gid: 0,
+            device: 0,
+            accessed: Duration::ZERO,
+            modified: Duration::ZERO,
+            changed: Duration::ZERO,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+}
+
+/// An entry of a directory listing.
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub inode: u64,
+    pub file_type: FileType,
+}
+
+/// A file, directory, symlink or device of a file system.
+///
+/// The operations a kind of inode doesn't have keep their default, which fails. The directory
+/// operations get single names, never `.` or `..`: the path resolution handles them. The inodes
+/// are `Any` so that a file system can find its own type behind the directories it is given.
+pub trait Inode: Any + Send + Sync {
+    fn metadata(&self) -> Result<Metadata>;
+
+    /// Reads from `offset`, returns 0 past the end of the file.
+    fn read_at(&self, _offset: u64, _buffer: &mut [u8]) -> Result<usize> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Writes at `offset`, growing the file as needed.
+    fn write_at(&self, _offset: u64, _buffer: &[u8]) -> Result<usize> {
+        Err(VfsError::NotSupported)
+    }
+
+    fn truncate(&self, _size: u64) -> Result<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Child of a directory named `name`.
+    fn lookup(&self, _name: &str) -> Result<Arc<dyn Inode>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    /// Entry `index` of a directory, `None` past the last one. The file systems list `.` and
+    /// `..` only if they store them.
+    fn read_dir(&self, _index: usize) -> Result<Option<DirEntry>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    /// Creates a regular file or a directory in a directory.
+    fn create(&self, _name: &str, _file_type: FileType, _mode: u16) -> Result<Arc<dyn Inode>> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Creates a symlink to `target` in a directory.
+    fn symlink(&self, _name: &str, _target: &str) -> Result<Arc<dyn Inode>> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Removes a child which isn't a directory.
+    fn unlink(&self, _name: &str) -> Result<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Removes an empty child directory.
+    fn rmdir(&self, _name: &str) -> Result<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Moves the child `name` of a directory to `new_name` in `new_dir`, a directory of the same
+    /// file system, replacing what is there. A directory only replaces an empty directory.
+    fn rename(&self, _name: &str, _new_dir: &Arc<dyn Inode>, _new_name: &str) -> Result<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Target of a symlink.
+    fn read_link(&self) -> Result<String> {
+        Err(VfsError::InvalidArgument)
+    }
+
+    /// Whether the inode is a terminal device.
+    fn is_terminal(&self) -> bool {
+        false
+    }
+}
+
+/// A mounted file system.
+pub trait FileSystem: Send + Sync {
+    /// Type of the file system, like `tmpfs`.
+    fn name(&self) -> &'static str;
+
+    fn root(&self) -> Result<Arc<dyn Inode>>;
+
+    /// Writes back what is cached.
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+}
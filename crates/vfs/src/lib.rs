@@ -0,0 +1,354 @@
+//! Virtual file system: the traits file systems implement, a mount table joining them in a single
+//! tree, and the path resolution and open files on top of them.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod block;
+pub mod error;
+pub mod file;
+pub mod inode;
+pub mod path;
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use spin::RwLock;
+
+pub use block::BlockDevice;
+pub use error::{Result, VfsError};
+pub use file::{File, OpenFile, OpenFlags, SeekFrom};
+pub use inode::{DirEntry, FileSystem, FileType, Inode, Metadata};
+
+struct Mount {
+    fs: Arc<dyn FileSystem>,
+    root: Arc<dyn Inode>,
+}
+
+/// An inode reached by a path, with its absolute path free of `.`, `..` and symlinks.
+#[derive(Clone)]
+pub struct Resolved {
+    pub inode: Arc<dyn Inode>,
+    pub path: String,
+}
+
+/// The mount table and the operations on paths.
+///
+/// Relative paths start at `cwd`, an absolute path. The table isn't locked while the file
+/// systems work, so they can block.
+pub struct Vfs {
+    /// Indexed by the absolute path of the mount point.
+    mounts: RwLock<BTreeMap<String, Mount>>,
+}
+
+impl Vfs {
+    pub const fn new() -> Self {
+        Self {
+            mounts: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Mounts `fs` on the directory at `path`, or as the root with `/`.
+    pub fn mount(&self, path: &str, fs: Arc<dyn FileSystem>) -> Result<()> {
+        let path = match path {
+            "/" => String::from("/"),
+            path => {
+                let dir = self.resolve("/", path, true)?;
+                if !dir.inode.metadata()?.is_dir() {
+                    return Err(VfsError::NotDirectory);
+                }
+                dir.path
+            }
+        };
+
+        let root = fs.root()?;
+        let mut mounts = self.mounts.write();
+        if mounts.contains_key(&path) {
+            return Err(VfsError::Busy);
+        }
+        mounts.insert(path, Mount { fs, root });
+        Ok(())
+    }
+
+    /// Unmounts the file system mounted at `path`, which must not have others mounted under it.
+    pub fn unmount(&self, path: &str) -> Result<Arc<dyn FileSystem>> {
+        let path = self.resolve("/", path, true)?.path;
+        let mut mounts = self.mounts.write();
+        if !mounts.contains_key(&path) {
+            return Err(VfsError::InvalidArgument);
+        }
+        let nested = mounts
+            .keys()
+            .any(|other| *other != path && path::is_within(other, &path));
+        if nested {
+            return Err(VfsError::Busy);
+        }
+
+        let mount = mounts.remove(&path).unwrap();
+        mount.fs.sync()?;
+        Ok(mount.fs)
+    }
+
+    /// Mount points and the types of their file systems.
+    pub fn mounts(&self) -> Vec<(String, &'static str)> {
+        self.mounts
+            .read()
+            .iter()
+            .map(|(path, mount)| (path.clone(), mount.fs.name()))
+            .collect()
+    }
+
+    /// Writes back the caches of every file system.
+    pub fn sync(&self) -> Result<()> {
+        let filesystems: Vec<_> = self.mounts.read().values().map(|m| m.fs.clone()).collect();
+        filesystems.iter().try_for_each(|fs| fs.sync())
+    }
+
+    /// Finds the inode at `path`. A symlink as the last component is followed only with
+    /// `follow`, a trailing slash always follows it.
+    pub fn resolve(&self, cwd: &str, path: &str, follow: bool) -> Result<Resolved> {
+        if path.is_empty() {
+            return Err(VfsError::NotFound);
+        }
+        if path.len() > path::PATH_MAX {
+            return Err(VfsError::NameTooLong);
+        }
+
+        let mut stack = match path.starts_with('/') {
+            true => vec![self.root()?],
+            false => self.walk(vec![self.root()?], cwd, true)?,
+        };
+        let follow = follow || path.ends_with('/');
+        stack = self.walk(stack, path, follow)?;
+
+        let resolved = stack.pop().unwrap();
+        if path.ends_with('/') && !resolved.inode.metadata()?.is_dir() {
+            return Err(VfsError::NotDirectory);
+        }
+        Ok(resolved)
+    }
+
+    /// Directory holding the last component of `path`, and that component. It can't be `.` or
+    /// `..`, and the root has none.
+    pub fn resolve_parent<'a>(&self, cwd: &str, path: &'a str) -> Result<(Resolved, &'a str)> {
+        let (dir, name) = path::split_last(path).ok_or(VfsError::Busy)?;
+        if name == "." || name == ".." {
+            return Err(VfsError::InvalidArgument);
+        }
+        if name.len() > path::NAME_MAX {
+            return Err(VfsError::NameTooLong);
+        }
+
+        let dir = self.resolve(cwd, dir, true)?;
+        if !dir.inode.metadata()?.is_dir() {
+            return Err(VfsError::NotDirectory);
+        }
+        Ok((dir, name))
+    }
+
+    pub fn open(
+        &self,
+        cwd: &str,
+        path: &str,
+        flags: OpenFlags,
+        mode: u16,
+    ) -> Result<Arc<OpenFile>> {
+        let follow = !flags.contains(OpenFlags::NO_FOLLOW);
+        let resolved = match self.resolve(cwd, path, follow) {
+            Ok(_) if flags.contains(OpenFlags::CREATE | OpenFlags::EXCLUSIVE) => {
+                return Err(VfsError::AlreadyExists)
+            }
+            Err(VfsError::NotFound) if flags.contains(OpenFlags::CREATE) => {
+                let (dir, name) = self.resolve_parent(cwd, path)?;
+                let inode = dir.inode.create(name, FileType::Regular, mode)?;
+                Resolved {
+                    inode,
+                    path: path::join(&dir.path, name),
+                }
+            }
+            result => result?,
+        };
+
+        let metadata = resolved.inode.metadata()?;
+        match metadata.file_type {
+            FileType::Symlink => return Err(VfsError::TooManySymlinks),
+            FileType::Directory if flags.contains(OpenFlags::WRITE) => {
+                return Err(VfsError::IsDirectory)
+            }
+            FileType::Directory => {}
+            _ if flags.contains(OpenFlags::DIRECTORY) => return Err(VfsError::NotDirectory),
+            FileType::Regular if flags.contains(OpenFlags::TRUNCATE | OpenFlags::WRITE) => {
+                resolved.inode.truncate(0)?;
+            }
+            _ => {}
+        }
+
+        Ok(Arc::new(OpenFile::new(
+            resolved.inode,
+            resolved.path,
+            flags,
+        )?))
+    }
+
+    pub fn metadata(&self, cwd: &str, path: &str, follow: bool) -> Result<Metadata> {
+        self.resolve(cwd, path, follow)?.inode.metadata()
+    }
+
+    pub fn create_dir(&self, cwd: &str, path: &str, mode: u16) -> Result<()> {
+        let (dir, name) = self.resolve_parent(cwd, path)?;
+        dir.inode.create(name, FileType::Directory, mode)?;
+        Ok(())
+    }
+
+    /// Creates a symlink at `path` pointing to `target`, which doesn't have to exist.
+    pub fn symlink(&self, cwd: &str, target: &str, path: &str) -> Result<()> {
+        if target.is_empty() || target.len() > path::PATH_MAX {
+            return Err(VfsError::InvalidArgument);
+        }
+        let (dir, name) = self.resolve_parent(cwd, path)?;
+        dir.inode.symlink(name, target)?;
+        Ok(())
+    }
+
+    pub fn read_link(&self, cwd: &str, path: &str) -> Result<String> {
+        self.resolve(cwd, path, false)?.inode.read_link()
+    }
+
+    /// Moves `old` to `new` in the same file system. A directory can't move under itself, and
+    /// mount points can't move.
+    pub fn rename(&self, cwd: &str, old: &str, new: &str) -> Result<()> {
+        let (old_dir, old_name) = self.resolve_parent(cwd, old)?;
+        let (new_dir, new_name) = self.resolve_parent(cwd, new)?;
+        let old_path = path::join(&old_dir.path, old_name);
+        let new_path = path::join(&new_dir.path, new_name);
+
+        let mounts = self.mounts.read();
+        if mounts.contains_key(&old_path) || mounts.contains_key(&new_path) {
+            return Err(VfsError::Busy);
+        }
+        if mount_point(&mounts, &old_dir.path) != mount_point(&mounts, &new_dir.path) {
+            return Err(VfsError::CrossDevice);
+        }
+        drop(mounts);
+        if new_path != old_path && path::is_within(&new_path, &old_path) {
+            return Err(VfsError::InvalidArgument);
+        }
+
+        old_dir.inode.rename(old_name, &new_dir.inode, new_name)
+    }
+
+    /// Sets the size of the regular file at `path`.
+    pub fn truncate(&self, cwd: &str, path: &str, size: u64) -> Result<()> {
+        let inode = self.resolve(cwd, path, true)?.inode;
+        match inode.metadata()?.file_type {
+            FileType::Regular => inode.truncate(size),
+            FileType::Directory => Err(VfsError::IsDirectory),
+            _ => Err(VfsError::InvalidArgument),
+        }
+    }
+
+    /// Removes a file, a symlink or a device node.
+    pub fn remove_file(&self, cwd: &str, path: &str) -> Result<()> {
+        let (dir, name) = self.resolve_parent(cwd, path)?;
+        if dir.inode.lookup(name)?.metadata()?.is_dir() {
+            return Err(VfsError::IsDirectory);
+        }
+        dir.inode.unlink(name)
+    }
+
+    /// Removes an empty directory, mount points can't be removed.
+    pub fn remove_dir(&self, cwd: &str, path: &str) -> Result<()> {
+        let (dir, name) = self.resolve_parent(cwd, path)?;
+        if !dir.inode.lookup(name)?.metadata()?.is_dir() {
+            return Err(VfsError::NotDirectory);
+        }
+        if self
+            .mounts
+            .read()
+            .contains_key(&path::join(&dir.path, name))
+        {
+            return Err(VfsError::Busy);
+        }
+        dir.inode.rmdir(name)
+    }
+
+    fn root(&self) -> Result<Resolved> {
+        self.mounted("/").ok_or(VfsError::NotFound)
+    }
+
+    /// Root of the file system mounted at `path`.
+    fn mounted(&self, path: &str) -> Option<Resolved> {
+        self.mounts.read().get(path).map(|mount| Resolved {
+            inode: mount.root.clone(),
+            path: String::from(path),
+        })
+    }
+
+    /// Walks `path` from the last directory of `stack`, which holds the directories down from
+    /// the root. `..` goes back up the stack, so it leaves mounted file systems and follows the
+    /// targets of symlinks, not the links.
+    fn walk(&self, mut stack: Vec<Resolved>, path: &str, follow: bool) -> Result<Vec<Resolved>> {
+        let mut pending: VecDeque<String> = path::components(path).map(String::from).collect();
+        let mut links = 0;
+
+        while let Some(name) = pending.pop_front() {
+            match name.as_str() {
+                "." => continue,
+                ".." => {
+                    if stack.len() > 1 {
+                        stack.pop();
+                    }
+                    continue;
+                }
+                name if name.len() > path::NAME_MAX => return Err(VfsError::NameTooLong),
+                _ => {}
+            }
+
+            let parent = stack.last().unwrap();
+            let path = path::join(&parent.path, &name);
+            let inode = match self.mounted(&path) {
+                Some(mounted) => mounted.inode,
+                None => parent.inode.lookup(&name)?,
+            };
+
+            let is_last = pending.is_empty();
+            if inode.metadata()?.file_type == FileType::Symlink && (follow || !is_last) {
+                links += 1;
+                if links > path::MAX_SYMLINKS {
+                    return Err(VfsError::TooManySymlinks);
+                }
+                let target = inode.read_link()?;
+                if target.starts_with('/') {
+                    stack.truncate(1);
+                }
+                for component in path::components(&target).rev() {
+                    pending.push_front(component.to_string());
+                }
+                continue;
+            }
+
+            stack.push(Resolved { inode, path });
+        }
+        Ok(stack)
+    }
+}
+
+/// Mount point of the file system holding the absolute, normalized `path`.
+fn mount_point<'a>(mounts: &'a BTreeMap<String, Mount>, path: &str) -> Option<&'a str> {
+    mounts
+        .keys()
+        .filter(|mount| path::is_within(path, mount))
+        .max_by_key(|mount| mount.len())
+        .map(String::as_str)
+}
+
+impl Default for Vfs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,48 @@
+use alloc::string::String;
+
+/// Longest path accepted.
+pub const PATH_MAX: usize = 4096;
+/// Longest file name accepted.
+pub const NAME_MAX: usize = 255;
+/// Symlinks followed in a single resolution before giving up.
+pub const MAX_SYMLINKS: usize = 40;
+
+/// Components of `path`, the empty ones between repeated slashes skipped.
+pub fn components(path: &str) -> impl DoubleEndedIterator<Item = &str> {
+    path.split('/').filter(|component| !component.is_empty())
+}
+
+/// Splits `path` in its directory and its last component, trailing slashes ignored. `None` for
+/// the root.
+pub fn split_last(path: &str) -> Option<(&str, &str)> {
+    let path = path.trim_end_matches('/');
+    match path.rfind('/') {
+        Some(index) => {
+            let dir = match &path[..index] {
+                "" => "/",
+                dir => dir,
+            };
+            Some((dir, &path[index + 1..]))
+        }
+        None if path.is_empty() => None,
+        None => Some((".", path)),
+    }
+}
+
+/// Path of `name` in the directory at the absolute path `dir`.
+pub fn join(dir: &str, name: &str) -> String {
+    let mut path = String::from(dir);
+    if !path.ends_with('/') {
+        path.push('/');
+    }
+    path.push_str(name);
+    path
+}
+
+/// Whether `path` is `ancestor` or under it, both being absolute and normalized.
+pub fn is_within(path: &str, ancestor: &str) -> bool {
+    match path.strip_prefix(ancestor) {
+        Some(rest) => ancestor == "/" || rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
@@ -0,0 +1,433 @@
+//! Tests of the path resolution and the mount table on a file system in memory. They run on the
+//! host: `cargo test -p vfs --target x86_64-unknown-linux-gnu -Z build-std=std,panic_unwind`.
+
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use vfs::{
+    path, DirEntry, File, FileSystem, FileType, Inode, Metadata, OpenFlags, Result, SeekFrom, Vfs,
+    VfsError,
+};
+
+static NEXT_INODE: AtomicU64 = AtomicU64::new(1);
+
+enum Contents {
+    File(Vec<u8>),
+    Directory(BTreeMap<String, Arc<MemInode>>),
+    Symlink(String),
+}
+
+struct MemInode {
+    inode: u64,
+    contents: Mutex<Contents>,
+}
+
+impl MemInode {
+    fn new(contents: Contents) -> Arc<Self> {
+        Arc::new(Self {
+            inode: NEXT_INODE.fetch_add(1, Ordering::Relaxed),
+            contents: Mutex::new(contents),
+        })
+    }
+
+    fn with_entries<R>(
+        &self,
+        f: impl FnOnce(&mut BTreeMap<String, Arc<MemInode>>) -> Result<R>,
+    ) -> Result<R> {
+        match &mut *self.contents.lock().unwrap() {
+            Contents::Directory(entries) => f(entries),
+            _ => Err(VfsError::NotDirectory),
+        }
+    }
+
+    fn add(&self, name: &str, child: Arc<MemInode>) -> Result<Arc<dyn Inode>> {
+        self.with_entries(|entries| {
+            if entries.contains_key(name) {
+                return Err(VfsError::AlreadyExists);
+            }
+            entries.insert(name.to_string(), child.clone());
+            Ok(child as Arc<dyn Inode>)
+        })
+    }
+}
+
+impl Inode for MemInode {
+    fn metadata(&self) -> Result<Metadata> {
+        let (file_type, size) = match &*self.contents.lock().unwrap() {
+            Contents::File(data) => (FileType::Regular, data.len()),
+            Contents::Directory(entries) => (FileType::Directory, entries.len()),
+            Contents::Symlink(target) => (FileType::Symlink, target.len()),
+        };
+        Ok(Metadata::new(self.inode, file_type, 0o755, size as u64))
+    }
+
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        match &*self.contents.lock().unwrap() {
+            Contents::File(data) => {
+                let data = data.get(offset as usize..).unwrap_or_default();
+                let len = buffer.len().min(data.len());
+                buffer[..len].copy_from_slice(&data[..len]);
+                Ok(len)
+            }
+            Contents::Directory(_) => Err(VfsError::IsDirectory),
+            Contents::Symlink(_) => Err(VfsError::InvalidArgument),
+        }
+    }
+
+    fn write_at(&self, offset: u64, buffer: &[u8]) -> Result<usize> {
+        match &mut *self.contents.lock().unwrap() {
+            Contents::File(data) => {
+                let end = offset as usize + buffer.len();
+                if data.len() < end {
+                    data.resize(end, 0);
+                }
+                data[offset as usize..end].copy_from_slice(buffer);
+                Ok(buffer.len())
+            }
+            _ => Err(VfsError::IsDirectory),
+        }
+    }
+
+    fn truncate(&self, size: u64) -> Result<()> {
+        match &mut *self.contents.lock().unwrap() {
+            Contents::File(data) => {
+                data.resize(size as usize, 0);
+                Ok(())
+            }
+            _ => Err(VfsError::InvalidArgument),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>> {
+        self.with_entries(|entries| {
+            let child = entries.get(name).ok_or(VfsError::NotFound)?;
+            Ok(child.clone() as Arc<dyn Inode>)
+        })
+    }
+
+    fn read_dir(&self, index: usize) -> Result<Option<DirEntry>> {
+        self.with_entries(|entries| {
+            Ok(entries.iter().nth(index).map(|(name, child)| DirEntry {
+                name: name.clone(),
+                inode: child.inode,
+                file_type: child.metadata().unwrap().file_type,
+            }))
+        })
+    }
+
+    fn create(&self, name: &str, file_type: FileType, _mode: u16) -> Result<Arc<dyn Inode>> {
+        let contents = match file_type {
+            FileType::Directory => Contents::Directory(BTreeMap::new()),
+            _ => Contents::File(Vec::new()),
+        };
+        self.add(name, MemInode::new(contents))
+    }
+
+    fn symlink(&self, name: &str, target: &str) -> Result<Arc<dyn Inode>> {
+        self.add(name, MemInode::new(Contents::Symlink(target.to_string())))
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        self.with_entries(|entries| entries.remove(name).map(|_| ()).ok_or(VfsError::NotFound))
+    }
+
+    fn rmdir(&self, name: &str) -> Result<()> {
+        self.unlink(name)
+    }
+
+    fn rename(&self, name: &str, new_dir: &Arc<dyn Inode>, new_name: &str) -> Result<()> {
+        let new_dir: Arc<dyn Any + Send + Sync> = new_dir.clone();
+        let new_dir = new_dir
+            .downcast::<MemInode>()
+            .map_err(|_| VfsError::CrossDevice)?;
+        let child = self.with_entries(|entries| entries.remove(name).ok_or(VfsError::NotFound))?;
+        new_dir.with_entries(|entries| {
+            entries.insert(new_name.to_string(), child);
+            Ok(())
+        })
+    }
+
+    fn read_link(&self) -> Result<String> {
+        match &*self.contents.lock().unwrap() {
+            Contents::Symlink(target) => Ok(target.clone()),
+            _ => Err(VfsError::InvalidArgument),
+        }
+    }
+}
+
+struct MemFs {
+    root: Arc<MemInode>,
+}
+
+impl MemFs {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            root: MemInode::new(Contents::Directory(BTreeMap::new())),
+        })
+    }
+}
+
+impl FileSystem for MemFs {
+    fn name(&self) -> &'static str {
+        "memfs"
+    }
+
+    fn root(&self) -> Result<Arc<dyn Inode>> {
+        Ok(self.root.clone())
+    }
+}
+
+/// A tree with `/data` and `/mnt` holding another file system:
+///
+/// ```text
+/// /data/file          "root data"
+/// /data/link    ->    file
+/// /mnt/               mounted
+/// /mnt/inner/file     "mounted data"
+/// /mnt/up       ->    ../data
+/// ```
+fn tree() -> Vfs {
+    let vfs = Vfs::new();
+    vfs.mount("/", MemFs::new()).unwrap();
+    vfs.create_dir("/", "/data", 0o755).unwrap();
+    vfs.create_dir("/", "/mnt", 0o755).unwrap();
+    write(&vfs, "/data/file", b"root data");
+    vfs.symlink("/", "file", "/data/link").unwrap();
+
+    vfs.mount("/mnt", MemFs::new()).unwrap();
+    vfs.create_dir("/", "/mnt/inner", 0o755).unwrap();
+    write(&vfs, "/mnt/inner/file", b"mounted data");
+    vfs.symlink("/", "../data", "/mnt/up").unwrap();
+    vfs
+}
+
+fn write(vfs: &Vfs, path: &str, data: &[u8]) {
+    let flags = OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE;
+    let file = vfs.open("/", path, flags, 0o644).unwrap();
+    assert_eq!(file.write(data).unwrap(), data.len());
+}
+
+fn read(vfs: &Vfs, cwd: &str, path: &str) -> Result<Vec<u8>> {
+    let file = vfs.open(cwd, path, OpenFlags::READ, 0)?;
+    let mut data = vec![0; 64];
+    let len = file.read(&mut data)?;
+    data.truncate(len);
+    Ok(data)
+}
+
+fn resolved_path(vfs: &Vfs, cwd: &str, path: &str) -> Result<String> {
+    Ok(vfs.resolve(cwd, path, true)?.path)
+}
+
+#[test]
+fn resolves_dot_and_dot_dot() {
+    let vfs = tree();
+    assert_eq!(
+        resolved_path(&vfs, "/", "/data/./file").unwrap(),
+        "/data/file"
+    );
+    assert_eq!(
+        resolved_path(&vfs, "/", "//data//file").unwrap(),
+        "/data/file"
+    );
+    assert_eq!(resolved_path(&vfs, "/data", "../mnt/.").unwrap(), "/mnt");
+    assert_eq!(resolved_path(&vfs, "/data", "file").unwrap(), "/data/file");
+    // The root is its own parent
+    assert_eq!(resolved_path(&vfs, "/", "/../../data").unwrap(), "/data");
+    assert_eq!(resolved_path(&vfs, "/", "/").unwrap(), "/");
+    assert_eq!(
+        resolved_path(&vfs, "/", "/data/missing/..").err(),
+        Some(VfsError::NotFound)
+    );
+    assert_eq!(resolved_path(&vfs, "/", "").err(), Some(VfsError::NotFound));
+}
+
+#[test]
+fn walks_across_mounts() {
+    let vfs = tree();
+    assert_eq!(read(&vfs, "/", "/mnt/inner/file").unwrap(), b"mounted data");
+    // `..` from the root of a mounted file system goes to the directory it is mounted on
+    assert_eq!(
+        resolved_path(&vfs, "/mnt/inner", "../../data").unwrap(),
+        "/data"
+    );
+    assert_eq!(
+        read(&vfs, "/mnt/inner", "../../data/file").unwrap(),
+        b"root data"
+    );
+    // And so does a relative symlink
+    assert_eq!(read(&vfs, "/", "/mnt/up/file").unwrap(), b"root data");
+    assert_eq!(resolved_path(&vfs, "/", "/mnt/up").unwrap(), "/data");
+    assert_eq!(resolved_path(&vfs, "/", "/mnt/inner/..").unwrap(), "/mnt");
+
+    let mounts: Vec<String> = vfs.mounts().into_iter().map(|(path, _)| path).collect();
+    assert_eq!(mounts, ["/", "/mnt"]);
+}
+
+#[test]
+fn follows_symlinks() {
+    let vfs = tree();
+    assert_eq!(read(&vfs, "/", "/data/link").unwrap(), b"root data");
+    let link = vfs.resolve("/", "/data/link", false).unwrap();
+    assert_eq!(link.path, "/data/link");
+    assert_eq!(link.inode.metadata().unwrap().file_type, FileType::Symlink);
+    assert_eq!(vfs.read_link("/", "/data/link").unwrap(), "file");
+
+    vfs.symlink("/", "/mnt/inner", "/data/absolute").unwrap();
+    assert_eq!(
+        resolved_path(&vfs, "/", "/data/absolute/file").unwrap(),
+        "/mnt/inner/file"
+    );
+    assert_eq!(
+        vfs.open("/", "/data/link", OpenFlags::READ | OpenFlags::NO_FOLLOW, 0)
+            .err(),
+        Some(VfsError::TooManySymlinks)
+    );
+}
+
+#[test]
+fn stops_symlink_loops() {
+    let vfs = tree();
+    vfs.symlink("/", "loop-b", "/data/loop-a").unwrap();
+    vfs.symlink("/", "loop-a", "/data/loop-b").unwrap();
+    assert_eq!(
+        resolved_path(&vfs, "/", "/data/loop-a").err(),
+        Some(VfsError::TooManySymlinks)
+    );
+
+    // A chain of exactly `MAX_SYMLINKS` links resolves, one more doesn't
+    for i in 0..=path::MAX_SYMLINKS {
+        let target = match i {
+            0 => String::from("file"),
+            i => format!("chain-{}", i - 1),
+        };
+        vfs.symlink("/", &target, &format!("/data/chain-{}", i))
+            .unwrap();
+    }
+    let last = path::MAX_SYMLINKS - 1;
+    assert_eq!(
+        resolved_path(&vfs, "/", &format!("/data/chain-{}", last)).unwrap(),
+        "/data/file"
+    );
+    assert_eq!(
+        resolved_path(&vfs, "/", &format!("/data/chain-{}", last + 1)).err(),
+        Some(VfsError::TooManySymlinks)
+    );
+}
+
+#[test]
+fn trailing_slashes_need_directories() {
+    let vfs = tree();
+    assert_eq!(resolved_path(&vfs, "/", "/data/").unwrap(), "/data");
+    assert_eq!(
+        resolved_path(&vfs, "/", "/data/file/").err(),
+        Some(VfsError::NotDirectory)
+    );
+    // A trailing slash follows the last symlink even without `follow`
+    vfs.symlink("/", "/mnt", "/data/dir-link").unwrap();
+    let resolved = vfs.resolve("/", "/data/dir-link/", false).unwrap();
+    assert_eq!(resolved.path, "/mnt");
+    assert_eq!(
+        vfs.resolve("/", "/data/link/", false).err(),
+        Some(VfsError::NotDirectory)
+    );
+}
+
+#[test]
+fn renames_within_a_file_system() {
+    let vfs = tree();
+    vfs.rename("/", "/data/file", "/data/moved").unwrap();
+    assert_eq!(read(&vfs, "/", "/data/moved").unwrap(), b"root data");
+    assert_eq!(
+        read(&vfs, "/", "/data/file").err(),
+        Some(VfsError::NotFound)
+    );
+
+    vfs.rename("/mnt", "inner/file", "file").unwrap();
+    assert_eq!(read(&vfs, "/", "/mnt/file").unwrap(), b"mounted data");
+    // Not under itself
+    assert_eq!(
+        vfs.rename("/", "/mnt/inner", "/mnt/inner/deeper").err(),
+        Some(VfsError::InvalidArgument)
+    );
+}
+
+#[test]
+fn refuses_renames_across_mounts() {
+    let vfs = tree();
+    assert_eq!(
+        vfs.rename("/", "/data/file", "/mnt/file").err(),
+        Some(VfsError::CrossDevice)
+    );
+    assert_eq!(
+        vfs.rename("/", "/mnt/inner/file", "/data/other").err(),
+        Some(VfsError::CrossDevice)
+    );
+    // Mount points don't move, and nothing takes their place
+    assert_eq!(
+        vfs.rename("/", "/mnt", "/moved").err(),
+        Some(VfsError::Busy)
+    );
+    assert_eq!(vfs.rename("/", "/data", "/mnt").err(), Some(VfsError::Busy));
+    assert_eq!(read(&vfs, "/", "/data/file").unwrap(), b"root data");
+}
+
+#[test]
+fn keeps_busy_mounts() {
+    let vfs = tree();
+    assert_eq!(vfs.mount("/mnt", MemFs::new()).err(), Some(VfsError::Busy));
+    assert_eq!(
+        vfs.mount("/data/file", MemFs::new()).err(),
+        Some(VfsError::NotDirectory)
+    );
+    assert_eq!(vfs.remove_dir("/", "/mnt").err(), Some(VfsError::Busy));
+
+    vfs.mount("/mnt/inner", MemFs::new()).unwrap();
+    // Not while another is mounted under it
+    assert_eq!(vfs.unmount("/mnt").err(), Some(VfsError::Busy));
+    vfs.unmount("/mnt/inner").unwrap();
+    assert_eq!(read(&vfs, "/", "/mnt/inner/file").unwrap(), b"mounted data");
+
+    vfs.unmount("/mnt").unwrap();
+    assert_eq!(
+        resolved_path(&vfs, "/", "/mnt/inner").err(),
+        Some(VfsError::NotFound)
+    );
+    assert_eq!(vfs.unmount("/mnt").err(), Some(VfsError::InvalidArgument));
+}
+
+#[test]
+fn moves_the_offset_of_open_files() {
+    let vfs = tree();
+    let file = vfs
+        .open("/", "/data/file", OpenFlags::READ | OpenFlags::WRITE, 0)
+        .unwrap();
+    let mut data = [0; 4];
+    assert_eq!(file.read(&mut data).unwrap(), 4);
+    assert_eq!(&data, b"root");
+    assert_eq!(file.write(b"-").unwrap(), 1);
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 5);
+    assert_eq!(file.read(&mut data).unwrap(), 4);
+    assert_eq!(&data, b"data");
+    assert_eq!(file.read(&mut data).unwrap(), 0);
+
+    let append = vfs
+        .open("/", "/data/file", OpenFlags::WRITE | OpenFlags::APPEND, 0)
+        .unwrap();
+    append.write(b"!").unwrap();
+    assert_eq!(read(&vfs, "/", "/data/file").unwrap(), b"root-data!");
+    assert_eq!(append.seek(SeekFrom::Current(0)).unwrap(), 10);
+
+    let dir = vfs.open("/", "/data", OpenFlags::READ, 0).unwrap();
+    let mut names = Vec::new();
+    while let Some(entry) = dir.read_dir().unwrap() {
+        names.push(entry.name);
+    }
+    assert_eq!(names, ["file", "link"]);
+}
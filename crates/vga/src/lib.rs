@@ -192,9 +192,9 @@ pub fn _print(args: fmt::Arguments) {
 }
 
 pub fn write(char: Char, row: usize, col: usize) {
-    BUFFER.lock().write(char, row, col)
+    interrupts::without_interrupts(|| BUFFER.lock().write(char, row, col))
 }
 
 pub fn read(row: usize, col: usize) -> Char {
-    BUFFER.lock().read(row, col)
+    interrupts::without_interrupts(|| BUFFER.lock().read(row, col))
 }
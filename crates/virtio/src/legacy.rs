@@ -0,0 +1,90 @@
+//! Registers of the legacy virtio PCI interface, in the I/O space at the first BAR.
+
+use x86::instructions::port::{Port, PortRead, PortWrite};
+
+const DEVICE_FEATURES: u16 = 0x00;
+const DRIVER_FEATURES: u16 = 0x04;
+const QUEUE_ADDRESS: u16 = 0x08;
+const QUEUE_SIZE: u16 = 0x0C;
+const QUEUE_SELECT: u16 = 0x0E;
+const QUEUE_NOTIFY: u16 = 0x10;
+const DEVICE_STATUS: u16 = 0x12;
+/// Start of the configuration of the device, while MSI-X is disabled.
+const DEVICE_CONFIG: u16 = 0x14;
+
+/// Bits of the device status.
+pub mod status {
+    pub const ACKNOWLEDGE: u8 = 1;
+    pub const DRIVER: u8 = 2;
+    pub const DRIVER_OK: u8 = 4;
+    pub const FAILED: u8 = 128;
+}
+
+pub struct LegacyTransport {
+    base: u16,
+}
+
+impl LegacyTransport {
+    /// # Safety
+    ///
+    /// `base` must be the I/O BAR of a virtio device, used by nothing else.
+    pub unsafe fn new(base: u16) -> Self {
+        Self { base }
+    }
+
+    fn read<T: PortRead>(&self, offset: u16) -> T {
+        unsafe { Port::new(self.base + offset).read() }
+    }
+
+    fn write<T: PortWrite>(&mut self, offset: u16, value: T) {
+        unsafe { Port::new(self.base + offset).write(value) }
+    }
+
+    /// Resets the device, which forgets its features and queues.
+    pub fn reset(&mut self) {
+        self.write(DEVICE_STATUS, 0u8);
+    }
+
+    pub fn status(&self) -> u8 {
+        self.read(DEVICE_STATUS)
+    }
+
+    /// Adds `status` to the bits already set.
+    pub fn add_status(&mut self, status: u8) {
+        let status = self.status() | status;
+        self.write(DEVICE_STATUS, status);
+    }
+
+    pub fn device_features(&self) -> u32 {
+        self.read(DEVICE_FEATURES)
+    }
+
+    pub fn set_driver_features(&mut self, features: u32) {
+        self.write(DRIVER_FEATURES, features);
+    }
+
+    /// Size of the queue `queue`, 0 if it doesn't exist.
+    pub fn queue_size(&mut self, queue: u16) -> u16 {
+        self.write(QUEUE_SELECT, queue);
+        self.read(QUEUE_SIZE)
+    }
+
+    /// Sets the page-aligned physical address of the queue `queue`, 0 disables it.
+    pub fn set_queue_address(&mut self, queue: u16, paddr: u64) {
+        self.write(QUEUE_SELECT, queue);
+        self.write(QUEUE_ADDRESS, (paddr >> 12) as u32);
+    }
+
+    /// Tells the device that the queue `queue` has new buffers.
+    pub fn notify(&mut self, queue: u16) {
+        self.write(QUEUE_NOTIFY, queue);
+    }
+
+    pub fn config_u8(&self, offset: u16) -> u8 {
+        self.read(DEVICE_CONFIG + offset)
+    }
+
+    pub fn config_u16(&self, offset: u16) -> u16 {
+        self.read(DEVICE_CONFIG + offset)
+    }
+}
@@ -1,15 +1,93 @@
+//! Virtio devices over the legacy PCI transport, the interface QEMU keeps for its transitional
+//! devices. The kernel provides the memory the devices reach through [`Hal`].
 #![no_std]
 
-#[derive(Clone)]
-#[repr(C)]
-struct VirtioPciCap {
-    vndr: u8,
-    next: u8,
-    cfg_type: u8,
-    bar: u8,
-    padding1: [u8; 3],
-    offset: u32,
-    length: u32,
+extern crate alloc;
+
+pub mod legacy;
+pub mod p9;
+pub mod queue;
+
+use core::{marker::PhantomData, ptr::NonNull};
+
+/// PCI vendor of the virtio devices.
+pub const PCI_VENDOR_ID: u16 = 0x1AF4;
+pub const PAGE_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// No memory for the queues or the buffers.
+    NoMemory,
+    /// The device has no queue at the index.
+    QueueUnavailable,
+    /// Every descriptor of the queue is in use.
+    QueueFull,
+    /// A message doesn't fit in the buffers of the device.
+    BufferTooSmall,
+    /// The device lacks a feature the driver needs.
+    Unsupported,
+    /// The device didn't answer in time. It was reset and answers no request anymore.
+    Timeout,
+}
+
+/// Allocator of the memory shared with the devices.
+///
+/// # Safety
+///
+/// `dma_alloc` must return zeroed pages, contiguous in physical memory and mapped at the returned
+/// address until they are given back to `dma_dealloc`.
+pub unsafe trait Hal {
+    /// Allocates `pages` pages, returns their physical address and a pointer to them.
+    fn dma_alloc(pages: usize) -> Option<(u64, NonNull<u8>)>;
+
+    /// # Safety
+    ///
+    /// The pages must come from `dma_alloc` and the device must not use them anymore.
+    unsafe fn dma_dealloc(paddr: u64, vaddr: NonNull<u8>, pages: usize);
+}
+
+/// Pages shared with a device, given back when dropped.
+pub struct Dma<H: Hal> {
+    paddr: u64,
+    vaddr: NonNull<u8>,
+    pages: usize,
+    _hal: PhantomData<H>,
 }
 
-impl VirtioPciCap {}
+// The pages are only reached through the owner of the buffer
+unsafe impl<H: Hal> Send for Dma<H> {}
+unsafe impl<H: Hal> Sync for Dma<H> {}
+
+impl<H: Hal> Dma<H> {
+    pub fn new(pages: usize) -> Result<Self, Error> {
+        let (paddr, vaddr) = H::dma_alloc(pages).ok_or(Error::NoMemory)?;
+        Ok(Self {
+            paddr,
+            vaddr,
+            pages,
+            _hal: PhantomData,
+        })
+    }
+
+    pub fn paddr(&self) -> u64 {
+        self.paddr
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.vaddr.as_ptr()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pages * PAGE_SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages == 0
+    }
+}
+
+impl<H: Hal> Drop for Dma<H> {
+    fn drop(&mut self) {
+        unsafe { H::dma_dealloc(self.paddr, self.vaddr, self.pages) };
+    }
+}
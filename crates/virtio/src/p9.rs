@@ -0,0 +1,131 @@
+//! The 9P transport device, exchanging the messages of a 9P client with a share of the host.
+
+use alloc::string::String;
+use core::ptr;
+
+use crate::{
+    legacy::{status, LegacyTransport},
+    queue::VirtQueue,
+    Dma, Error, Hal, PAGE_SIZE,
+};
+
+/// PCI device of the transitional 9P device.
+pub const PCI_DEVICE_ID: u16 = 0x1009;
+
+/// The configuration holds the tag naming the share.
+const FEATURE_MOUNT_TAG: u32 = 1;
+
+const BUFFER_PAGES: usize = 4;
+/// Largest request and response, the `msize` to negotiate.
+pub const MAX_MESSAGE_SIZE: usize = BUFFER_PAGES * PAGE_SIZE;
+
+pub struct Virtio9p<H: Hal> {
+    transport: LegacyTransport,
+    queue: VirtQueue<H>,
+    request: Dma<H>,
+    response: Dma<H>,
+    tag: String,
+    /// Reset after a request timed out.
+    failed: bool,
+}
+
+impl<H: Hal> Virtio9p<H> {
+    pub fn new(mut transport: LegacyTransport) -> Result<Self, Error> {
+        transport.reset();
+        transport.add_status(status::ACKNOWLEDGE | status::DRIVER);
+        if transport.device_features() & FEATURE_MOUNT_TAG == 0 {
+            return Err(fail(&mut transport, Error::Unsupported));
+        }
+        transport.set_driver_features(FEATURE_MOUNT_TAG);
+
+        let len = transport.config_u16(0);
+        let tag = (0..len)
+            .map(|i| transport.config_u8(2 + i) as char)
+            .collect();
+        let queue = match VirtQueue::new(&mut transport, 0) {
+            Ok(queue) => queue,
+            Err(err) => return Err(fail(&mut transport, err)),
+        };
+        let buffers =
+            Dma::new(BUFFER_PAGES).and_then(|request| Ok((request, Dma::new(BUFFER_PAGES)?)));
+        let (request, response) = match buffers {
+            Ok(buffers) => buffers,
+            // The queue is freed once the device forgot it
+            Err(err) => return Err(fail(&mut transport, err)),
+        };
+        transport.add_status(status::DRIVER_OK);
+
+        Ok(Self {
+            transport,
+            queue,
+            request,
+            response,
+            tag,
+            failed: false,
+        })
+    }
+
+    /// Tag of the share, given to QEMU with `mount_tag`.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Sends `request` and waits for the response, returns its length. The device is polled, it
+    /// answers as soon as the host did the operation.
+    ///
+    /// `wait` runs between the polls and gives up on the request when it returns `false`. The
+    /// device is reset then, it could still write the response into the buffers of the next one.
+    pub fn request(
+        &mut self,
+        request: &[u8],
+        response: &mut [u8],
+        mut wait: impl FnMut() -> bool,
+    ) -> Result<usize, Error> {
+        if self.failed {
+            return Err(Error::Timeout);
+        }
+        if request.len() > MAX_MESSAGE_SIZE {
+            return Err(Error::BufferTooSmall);
+        }
+        let response_len = response.len().min(MAX_MESSAGE_SIZE);
+        unsafe {
+            ptr::copy_nonoverlapping(request.as_ptr(), self.request.as_ptr(), request.len());
+        }
+
+        let head = self.queue.add(
+            &[(self.request.paddr(), request.len() as u32)],
+            &[(self.response.paddr(), response_len as u32)],
+        )?;
+        self.transport.notify(self.queue.index());
+        let len = loop {
+            match self.queue.pop_used() {
+                Some((used, len)) => {
+                    debug_assert_eq!(used, head);
+                    break (len as usize).min(response_len);
+                }
+                None if wait() => {}
+                None => {
+                    self.failed = true;
+                    return Err(fail(&mut self.transport, Error::Timeout));
+                }
+            }
+        };
+
+        unsafe { ptr::copy_nonoverlapping(self.response.as_ptr(), response.as_mut_ptr(), len) };
+        Ok(len)
+    }
+}
+
+impl<H: Hal> Drop for Virtio9p<H> {
+    /// Stops the device before its queue and buffers are freed.
+    fn drop(&mut self) {
+        self.transport.reset();
+    }
+}
+
+/// Resets a device which couldn't be set up, and marks it as failed.
+fn fail(transport: &mut LegacyTransport, err: Error) -> Error {
+    transport.reset();
+    transport.add_status(status::FAILED);
+    err
+}
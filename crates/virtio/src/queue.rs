@@ -0,0 +1,177 @@
+//! Split virtqueues, laid out in contiguous pages as the legacy interface wants them.
+
+use core::{
+    mem::size_of,
+    ptr,
+    sync::atomic::{fence, Ordering},
+};
+
+use crate::{legacy::LegacyTransport, Dma, Error, Hal, PAGE_SIZE};
+
+/// The descriptor continues in `next`.
+const DESC_F_NEXT: u16 = 1;
+/// The device writes the buffer instead of reading it.
+const DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// Offsets of the available and used rings, and the pages of a queue of `size` descriptors:
+/// the descriptors and the available ring, then the used ring on the next page.
+fn layout(size: usize) -> (usize, usize, usize) {
+    let avail = size * size_of::<Descriptor>();
+    let used = (avail + 2 * (3 + size)).next_multiple_of(PAGE_SIZE);
+    let end = used + (2 * 3 + 8 * size).next_multiple_of(PAGE_SIZE);
+    (avail, used, end / PAGE_SIZE)
+}
+
+pub struct VirtQueue<H: Hal> {
+    dma: Dma<H>,
+    index: u16,
+    size: u16,
+    avail: usize,
+    used: usize,
+    /// First descriptor of the free list, chained by their `next`.
+    free_head: u16,
+    free_count: u16,
+    /// Our copy of the index of the available ring.
+    avail_idx: u16,
+    /// Index of the used ring seen last.
+    last_used: u16,
+}
+
+impl<H: Hal> VirtQueue<H> {
+    /// Allocates the queue `index` of the device and gives it its address.
+    pub fn new(transport: &mut LegacyTransport, index: u16) -> Result<Self, Error> {
+        let size = transport.queue_size(index);
+        if size == 0 {
+            return Err(Error::QueueUnavailable);
+        }
+
+        let (avail, used, pages) = layout(size as usize);
+        let dma = Dma::new(pages)?;
+        let queue = Self {
+            dma,
+            index,
+            size,
+            avail,
+            used,
+            free_head: 0,
+            free_count: size,
+            avail_idx: 0,
+            last_used: 0,
+        };
+        for i in 0..size {
+            let mut descriptor = queue.descriptor(i);
+            descriptor.next = i.wrapping_add(1);
+            queue.set_descriptor(i, descriptor);
+        }
+
+        transport.set_queue_address(index, queue.dma.paddr());
+        Ok(queue)
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    fn descriptor(&self, index: u16) -> Descriptor {
+        let descriptors = self.dma.as_ptr() as *const Descriptor;
+        unsafe { ptr::read_volatile(descriptors.add(index as usize)) }
+    }
+
+    fn set_descriptor(&self, index: u16, descriptor: Descriptor) {
+        let descriptors = self.dma.as_ptr() as *mut Descriptor;
+        unsafe { ptr::write_volatile(descriptors.add(index as usize), descriptor) };
+    }
+
+    /// Pointer to the `u16` at `offset` of the queue.
+    fn field(&self, offset: usize) -> *mut u16 {
+        unsafe { self.dma.as_ptr().add(offset) as *mut u16 }
+    }
+
+    /// Makes a chain of the `inputs` the device reads followed by the `outputs` it writes, as
+    /// physical addresses and lengths, available to the device and returns its head. The device
+    /// still has to be notified.
+    pub fn add(&mut self, inputs: &[(u64, u32)], outputs: &[(u64, u32)]) -> Result<u16, Error> {
+        let count = inputs.len() + outputs.len();
+        if count == 0 || count > self.free_count as usize {
+            return Err(Error::QueueFull);
+        }
+
+        let head = self.free_head;
+        let buffers = inputs
+            .iter()
+            .map(|buffer| (buffer, 0))
+            .chain(outputs.iter().map(|buffer| (buffer, DESC_F_WRITE)));
+        for (i, (&(addr, len), flags)) in buffers.enumerate() {
+            let index = self.free_head;
+            let next = self.descriptor(index).next;
+            let flags = match i + 1 < count {
+                true => flags | DESC_F_NEXT,
+                false => flags,
+            };
+            self.set_descriptor(
+                index,
+                Descriptor {
+                    addr,
+                    len,
+                    flags,
+                    next,
+                },
+            );
+            self.free_head = next;
+        }
+        self.free_count -= count as u16;
+
+        let slot = (self.avail_idx % self.size) as usize;
+        unsafe { ptr::write_volatile(self.field(self.avail + 4 + 2 * slot), head) };
+        // The device must see the descriptors and the ring entry before the new index
+        fence(Ordering::SeqCst);
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        unsafe { ptr::write_volatile(self.field(self.avail + 2), self.avail_idx) };
+        fence(Ordering::SeqCst);
+        Ok(head)
+    }
+
+    /// Takes the next chain the device is done with, returns its head and the length the device
+    /// wrote. Its descriptors go back to the free list.
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        let used_idx = unsafe { ptr::read_volatile(self.field(self.used + 2)) };
+        if used_idx == self.last_used {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+
+        let slot = (self.last_used % self.size) as usize;
+        let element = unsafe { self.dma.as_ptr().add(self.used + 4 + 8 * slot) as *const u32 };
+        let (head, len) = unsafe {
+            (
+                ptr::read_volatile(element) as u16,
+                ptr::read_volatile(element.add(1)),
+            )
+        };
+        self.last_used = self.last_used.wrapping_add(1);
+
+        let mut index = head;
+        loop {
+            let descriptor = self.descriptor(index);
+            self.free_count += 1;
+            if descriptor.flags & DESC_F_NEXT == 0 {
+                let mut descriptor = descriptor;
+                descriptor.next = self.free_head;
+                self.set_descriptor(index, descriptor);
+                break;
+            }
+            index = descriptor.next;
+        }
+        self.free_head = head;
+        Some((head, len))
+    }
+}
@@ -41,6 +41,15 @@ impl DescriptorFlags {
     pub const KERNEL_CODE64: Self = Self::from_bits_truncate(
         Self::COMMON.bits() | Self::EXECUTABLE.bits() | Self::LONG_MODE.bits(),
     );
+
+    pub const KERNEL_DATA: Self =
+        Self::from_bits_truncate(Self::COMMON.bits() | Self::DEFAULT_SIZE.bits());
+
+    pub const USER_CODE64: Self =
+        Self::from_bits_truncate(Self::KERNEL_CODE64.bits() | Self::DPL_RING_3.bits());
+
+    pub const USER_DATA: Self =
+        Self::from_bits_truncate(Self::KERNEL_DATA.bits() | Self::DPL_RING_3.bits());
 }
 
 pub enum Descriptor {
@@ -62,6 +71,18 @@ impl Descriptor {
         Descriptor::UserSegment(DescriptorFlags::KERNEL_CODE64.bits())
     }
 
+    pub fn kernel_data_segment() -> Descriptor {
+        Descriptor::UserSegment(DescriptorFlags::KERNEL_DATA.bits())
+    }
+
+    pub fn user_code_segment() -> Descriptor {
+        Descriptor::UserSegment(DescriptorFlags::USER_CODE64.bits())
+    }
+
+    pub fn user_data_segment() -> Descriptor {
+        Descriptor::UserSegment(DescriptorFlags::USER_DATA.bits())
+    }
+
     pub fn tss_segment(tss: &'static TaskStateSegment) -> Descriptor {
         let ptr = (tss as *const _) as u64;
         let mut low = DescriptorFlags::PRESENT.bits();
@@ -3,7 +3,10 @@ use core::{
     ops::{Index, IndexMut},
 };
 
-use crate::segmentation::{self, SegmentSelector};
+use crate::{
+    segmentation::{self, SegmentSelector},
+    PrivilegeLevel,
+};
 use bit_field::BitField;
 
 use super::DescriptorTablePointer;
@@ -23,6 +26,12 @@ impl EntryOptions {
         self.0.set_bits(0..3, index + 1);
         self
     }
+
+    /// Minimum privilege level allowed to raise the interrupt with `int`.
+    pub fn set_privilege_level(&mut self, dpl: PrivilegeLevel) -> &mut Self {
+        self.0.set_bits(13..15, dpl as u16);
+        self
+    }
 }
 
 impl Default for EntryOptions {
@@ -43,7 +52,12 @@ pub struct Entry {
 }
 
 impl Entry {
-    fn set_handler_addr(&mut self, addr: u64) -> &mut EntryOptions {
+    /// Points the entry to a handler written in assembly.
+    ///
+    /// # Safety
+    /// `addr` must be the address of code that returns with `iretq`, popping the error code the
+    /// CPU pushes for the exception if there is one.
+    pub unsafe fn set_handler_addr(&mut self, addr: u64) -> &mut EntryOptions {
         self.pointer_low = addr as u16;
         self.pointer_middle = (addr >> 16) as u16;
         self.pointer_high = (addr >> 32) as u32;
@@ -53,7 +67,7 @@ impl Entry {
     }
 
     pub fn set_handler(&mut self, f: HandlerFunc) -> &mut EntryOptions {
-        self.set_handler_addr(f as u64)
+        unsafe { self.set_handler_addr(f as u64) }
     }
 }
 
@@ -0,0 +1,40 @@
+use core::arch::asm;
+
+/// Area written by `fxsave`, holding the x87, MMX and SSE state.
+#[derive(Clone)]
+#[repr(C, align(16))]
+pub struct FxSaveArea([u8; 512]);
+
+impl FxSaveArea {
+    /// State after `fninit`, with all the SIMD exceptions masked.
+    pub const fn new() -> Self {
+        let mut area = [0; 512];
+        // FCW
+        area[0] = 0x7F;
+        area[1] = 0x03;
+        // MXCSR
+        area[24] = 0x80;
+        area[25] = 0x1F;
+        Self(area)
+    }
+}
+
+impl Default for FxSaveArea {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Saves the FPU and SSE registers, `Cr4Flags::OSFXSR` must be set.
+pub unsafe fn fxsave(area: &mut FxSaveArea) {
+    unsafe {
+        asm!("fxsave64 [{}]", in(reg) area, options(nostack, preserves_flags));
+    }
+}
+
+/// Restores the registers saved by `fxsave`.
+pub unsafe fn fxrstor(area: &FxSaveArea) {
+    unsafe {
+        asm!("fxrstor64 [{}]", in(reg) area, options(nostack, preserves_flags, readonly));
+    }
+}
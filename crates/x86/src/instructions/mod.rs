@@ -2,6 +2,7 @@ use core::arch::asm;
 
 use crate::segmentation::SegmentSelector;
 
+pub mod fpu;
 pub mod interrupts;
 pub mod port;
 pub mod tlb;
@@ -17,3 +18,10 @@ pub fn hlt() {
         asm!("hlt", options(nomem, nostack, preserves_flags));
     }
 }
+
+/// Exchanges the GS base with the value of the `KernelGsBase` MSR.
+pub unsafe fn swapgs() {
+    unsafe {
+        asm!("swapgs", options(nostack, preserves_flags));
+    }
+}
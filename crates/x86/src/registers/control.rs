@@ -30,5 +30,73 @@ impl Cr3 {
         let frame = PhysFrame::containing_address(addr);
         (frame, (value & 0xFFF) as u16)
     }
+
+    /// Switches to the page tables at `frame`, which flushes the non-global TLB entries.
+    pub unsafe fn write(frame: PhysFrame, flags: Cr3Flags) {
+        let value = frame.start_address().as_u64() | flags.bits();
+        unsafe {
+            asm!("mov cr3, {}", in(reg) value, options(nostack, preserves_flags));
+        }
+    }
+}
+
+pub struct Cr0;
+
+bitflags! {
+    pub struct Cr0Flags: u64 {
+        const PROTECTED_MODE_ENABLE = 1;
+        const MONITOR_COPROCESSOR = 1 << 1;
+        const EMULATE_COPROCESSOR = 1 << 2;
+        const TASK_SWITCHED = 1 << 3;
+        const NUMERIC_ERROR = 1 << 5;
+        const WRITE_PROTECT = 1 << 16;
+        const PAGING = 1 << 31;
+    }
 }
 
+impl Cr0 {
+    pub fn read() -> Cr0Flags {
+        let value: u64;
+        unsafe {
+            asm!("mov {}, cr0", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+        Cr0Flags::from_bits_retain(value)
+    }
+
+    pub unsafe fn write(flags: Cr0Flags) {
+        unsafe {
+            asm!("mov cr0, {}", in(reg) flags.bits(), options(nostack, preserves_flags));
+        }
+    }
+}
+
+pub struct Cr4;
+
+bitflags! {
+    pub struct Cr4Flags: u64 {
+        const PHYSICAL_ADDRESS_EXTENSION = 1 << 5;
+        const PAGE_GLOBAL = 1 << 7;
+        /// Enables `fxsave`, `fxrstor` and the SSE instructions.
+        const OSFXSR = 1 << 9;
+        /// SIMD floating point exceptions are reported with #XM instead of #UD.
+        const OSXMMEXCPT_ENABLE = 1 << 10;
+        const FSGSBASE = 1 << 16;
+        const OSXSAVE = 1 << 18;
+    }
+}
+
+impl Cr4 {
+    pub fn read() -> Cr4Flags {
+        let value: u64;
+        unsafe {
+            asm!("mov {}, cr4", out(reg) value, options(nomem, nostack, preserves_flags));
+        }
+        Cr4Flags::from_bits_retain(value)
+    }
+
+    pub unsafe fn write(flags: Cr4Flags) {
+        unsafe {
+            asm!("mov cr4, {}", in(reg) flags.bits(), options(nostack, preserves_flags));
+        }
+    }
+}
@@ -1,2 +1,3 @@
 pub mod control;
+pub mod model_specific;
 pub mod rflags;
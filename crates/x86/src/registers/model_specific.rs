@@ -0,0 +1,193 @@
+use core::arch::asm;
+
+use bitflags::bitflags;
+
+use crate::{
+    addr::{PhysAddr, VirtAddr},
+    registers::rflags::RFlags,
+    segmentation::SegmentSelector,
+    structures::paging::frame::PhysFrame,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Msr(u32);
+
+impl Msr {
+    pub const fn new(register: u32) -> Self {
+        Self(register)
+    }
+
+    pub unsafe fn read(&self) -> u64 {
+        let (high, low): (u32, u32);
+        unsafe {
+            asm!(
+                "rdmsr",
+                in("ecx") self.0,
+                out("eax") low,
+                out("edx") high,
+                options(nomem, nostack, preserves_flags)
+            );
+        }
+        ((high as u64) << 32) | (low as u64)
+    }
+
+    pub unsafe fn write(&mut self, value: u64) {
+        let low = value as u32;
+        let high = (value >> 32) as u32;
+        unsafe {
+            asm!(
+                "wrmsr",
+                in("ecx") self.0,
+                in("eax") low,
+                in("edx") high,
+                options(nostack, preserves_flags)
+            );
+        }
+    }
+}
+
+pub struct ApicBase;
+
+bitflags! {
+    pub struct ApicBaseFlags: u64 {
+        const BSP = 1 << 8;
+        const X2APIC_ENABLE = 1 << 10;
+        const LAPIC_ENABLE = 1 << 11;
+    }
+}
+
+impl ApicBase {
+    pub const MSR: Msr = Msr::new(0x1B);
+
+    pub fn read() -> (PhysFrame, ApicBaseFlags) {
+        let value = unsafe { Self::MSR.read() };
+        let addr = PhysAddr::new(value & 0x_000f_ffff_ffff_f000);
+        let frame = PhysFrame::containing_address(addr);
+        (frame, ApicBaseFlags::from_bits_truncate(value))
+    }
+
+    pub unsafe fn write(frame: PhysFrame, flags: ApicBaseFlags) {
+        let mut msr = Self::MSR;
+        unsafe { msr.write(frame.start_address().as_u64() | flags.bits()) };
+    }
+}
+
+/// Base of the FS segment, used for thread local storage.
+pub struct FsBase;
+
+impl FsBase {
+    pub const MSR: Msr = Msr::new(0xC000_0100);
+
+    pub fn read() -> VirtAddr {
+        VirtAddr::new(unsafe { Self::MSR.read() })
+    }
+
+    pub unsafe fn write(addr: VirtAddr) {
+        let mut msr = Self::MSR;
+        unsafe { msr.write(addr.as_u64()) };
+    }
+}
+
+/// Base of the GS segment.
+pub struct GsBase;
+
+impl GsBase {
+    pub const MSR: Msr = Msr::new(0xC000_0101);
+
+    pub fn read() -> VirtAddr {
+        VirtAddr::new(unsafe { Self::MSR.read() })
+    }
+
+    pub unsafe fn write(addr: VirtAddr) {
+        let mut msr = Self::MSR;
+        unsafe { msr.write(addr.as_u64()) };
+    }
+}
+
+/// Value exchanged with the GS base by `swapgs`.
+pub struct KernelGsBase;
+
+impl KernelGsBase {
+    pub const MSR: Msr = Msr::new(0xC000_0102);
+
+    pub fn read() -> VirtAddr {
+        VirtAddr::new(unsafe { Self::MSR.read() })
+    }
+
+    pub unsafe fn write(addr: VirtAddr) {
+        let mut msr = Self::MSR;
+        unsafe { msr.write(addr.as_u64()) };
+    }
+}
+
+/// Extended feature enable register.
+pub struct Efer;
+
+bitflags! {
+    pub struct EferFlags: u64 {
+        const SYSTEM_CALL_EXTENSIONS = 1;
+        const LONG_MODE_ENABLE = 1 << 8;
+        const LONG_MODE_ACTIVE = 1 << 10;
+        const NO_EXECUTE_ENABLE = 1 << 11;
+    }
+}
+
+impl Efer {
+    pub const MSR: Msr = Msr::new(0xC000_0080);
+
+    pub fn read() -> EferFlags {
+        EferFlags::from_bits_retain(unsafe { Self::MSR.read() })
+    }
+
+    pub unsafe fn write(flags: EferFlags) {
+        let mut msr = Self::MSR;
+        unsafe { msr.write(flags.bits()) };
+    }
+}
+
+/// Segments loaded by `syscall` and `sysret`.
+pub struct Star;
+
+impl Star {
+    pub const MSR: Msr = Msr::new(0xC000_0081);
+
+    /// `syscall` loads `kernel_code` and the next selector as SS, `sysret` loads the selector 16
+    /// bytes after `sysret_base` as CS and the one 8 bytes after it as SS, with RPL 3.
+    pub unsafe fn write(kernel_code: SegmentSelector, sysret_base: SegmentSelector) {
+        let value = (u64::from(sysret_base.0) << 48) | (u64::from(kernel_code.0) << 32);
+        let mut msr = Self::MSR;
+        unsafe { msr.write(value) };
+    }
+}
+
+/// Entry point of `syscall` in long mode.
+pub struct LStar;
+
+impl LStar {
+    pub const MSR: Msr = Msr::new(0xC000_0082);
+
+    pub fn read() -> VirtAddr {
+        VirtAddr::new(unsafe { Self::MSR.read() })
+    }
+
+    pub unsafe fn write(addr: VirtAddr) {
+        let mut msr = Self::MSR;
+        unsafe { msr.write(addr.as_u64()) };
+    }
+}
+
+/// RFLAGS bits cleared by `syscall`.
+pub struct SFMask;
+
+impl SFMask {
+    pub const MSR: Msr = Msr::new(0xC000_0084);
+
+    pub fn read() -> RFlags {
+        RFlags::from_bits_truncate(unsafe { Self::MSR.read() })
+    }
+
+    pub unsafe fn write(flags: RFlags) {
+        let mut msr = Self::MSR;
+        unsafe { msr.write(flags.bits()) };
+    }
+}
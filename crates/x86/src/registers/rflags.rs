@@ -13,13 +13,14 @@ bitflags! {
         const RESUME_FLAG = 1 << 16;
         const NESTED_TASK = 1 << 14;
         const IOPL_HIGH = 1 << 13;
-        const OVERFLOW_FLAG = 1 << 10;
+        const OVERFLOW_FLAG = 1 << 11;
+        const DIRECTION_FLAG = 1 << 10;
         const INTERRUPT_FLAG = 1 << 9;
-        const TRAP_FLAG = 1 << 9;
+        const TRAP_FLAG = 1 << 8;
         const SIGN_FLAG = 1 << 7;
         const ZERO_FLAG = 1 << 6;
         const AUXILIARY_CARRY_FLAG = 1 << 4;
-        const PARITY_FLAG = 1 << 4;
+        const PARITY_FLAG = 1 << 2;
         const CARRY_FLAG = 1;
     }
 }
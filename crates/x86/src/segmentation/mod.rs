@@ -11,6 +11,14 @@ impl SegmentSelector {
     pub fn new(addr: u16, rpl: PrivilegeLevel) -> Self {
         SegmentSelector(addr << 3 | (rpl as u16))
     }
+
+    pub fn index(self) -> u16 {
+        self.0 >> 3
+    }
+
+    pub fn rpl(self) -> PrivilegeLevel {
+        PrivilegeLevel::from(self.0 & 0b11)
+    }
 }
 
 pub struct CS;
@@ -56,3 +64,50 @@ impl CS {
         }
     }
 }
+
+macro_rules! data_segment {
+    ($(#[$attr:meta])* $name:ident, $reg:literal) => {
+        $(#[$attr])*
+        pub struct $name;
+
+        impl $name {
+            pub fn get_reg() -> SegmentSelector {
+                let selector: u16;
+                unsafe {
+                    asm!(
+                        concat!("mov {0:x}, ", $reg),
+                        out(reg) selector,
+                        options(nomem, nostack, preserves_flags)
+                    );
+                }
+
+                SegmentSelector(selector)
+            }
+
+            /// The selector must be null or point to a present data segment of the loaded GDT.
+            pub unsafe fn set_reg(sel: SegmentSelector) {
+                unsafe {
+                    asm!(
+                        concat!("mov ", $reg, ", {0:x}"),
+                        in(reg) sel.0,
+                        options(nostack, preserves_flags)
+                    );
+                }
+            }
+        }
+    };
+}
+
+data_segment!(DS, "ds");
+data_segment!(SS, "ss");
+data_segment!(ES, "es");
+data_segment!(
+    /// Loading a selector resets the FS base, `FsBase` must be written afterwards.
+    FS,
+    "fs"
+);
+data_segment!(
+    /// Loading a selector resets the GS base, `GsBase` must be written afterwards.
+    GS,
+    "gs"
+);
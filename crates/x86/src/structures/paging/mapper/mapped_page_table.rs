@@ -7,7 +7,10 @@ use crate::structures::paging::{
     page_table::{self, FrameError, PageTable, PageTableEntry, PageTableFlags},
 };
 
-use super::{MapToError, MappedFrame, Mapper, MapperFlush, Translate, TranslateResult};
+use super::{
+    FlagUpdateError, MapToError, MappedFrame, Mapper, MapperFlush, Translate, TranslateResult,
+    UnmapError,
+};
 
 pub unsafe trait PageTableFrameMapping {
     fn frame_to_pointer(&self, frame: PhysFrame) -> *mut PageTable;
@@ -61,6 +64,56 @@ impl<'a, P: PageTableFrameMapping> MappedPageTable<'a, P> {
         p1[page.p1_index().into()].set_frame(frame, flags);
         Ok(MapperFlush::new(page))
     }
+
+    fn unmap_4kib(
+        &mut self,
+        page: Page<Size4KiB>,
+    ) -> Result<(PhysFrame<Size4KiB>, MapperFlush<Size4KiB>), UnmapError> {
+        let p4 = &mut self.level_4_table;
+        let p3 = self
+            .page_table_walker
+            .next_table_mut(&mut p4[page.p4_index().into()])?;
+        let p2 = self
+            .page_table_walker
+            .next_table_mut(&mut p3[page.p3_index().into()])?;
+        let p1 = self
+            .page_table_walker
+            .next_table_mut(&mut p2[page.p2_index().into()])?;
+
+        let entry = &mut p1[page.p1_index().into()];
+        let frame = entry.frame().map_err(|err| match err {
+            FrameError::FrameNotPresent => UnmapError::PageNotMapped,
+            FrameError::HugeFrame => UnmapError::ParentEntryHugePage,
+        })?;
+
+        entry.set_unused();
+        Ok((frame, MapperFlush::new(page)))
+    }
+
+    fn update_flags_4kib(
+        &mut self,
+        page: Page<Size4KiB>,
+        flags: PageTableFlags,
+    ) -> Result<MapperFlush<Size4KiB>, FlagUpdateError> {
+        let p4 = &mut self.level_4_table;
+        let p3 = self
+            .page_table_walker
+            .next_table_mut(&mut p4[page.p4_index().into()])?;
+        let p2 = self
+            .page_table_walker
+            .next_table_mut(&mut p3[page.p3_index().into()])?;
+        let p1 = self
+            .page_table_walker
+            .next_table_mut(&mut p2[page.p2_index().into()])?;
+
+        let entry = &mut p1[page.p1_index().into()];
+        if entry.is_unused() {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+
+        entry.set_flags(flags);
+        Ok(MapperFlush::new(page))
+    }
 }
 
 impl<'a, P: PageTableFrameMapping> Translate for MappedPageTable<'a, P> {
@@ -151,6 +204,21 @@ impl<'a, P: PageTableFrameMapping> Mapper<Size4KiB> for MappedPageTable<'a, P> {
     {
         self.map_to_4kib(page, frame, flags, parent_table_flags, frame_allocator)
     }
+
+    fn unmap(
+        &mut self,
+        page: Page<Size4KiB>,
+    ) -> Result<(PhysFrame<Size4KiB>, MapperFlush<Size4KiB>), UnmapError> {
+        self.unmap_4kib(page)
+    }
+
+    unsafe fn update_flags(
+        &mut self,
+        page: Page<Size4KiB>,
+        flags: PageTableFlags,
+    ) -> Result<MapperFlush<Size4KiB>, FlagUpdateError> {
+        self.update_flags_4kib(page, flags)
+    }
 }
 
 #[derive(Debug)]
@@ -249,6 +317,24 @@ impl<P: PageTableFrameMapping> PageTableWalker<P> {
     }
 }
 
+impl From<PageTableWalkError> for UnmapError {
+    fn from(value: PageTableWalkError) -> Self {
+        match value {
+            PageTableWalkError::MappedToHugePage => UnmapError::ParentEntryHugePage,
+            PageTableWalkError::NotMapped => UnmapError::PageNotMapped,
+        }
+    }
+}
+
+impl From<PageTableWalkError> for FlagUpdateError {
+    fn from(value: PageTableWalkError) -> Self {
+        match value {
+            PageTableWalkError::MappedToHugePage => FlagUpdateError::ParentEntryHugePage,
+            PageTableWalkError::NotMapped => FlagUpdateError::PageNotMapped,
+        }
+    }
+}
+
 impl From<FrameError> for PageTableWalkError {
     fn from(err: FrameError) -> Self {
         match err {
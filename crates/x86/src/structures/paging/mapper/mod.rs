@@ -96,6 +96,16 @@ pub trait Mapper<S: PageSize> {
     where
         Self: Sized,
         A: FrameAllocator<Size4KiB> + ?Sized;
+
+    /// Removes the mapping of `page` and returns the frame it pointed to, the page tables are kept.
+    fn unmap(&mut self, page: Page<S>) -> Result<(PhysFrame<S>, MapperFlush<S>), UnmapError>;
+
+    /// Replaces the flags of the mapped `page`, the flags of the parent tables are kept.
+    unsafe fn update_flags(
+        &mut self,
+        page: Page<S>,
+        flags: PageTableFlags,
+    ) -> Result<MapperFlush<S>, FlagUpdateError>;
 }
 
 #[derive(Debug)]
@@ -104,3 +114,15 @@ pub enum MapToError<S: PageSize> {
     ParentEntryHugePage,
     PageAlreadyMapped(PhysFrame<S>),
 }
+
+#[derive(Debug)]
+pub enum UnmapError {
+    ParentEntryHugePage,
+    PageNotMapped,
+}
+
+#[derive(Debug)]
+pub enum FlagUpdateError {
+    ParentEntryHugePage,
+    PageNotMapped,
+}
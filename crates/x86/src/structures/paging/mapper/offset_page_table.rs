@@ -5,7 +5,7 @@ use crate::{
 
 use super::{
     mapped_page_table::{MappedPageTable, PageTableFrameMapping},
-    Mapper, Translate,
+    FlagUpdateError, Mapper, MapperFlush, Translate, UnmapError,
 };
 
 #[derive(Debug)]
@@ -65,4 +65,19 @@ impl<'a> Mapper<Size4KiB> for OffsetPageTable<'a> {
             )
         }
     }
+
+    fn unmap(
+        &mut self,
+        page: crate::structures::paging::page::Page<Size4KiB>,
+    ) -> Result<(PhysFrame<Size4KiB>, MapperFlush<Size4KiB>), UnmapError> {
+        self.inner.unmap(page)
+    }
+
+    unsafe fn update_flags(
+        &mut self,
+        page: crate::structures::paging::page::Page<Size4KiB>,
+        flags: crate::structures::paging::page_table::PageTableFlags,
+    ) -> Result<MapperFlush<Size4KiB>, FlagUpdateError> {
+        unsafe { self.inner.update_flags(page, flags) }
+    }
 }
@@ -108,7 +108,7 @@ impl PageTableEntry {
         self.0 = self.addr().as_u64() | flags.bits();
     }
 
-    fn set_unused(&mut self) {
+    pub fn set_unused(&mut self) {
         self.0 = 0;
     }
 }
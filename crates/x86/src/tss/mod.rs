@@ -11,16 +11,22 @@ pub struct TaskStateSegment {
     pub iomap_base: u16,
 }
 
-impl Default for TaskStateSegment {
-    fn default() -> Self {
+impl TaskStateSegment {
+    pub const fn new() -> Self {
         Self {
-            reserved_1: Default::default(),
-            privilege_stack_table: Default::default(),
-            reserved_2: Default::default(),
-            interrupt_stack_table: Default::default(),
-            reserved_3: Default::default(),
-            reserved_4: Default::default(),
+            reserved_1: 0,
+            privilege_stack_table: [0; 3],
+            reserved_2: 0,
+            interrupt_stack_table: [0; 7],
+            reserved_3: 0,
+            reserved_4: 0,
             iomap_base: size_of::<TaskStateSegment>() as u16,
         }
     }
 }
+
+impl Default for TaskStateSegment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
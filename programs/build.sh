@@ -0,0 +1,24 @@
+#!/bin/sh
+# Rebuilds the programs embedded in the kernel from their sources. The ones built with the GNU
+# assembler and linker are checked in so that the kernel builds without them. The kernel embeds
+# the others when they're built, musl-hello needs musl-gcc and userland-hello a nightly toolchain
+# with the rust-src component.
+set -e
+cd "$(dirname "$0")"
+
+objects=$(mktemp -d)
+trap 'rm -rf "$objects"' EXIT
+
+as hello.s -o "$objects/hello.o"
+ld -static -nostdlib -s -Ttext-segment=0x400000000000 -o hello "$objects/hello.o"
+
+# At the usual Linux load address
+as linux-hello.s -o "$objects/linux-hello.o"
+ld -static -nostdlib -s -o linux-hello "$objects/linux-hello.o"
+
+# Unmodified, against a static musl
+musl-gcc -static -Os -s -o musl-hello musl-hello.c
+
+# The example of the userland runtime, with the target of the programs
+(cd .. && cargo build -p userland --example hello --release --target x86_64-kerwanos-user.json)
+cp ../target/x86_64-kerwanos-user/release/examples/hello userland-hello
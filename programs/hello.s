@@ -0,0 +1,57 @@
+# Prints a greeting and its arguments, one per line, with the kerwanos syscall ABI.
+#
+# Built by programs/build.sh.
+
+    .intel_syntax noprefix
+
+    .set SYS_WRITE, 0
+    .set SYS_EXIT, 1
+    .set STDOUT, 1
+
+    .text
+    .global _start
+_start:
+    lea rsi, [rip + greeting]
+    mov rdx, OFFSET greeting_len
+    call write
+
+    # argc then the argv pointers are at the top of the stack
+    mov r12, [rsp]
+    lea r13, [rsp + 8]
+1:
+    test r12, r12
+    jz 3f
+    mov rsi, [r13]
+    xor rdx, rdx
+2:
+    cmp byte ptr [rsi + rdx], 0
+    je 4f
+    inc rdx
+    jmp 2b
+4:
+    call write
+    lea rsi, [rip + newline]
+    mov rdx, 1
+    call write
+    add r13, 8
+    dec r12
+    jmp 1b
+3:
+    mov rax, SYS_EXIT
+    xor rdi, rdi
+    syscall
+    ud2
+
+# Writes rdx bytes at rsi to the console
+write:
+    mov rax, SYS_WRITE
+    mov rdi, STDOUT
+    syscall
+    ret
+
+    .section .rodata
+greeting:
+    .ascii "Hello from ring 3!\n"
+    .set greeting_len, . - greeting
+newline:
+    .ascii "\n"
@@ -0,0 +1,207 @@
+# Goes through the system calls a static musl program makes, with the Linux x86_64 ABI, and
+# prints what the kernel reports. Linked at the usual Linux address.
+#
+# Built by programs/build.sh.
+
+    .intel_syntax noprefix
+
+    .set SYS_WRITE, 1
+    .set SYS_CLOSE, 3
+    .set SYS_MMAP, 9
+    .set SYS_MUNMAP, 11
+    .set SYS_BRK, 12
+    .set SYS_IOCTL, 16
+    .set SYS_WRITEV, 20
+    .set SYS_UNAME, 63
+    .set SYS_ARCH_PRCTL, 158
+    .set SYS_SET_TID_ADDRESS, 218
+    .set SYS_CLOCK_GETTIME, 228
+    .set SYS_EXIT_GROUP, 231
+    .set SYS_OPENAT, 257
+
+    .set STDOUT, 1
+    .set AT_FDCWD, -100
+    .set O_WRONLY, 1
+    .set ARCH_SET_FS, 0x1002
+    .set TCGETS, 0x5401
+    .set CLOCK_MONOTONIC, 1
+    .set PROT_READ_WRITE, 3
+    .set MAP_PRIVATE_ANONYMOUS, 0x22
+
+    .text
+    .global _start
+_start:
+    # Thread pointer, musl points it at its thread descriptor
+    mov rax, SYS_ARCH_PRCTL
+    mov rdi, ARCH_SET_FS
+    lea rsi, [rip + tls]
+    syscall
+    test rax, rax
+    jnz fail
+    lea rax, [rip + tls]
+    cmp rax, fs:0
+    jne fail
+
+    mov rax, SYS_SET_TID_ADDRESS
+    lea rdi, [rip + tid]
+    syscall
+
+    # "Hello from <sysname> <release> (<machine>)"
+    mov rax, SYS_UNAME
+    lea rdi, [rip + utsname]
+    syscall
+    test rax, rax
+    jnz fail
+    lea rdi, [rip + utsname]
+    call strlen
+    mov [rip + iov_sysname_len], rax
+    lea rdi, [rip + utsname + 65 * 2]
+    call strlen
+    mov [rip + iov_release_len], rax
+    lea rdi, [rip + utsname + 65 * 4]
+    call strlen
+    mov [rip + iov_machine_len], rax
+    mov rax, SYS_WRITEV
+    mov rdi, STDOUT
+    lea rsi, [rip + iov]
+    mov rdx, 7
+    syscall
+    test rax, rax
+    js fail
+
+    mov rax, SYS_IOCTL
+    mov rdi, STDOUT
+    mov rsi, TCGETS
+    lea rdx, [rip + termios]
+    syscall
+    test rax, rax
+    jnz fail
+
+    mov rax, SYS_CLOCK_GETTIME
+    mov rdi, CLOCK_MONOTONIC
+    lea rsi, [rip + timespec]
+    syscall
+    test rax, rax
+    jnz fail
+
+    # Grows the heap by two pages and touches them
+    mov rax, SYS_BRK
+    xor rdi, rdi
+    syscall
+    mov r12, rax
+    lea rdi, [rax + 0x2000]
+    mov rax, SYS_BRK
+    syscall
+    lea rdx, [r12 + 0x2000]
+    cmp rax, rdx
+    jne fail
+    mov qword ptr [r12 + 0x1ff8], 1
+
+    mov rax, SYS_MMAP
+    xor rdi, rdi
+    mov rsi, 0x3000
+    mov rdx, PROT_READ_WRITE
+    mov r10, MAP_PRIVATE_ANONYMOUS
+    mov r8, -1
+    xor r9, r9
+    syscall
+    test rax, rax
+    js fail
+    mov qword ptr [rax + 0x2ff8], 1
+    mov rdi, rax
+    mov rax, SYS_MUNMAP
+    mov rsi, 0x3000
+    syscall
+    test rax, rax
+    jnz fail
+
+    mov rax, SYS_OPENAT
+    mov rdi, AT_FDCWD
+    lea rsi, [rip + tty]
+    mov rdx, O_WRONLY
+    syscall
+    test rax, rax
+    js fail
+    mov r12, rax
+    mov rdi, rax
+    mov rax, SYS_WRITE
+    lea rsi, [rip + done]
+    mov rdx, OFFSET done_len
+    syscall
+    mov rax, SYS_CLOSE
+    mov rdi, r12
+    syscall
+
+    xor rdi, rdi
+    jmp exit
+fail:
+    mov rax, SYS_WRITE
+    mov rdi, STDOUT
+    lea rsi, [rip + failed]
+    mov rdx, OFFSET failed_len
+    syscall
+    mov rdi, 1
+exit:
+    mov rax, SYS_EXIT_GROUP
+    syscall
+    ud2
+
+# Length of the string at rdi
+strlen:
+    xor rax, rax
+1:
+    cmp byte ptr [rdi + rax], 0
+    je 2f
+    inc rax
+    jmp 1b
+2:
+    ret
+
+    .section .rodata
+hello:
+    .ascii "Hello from "
+    .set hello_len, . - hello
+space:
+    .ascii " "
+open_paren:
+    .ascii " ("
+close_paren:
+    .ascii ")\n"
+tty:
+    .asciz "/dev/tty"
+done:
+    .ascii "Linux system calls work\n"
+    .set done_len, . - done
+failed:
+    .ascii "A Linux system call failed\n"
+    .set failed_len, . - failed
+
+    .data
+    .balign 8
+iov:
+    .quad hello, hello_len
+    .quad utsname
+iov_sysname_len:
+    .quad 0
+    .quad space, 1
+    .quad utsname + 65 * 2
+iov_release_len:
+    .quad 0
+    .quad open_paren, 2
+    .quad utsname + 65 * 4
+iov_machine_len:
+    .quad 0
+    .quad close_paren, 2
+tls:
+    .quad tls
+
+    .bss
+    .balign 8
+tid:
+    .skip 8
+timespec:
+    .skip 16
+termios:
+    .skip 60
+utsname:
+    .skip 65 * 6
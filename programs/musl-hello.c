@@ -0,0 +1,34 @@
+/*
+ * A static C program on musl, run unmodified. The startup of musl sets the thread pointer and the
+ * thread id address, malloc grows the heap with brk and maps the large blocks, and stdio asks
+ * whether stdout is a terminal before writing with writev. Exits with 42.
+ *
+ * Built by programs/build.sh, with musl-gcc.
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* Reached through the thread pointer */
+static __thread int greetings;
+
+int main(int argc, char **argv)
+{
+	char *name = malloc(strlen(argv[0]) + 1);
+	/* Above the mmap threshold of musl */
+	char *large = malloc(1 << 20);
+	if (name == NULL || large == NULL)
+		return 1;
+	strcpy(name, argv[0]);
+	memset(large, 1, 1 << 20);
+
+	greetings++;
+	printf("Hello from %s with %d arguments, greeting %d\n", name, argc - 1, greetings);
+	for (int i = 1; i < argc; i++)
+		printf("  %s\n", argv[i]);
+
+	free(large);
+	free(name);
+	return 42;
+}
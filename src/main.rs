@@ -12,38 +12,42 @@ use std::println;
 
 use bootloader::{entry_point, BootInfo};
 use kernel::{
-    allocator,
+    allocator, fs,
     memory::{self, BootInfoFrameAllocator},
-    task::{executor::Executor, Task},
+    percpu, smp,
+    task::{
+        executor::{self, Executor},
+        Priority, Task,
+    },
+    thread,
+    time::{self, ClockSource},
     tty::TTY,
 };
 use lazy_static::lazy_static;
-use pci::{
-    access::{CSpaceAccess, CSpaceAccessMethod, IoCSpaceAccessMethod},
-    structures::{
-        device::Device,
-        register::{bar::BaseAddressRegister, command::CommandRegister, status::StatusRegister},
-    },
-};
+use pci::{access::CSpaceAccessMethod, structures::device::Device};
 use shell::Shell;
 use spin::Mutex;
-use x86::{
-    addr::{PhysAddr, VirtAddr},
-    structures::paging::{
-        frame::PhysFrame,
-        mapper::{Mapper, Translate},
-        page_table::PageTableFlags,
-    },
-};
+use x86::{addr::VirtAddr, instructions::interrupts};
 
+/// There is no unwinding. A panicking task is reported to its `JoinHandle` and its executor moves
+/// to a new thread, any other panic halts the CPU: the panicking code may hold locks and leave its
+/// data half modified, so nothing else runs on it.
 #[panic_handler]
 fn panic_handler(info: &PanicInfo) -> ! {
     println!("{}", info);
-    loop {}
+    unsafe { executor::resume_after_panic() };
+    interrupts::disable();
+    kernel::hlt_loop()
 }
 
 entry_point!(kernel_main);
 
+/// Mount options of the tmpfs at `/`.
+const ROOT_FS_OPTIONS: &str = "size=16m";
+/// Tag of the virtio 9P share of the host, and where it is mounted.
+const HOST_SHARE_TAG: &str = "host0";
+const HOST_SHARE_PATH: &str = "/host";
+
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
     println!("Initializing Kernel");
     kernel::init();
@@ -52,16 +56,35 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
     let mut mapper = unsafe { memory::init(phys_mem_offset) };
     let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    let trampoline = smp::reserve_trampoline(&mut frame_allocator);
 
     println!("Initializing Heap");
     allocator::init_heap(&mut mapper, &mut frame_allocator).expect("failed to initialize heap");
 
+    println!("Initializing Per-CPU Data");
+    percpu::init(0);
+
+    println!("Initializing ACPI");
+    let acpi = unsafe { acpi::Acpi::new(phys_mem_offset) }.expect("failed to initialize ACPI");
+
+    println!("Initializing Timer");
+    let clock_source = time::init(ClockSource::Hpet, &acpi, &mut mapper, &mut frame_allocator);
+    println!("Using {:?} as clock source", clock_source);
+
+    println!("Initializing Threads");
+    thread::init(&mut mapper, &mut frame_allocator).expect("failed to initialize threads");
+
+    println!("Starting Application Processors");
+    match trampoline.and_then(|frame| smp::init(frame, &acpi, &mut mapper, &mut frame_allocator)) {
+        Ok(count) => println!("{} CPUs online", count),
+        Err(err) => println!("WARNING: SMP unavailable ({:?})", err),
+    }
+
     println!("Initializing PCI");
     let devices = pci::scan_buses(CSpaceAccessMethod::Io);
 
     for device in devices {
         if let Device::General(device) = device {
-            let sam = IoCSpaceAccessMethod::new(device.common.location);
             std::println!(
                 "pci v:{:#x} d:{:#x} bc:{:#x} sc:{:#x} ht:{:#x}",
                 device.common.vendor_id,
@@ -70,90 +93,34 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
                 device.common.subclass,
                 device.common.header_type,
             );
-
-            // if device.common.device_id == 0x1009 {
-            //     for (i, bar) in device.bars.iter().enumerate() {
-            //         match bar {
-            //             BaseAddressRegister::Memory(bar) => {
-            //                 let addr = bar.address();
-            //                 if addr == 0x0 {
-            //                     continue;
-            //                 }
-            //
-            //                 // let size = bar.size();
-            //                 // let register = 0x10 + (i as u8) * 4;
-            //                 // let phys_addr = PhysAddr::new(bar.address() as u64);
-            //                 // sam.write(register, 0xFFFFFFFF);
-            //                 // let res = sam.read(register);
-            //                 // sam.write(register, bar.bits());
-            //                 //
-            //                 // let size = !(res & 0xFFFFFF0) + 1;
-            //                 //
-            //                 // serial::println!("-----");
-            //                 // serial::println!("Addr: {:#x}", bar.address());
-            //                 // serial::println!("Size: {:#x} {:#x}", res, size);
-            //             }
-            //             _ => {}
-            //         }
-            //     }
-            // }
-
-            if device.common.device_id == 0x1009 {
-                std::println!("{:#b}", device.capabilities_pointer);
-
-                let mut next_ptr = device.capabilities_pointer;
-                while next_ptr != 0 {
-                    let (capability_id, nptr, length, typ) = sam.read8(next_ptr);
-                    let (bar_index, _, _, _) = sam.read8(device.capabilities_pointer + 0x4);
-                    let offset = sam.read(device.capabilities_pointer + 0x8);
-                    let length = sam.read(device.capabilities_pointer + 0xC);
-                    serial::println!("------");
-                    serial::println!("Capability {:#x}", capability_id);
-                    if capability_id == 0x9 {
-                        let bar = device.bars[bar_index as usize];
-                        serial::println!("VType {:#x}", typ);
-                        serial::println!("Bar {:#x}", bar.address());
-
-                        let phys_addr = VirtAddr::new(bar.address().into());
-                        // let page = {};
-                        // let frame = PhysFrame::containing_address(phys_addr);
-                        // let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
-                        // mapper.map_to(page, frame, flags, frame_allocator)
-
-                        let test = mapper.translate_addr(phys_addr);
-                        match test {
-                            Some(t) => serial::println!("Test: {:#x}", t.as_u64()),
-                            None => serial::println!("Not found"),
-                        };
-
-                        // let test: &'static mut u32 = unsafe { &mut *(addr as *mut u32) };
-
-                        // serial::println!("Offset {:#x}", length);
-                    }
-
-                    next_ptr = nptr;
-                }
-                // let offset = sam.read(device.capabilities_pointer + 0x4);
-                // let (bar, _, _, _) = sam.read8(device.capabilities_pointer + 0x8);
-                // let (typ, len, next, vndr) = sam.read8(device.capabilities_pointer + 0xC);
-                //
-                // std::println!("Length {}", length);
-                // std::println!("Offset {:#b}", offset);
-                // std::println!("Bar {:#b}", offset);
-                // std::println!("Type {}", typ);
-                // std::println!("len {}", len);
-                // }
-            }
         }
     }
 
+    memory::install(mapper, frame_allocator, phys_mem_offset);
+
+    println!("Mounting File Systems");
+    fs::init(ROOT_FS_OPTIONS).expect("failed to mount the root file system");
+    match fs::initramfs::unpack(fs::initramfs::ARCHIVE) {
+        Ok(count) => println!("{} initramfs entries unpacked", count),
+        Err(err) => println!("WARNING: initramfs not unpacked ({:?})", err),
+    }
+    match fs::p9::mount(HOST_SHARE_TAG, HOST_SHARE_PATH) {
+        Ok(()) => println!("Host share mounted at {}", HOST_SHARE_PATH),
+        Err(err) => println!("WARNING: host share not mounted ({:?})", err),
+    }
+
     #[cfg(test)]
     test_main();
 
     let mut executor = Executor::new();
-    executor.spawn(Task::new(shell::init()));
+    executor.spawn(
+        Task::new(shell::init())
+            .with_name("shell")
+            .with_priority(Priority::High),
+    );
 
-    executor.run();
+    thread::spawn("executor", move || executor.run()).expect("failed to start the executor");
+    thread::exit();
 }
 
 #[cfg(test)]
@@ -172,3 +139,574 @@ fn trivial_assertion() {
     print!("Test");
     assert_eq!(1, 1);
 }
+
+#[test_case]
+fn user_slices_stay_below_user_end() {
+    use kernel::{
+        memory::USER_END,
+        syscall::{user_ptr::UserSlice, SyscallError},
+    };
+    assert_eq!(
+        UserSlice::new(u64::MAX, 2).err(),
+        Some(SyscallError::BadAddress)
+    );
+    assert_eq!(
+        UserSlice::new(USER_END - 8, 16).err(),
+        Some(SyscallError::BadAddress)
+    );
+    assert!(UserSlice::new(USER_END - 16, 16).is_ok());
+}
+
+#[test_case]
+fn user_slices_refuse_kernel_pages() {
+    use kernel::syscall::{user_ptr::UserSlice, SyscallError};
+    // Below `USER_END` but not mapped with user access
+    static KERNEL_DATA: [u8; 16] = [0; 16];
+    let slice = UserSlice::new(KERNEL_DATA.as_ptr() as u64, 16).unwrap();
+    assert_eq!(slice.read().err(), Some(SyscallError::BadAddress));
+    assert_eq!(slice.write(&[1; 16]).err(), Some(SyscallError::BadAddress));
+    assert_eq!(KERNEL_DATA, [0; 16]);
+
+    // Nothing to check in an empty slice
+    let empty = UserSlice::new(KERNEL_DATA.as_ptr() as u64, 0).unwrap();
+    assert_eq!(empty.read(), Ok(alloc::vec::Vec::new()));
+}
+
+#[test_case]
+fn user_programs_return_through_the_syscall_entry() {
+    use kernel::{loader::programs, process};
+    for name in ["hello", "linux-hello"] {
+        let program = programs::find(name).unwrap();
+        let pid = process::spawn(
+            name,
+            program.bytes,
+            &[],
+            &[],
+            program.personality,
+            None,
+            None,
+        )
+        .unwrap();
+        // Without a parent it is reaped once it exits
+        let mut yields = 0;
+        while process::processes()
+            .iter()
+            .any(|process| process.pid == pid)
+        {
+            assert!(yields < 100_000, "{} didn't exit", name);
+            thread::yield_now();
+            yields += 1;
+        }
+    }
+}
+
+#[test_case]
+fn address_spaces_keep_the_kernel_pages() {
+    use kernel::memory::{AddressSpace, MapError};
+    use x86::structures::paging::page::Page;
+    // The kernel image shares the first top level entry with the programs
+    let page = Page::new_containing_address(VirtAddr::new(kernel::hlt_loop as *const () as u64));
+    memory::with(|memory| {
+        let mut space = AddressSpace::new(memory).unwrap();
+        let unmapped = space.unmap(memory, page);
+        assert!(matches!(unmapped, Err(MapError::KernelRegion)));
+        assert!(space.translate(memory, page.start_address()).is_some());
+        drop(space.destroy(memory));
+    })
+    .unwrap();
+}
+
+#[test_case]
+fn kernel_stacks_sit_above_a_guard_page() {
+    use kernel::memory::{AddressSpace, KernelStack, STACKS_START};
+    let mut stack = KernelStack::new().unwrap();
+    let bottom = VirtAddr::new(stack.as_mut_slice().as_ptr() as u64);
+    let top = stack.top();
+    memory::with(|memory| {
+        assert!(memory.page_flags(bottom).is_some());
+        assert!(memory.page_flags(top - 1u64).is_some());
+        assert!(memory.page_flags(bottom - 1u64).is_none());
+        // The address spaces of the programs have the stacks mapped after they were made too
+        let space = AddressSpace::new(memory).unwrap();
+        assert!(space.translate(memory, bottom).is_some());
+        drop(space.destroy(memory));
+    })
+    .unwrap();
+
+    drop(stack);
+    assert_eq!(memory::with(|memory| memory.page_flags(bottom)), Some(None));
+
+    let address = thread::spawn("stack", || {
+        let local = 0u8;
+        &local as *const u8 as u64
+    })
+    .unwrap();
+    assert!(address.join() >= STACKS_START);
+}
+
+#[test_case]
+fn mmio_regions_are_unmapped() {
+    use x86::{addr::PhysAddr, structures::paging::page_table::PageTableFlags};
+    // The VGA text buffer, spanning two pages from an unaligned address
+    let (phys, size) = (PhysAddr::new(0xB8800), 4096);
+    memory::with(|memory| {
+        let base =
+            memory::map_mmio(phys, size, &mut memory.mapper, &mut memory.frame_allocator).unwrap();
+        let flags = memory.page_flags(base + (size - 1)).unwrap();
+        assert!(flags.contains(PageTableFlags::NO_CACHE));
+
+        memory::unmap_mmio(base, size, &mut memory.mapper);
+        assert!(memory.page_flags(base).is_none());
+        assert!(memory.page_flags(base + (size - 1)).is_none());
+    })
+    .unwrap();
+}
+
+#[test_case]
+fn tmpfs_files_only_count_written_pages() {
+    use kernel::fs::tmpfs::TmpFs;
+    use vfs::{FileSystem, FileType};
+    let fs = TmpFs::new(64 * 1024);
+    let file = fs
+        .root()
+        .unwrap()
+        .create("sparse", FileType::Regular, 0o644)
+        .unwrap();
+    assert_eq!(file.write_at(60 * 1024, b"end").unwrap(), 3);
+    assert_eq!(fs.usage(), (64 * 1024, 4096));
+
+    let mut data = [1; 8];
+    assert_eq!(file.read_at(4096, &mut data).unwrap(), 8);
+    assert_eq!(data, [0; 8]);
+    file.truncate(4096).unwrap();
+    assert_eq!(fs.usage(), (64 * 1024, 0));
+}
+
+#[test_case]
+fn loader_maps_the_embedded_programs() {
+    use kernel::loader::{self, programs, USER_STACK_TOP};
+    use x86::structures::paging::page_table::PageTableFlags;
+    let hello = programs::find("hello").unwrap();
+    let program = loader::load(hello.bytes, &["hello", "world"], &[]).unwrap();
+    assert_eq!(program.entry.as_u64(), 0x4000_0000_1000);
+    assert_eq!(program.brk.as_u64(), 0x4000_0000_3000);
+    assert!(program.stack_pointer.as_u64() < USER_STACK_TOP);
+    assert_eq!(program.stack_pointer.as_u64() % 16, 0);
+
+    let space = program.address_space;
+    memory::with(|memory| {
+        let (_, flags) = space.translate(memory, program.entry).unwrap();
+        assert!(flags.contains(PageTableFlags::USER_ACCESSIBLE));
+        assert!(!flags.intersects(PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE));
+        drop(space.destroy(memory));
+    })
+    .unwrap();
+}
+
+#[test_case]
+fn loader_refuses_kernel_segments() {
+    use kernel::{
+        allocator::HEAP_START,
+        loader::{self, programs, LoadError},
+        memory::{MapError, USER_END},
+    };
+    // Address of the last of the three segments of `hello`
+    const VADDR: usize = 64 + 2 * 56 + 16;
+    let mut bytes = programs::find("hello").unwrap().bytes.to_vec();
+
+    bytes[VADDR..VADDR + 8].copy_from_slice(&USER_END.to_le_bytes());
+    let past_end = loader::load(&bytes, &[], &[]);
+    assert!(matches!(past_end, Err(LoadError::InvalidSegment)));
+
+    bytes[VADDR..VADDR + 8].copy_from_slice(&(HEAP_START as u64).to_le_bytes());
+    let in_heap = loader::load(&bytes, &[], &[]);
+    assert!(matches!(
+        in_heap,
+        Err(LoadError::Map(MapError::KernelRegion))
+    ));
+}
+
+/// Waker counting its wakeups, to poll the futures of `kernel::sync` by hand.
+#[cfg(test)]
+mod wakes {
+    use alloc::sync::Arc;
+    use core::{
+        future::Future,
+        pin::Pin,
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Poll},
+    };
+    use futures_util::task::{self, ArcWake};
+
+    #[derive(Default)]
+    pub struct Wakes(AtomicUsize);
+
+    impl Wakes {
+        pub fn new() -> Arc<Self> {
+            Arc::default()
+        }
+
+        pub fn poll<F: Future + Unpin>(self: &Arc<Self>, future: &mut F) -> Poll<F::Output> {
+            let waker = task::waker(self.clone());
+            Pin::new(future).poll(&mut Context::from_waker(&waker))
+        }
+
+        pub fn count(&self) -> usize {
+            self.0.load(Ordering::Relaxed)
+        }
+    }
+
+    impl ArcWake for Wakes {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Polls `future` until it is ready, the other threads run in between.
+    pub fn wait<F: Future + Unpin>(future: &mut F) -> F::Output {
+        let wakes = Wakes::new();
+        loop {
+            if let Poll::Ready(output) = wakes.poll(future) {
+                return output;
+            }
+            kernel::thread::yield_now();
+        }
+    }
+}
+
+/// Starts the executor thread of the tests on the first call, it then backs `task::spawn`.
+#[cfg(test)]
+fn start_executor() {
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use kernel::task::Task;
+    static STARTED: AtomicBool = AtomicBool::new(false);
+    if STARTED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+
+    let mut executor = Executor::new();
+    let (task, mut started) = Task::with_handle(async {});
+    executor.spawn(task);
+    thread::spawn("executor", move || executor.run()).unwrap();
+    wakes::wait(&mut started).unwrap();
+}
+
+#[test_case]
+fn task_panics_are_reported_through_the_join_handle() {
+    use kernel::task::{self, JoinError};
+    start_executor();
+    let mut panicked = task::spawn(async {
+        if true {
+            panic!("a task panicking on purpose");
+        }
+    });
+    assert_eq!(wakes::wait(&mut panicked), Err(JoinError::Panicked));
+
+    // The executor carries on in a new thread
+    let mut next = task::spawn(async { 42 });
+    assert_eq!(wakes::wait(&mut next), Ok(42));
+}
+
+#[test_case]
+fn tasks_can_be_killed_right_after_spawning() {
+    use core::future;
+    use kernel::task::{self, stats, JoinError};
+    start_executor();
+    let mut killed = task::spawn_named("killed-when-queued", future::pending::<()>());
+    let id = stats::tasks()
+        .into_iter()
+        .find(|task| task.name.as_deref() == Some("killed-when-queued"))
+        .expect("a spawned task isn't listed")
+        .id;
+    assert!(task::kill(id));
+    assert_eq!(wakes::wait(&mut killed), Err(JoinError::Cancelled));
+    assert!(stats::task(id).is_none());
+}
+
+#[test_case]
+fn sync_mutex_passes_a_dropped_wakeup_on() {
+    use crate::wakes::Wakes;
+    use kernel::sync::Mutex;
+    let mutex = Mutex::new(0);
+    let guard = mutex.try_lock().unwrap();
+    let (first_wakes, second_wakes) = (Wakes::new(), Wakes::new());
+    let mut first = mutex.lock();
+    let mut second = mutex.lock();
+    assert!(first_wakes.poll(&mut first).is_pending());
+    assert!(second_wakes.poll(&mut second).is_pending());
+
+    drop(guard);
+    assert_eq!((first_wakes.count(), second_wakes.count()), (1, 0));
+    drop(first);
+    assert_eq!(second_wakes.count(), 1);
+    assert!(second_wakes.poll(&mut second).is_ready());
+    assert!(mutex.try_lock().is_some());
+}
+
+#[test_case]
+fn sync_rwlock_lets_a_waiting_writer_in_first() {
+    use crate::wakes::Wakes;
+    use core::task::Poll;
+    use kernel::sync::RwLock;
+    let lock = RwLock::new(0);
+    let reader = lock.try_read().unwrap();
+    let (write_wakes, read_wakes) = (Wakes::new(), Wakes::new());
+    let mut write = lock.write();
+    assert!(write_wakes.poll(&mut write).is_pending());
+    // Readers coming after the writer wait for it
+    assert!(lock.try_read().is_none());
+    let mut read = lock.read();
+    assert!(read_wakes.poll(&mut read).is_pending());
+
+    drop(reader);
+    assert_eq!((write_wakes.count(), read_wakes.count()), (1, 0));
+    match write_wakes.poll(&mut write) {
+        Poll::Ready(mut guard) => *guard += 1,
+        Poll::Pending => panic!("the writer didn't get the lock"),
+    }
+    assert_eq!(read_wakes.count(), 1);
+    match read_wakes.poll(&mut read) {
+        Poll::Ready(guard) => assert_eq!(*guard, 1),
+        Poll::Pending => panic!("the reader didn't get the lock"),
+    };
+}
+
+#[test_case]
+fn sync_semaphore_passes_a_dropped_permit_on() {
+    use crate::wakes::Wakes;
+    use kernel::sync::Semaphore;
+    let semaphore = Semaphore::new(1);
+    let permit = semaphore.try_acquire().unwrap();
+    let (first_wakes, second_wakes) = (Wakes::new(), Wakes::new());
+    let mut first = semaphore.acquire();
+    let mut second = semaphore.acquire();
+    assert!(first_wakes.poll(&mut first).is_pending());
+    assert!(second_wakes.poll(&mut second).is_pending());
+
+    drop(permit);
+    assert_eq!((first_wakes.count(), second_wakes.count()), (1, 0));
+    drop(first);
+    assert_eq!(second_wakes.count(), 1);
+    assert!(second_wakes.poll(&mut second).is_ready());
+    assert_eq!(semaphore.available_permits(), 1);
+}
+
+#[test_case]
+fn sync_notify_passes_a_dropped_notification_on() {
+    use crate::wakes::Wakes;
+    use kernel::sync::Notify;
+    let notify = Notify::new();
+    let wakes = Wakes::new();
+    // Kept until someone waits
+    notify.notify_one();
+    assert!(wakes.poll(&mut notify.notified()).is_ready());
+    assert!(wakes.poll(&mut notify.notified()).is_pending());
+
+    let (first_wakes, second_wakes) = (Wakes::new(), Wakes::new());
+    let mut first = notify.notified();
+    let mut second = notify.notified();
+    assert!(first_wakes.poll(&mut first).is_pending());
+    assert!(second_wakes.poll(&mut second).is_pending());
+    notify.notify_one();
+    assert_eq!((first_wakes.count(), second_wakes.count()), (1, 0));
+    drop(first);
+    assert_eq!(second_wakes.count(), 1);
+    assert!(second_wakes.poll(&mut second).is_ready());
+
+    // Without another waiter it is kept for the next one
+    let mut alone = notify.notified();
+    assert!(wakes.poll(&mut alone).is_pending());
+    notify.notify_one();
+    drop(alone);
+    assert!(wakes.poll(&mut notify.notified()).is_ready());
+
+    // `notify_waiters` leaves nothing behind
+    let mut waiter = notify.notified();
+    assert!(wakes.poll(&mut waiter).is_pending());
+    notify.notify_waiters();
+    drop(waiter);
+    assert!(wakes.poll(&mut notify.notified()).is_pending());
+}
+
+#[test_case]
+fn sync_channels_wake_the_other_side() {
+    use crate::wakes::Wakes;
+    use core::task::Poll;
+    use kernel::sync::{
+        mpsc::{self, TrySendError},
+        oneshot,
+    };
+    let (sender, mut receiver) = mpsc::channel(2);
+    sender.try_send(1).unwrap();
+    sender.try_send(2).unwrap();
+    assert_eq!(sender.try_send(3), Err(TrySendError::Full(3)));
+    let send_wakes = Wakes::new();
+    let mut send = sender.send(3);
+    assert!(send_wakes.poll(&mut send).is_pending());
+    assert_eq!(receiver.try_recv(), Ok(1));
+    assert_eq!(send_wakes.count(), 1);
+    assert!(matches!(send_wakes.poll(&mut send), Poll::Ready(Ok(()))));
+    drop(send);
+
+    let recv_wakes = Wakes::new();
+    for value in [2, 3] {
+        let polled = recv_wakes.poll(&mut receiver.recv());
+        assert!(matches!(polled, Poll::Ready(Some(v)) if v == value));
+    }
+    assert!(recv_wakes.poll(&mut receiver.recv()).is_pending());
+    drop(sender);
+    assert_eq!(recv_wakes.count(), 1);
+    assert!(matches!(
+        recv_wakes.poll(&mut receiver.recv()),
+        Poll::Ready(None)
+    ));
+
+    let (sender, mut receiver) = oneshot::channel();
+    assert!(recv_wakes.poll(&mut receiver).is_pending());
+    sender.send(7).unwrap();
+    assert_eq!(recv_wakes.count(), 2);
+    assert!(matches!(recv_wakes.poll(&mut receiver), Poll::Ready(Ok(7))));
+
+    let (sender, mut receiver) = oneshot::channel::<()>();
+    drop(sender);
+    assert!(matches!(
+        recv_wakes.poll(&mut receiver),
+        Poll::Ready(Err(oneshot::RecvError))
+    ));
+}
+
+#[test_case]
+fn thread_sleeps_are_cut_short_by_a_wake() {
+    use alloc::sync::Arc;
+    use core::{
+        sync::atomic::{AtomicBool, Ordering},
+        time::Duration,
+    };
+    let stop = Arc::new(AtomicBool::new(false));
+    let sleeper_stop = stop.clone();
+    let sleeper = thread::spawn("sleeper", move || {
+        thread::sleep_while(Duration::from_secs(60), || {
+            !sleeper_stop.load(Ordering::Relaxed)
+        })
+    })
+    .unwrap();
+    thread::sleep(Duration::from_millis(20));
+
+    let start = time::uptime();
+    stop.store(true, Ordering::Relaxed);
+    thread::wake(sleeper.id());
+    assert!(!sleeper.join());
+    assert!(time::uptime() - start < Duration::from_secs(1));
+    assert!(thread::sleep_while(Duration::from_millis(10), || true));
+}
+
+#[test_case]
+fn frame_allocator_takes_a_low_frame_out_of_order() {
+    use alloc::boxed::Box;
+    use bootloader::bootinfo::{FrameRange, MemoryMap, MemoryRegion, MemoryRegionType};
+    use x86::{
+        addr::PhysAddr,
+        structures::paging::{frame::PhysFrame, frame_alloc::FrameAllocator},
+    };
+    const LIMIT: PhysAddr = PhysAddr::new(0x10_0000);
+    let allocator = |regions: &[(u64, u64)]| {
+        let mut memory_map = MemoryMap::new();
+        for &(start, end) in regions {
+            memory_map.add_region(MemoryRegion {
+                range: FrameRange::new(start, end),
+                region_type: MemoryRegionType::Usable,
+            });
+        }
+        unsafe { BootInfoFrameAllocator::init(Box::leak(Box::new(memory_map))) }
+    };
+    let address = |frame: Option<PhysFrame>| frame.map(|frame| frame.start_address().as_u64());
+
+    let mut frame_allocator = allocator(&[(0x8000, 0xA000), (0x20_0000, 0x20_2000)]);
+    assert_eq!(address(frame_allocator.allocate_frame()), Some(0x8000));
+    assert_eq!(address(frame_allocator.allocate_below(LIMIT)), Some(0x9000));
+    // Only one frame is taken out of order
+    assert!(frame_allocator.allocate_below(LIMIT).is_none());
+    assert_eq!(address(frame_allocator.allocate_frame()), Some(0x20_0000));
+    assert_eq!(address(frame_allocator.allocate_frame()), Some(0x20_1000));
+    assert!(frame_allocator.allocate_frame().is_none());
+
+    // Found wherever the low memory is in the map
+    let mut frame_allocator = allocator(&[(0x20_0000, 0x20_2000), (0x7000, 0x8000)]);
+    assert_eq!(address(frame_allocator.allocate_below(LIMIT)), Some(0x7000));
+    assert_eq!(address(frame_allocator.allocate_frame()), Some(0x20_0000));
+    assert_eq!(address(frame_allocator.allocate_frame()), Some(0x20_1000));
+    assert!(frame_allocator.allocate_frame().is_none());
+
+    let mut high_only = allocator(&[(0x20_0000, 0x20_1000)]);
+    assert!(high_only.allocate_below(LIMIT).is_none());
+}
+
+/// Runs the embedded program `name` as a child of the kernel with a pipe as its stdout, returns its
+/// exit code and what it wrote. The programs built with more than the GNU tools must be built with
+/// `programs/build.sh` first, so these tests come last.
+#[cfg(test)]
+fn run_program(name: &str, args: &[&str], env: &[&str]) -> (i32, alloc::string::String) {
+    use alloc::{string::String, sync::Arc, vec::Vec};
+    use kernel::{
+        ipc::pipe,
+        loader::programs,
+        process::{
+            self,
+            handle::{Handle, HandleTable},
+            Pid,
+        },
+    };
+    let program = programs::find(name)
+        .unwrap_or_else(|| panic!("{} isn't built, run programs/build.sh", name));
+    let (reader, writer) = pipe::pipe();
+    let mut handles = HandleTable::with_console();
+    handles.remove(1).unwrap();
+    assert_eq!(handles.insert(Arc::new(writer)), Ok(1));
+    let pid = process::spawn(
+        name,
+        program.bytes,
+        args,
+        env,
+        program.personality,
+        Some(Pid::KERNEL),
+        Some(handles),
+    )
+    .unwrap();
+
+    // Read until the program exits and closes its end
+    let mut output = Vec::new();
+    let mut buffer = [0; 256];
+    loop {
+        match reader.read(&mut buffer).unwrap() {
+            0 => break,
+            len => output.extend_from_slice(&buffer[..len]),
+        }
+    }
+    let (reaped, code) = process::wait(Pid::KERNEL, Some(pid)).unwrap();
+    assert_eq!(reaped, pid);
+    (code, String::from_utf8(output).unwrap())
+}
+
+#[test_case]
+fn musl_programs_run_unmodified() {
+    let (code, output) = run_program("musl-hello", &["musl-hello", "world"], &[]);
+    assert_eq!(
+        output,
+        "Hello from musl-hello with 1 arguments, greeting 1\n  world\n"
+    );
+    assert_eq!(code, 42);
+}
+
+#[test_case]
+fn userland_runtime_programs_get_their_args_and_env() {
+    let (code, output) = run_program(
+        "userland-hello",
+        &["userland-hello", "a", "b"],
+        &["GREETING=hi"],
+    );
+    assert_eq!(
+        output,
+        "Hello from userland-hello with 2 arguments\n  a\n  b\nGREETING is hi\n"
+    );
+    assert_eq!(code, 2);
+}